@@ -0,0 +1,87 @@
+//! Per-bin anomaly scores against a baseline.
+//!
+//! Monitoring views highlight buckets that stray from their usual level:
+//! each bin's value (a count or an aggregate) is scored as
+//! `(value - mean) / std` against a baseline histogram's per-bin mean and
+//! standard deviation, and bins at or beyond a threshold are flagged. One
+//! pass, no allocation beyond the result.
+//!
+//! A bin with zero deviation scores 0 when it matches its mean and an
+//! infinite score (always flagged) otherwise. NaN inputs score NaN and are
+//! never flagged.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+
+/// Bin scores with the flagged bins.
+#[wasm_bindgen]
+pub struct BinScores {
+    scores: Vec<f64>,
+    anomalies: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl BinScores {
+    /// z-score of every bin.
+    #[wasm_bindgen(getter)]
+    pub fn scores(&self) -> Vec<f64> {
+        self.scores.clone()
+    }
+
+    /// Bins whose absolute score reaches the threshold, ascending.
+    #[wasm_bindgen(getter)]
+    pub fn anomalies(&self) -> Vec<u32> {
+        self.anomalies.clone()
+    }
+}
+
+fn z_score(value: f64, mean: f64, std: f64) -> f64 {
+    if std == 0.0 && value == mean {
+        0.0
+    } else {
+        (value - mean) / std
+    }
+}
+
+/// Scores `values` (one per bin) against the baseline `means` and `stds`
+/// (aligned, deviations non-negative) and flags bins with `|z| >=
+/// threshold`.
+#[wasm_bindgen(js_name = binZScores)]
+pub fn bin_z_scores(
+    values: &[f64],
+    means: &[f64],
+    stds: &[f64],
+    threshold: f64,
+) -> Result<BinScores, KernelError> {
+    if means.len() != values.len() || stds.len() != values.len() {
+        return Err(
+            KernelError::invalid_argument("baseline lengths differ from values")
+                .with("values", values.len() as f64)
+                .with("means", means.len() as f64)
+                .with("stds", stds.len() as f64),
+        );
+    }
+    if let Some(bin) = stds.iter().position(|&std| std < 0.0) {
+        return Err(
+            KernelError::invalid_argument("standard deviation is negative")
+                .with("bin", bin as f64)
+                .with("std", stds[bin]),
+        );
+    }
+    if threshold.is_nan() || threshold < 0.0 {
+        return Err(
+            KernelError::invalid_argument("threshold must be non-negative")
+                .with("threshold", threshold),
+        );
+    }
+    let scores: Vec<f64> = values
+        .iter()
+        .zip(means.iter().zip(stds))
+        .map(|(&value, (&mean, &std))| z_score(value, mean, std))
+        .collect();
+    let anomalies = (0..scores.len() as u32)
+        .filter(|&bin| scores[bin as usize].abs() >= threshold)
+        .collect();
+    Ok(BinScores { scores, anomalies })
+}
@@ -0,0 +1,1225 @@
+//! Arrow IPC stream ingestion.
+//!
+//! Parses the encapsulated IPC stream format (schema message followed by
+//! record batches, optionally terminated by an end-of-stream marker) and
+//! registers each supported top-level field as a column in the wasm-resident
+//! store, so Arrow data from the server never becomes JS objects. Batches are
+//! concatenated per field. Fields whose type is not supported yet are skipped
+//! (their handle is `0`) and logged, rather than failing the whole stream;
+//! only the view types, whose buffer count varies per batch, fail it.
+//! Dictionary-encoded string fields keep their encoding: the column stores
+//! the index buffer and the dictionary, with delta batches appended to it.
+//! Zstd-compressed bodies are decompressed when the `zstd` feature is on.
+//!
+//! Large or streamed results (DuckDB-WASM query batches) go through an
+//! ingest session instead: chunks are decoded as they arrive and only the
+//! unfinished tail of a split message is buffered.
+
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, Bitmap, Column, Values};
+use crate::error::{ErrorKind, KernelError};
+use crate::flatbuf::{read_u32, Table};
+use crate::memory;
+use crate::recovery;
+use crate::temporal::{TimeUnit, DAY_MS};
+
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+
+// `MessageHeader` union tags (Message.fbs).
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_DICTIONARY_BATCH: u8 = 2;
+const HEADER_RECORD_BATCH: u8 = 3;
+
+// `CompressionType` (Message.fbs).
+const CODEC_LZ4_FRAME: u8 = 0;
+const CODEC_ZSTD: u8 = 1;
+
+// `Type` union tags (Schema.fbs).
+const TYPE_NULL: u8 = 1;
+const TYPE_INT: u8 = 2;
+const TYPE_FLOATING_POINT: u8 = 3;
+const TYPE_BINARY: u8 = 4;
+const TYPE_UTF8: u8 = 5;
+const TYPE_BOOL: u8 = 6;
+const TYPE_DECIMAL: u8 = 7;
+const TYPE_DATE: u8 = 8;
+const TYPE_TIME: u8 = 9;
+const TYPE_TIMESTAMP: u8 = 10;
+const TYPE_INTERVAL: u8 = 11;
+const TYPE_LIST: u8 = 12;
+const TYPE_STRUCT: u8 = 13;
+const TYPE_UNION: u8 = 14;
+const TYPE_FIXED_SIZE_BINARY: u8 = 15;
+const TYPE_FIXED_SIZE_LIST: u8 = 16;
+const TYPE_MAP: u8 = 17;
+const TYPE_DURATION: u8 = 18;
+const TYPE_LARGE_BINARY: u8 = 19;
+const TYPE_LARGE_UTF8: u8 = 20;
+const TYPE_LARGE_LIST: u8 = 21;
+const TYPE_RUN_END_ENCODED: u8 = 22;
+const TYPE_LIST_VIEW: u8 = 25;
+const TYPE_LARGE_LIST_VIEW: u8 = 26;
+
+// `UnionMode` (Schema.fbs).
+const UNION_DENSE: i16 = 1;
+
+fn malformed(what: &str) -> KernelError {
+    KernelError::new(
+        ErrorKind::MalformedInput,
+        format!("malformed arrow stream: {what}"),
+    )
+}
+
+fn unsupported(what: String) -> KernelError {
+    KernelError::new(ErrorKind::Unsupported, what)
+}
+
+/// Physical interpretation of a field we know how to ingest.
+#[derive(Clone, Copy, PartialEq)]
+enum FieldKind {
+    Int {
+        bits: i32,
+        signed: bool,
+    },
+    Float16,
+    Float32,
+    Float64,
+    Bool,
+    Utf8 {
+        large: bool,
+    },
+    Timestamp(TimeUnit),
+    Decimal128 {
+        scale: i32,
+    },
+    /// `Date32` (days) or `Date64` (milliseconds).
+    Date {
+        days: bool,
+    },
+    /// Dictionary-encoded `Utf8`/`LargeUtf8`, indexed by an integer buffer.
+    Dictionary {
+        id: i64,
+        index_bits: i32,
+        index_signed: bool,
+        large: bool,
+    },
+    Skipped,
+}
+
+#[derive(PartialEq)]
+struct FieldSpec {
+    name: String,
+    kind: FieldKind,
+    timezone: Option<String>,
+    /// Field nodes and buffers consumed by this field including children.
+    nodes: usize,
+    buffers: usize,
+}
+
+/// Buffers a field of the given type contributes, excluding children.
+fn own_buffer_count(type_id: u8, type_table: Option<Table<'_>>) -> Result<usize, KernelError> {
+    Ok(match type_id {
+        TYPE_NULL | TYPE_RUN_END_ENCODED => 0,
+        TYPE_STRUCT | TYPE_FIXED_SIZE_LIST => 1,
+        // Type ids, plus offsets when dense; unions have no validity buffer.
+        TYPE_UNION => match type_table.map_or(Ok(0), |union| union.i16_or(0, 0))? {
+            UNION_DENSE => 2,
+            _ => 1,
+        },
+        TYPE_INT
+        | TYPE_FLOATING_POINT
+        | TYPE_BOOL
+        | TYPE_DECIMAL
+        | TYPE_DATE
+        | TYPE_TIME
+        | TYPE_TIMESTAMP
+        | TYPE_INTERVAL
+        | TYPE_FIXED_SIZE_BINARY
+        | TYPE_DURATION
+        | TYPE_LIST
+        | TYPE_MAP
+        | TYPE_LARGE_LIST => 2,
+        TYPE_BINARY | TYPE_UTF8 | TYPE_LARGE_BINARY | TYPE_LARGE_UTF8 | TYPE_LIST_VIEW
+        | TYPE_LARGE_LIST_VIEW => 3,
+        other => return Err(unsupported(format!("unsupported arrow type id {other}"))),
+    })
+}
+
+/// Nesting bound for schema fields, so hostile input cannot blow the stack.
+const MAX_DEPTH: usize = 64;
+
+fn parse_field(field: Table<'_>, depth: usize) -> Result<FieldSpec, KernelError> {
+    if depth > MAX_DEPTH {
+        return Err(malformed("schema nesting too deep"));
+    }
+    let name = field.string(0)?.unwrap_or_default().to_owned();
+    let type_id = field.u8_or(2, 0)?;
+    let type_table = field.table(3)?;
+    let dictionary = field.table(4)?;
+
+    let mut nodes = 1;
+    let mut buffers = if dictionary.is_some() {
+        2
+    } else {
+        own_buffer_count(type_id, type_table)?
+    };
+    for child in field.tables(5)? {
+        let child = parse_field(child, depth + 1)?;
+        nodes += child.nodes;
+        buffers += child.buffers;
+    }
+
+    let kind = match (type_id, type_table, dictionary) {
+        (TYPE_UTF8 | TYPE_LARGE_UTF8, _, Some(encoding)) => {
+            let index_type = encoding.table(1)?;
+            FieldKind::Dictionary {
+                id: encoding.i64_or(0, 0)?,
+                index_bits: index_type.map_or(Ok(32), |int| int.i32_or(0, 32))?,
+                index_signed: index_type.map_or(Ok(true), |int| int.bool_or(1, false))?,
+                large: type_id == TYPE_LARGE_UTF8,
+            }
+        }
+        (_, _, Some(_)) => FieldKind::Skipped,
+        (TYPE_INT, Some(int), None) => FieldKind::Int {
+            bits: int.i32_or(0, 0)?,
+            signed: int.bool_or(1, false)?,
+        },
+        (TYPE_FLOATING_POINT, Some(float), None) => match float.i16_or(0, 0)? {
+            0 => FieldKind::Float16,
+            1 => FieldKind::Float32,
+            2 => FieldKind::Float64,
+            _ => FieldKind::Skipped,
+        },
+        (TYPE_BOOL, _, None) => FieldKind::Bool,
+        (TYPE_UTF8, _, None) => FieldKind::Utf8 { large: false },
+        (TYPE_LARGE_UTF8, _, None) => FieldKind::Utf8 { large: true },
+        (TYPE_TIMESTAMP, Some(timestamp), None) => {
+            FieldKind::Timestamp(TimeUnit::from_arrow(timestamp.i16_or(0, 0)?)?)
+        }
+        (TYPE_DECIMAL, Some(decimal), None) if decimal.i32_or(2, 128)? == 128 => {
+            FieldKind::Decimal128 {
+                scale: decimal.i32_or(1, 0)?,
+            }
+        }
+        (TYPE_DATE, Some(date), None) => FieldKind::Date {
+            days: date.i16_or(0, 1)? == 0,
+        },
+        _ => FieldKind::Skipped,
+    };
+    let timezone = match (type_id, type_table) {
+        (TYPE_TIMESTAMP, Some(timestamp)) => timestamp.string(1)?.map(str::to_owned),
+        _ => None,
+    };
+    Ok(FieldSpec {
+        name,
+        kind,
+        timezone,
+        nodes,
+        buffers,
+    })
+}
+
+/// Accumulates one field's batches until the stream is finished.
+struct ColumnBuilder {
+    name: String,
+    len: usize,
+    values: Values,
+    bools: Bitmap,
+    validity: Option<Bitmap>,
+}
+
+impl ColumnBuilder {
+    fn new(spec: &FieldSpec) -> Option<Self> {
+        let values = match spec.kind {
+            FieldKind::Int {
+                bits: 8,
+                signed: true,
+            } => Values::Int8(Vec::new()),
+            FieldKind::Int {
+                bits: 16,
+                signed: true,
+            } => Values::Int16(Vec::new()),
+            FieldKind::Int {
+                bits: 32,
+                signed: true,
+            } => Values::Int32(Vec::new()),
+            FieldKind::Int {
+                bits: 64,
+                signed: true,
+            } => Values::Int64(Vec::new()),
+            FieldKind::Int {
+                bits: 8,
+                signed: false,
+            } => Values::UInt8(Vec::new()),
+            FieldKind::Int {
+                bits: 16,
+                signed: false,
+            } => Values::UInt16(Vec::new()),
+            FieldKind::Int {
+                bits: 32,
+                signed: false,
+            } => Values::UInt32(Vec::new()),
+            FieldKind::Int {
+                bits: 64,
+                signed: false,
+            } => Values::UInt64(Vec::new()),
+            FieldKind::Float16 => Values::Float16(Vec::new()),
+            FieldKind::Float32 => Values::Float32(Vec::new()),
+            FieldKind::Float64 => Values::Float64(Vec::new()),
+            FieldKind::Bool => Values::Bool(Vec::new()),
+            FieldKind::Utf8 { .. } => Values::Utf8 {
+                offsets: vec![0],
+                data: Vec::new(),
+            },
+            FieldKind::Timestamp(_) | FieldKind::Date { .. } => Values::Timestamp {
+                millis: Vec::new(),
+                timezone: spec.timezone.clone(),
+            },
+            FieldKind::Decimal128 { scale } => Values::Decimal128 {
+                values: Vec::new(),
+                scale,
+            },
+            FieldKind::Dictionary { .. } => Values::Dictionary {
+                indices: Vec::new(),
+                values: Box::new(Values::Utf8 {
+                    offsets: vec![0],
+                    data: Vec::new(),
+                }),
+            },
+            FieldKind::Int { .. } | FieldKind::Skipped => return None,
+        };
+        Some(ColumnBuilder {
+            name: spec.name.clone(),
+            len: 0,
+            values,
+            bools: Bitmap::default(),
+            validity: None,
+        })
+    }
+
+    fn append_validity(&mut self, bitmap: Option<&[u8]>, rows: usize) {
+        match (bitmap, self.validity.as_mut()) {
+            (Some(bits), Some(validity)) => validity.extend_from(bits, rows),
+            (Some(bits), None) => {
+                let mut validity = Bitmap::default();
+                validity.extend_constant(true, self.len);
+                validity.extend_from(bits, rows);
+                self.validity = Some(validity);
+            }
+            (None, Some(validity)) => validity.extend_constant(true, rows),
+            (None, None) => {}
+        }
+    }
+
+    /// Appends one batch given the field's own buffers (validity first).
+    fn append(
+        &mut self,
+        kind: FieldKind,
+        rows: usize,
+        null_count: usize,
+        buffers: &[&[u8]],
+    ) -> Result<(), KernelError> {
+        let bitmap_bytes = rows.div_ceil(8);
+        let validity = match buffers.first() {
+            Some(bits) if null_count > 0 && bits.len() >= bitmap_bytes => Some(*bits),
+            Some(_) if null_count > 0 => return Err(malformed("validity buffer too short")),
+            _ => None,
+        };
+        let values = buffers.get(1).copied().unwrap_or_default();
+        match (&mut self.values, kind) {
+            (Values::Int8(out), _) => extend_le(out, values, rows, i8::from_le_bytes)?,
+            (Values::Int16(out), _) => extend_le(out, values, rows, i16::from_le_bytes)?,
+            (Values::Int32(out), _) => extend_le(out, values, rows, i32::from_le_bytes)?,
+            (Values::Int64(out), _) => extend_le(out, values, rows, i64::from_le_bytes)?,
+            (Values::UInt8(out), _) => extend_le(out, values, rows, u8::from_le_bytes)?,
+            (Values::UInt16(out), _) => extend_le(out, values, rows, u16::from_le_bytes)?,
+            (Values::UInt32(out), _) => extend_le(out, values, rows, u32::from_le_bytes)?,
+            (Values::UInt64(out), _) => extend_le(out, values, rows, u64::from_le_bytes)?,
+            (Values::Float16(out), _) => extend_le(out, values, rows, u16::from_le_bytes)?,
+            (Values::Float32(out), _) => extend_le(out, values, rows, f32::from_le_bytes)?,
+            (Values::Float64(out), _) => extend_le(out, values, rows, f64::from_le_bytes)?,
+            (Values::Bool(_), _) => {
+                if values.len() < bitmap_bytes {
+                    return Err(malformed("bool buffer too short"));
+                }
+                self.bools.extend_from(values, rows);
+            }
+            (Values::Utf8 { offsets, data }, FieldKind::Utf8 { large }) => {
+                let bytes = buffers.get(2).copied().unwrap_or_default();
+                append_utf8(offsets, data, values, bytes, rows, large)?;
+            }
+            (Values::Timestamp { millis, .. }, FieldKind::Timestamp(unit)) => {
+                extend_le(millis, values, rows, |raw| {
+                    unit.to_epoch_ms(i64::from_le_bytes(raw))
+                })?
+            }
+            (Values::Timestamp { millis, .. }, FieldKind::Date { days: true }) => {
+                extend_le(millis, values, rows, |raw| {
+                    f64::from(i32::from_le_bytes(raw)) * DAY_MS
+                })?
+            }
+            (Values::Timestamp { millis, .. }, _) => {
+                extend_le(millis, values, rows, |raw| i64::from_le_bytes(raw) as f64)?
+            }
+            (Values::Decimal128 { values: out, .. }, _) => {
+                extend_le(out, values, rows, i128::from_le_bytes)?
+            }
+            (
+                Values::Dictionary { indices, .. },
+                FieldKind::Dictionary {
+                    index_bits,
+                    index_signed,
+                    ..
+                },
+            ) => extend_indices(indices, values, rows, index_bits, index_signed)?,
+            (Values::Utf8 { .. } | Values::Dictionary { .. }, _) => {
+                return Err(malformed("builder does not match field type"))
+            }
+        }
+        self.append_validity(validity, rows);
+        self.len += rows;
+        Ok(())
+    }
+
+    fn finish(self) -> Column {
+        let values = match self.values {
+            Values::Bool(_) => Values::Bool(self.bools.bytes),
+            values => values,
+        };
+        Column {
+            name: self.name,
+            len: self.len,
+            values,
+            validity: self.validity.map(|bitmap| bitmap.bytes),
+        }
+    }
+}
+
+/// Decodes `rows` little-endian values of `N` bytes each.
+fn extend_le<T, const N: usize>(
+    out: &mut Vec<T>,
+    bytes: &[u8],
+    rows: usize,
+    decode: impl Fn([u8; N]) -> T,
+) -> Result<(), KernelError> {
+    let bytes = rows
+        .checked_mul(N)
+        .and_then(|len| bytes.get(..len))
+        .ok_or_else(|| malformed("value buffer too short"))?;
+    out.extend(
+        bytes
+            .chunks_exact(N)
+            .map(|chunk| decode(chunk.try_into().expect("chunk of N bytes"))),
+    );
+    Ok(())
+}
+
+fn append_utf8(
+    offsets: &mut Vec<u32>,
+    data: &mut Vec<u8>,
+    offset_bytes: &[u8],
+    bytes: &[u8],
+    rows: usize,
+    large: bool,
+) -> Result<(), KernelError> {
+    // `extend_le` checks the buffer holds all of them before allocating.
+    let count = rows
+        .checked_add(1)
+        .ok_or_else(|| malformed("value buffer too short"))?;
+    let mut batch_offsets = Vec::new();
+    if large {
+        extend_le(&mut batch_offsets, offset_bytes, count, i64::from_le_bytes)?;
+    } else {
+        let mut narrow = Vec::new();
+        extend_le(&mut narrow, offset_bytes, count, i32::from_le_bytes)?;
+        batch_offsets.extend(narrow.into_iter().map(i64::from));
+    }
+    let first = batch_offsets[0];
+    let last = batch_offsets[rows];
+    if first < 0 || last < first || last as usize > bytes.len() {
+        return Err(malformed("utf8 offsets out of bounds"));
+    }
+    let base = data.len() as i64;
+    if base + (last - first) > i64::from(u32::MAX) {
+        return Err(unsupported("utf8 column exceeds 4 GiB".to_owned()));
+    }
+    let mut previous = first;
+    for &offset in &batch_offsets[1..] {
+        if offset < previous || offset > last {
+            return Err(malformed("utf8 offsets not monotonic"));
+        }
+        previous = offset;
+    }
+    data.extend_from_slice(&bytes[first as usize..last as usize]);
+    offsets.extend(
+        batch_offsets[1..]
+            .iter()
+            .map(|&offset| (base + offset - first) as u32),
+    );
+    Ok(())
+}
+
+/// Decodes dictionary indices of any integer width to `u32`. Negative or
+/// oversized indices become `u32::MAX` and are rejected when the column is
+/// finished, unless the row is null.
+fn extend_indices(
+    out: &mut Vec<u32>,
+    bytes: &[u8],
+    rows: usize,
+    bits: i32,
+    signed: bool,
+) -> Result<(), KernelError> {
+    let mut wide: Vec<i64> = Vec::new();
+    match (bits, signed) {
+        (8, true) => {
+            let mut narrow = Vec::new();
+            extend_le(&mut narrow, bytes, rows, i8::from_le_bytes)?;
+            wide.extend(narrow.into_iter().map(i64::from));
+        }
+        (8, false) => wide.extend(bytes.iter().take(rows).map(|&byte| i64::from(byte))),
+        (16, true) => {
+            let mut narrow = Vec::new();
+            extend_le(&mut narrow, bytes, rows, i16::from_le_bytes)?;
+            wide.extend(narrow.into_iter().map(i64::from));
+        }
+        (16, false) => {
+            let mut narrow = Vec::new();
+            extend_le(&mut narrow, bytes, rows, u16::from_le_bytes)?;
+            wide.extend(narrow.into_iter().map(i64::from));
+        }
+        (32, true) => {
+            let mut narrow = Vec::new();
+            extend_le(&mut narrow, bytes, rows, i32::from_le_bytes)?;
+            wide.extend(narrow.into_iter().map(i64::from));
+        }
+        (32, false) => {
+            let mut narrow = Vec::new();
+            extend_le(&mut narrow, bytes, rows, u32::from_le_bytes)?;
+            wide.extend(narrow.into_iter().map(i64::from));
+        }
+        (64, _) => extend_le(&mut wide, bytes, rows, i64::from_le_bytes)?,
+        _ => return Err(malformed("invalid dictionary index width")),
+    }
+    if wide.len() < rows {
+        return Err(malformed("value buffer too short"));
+    }
+    out.extend(
+        wide.into_iter()
+            .map(|index| u32::try_from(index).unwrap_or(u32::MAX)),
+    );
+    Ok(())
+}
+
+/// Field nodes and buffers of one record batch body.
+/// Decompresses one Arrow body buffer, appending to the output.
+type Inflate = fn(&[u8], &mut Vec<u8>) -> Result<(), KernelError>;
+
+fn inflater(codec: u8) -> Result<Inflate, KernelError> {
+    match codec {
+        #[cfg(feature = "zstd")]
+        CODEC_ZSTD => Ok(crate::compression::zstd_decompress),
+        #[cfg(not(feature = "zstd"))]
+        CODEC_ZSTD => Err(unsupported(
+            "zstd-compressed arrow bodies need the `zstd` feature".to_owned(),
+        )),
+        CODEC_LZ4_FRAME => Err(unsupported(
+            "lz4-compressed arrow bodies are not supported".to_owned(),
+        )),
+        other => Err(malformed(&format!("unknown compression codec {other}"))),
+    }
+}
+
+/// The `len` bytes of a message body at `offset`.
+fn slice(body: &[u8], offset: usize, len: usize) -> Result<&[u8], KernelError> {
+    body.get(offset..)
+        .and_then(|rest| rest.get(..len))
+        .ok_or_else(|| malformed("buffer outside message body"))
+}
+
+struct BatchLayout<'a> {
+    nodes: &'a [u8],
+    buffers: Cow<'a, [u8]>,
+    body: Cow<'a, [u8]>,
+}
+
+impl<'a> BatchLayout<'a> {
+    fn new(batch: Table<'a>, body: &'a [u8]) -> Result<Self, KernelError> {
+        let mut layout = BatchLayout {
+            nodes: batch.structs(1, 16)?,
+            buffers: Cow::Borrowed(batch.structs(2, 16)?),
+            body: Cow::Borrowed(body),
+        };
+        if let Some(compression) = batch.table(3)? {
+            layout.decompress(compression.u8_or(0, 0)?)?;
+        }
+        Ok(layout)
+    }
+
+    /// Replaces a compressed body (`BodyCompression` with the `BUFFER`
+    /// method) by its decompressed buffers, rewriting the buffer table to
+    /// match. Each compressed buffer starts with its uncompressed length as
+    /// an `i64`, or `-1` when the producer left it uncompressed.
+    fn decompress(&mut self, codec: u8) -> Result<(), KernelError> {
+        let inflate = inflater(codec)?;
+        let count = self.buffers.len() / 16;
+        let mut body = Vec::new();
+        let mut buffers = Vec::with_capacity(count * 16);
+        for index in 0..count {
+            let (offset, len) = Self::pair(&self.buffers, index)?;
+            let compressed = slice(&self.body, offset, len)?;
+            let start = body.len();
+            if let Some((prefix, payload)) = compressed.split_first_chunk::<8>() {
+                match i64::from_le_bytes(*prefix) {
+                    -1 => body.extend_from_slice(payload),
+                    expected => {
+                        inflate(payload, &mut body)?;
+                        if body.len() - start != expected as usize {
+                            return Err(malformed("decompressed buffer length mismatch"));
+                        }
+                    }
+                }
+            } else if !compressed.is_empty() {
+                return Err(malformed("compressed buffer without length prefix"));
+            }
+            buffers.extend_from_slice(&(start as i64).to_le_bytes());
+            buffers.extend_from_slice(&((body.len() - start) as i64).to_le_bytes());
+            body.resize(body.len().next_multiple_of(8), 0);
+        }
+        self.buffers = Cow::Owned(buffers);
+        self.body = Cow::Owned(body);
+        Ok(())
+    }
+
+    fn pair(bytes: &[u8], index: usize) -> Result<(usize, usize), KernelError> {
+        let entry = bytes
+            .get(index * 16..index * 16 + 16)
+            .ok_or_else(|| malformed("field node or buffer index out of range"))?;
+        let first = i64::from_le_bytes(entry[..8].try_into().expect("8 bytes"));
+        let second = i64::from_le_bytes(entry[8..].try_into().expect("8 bytes"));
+        match (usize::try_from(first), usize::try_from(second)) {
+            (Ok(first), Ok(second)) => Ok((first, second)),
+            _ => Err(malformed("negative length or offset")),
+        }
+    }
+
+    /// `(length, null_count)` of field node `index`.
+    fn node(&self, index: usize) -> Result<(usize, usize), KernelError> {
+        Self::pair(self.nodes, index)
+    }
+
+    /// Body slices of `count` buffers starting at `first`.
+    fn buffers(&self, first: usize, count: usize) -> Result<Vec<&[u8]>, KernelError> {
+        (first..first + count)
+            .map(|index| {
+                let (offset, len) = Self::pair(&self.buffers, index)?;
+                slice(&self.body, offset, len)
+            })
+            .collect()
+    }
+}
+
+/// Incremental decoder over a sequence of IPC messages.
+#[derive(Default)]
+pub(crate) struct StreamDecoder {
+    fields: Vec<FieldSpec>,
+    builders: Vec<Option<ColumnBuilder>>,
+    /// Dictionaries of top-level dictionary fields by id, always `Utf8`.
+    dictionaries: HashMap<i64, Values>,
+    has_schema: bool,
+    read_batches: bool,
+    rows: usize,
+    /// Trailing bytes of a message split across pushed chunks.
+    pending: Vec<u8>,
+}
+
+fn peek_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    read_u32(bytes, pos).ok()
+}
+
+impl StreamDecoder {
+    /// Feeds the next chunk of the stream. Chunks may split messages
+    /// anywhere, and may be whole streams of their own: end-of-stream
+    /// markers are skipped and a repeated identical schema is accepted.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Result<(), KernelError> {
+        // Decoded columns take about as much memory as their messages.
+        memory::check_budget(chunk.len())?;
+        if self.pending.is_empty() {
+            let consumed = self.decode_messages(chunk)?;
+            self.pending.extend_from_slice(&chunk[consumed..]);
+        } else {
+            let mut pending = std::mem::take(&mut self.pending);
+            pending.extend_from_slice(chunk);
+            let consumed = self.decode_messages(&pending)?;
+            pending.drain(..consumed);
+            self.pending = pending;
+        }
+        Ok(())
+    }
+
+    /// Consumes every complete message in `bytes` and returns how many bytes
+    /// that was; a trailing partial message is left for the next chunk.
+    fn decode_messages(&mut self, bytes: &[u8]) -> Result<usize, KernelError> {
+        let mut pos = 0;
+        loop {
+            let start = pos;
+            let Some(mut meta_len) = peek_u32(bytes, pos) else {
+                return Ok(start);
+            };
+            pos += 4;
+            if meta_len == CONTINUATION {
+                let Some(len) = peek_u32(bytes, pos) else {
+                    return Ok(start);
+                };
+                meta_len = len;
+                pos += 4;
+            }
+            if meta_len == 0 {
+                // End-of-stream marker; another stream may follow.
+                continue;
+            }
+            let meta_end = pos
+                .checked_add(meta_len as usize)
+                .ok_or_else(|| malformed("message length out of range"))?;
+            let Some(metadata) = bytes.get(pos..meta_end) else {
+                return Ok(start);
+            };
+            let message = Table::root(metadata)?;
+            let body_len = usize::try_from(message.i64_or(3, 0)?)
+                .map_err(|_| malformed("negative body length"))?;
+            let end = meta_end
+                .checked_add(body_len)
+                .ok_or_else(|| malformed("body length out of range"))?;
+            let Some(body) = bytes.get(meta_end..end) else {
+                return Ok(start);
+            };
+            pos = end;
+
+            let header = message
+                .table(2)?
+                .ok_or_else(|| malformed("missing header"))?;
+            match message.u8_or(1, 0)? {
+                HEADER_SCHEMA => self.read_schema(header)?,
+                HEADER_RECORD_BATCH => self.read_record_batch(header, body)?,
+                HEADER_DICTIONARY_BATCH => self.read_dictionary_batch(header, body)?,
+                other => {
+                    return Err(unsupported(format!(
+                        "unsupported arrow message type {other}"
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Rows decoded so far.
+    pub(crate) fn rows(&self) -> usize {
+        self.rows
+    }
+
+    fn read_schema(&mut self, schema: Table<'_>) -> Result<(), KernelError> {
+        if schema.i16_or(0, 0)? != 0 {
+            return Err(unsupported(
+                "big-endian arrow streams are not supported".to_owned(),
+            ));
+        }
+        let fields: Vec<FieldSpec> = schema
+            .tables(1)?
+            .into_iter()
+            .map(|field| parse_field(field, 0))
+            .collect::<Result<_, _>>()?;
+        if self.has_schema {
+            // Chunked producers resend the schema with every chunk.
+            if fields != self.fields {
+                return Err(malformed("schema changed mid-stream"));
+            }
+            return Ok(());
+        }
+        self.fields = fields;
+        self.builders = self.fields.iter().map(ColumnBuilder::new).collect();
+        for spec in &self.fields {
+            if let FieldKind::Dictionary { id, .. } = spec.kind {
+                self.dictionaries.insert(id, empty_utf8());
+            }
+        }
+        for (spec, builder) in self.fields.iter().zip(&self.builders) {
+            if builder.is_none() {
+                kernel_log!(
+                    Warn,
+                    "arrow",
+                    "skipping unsupported field",
+                    name = spec.name.as_str()
+                );
+            }
+        }
+        self.has_schema = true;
+        Ok(())
+    }
+
+    fn read_record_batch(&mut self, batch: Table<'_>, body: &[u8]) -> Result<(), KernelError> {
+        if !self.has_schema {
+            return Err(malformed("record batch before schema"));
+        }
+        let layout = BatchLayout::new(batch, body)?;
+        let rows =
+            usize::try_from(batch.i64_or(0, 0)?).map_err(|_| malformed("negative batch length"))?;
+        let mut node_index = 0;
+        let mut buffer_index = 0;
+        for (spec, builder) in self.fields.iter().zip(self.builders.iter_mut()) {
+            if let Some(builder) = builder {
+                let (rows, null_count) = layout.node(node_index)?;
+                let slices = layout.buffers(buffer_index, own_buffer_count_for(spec.kind))?;
+                builder.append(spec.kind, rows, null_count, &slices)?;
+            }
+            node_index += spec.nodes;
+            buffer_index += spec.buffers;
+        }
+        self.read_batches = true;
+        self.rows += rows;
+        Ok(())
+    }
+
+    fn read_dictionary_batch(&mut self, header: Table<'_>, body: &[u8]) -> Result<(), KernelError> {
+        let id = header.i64_or(0, 0)?;
+        let is_delta = header.bool_or(2, false)?;
+        let large = self.fields.iter().find_map(|spec| match spec.kind {
+            FieldKind::Dictionary {
+                id: field_id,
+                large,
+                ..
+            } if field_id == id => Some(large),
+            _ => None,
+        });
+        let (Some(large), Some(dictionary)) = (large, self.dictionaries.get_mut(&id)) else {
+            // Dictionary of a nested or skipped field.
+            return Ok(());
+        };
+        let batch = header
+            .table(1)?
+            .ok_or_else(|| malformed("dictionary batch without data"))?;
+        let layout = BatchLayout::new(batch, body)?;
+        let (rows, _) = layout.node(0)?;
+        let slices = layout.buffers(0, 3)?;
+        if is_delta {
+            let Values::Utf8 { offsets, data } = dictionary else {
+                unreachable!("dictionaries are always utf8");
+            };
+            return append_utf8(offsets, data, slices[1], slices[2], rows, large);
+        }
+        let mut replacement = empty_utf8();
+        if let Values::Utf8 { offsets, data } = &mut replacement {
+            append_utf8(offsets, data, slices[1], slices[2], rows, large)?;
+        }
+        // Indices already read stay valid only if the old dictionary is a
+        // prefix of the new one, which is what chunked producers send.
+        let existing = dictionary.label_count();
+        let extends = existing <= replacement.label_count()
+            && (0..existing).all(|index| dictionary.label(index) == replacement.label(index));
+        if self.read_batches && !extends {
+            return Err(unsupported(
+                "arrow dictionary replacement is not supported".to_owned(),
+            ));
+        }
+        *dictionary = replacement;
+        Ok(())
+    }
+
+    /// Registers the accumulated columns, returning one handle per schema
+    /// field (`0` for skipped fields).
+    pub(crate) fn finish(self) -> Result<Vec<u32>, KernelError> {
+        if !self.has_schema {
+            return Err(malformed("stream has no schema"));
+        }
+        if !self.pending.is_empty() {
+            return Err(malformed("stream ends inside a message"));
+        }
+        let dictionaries = self.dictionaries;
+        let finished = self
+            .fields
+            .iter()
+            .zip(self.builders)
+            .map(|(spec, builder)| {
+                let Some(builder) = builder else {
+                    return Ok(None);
+                };
+                let mut column = builder.finish();
+                if let FieldKind::Dictionary { id, .. } = spec.kind {
+                    attach_dictionary(&mut column, &dictionaries[&id])?;
+                }
+                Ok(Some(column))
+            })
+            .collect::<Result<Vec<_>, KernelError>>()?;
+        Ok(finished
+            .into_iter()
+            .map(|column| column.map_or(0, columns::register))
+            .collect())
+    }
+}
+
+fn empty_utf8() -> Values {
+    Values::Utf8 {
+        offsets: vec![0],
+        data: Vec::new(),
+    }
+}
+
+/// Installs the final dictionary and checks every non-null index against it.
+fn attach_dictionary(column: &mut Column, dictionary: &Values) -> Result<(), KernelError> {
+    let labels = dictionary.label_count();
+    let Values::Dictionary { indices, .. } = &column.values else {
+        return Ok(());
+    };
+    let out_of_range = indices
+        .iter()
+        .enumerate()
+        .any(|(row, &index)| column.is_valid(row) && index as usize >= labels);
+    if out_of_range {
+        return Err(malformed("dictionary index out of range"));
+    }
+    if let Values::Dictionary { values, .. } = &mut column.values {
+        **values = dictionary.clone();
+    }
+    Ok(())
+}
+
+fn own_buffer_count_for(kind: FieldKind) -> usize {
+    match kind {
+        FieldKind::Utf8 { .. } => 3,
+        _ => 2,
+    }
+}
+
+/// Decodes an Arrow IPC stream and registers its columns. Returns one column
+/// handle per top-level schema field, in schema order; `0` marks a field whose
+/// type is not supported and was skipped.
+#[wasm_bindgen(js_name = ingestArrowStream)]
+pub fn ingest_arrow_stream(bytes: &[u8]) -> Result<Vec<u32>, KernelError> {
+    let mut decoder = StreamDecoder::default();
+    decoder.push(bytes)?;
+    decoder.finish()
+}
+
+thread_local! {
+    static SESSIONS: RefCell<HashMap<u32, StreamDecoder>> = RefCell::new(HashMap::new());
+    static NEXT_SESSION: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Drops every ingest session; see `hardReset`.
+pub(crate) fn reset() {
+    SESSIONS.with(|sessions| recovery::reset_cell(sessions, HashMap::new()));
+    NEXT_SESSION.with(|next| next.set(0));
+}
+
+fn unknown_session(session: u32) -> KernelError {
+    KernelError::invalid_argument("unknown arrow ingest session")
+        .with("session", f64::from(session))
+}
+
+/// Starts a chunked ingest, e.g. of DuckDB-WASM result batches as they
+/// arrive. Returns a session id for `pushArrowChunk`.
+#[wasm_bindgen(js_name = beginArrowIngest)]
+pub fn begin_arrow_ingest() -> u32 {
+    let session = NEXT_SESSION.with(|next| {
+        let session = next.get().wrapping_add(1).max(1);
+        next.set(session);
+        session
+    });
+    SESSIONS.with(|sessions| {
+        sessions
+            .borrow_mut()
+            .insert(session, StreamDecoder::default())
+    });
+    session
+}
+
+/// Decodes the next chunk of IPC bytes. A chunk may be any slice of the
+/// stream, including a view over shared memory (it is copied in), or a
+/// complete stream per batch as produced by `tableToIPC` on each chunk of a
+/// DuckDB result. Returns the number of rows decoded so far. A failed chunk
+/// ends the session.
+#[wasm_bindgen(js_name = pushArrowChunk)]
+pub fn push_arrow_chunk(session: u32, chunk: &[u8]) -> Result<u32, KernelError> {
+    SESSIONS.with(|sessions| {
+        let mut sessions = sessions.borrow_mut();
+        let decoder = sessions
+            .get_mut(&session)
+            .ok_or_else(|| unknown_session(session))?;
+        match decoder.push(chunk) {
+            Ok(()) => Ok(decoder.rows() as u32),
+            Err(err) => {
+                sessions.remove(&session);
+                Err(err)
+            }
+        }
+    })
+}
+
+/// Ends the session and registers its columns; returns handles as
+/// `ingestArrowStream` does.
+#[wasm_bindgen(js_name = finishArrowIngest)]
+pub fn finish_arrow_ingest(session: u32) -> Result<Vec<u32>, KernelError> {
+    let decoder = SESSIONS
+        .with(|sessions| sessions.borrow_mut().remove(&session))
+        .ok_or_else(|| unknown_session(session))?;
+    decoder.finish()
+}
+
+/// Drops a session without registering anything. Unknown ids are ignored.
+#[wasm_bindgen(js_name = abortArrowIngest)]
+pub fn abort_arrow_ingest(session: u32) {
+    SESSIONS.with(|sessions| {
+        sessions.borrow_mut().remove(&session);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrow_export::{encode_stream, ExportColumn};
+    use crate::flatbuf::{TableBuilder, Value};
+
+    fn message(header_type: u8, header: TableBuilder, body: &[u8]) -> Vec<u8> {
+        let metadata = TableBuilder::new()
+            .add(0, Value::I16(4))
+            .add(1, Value::U8(header_type))
+            .add(2, Value::Table(header))
+            .add(3, Value::I64(body.len() as i64))
+            .finish();
+        let mut out = CONTINUATION.to_le_bytes().to_vec();
+        out.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        out.extend_from_slice(&metadata);
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn field(name: &str, type_id: u8, type_table: TableBuilder) -> TableBuilder {
+        TableBuilder::new()
+            .add(0, Value::String(name.to_owned()))
+            .add(2, Value::U8(type_id))
+            .add(3, Value::Table(type_table))
+    }
+
+    fn int32_field(name: &str) -> TableBuilder {
+        let int = TableBuilder::new()
+            .add(0, Value::I32(32))
+            .add(1, Value::Bool(true));
+        field(name, TYPE_INT, int)
+    }
+
+    fn schema(fields: Vec<TableBuilder>) -> Vec<u8> {
+        message(
+            HEADER_SCHEMA,
+            TableBuilder::new().add(1, Value::Tables(fields)),
+            &[],
+        )
+    }
+
+    fn pairs(pairs: &[(i64, i64)]) -> Value {
+        Value::Structs {
+            count: pairs.len(),
+            bytes: pairs
+                .iter()
+                .flat_map(|&(first, second)| [first.to_le_bytes(), second.to_le_bytes()])
+                .flatten()
+                .collect(),
+        }
+    }
+
+    fn batch(rows: i64, nodes: &[(i64, i64)], buffers: &[(i64, i64)], body: &[u8]) -> Vec<u8> {
+        let header = TableBuilder::new()
+            .add(0, Value::I64(rows))
+            .add(1, pairs(nodes))
+            .add(2, pairs(buffers));
+        message(HEADER_RECORD_BATCH, header, body)
+    }
+
+    fn error_code(stream: &[u8]) -> u32 {
+        ingest_arrow_stream(stream)
+            .expect_err("stream should fail")
+            .code()
+    }
+
+    /// One utf8 field holding `offsets` into `data`, as `rows` rows.
+    fn utf8_stream(rows: i64, offsets: &[i32], data: &[u8]) -> Vec<u8> {
+        let mut stream = schema(vec![field("s", TYPE_UTF8, TableBuilder::new())]);
+        let mut body: Vec<u8> = offsets
+            .iter()
+            .flat_map(|offset| offset.to_le_bytes())
+            .collect();
+        let offsets_len = body.len() as i64;
+        body.extend_from_slice(data);
+        let buffers = [(0, 0), (0, offsets_len), (offsets_len, data.len() as i64)];
+        stream.extend(batch(rows, &[(rows, 0)], &buffers, &body));
+        stream
+    }
+
+    #[test]
+    fn exported_streams_round_trip() {
+        let stream = encode_stream(&[
+            ("x".to_owned(), ExportColumn::Float64(vec![1.5, -2.0])),
+            ("n".to_owned(), ExportColumn::UInt32(vec![3, 4])),
+            (
+                "s".to_owned(),
+                ExportColumn::Utf8(vec!["a".to_owned(), "bc".to_owned()]),
+            ),
+        ]);
+        let handles = ingest_arrow_stream(&stream).unwrap();
+        assert_eq!(handles.len(), 3);
+        columns::with_column(handles[0], |column| {
+            assert!(matches!(&column.values, Values::Float64(values) if values == &[1.5, -2.0]));
+            Ok(())
+        })
+        .unwrap();
+        columns::with_column(handles[1], |column| {
+            assert!(matches!(&column.values, Values::UInt32(values) if values == &[3, 4]));
+            Ok(())
+        })
+        .unwrap();
+        columns::with_column(handles[2], |column| {
+            assert_eq!(column.values.label(0), Some(&b"a"[..]));
+            assert_eq!(column.values.label(1), Some(&b"bc"[..]));
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn truncated_streams_fail_without_panicking() {
+        let stream = encode_stream(&[(
+            "s".to_owned(),
+            ExportColumn::Utf8(vec!["abc".to_owned(), "de".to_owned()]),
+        )]);
+        for len in 0..stream.len() {
+            // Cuts between messages decode what came before them.
+            let _ = ingest_arrow_stream(&stream[..len]);
+        }
+        assert!(ingest_arrow_stream(&stream[..stream.len() - 1]).is_err());
+        assert!(ingest_arrow_stream(&[]).is_err());
+    }
+
+    #[test]
+    fn non_monotonic_utf8_offsets_are_malformed() {
+        let stream = utf8_stream(3, &[0, 3, 1, 4], b"abcd");
+        assert_eq!(error_code(&stream), ErrorKind::MalformedInput as u32);
+        let stream = utf8_stream(3, &[0, 1, 3, 4], b"abcd");
+        assert!(ingest_arrow_stream(&stream).is_ok());
+    }
+
+    #[test]
+    fn oversized_lengths_are_malformed() {
+        // Row counts whose buffers would overflow.
+        let stream = utf8_stream(i64::MAX, &[0, 1], b"a");
+        assert_eq!(error_code(&stream), ErrorKind::MalformedInput as u32);
+        let mut stream = schema(vec![int32_field("a")]);
+        stream.extend(batch(
+            i64::MAX,
+            &[(i64::MAX, 0)],
+            &[(0, 0), (0, 8)],
+            &[0; 8],
+        ));
+        assert_eq!(error_code(&stream), ErrorKind::MalformedInput as u32);
+        // A buffer whose offset plus length overflows.
+        let mut stream = schema(vec![int32_field("a")]);
+        let buffers = [(0, 0), (i64::MAX, i64::MAX)];
+        stream.extend(batch(2, &[(2, 0)], &buffers, &[0; 8]));
+        assert_eq!(error_code(&stream), ErrorKind::MalformedInput as u32);
+        // Metadata or a body longer than the stream.
+        let mut stream = schema(vec![int32_field("a")]);
+        stream.extend_from_slice(&CONTINUATION.to_le_bytes());
+        stream.extend_from_slice(&(u32::MAX - 1).to_le_bytes());
+        assert_eq!(error_code(&stream), ErrorKind::MalformedInput as u32);
+        let mut stream = schema(vec![int32_field("a")]);
+        let mut message = batch(2, &[(2, 0)], &[(0, 0), (0, 8)], &[0; 8]);
+        message.truncate(message.len() - 1);
+        stream.extend(message);
+        assert_eq!(error_code(&stream), ErrorKind::MalformedInput as u32);
+    }
+
+    #[test]
+    fn union_and_list_view_fields_are_skipped() {
+        let dense = TableBuilder::new().add(0, Value::I16(UNION_DENSE));
+        let mut stream = schema(vec![
+            int32_field("a"),
+            field("u", TYPE_UNION, dense),
+            field("l", TYPE_LIST_VIEW, TableBuilder::new()),
+            field("s", TYPE_UTF8, TableBuilder::new()),
+        ]);
+        // a: values; u: type ids and offsets; l: validity, offsets and
+        // sizes; s: validity, offsets and data.
+        let mut body = vec![0; 40];
+        body[..8].copy_from_slice(&[7, 0, 0, 0, 9, 0, 0, 0]);
+        body.extend(
+            [0, 2, 4]
+                .iter()
+                .flat_map(|offset: &i32| offset.to_le_bytes()),
+        );
+        body.extend_from_slice(b"hiyo");
+        let buffers = [
+            (0, 0),
+            (0, 8),
+            (8, 2),
+            (16, 8),
+            (0, 0),
+            (24, 8),
+            (32, 8),
+            (0, 0),
+            (40, 12),
+            (52, 4),
+        ];
+        let nodes = [(2, 0), (2, 0), (2, 0), (2, 0)];
+        stream.extend(batch(2, &nodes, &buffers, &body));
+        let handles = ingest_arrow_stream(&stream).unwrap();
+        assert_eq!(handles[1..3], [0, 0]);
+        columns::with_column(handles[0], |column| {
+            assert!(matches!(&column.values, Values::Int32(values) if values == &[7, 9]));
+            Ok(())
+        })
+        .unwrap();
+        columns::with_column(handles[3], |column| {
+            assert_eq!(column.values.label(0), Some(&b"hi"[..]));
+            assert_eq!(column.values.label(1), Some(&b"yo"[..]));
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn view_fields_are_unsupported() {
+        let stream = schema(vec![field("v", 24, TableBuilder::new())]);
+        assert_eq!(error_code(&stream), ErrorKind::Unsupported as u32);
+    }
+
+    /// A struct field nesting `depth` more struct fields.
+    fn nested_struct(depth: usize) -> TableBuilder {
+        let mut field = int32_field("leaf");
+        for _ in 0..depth {
+            field = TableBuilder::new()
+                .add(0, Value::String("s".to_owned()))
+                .add(2, Value::U8(TYPE_STRUCT))
+                .add(3, Value::Table(TableBuilder::new()))
+                .add(5, Value::Tables(vec![field]));
+        }
+        field
+    }
+
+    #[test]
+    fn schema_nesting_is_bounded() {
+        let mut stream = schema(vec![nested_struct(8), int32_field("x")]);
+        stream.extend_from_slice(&CONTINUATION.to_le_bytes());
+        stream.extend_from_slice(&0u32.to_le_bytes());
+        assert!(ingest_arrow_stream(&stream).is_ok());
+        let stream = schema(vec![nested_struct(MAX_DEPTH + 1)]);
+        assert_eq!(error_code(&stream), ErrorKind::MalformedInput as u32);
+    }
+
+    #[test]
+    fn offsets_past_the_buffer_are_malformed() {
+        let mut buf = vec![0u8; 8];
+        buf[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Table::root(&buf[4..]).is_err());
+        buf[..4].copy_from_slice(&8u32.to_le_bytes());
+        assert!(Table::root(&buf).is_err());
+    }
+}
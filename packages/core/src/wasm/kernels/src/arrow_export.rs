@@ -0,0 +1,223 @@
+//! Group results as an Arrow IPC stream.
+//!
+//! Serializes a group snapshot (keys, counts and any per-bin aggregates) as a
+//! single-batch IPC stream: schema, one record batch, end-of-stream marker.
+//! The bytes can be handed to arrow-js `tableFromIPC`, DuckDB-WASM's
+//! `insertArrowFromIPCStream`, or uploaded as-is.
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::flatbuf::{TableBuilder, Value};
+
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+/// `MetadataVersion.V5`.
+const METADATA_VERSION: i16 = 4;
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_RECORD_BATCH: u8 = 3;
+const TYPE_INT: u8 = 2;
+const TYPE_FLOATING_POINT: u8 = 3;
+const TYPE_UTF8: u8 = 5;
+/// `Precision.DOUBLE`.
+const DOUBLE: i16 = 2;
+
+/// One result column; serializes as a plain array for the MessagePack
+/// encoder.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub(crate) enum ExportColumn {
+    Float64(Vec<f64>),
+    UInt32(Vec<u32>),
+    Utf8(Vec<String>),
+}
+
+impl ExportColumn {
+    fn len(&self) -> usize {
+        match self {
+            ExportColumn::Float64(values) => values.len(),
+            ExportColumn::UInt32(values) => values.len(),
+            ExportColumn::Utf8(values) => values.len(),
+        }
+    }
+
+    fn field(&self, name: &str) -> TableBuilder {
+        let (type_id, type_table) = match self {
+            ExportColumn::Float64(_) => (
+                TYPE_FLOATING_POINT,
+                TableBuilder::new().add(0, Value::I16(DOUBLE)),
+            ),
+            ExportColumn::UInt32(_) => (
+                TYPE_INT,
+                TableBuilder::new()
+                    .add(0, Value::I32(32))
+                    .add(1, Value::Bool(false)),
+            ),
+            ExportColumn::Utf8(_) => (TYPE_UTF8, TableBuilder::new()),
+        };
+        TableBuilder::new()
+            .add(0, Value::String(name.to_owned()))
+            .add(1, Value::Bool(false))
+            .add(2, Value::U8(type_id))
+            .add(3, Value::Table(type_table))
+            .add(5, Value::Tables(Vec::new()))
+    }
+
+    /// Data buffers after the (empty) validity buffer.
+    fn buffers(&self) -> Vec<Vec<u8>> {
+        match self {
+            ExportColumn::Float64(values) => {
+                vec![values
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect()]
+            }
+            ExportColumn::UInt32(values) => {
+                vec![values
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect()]
+            }
+            ExportColumn::Utf8(values) => {
+                let mut offsets = Vec::with_capacity((values.len() + 1) * 4);
+                let mut data = Vec::new();
+                offsets.extend_from_slice(&0i32.to_le_bytes());
+                for value in values {
+                    data.extend_from_slice(value.as_bytes());
+                    offsets.extend_from_slice(&(data.len() as i32).to_le_bytes());
+                }
+                vec![offsets, data]
+            }
+        }
+    }
+}
+
+/// Frames one encapsulated message: continuation marker, padded metadata
+/// length, metadata, body.
+fn write_message(out: &mut Vec<u8>, header_type: u8, header: TableBuilder, body: &[u8]) {
+    let metadata = TableBuilder::new()
+        .add(0, Value::I16(METADATA_VERSION))
+        .add(1, Value::U8(header_type))
+        .add(2, Value::Table(header))
+        .add(3, Value::I64(body.len() as i64))
+        .finish();
+    out.extend_from_slice(&CONTINUATION.to_le_bytes());
+    out.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    out.extend_from_slice(&metadata);
+    out.extend_from_slice(body);
+}
+
+/// Encodes `columns` as a schema plus one record batch. All columns must
+/// have the same length.
+pub(crate) fn encode_stream(columns: &[(String, ExportColumn)]) -> Vec<u8> {
+    let rows = columns.first().map_or(0, |(_, column)| column.len());
+    let mut out = Vec::new();
+
+    let fields = columns
+        .iter()
+        .map(|(name, column)| column.field(name))
+        .collect();
+    write_message(
+        &mut out,
+        HEADER_SCHEMA,
+        TableBuilder::new().add(1, Value::Tables(fields)),
+        &[],
+    );
+
+    let mut body = Vec::new();
+    let mut nodes = Vec::new();
+    let mut buffers = Vec::new();
+    let mut push_buffer = |body: &mut Vec<u8>, bytes: &[u8]| {
+        buffers.extend_from_slice(&(body.len() as i64).to_le_bytes());
+        buffers.extend_from_slice(&(bytes.len() as i64).to_le_bytes());
+        body.extend_from_slice(bytes);
+        body.resize(body.len().next_multiple_of(8), 0);
+    };
+    for (_, column) in columns {
+        nodes.extend_from_slice(&(rows as i64).to_le_bytes());
+        nodes.extend_from_slice(&0i64.to_le_bytes());
+        push_buffer(&mut body, &[]);
+        for buffer in column.buffers() {
+            push_buffer(&mut body, &buffer);
+        }
+    }
+    let buffer_count = buffers.len() / 16;
+    let batch = TableBuilder::new()
+        .add(0, Value::I64(rows as i64))
+        .add(
+            1,
+            Value::Structs {
+                count: columns.len(),
+                bytes: nodes,
+            },
+        )
+        .add(
+            2,
+            Value::Structs {
+                count: buffer_count,
+                bytes: buffers,
+            },
+        );
+    write_message(&mut out, HEADER_RECORD_BATCH, batch, &body);
+
+    out.extend_from_slice(&CONTINUATION.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out
+}
+
+/// Assembles a group snapshot into named columns: `key` (`labels` when
+/// given, otherwise the numeric `keys`), `count`, then one column per entry
+/// of `aggregate_names`, read back to back from `aggregates`.
+pub(crate) fn group_columns(
+    keys: &[f64],
+    counts: &[u32],
+    labels: Option<Vec<String>>,
+    aggregate_names: Vec<String>,
+    aggregates: &[f64],
+) -> Result<Vec<(String, ExportColumn)>, KernelError> {
+    let rows = counts.len();
+    let key = match labels {
+        Some(labels) => ExportColumn::Utf8(labels),
+        None => ExportColumn::Float64(keys.to_vec()),
+    };
+    if key.len() != rows {
+        return Err(
+            KernelError::invalid_argument("keys and counts differ in length")
+                .with("keys", key.len() as f64)
+                .with("counts", rows as f64),
+        );
+    }
+    if aggregates.len() != aggregate_names.len() * rows {
+        return Err(
+            KernelError::invalid_argument("aggregates do not match names times rows")
+                .with("aggregates", aggregates.len() as f64)
+                .with("expected", (aggregate_names.len() * rows) as f64),
+        );
+    }
+
+    let mut columns = vec![
+        ("key".to_owned(), key),
+        ("count".to_owned(), ExportColumn::UInt32(counts.to_vec())),
+    ];
+    for (index, name) in aggregate_names.into_iter().enumerate() {
+        let values = aggregates[index * rows..(index + 1) * rows].to_vec();
+        columns.push((name, ExportColumn::Float64(values)));
+    }
+    Ok(columns)
+}
+
+/// Serializes a group snapshot as an Arrow IPC stream with columns `key`
+/// (`labels` as utf8 when given, otherwise the numeric `keys`), `count`
+/// (uint32) and one float64 column per entry of `aggregateNames`.
+/// `aggregates` holds those columns back to back, each `counts.length` long.
+#[wasm_bindgen(js_name = encodeGroupsArrow)]
+pub fn encode_groups_arrow(
+    keys: &[f64],
+    counts: &[u32],
+    labels: Option<Vec<String>>,
+    aggregate_names: Vec<String>,
+    aggregates: &[f64],
+) -> Result<Vec<u8>, KernelError> {
+    let columns = group_columns(keys, counts, labels, aggregate_names, aggregates)?;
+    Ok(encode_stream(&columns))
+}
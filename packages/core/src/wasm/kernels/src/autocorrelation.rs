@@ -0,0 +1,47 @@
+//! Autocorrelation of a filtered series.
+//!
+//! Seasonality panels plot how a series correlates with itself shifted by
+//! `k` steps. The series is a numeric column's rows in row order (the store
+//! keeps time-ordered data that way) restricted to a row mask, so the
+//! currently filtered view is analysed without copying it out to JS.
+//!
+//! Uses the standard biased estimator (as R's `acf` and statsmodels): lag
+//! `k` sums the `n - k` products of mean-centred values and divides by the
+//! sum of squares, so every lag shares one denominator. Direct summation
+//! costs `O(n * maxLag)`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::select::masked_values;
+
+/// Autocorrelation at lags `0..=maxLag` of the numeric column behind
+/// `handle`, over the rows set in `mask` (an LSB-first row bitmap in the
+/// layout's `activeMask` format; all rows when omitted). Nulls and NaNs are
+/// dropped from the series. Lags the series is too short for, and every lag
+/// of a constant series, are NaN.
+#[wasm_bindgen]
+pub fn autocorrelation(
+    handle: u32,
+    max_lag: u32,
+    mask: Option<Vec<u8>>,
+) -> Result<Vec<f64>, KernelError> {
+    let series = masked_values(handle, mask.as_deref(), "autocorrelation")?;
+    let len = series.len();
+    let mean = series.iter().sum::<f64>() / len as f64;
+    let centred: Vec<f64> = series.iter().map(|&value| value - mean).collect();
+    let squares: f64 = centred.iter().map(|value| value * value).sum();
+    Ok((0..=max_lag as usize)
+        .map(|lag| {
+            if lag >= len || squares == 0.0 {
+                return f64::NAN;
+            }
+            let products: f64 = centred
+                .iter()
+                .zip(&centred[lag..])
+                .map(|(a, b)| a * b)
+                .sum();
+            products / squares
+        })
+        .collect())
+}
@@ -0,0 +1,16 @@
+//! 64-bit integers across the JS boundary.
+//!
+//! A `number` is exact only up to 2^53, so 64-bit counts and sums are also
+//! offered as `BigUint64Array`s, and for engines without BigInt typed arrays
+//! as two `Uint32Array`s of high and low words:
+//! `value = high * 2 ** 32 + low`.
+
+/// High 32 bits of each value.
+pub(crate) fn high_words(values: &[u64]) -> Vec<u32> {
+    values.iter().map(|&value| (value >> 32) as u32).collect()
+}
+
+/// Low 32 bits of each value.
+pub(crate) fn low_words(values: &[u64]) -> Vec<u32> {
+    values.iter().map(|&value| value as u32).collect()
+}
@@ -0,0 +1,194 @@
+//! Bit-packed boolean columns.
+//!
+//! Flag columns arrive as one byte per row (`Uint8Array`, nonzero for true)
+//! while masks and predicates take LSB-first row bitmaps in the layout's
+//! `activeMask` format, eight rows per byte. `packBools` and `unpackBools`
+//! convert between the two, sixteen rows per step where `simd128` is
+//! available and parity is off, so a packed flag column can go straight to
+//! `setFilterMask` or any other `mask` argument. `boolColumnMask` does the
+//! same for a boolean column in the store.
+
+use wasm_bindgen::prelude::*;
+
+#[cfg(target_feature = "simd128")]
+use std::arch::wasm32::{
+    i8x16_bitmask, i8x16_ne, u16x8_splat, u8x16, u8x16_min, u8x16_splat, u8x16_swizzle, v128,
+    v128_and, v128_load, v128_store,
+};
+
+use crate::columns::{self, Values};
+use crate::error::{ErrorKind, KernelError};
+
+/// Sets bit `row` of `bits` for every nonzero `values[row]`; `bits` holds
+/// at least `values.len().div_ceil(8)` zeroed bytes.
+fn pack(values: &[u8], bits: &mut [u8]) {
+    #[cfg(target_feature = "simd128")]
+    let done = if crate::parity::simd_allowed() {
+        const LANES: usize = 16;
+        let whole = values.len() - values.len() % LANES;
+        // SAFETY: every load stays within the first `whole` values.
+        unsafe {
+            for index in (0..whole).step_by(LANES) {
+                let lanes = v128_load(values.as_ptr().add(index) as *const v128);
+                let set = i8x16_bitmask(i8x16_ne(lanes, u8x16_splat(0)));
+                bits[index / 8..index / 8 + 2].copy_from_slice(&set.to_le_bytes());
+            }
+        }
+        whole
+    } else {
+        0
+    };
+    #[cfg(not(target_feature = "simd128"))]
+    let done = 0;
+    for (row, &value) in values.iter().enumerate().skip(done) {
+        if value != 0 {
+            bits[row >> 3] |= 1 << (row & 7);
+        }
+    }
+}
+
+/// Writes bit `row` of `bits` to `values[row]` as 0 or 1.
+fn unpack(bits: &[u8], values: &mut [u8]) {
+    #[cfg(target_feature = "simd128")]
+    let done = if crate::parity::simd_allowed() {
+        const LANES: usize = 16;
+        let whole = values.len() - values.len() % LANES;
+        // Lanes 0-7 take the first byte of the pair, lanes 8-15 the second,
+        // and each lane keeps its own bit of it.
+        let spread = u8x16(0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1);
+        let lane_bits = u8x16(1, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128);
+        // SAFETY: every store stays within the first `whole` values.
+        unsafe {
+            for index in (0..whole).step_by(LANES) {
+                let pair = u16::from_le_bytes([bits[index / 8], bits[index / 8 + 1]]);
+                let lanes = u8x16_swizzle(u16x8_splat(pair), spread);
+                let flags = u8x16_min(v128_and(lanes, lane_bits), u8x16_splat(1));
+                v128_store(values.as_mut_ptr().add(index) as *mut v128, flags);
+            }
+        }
+        whole
+    } else {
+        0
+    };
+    #[cfg(not(target_feature = "simd128"))]
+    let done = 0;
+    for (row, value) in values.iter_mut().enumerate().skip(done) {
+        *value = (bits[row >> 3] >> (row & 7)) & 1;
+    }
+}
+
+/// Packs a byte-per-row boolean column (nonzero is true) into an LSB-first
+/// row bitmap.
+#[wasm_bindgen(js_name = packBools)]
+pub fn pack_bools(values: &[u8]) -> Vec<u8> {
+    let mut bits = vec![0; values.len().div_ceil(8)];
+    pack(values, &mut bits);
+    bits
+}
+
+/// Unpacks the first `len` rows of an LSB-first row bitmap into one byte
+/// per row, 0 or 1.
+#[wasm_bindgen(js_name = unpackBools)]
+pub fn unpack_bools(bits: &[u8], len: u32) -> Result<Vec<u8>, KernelError> {
+    let len = len as usize;
+    if bits.len() < len.div_ceil(8) {
+        return Err(KernelError::invalid_argument("bitmap is too short")
+            .with("needed", len.div_ceil(8) as f64)
+            .with("available", bits.len() as f64));
+    }
+    let mut values = vec![0; len];
+    unpack(bits, &mut values);
+    Ok(values)
+}
+
+/// The boolean column behind `handle` as a row mask: bits set for rows
+/// that are true, nulls cleared.
+#[wasm_bindgen(js_name = boolColumnMask)]
+pub fn bool_column_mask(handle: u32) -> Result<Vec<u8>, KernelError> {
+    columns::with_column(handle, |column| {
+        let Values::Bool(bits) = &column.values else {
+            return Err(KernelError::new(
+                ErrorKind::Unsupported,
+                "boolColumnMask requires a bool column",
+            ));
+        };
+        let bytes = column.len.div_ceil(8);
+        let mut mask: Vec<u8> = bits.iter().copied().take(bytes).collect();
+        mask.resize(bytes, 0);
+        if let Some(validity) = &column.validity {
+            for (byte, &valid) in mask.iter_mut().zip(validity) {
+                *byte &= valid;
+            }
+        }
+        if !column.len.is_multiple_of(8) {
+            if let Some(last) = mask.last_mut() {
+                *last &= (1 << (column.len % 8)) - 1;
+            }
+        }
+        Ok(mask)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::Column;
+    use crate::parity::set_simd_parity;
+
+    /// Flags covering every lane pattern across whole vectors and tails.
+    fn flags(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|row| match row % 5 {
+                0 => 0,
+                1 => 1,
+                2 => 0x80,
+                3 => (row * 31 % 3) as u8,
+                _ => 0xff,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vector_and_scalar_paths_agree() {
+        for len in 0..=67 {
+            let values = flags(len);
+            let mut expected = vec![0u8; len.div_ceil(8)];
+            for (row, &value) in values.iter().enumerate() {
+                expected[row / 8] |= u8::from(value != 0) << (row % 8);
+            }
+            let unpacked: Vec<u8> = values.iter().map(|&value| u8::from(value != 0)).collect();
+            for parity in [false, true] {
+                set_simd_parity(parity);
+                assert_eq!(pack_bools(&values), expected, "len {len} parity {parity}");
+                assert_eq!(unpack_bools(&expected, len as u32).unwrap(), unpacked);
+            }
+        }
+        set_simd_parity(false);
+    }
+
+    #[test]
+    fn unpacking_ignores_bits_past_the_length() {
+        assert_eq!(unpack_bools(&[0xff, 0xff], 10).unwrap(), [1; 10]);
+        assert!(unpack_bools(&[0xff], 9).is_err());
+        assert!(unpack_bools(&[], 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn bool_masks_clear_nulls_and_trailing_bits() {
+        let handle = columns::register(Column {
+            name: "b".to_owned(),
+            len: 10,
+            values: Values::Bool(vec![0xff, 0xff]),
+            validity: Some(vec![0xf0, 0x01]),
+        });
+        assert_eq!(bool_column_mask(handle).unwrap(), [0xf0, 0x01]);
+        let floats = columns::register(Column {
+            name: "f".to_owned(),
+            len: 1,
+            values: Values::Float64(vec![1.0]),
+            validity: None,
+        });
+        let error = bool_column_mask(floats).unwrap_err();
+        assert_eq!(error.code(), ErrorKind::Unsupported as u32);
+    }
+}
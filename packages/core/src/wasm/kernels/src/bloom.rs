@@ -0,0 +1,151 @@
+//! Bloom filters over key columns.
+//!
+//! Pre-filters one table's rows by the keys present in another before an
+//! expensive join in JS or on the server: build a filter from the key
+//! column of one table, ship or keep its bytes, and probe the other table's
+//! key column for a row bitmap. Probing never drops a row whose key was
+//! added; it lets through a row whose key was not with about the false
+//! positive rate the filter was sized for.
+//!
+//! Filters serialize as the probe count (one byte) and the bit count (`u32`
+//! little-endian) followed by the bits, LSB-first. Keys hash as in
+//! `distinctRows`, so both columns must hold the same key type.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns;
+use crate::error::KernelError;
+use crate::hash;
+
+const HEADER: usize = 5;
+const MAX_PROBES: f64 = 16.0;
+
+/// Checks a serialized filter and returns its probe and bit counts.
+fn check_filter(filter: &[u8]) -> Result<(usize, u64), KernelError> {
+    if filter.len() < HEADER {
+        return Err(KernelError::invalid_argument("bloom filter is too short")
+            .with("length", filter.len() as f64));
+    }
+    let probes = usize::from(filter[0]);
+    let bits = u32::from_le_bytes([filter[1], filter[2], filter[3], filter[4]]);
+    if probes == 0 || bits == 0 || filter.len() != HEADER + (bits as usize).div_ceil(8) {
+        return Err(
+            KernelError::invalid_argument("bloom filter header does not match its length")
+                .with("probes", probes as f64)
+                .with("bits", f64::from(bits))
+                .with("length", filter.len() as f64),
+        );
+    }
+    Ok((probes, u64::from(bits)))
+}
+
+/// Serialized Bloom filter of the keys of the column behind `handle` at
+/// the rows set in `mask` (an LSB-first row bitmap in the layout's
+/// `activeMask` format; every row when omitted), sized for
+/// `falsePositiveRate` assuming every row holds a distinct key.
+#[wasm_bindgen(js_name = buildBloomFilter)]
+pub fn build_bloom_filter(
+    handle: u32,
+    false_positive_rate: f64,
+    mask: Option<Vec<u8>>,
+) -> Result<Vec<u8>, KernelError> {
+    if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+        return Err(
+            KernelError::invalid_argument("false positive rate must be in (0, 1)")
+                .with("rate", false_positive_rate),
+        );
+    }
+    let hashes = hash::row_hashes(handle, mask.as_deref())?;
+    let keys = hashes.len().max(1) as f64;
+    let ln2 = std::f64::consts::LN_2;
+    let bits = (-keys * false_positive_rate.ln() / (ln2 * ln2))
+        .ceil()
+        .clamp(64.0, f64::from(u32::MAX));
+    let probes = (bits / keys * ln2).round().clamp(1.0, MAX_PROBES) as usize;
+    let bits = bits as u32;
+    let mut filter = vec![0u8; HEADER + (bits as usize).div_ceil(8)];
+    filter[0] = probes as u8;
+    filter[1..HEADER].copy_from_slice(&bits.to_le_bytes());
+    let set = &mut filter[HEADER..];
+    for (_, hash) in hashes {
+        for probe in hash::probes(hash, probes) {
+            let at = (probe % u64::from(bits)) as usize;
+            set[at >> 3] |= 1 << (at & 7);
+        }
+    }
+    Ok(filter)
+}
+
+/// Probes `filter` with the keys of the column behind `handle`: an LSB-first
+/// row bitmap, in the layout's `activeMask` format, with the bit set for
+/// every row whose key may have been added.
+#[wasm_bindgen(js_name = probeBloomFilter)]
+pub fn probe_bloom_filter(filter: &[u8], handle: u32) -> Result<Vec<u8>, KernelError> {
+    let (probes, bits) = check_filter(filter)?;
+    let set = &filter[HEADER..];
+    columns::with_column(handle, |column| {
+        let mut mask = vec![0u8; column.len.div_ceil(8)];
+        let mut key = Vec::new();
+        for row in 0..column.len {
+            let hash = hash::key_hash(column, row, &mut key);
+            let present = hash::probes(hash, probes).all(|probe| {
+                let at = (probe % bits) as usize;
+                set[at >> 3] & (1 << (at & 7)) != 0
+            });
+            if present {
+                mask[row >> 3] |= 1 << (row & 7);
+            }
+        }
+        Ok(mask)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::ints;
+
+    fn keys(start: i32, len: i32) -> u32 {
+        ints((start..start + len).collect())
+    }
+
+    fn set_rows(mask: &[u8]) -> usize {
+        mask.iter().map(|byte| byte.count_ones() as usize).sum()
+    }
+
+    #[test]
+    fn probes_keep_every_added_key_near_the_false_positive_rate() {
+        let filter = build_bloom_filter(keys(0, 10_000), 0.01, None).unwrap();
+        let (probes, bits) = check_filter(&filter).unwrap();
+        assert_eq!((probes, bits), (7, 95_851));
+        // Rows 0..5000 hold added keys; rows 5000..15000 do not.
+        let mask = probe_bloom_filter(&filter, keys(5_000, 15_000)).unwrap();
+        assert!(mask[..625].iter().all(|&byte| byte == 0xff));
+        let false_positives = set_rows(&mask[625..]);
+        assert!(false_positives < 200, "{false_positives} false positives");
+    }
+
+    #[test]
+    fn masked_builds_add_only_the_masked_keys() {
+        let handle = keys(0, 64);
+        // Even rows only.
+        let filter = build_bloom_filter(handle, 0.001, Some(vec![0x55; 8])).unwrap();
+        let mask = probe_bloom_filter(&filter, handle).unwrap();
+        assert_eq!(mask.iter().fold(0xff, |all, &byte| all & byte), 0x55);
+        assert!(set_rows(&mask) < 34);
+    }
+
+    #[test]
+    fn malformed_filters_are_rejected() {
+        let handle = keys(0, 10);
+        let filter = build_bloom_filter(handle, 0.1, None).unwrap();
+        assert!(probe_bloom_filter(&filter[..filter.len() - 1], handle).is_err());
+        assert!(probe_bloom_filter(&filter[..4], handle).is_err());
+        let mut zero_probes = filter.clone();
+        zero_probes[0] = 0;
+        assert!(probe_bloom_filter(&zero_probes, handle).is_err());
+        for rate in [0.0, 1.0, f64::NAN] {
+            assert!(build_bloom_filter(handle, rate, None).is_err());
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! Binning straight from Arrow buffer layouts.
+//!
+//! JS hands over the raw buffers of an arrow-js `Data` (values, validity
+//! bitmap, and offsets for variable-length types) as byte views, and the
+//! kernels write bin indices into the scratch buffer, ready for
+//! `accumulateScratch`. Nothing is converted to JS numbers or strings on the
+//! way. `offset` is the `Data` offset: an element index into the values and
+//! offsets buffers and a bit index into the validity bitmap.
+//!
+//! Dictionary-encoded vectors skip the string round trip entirely: the
+//! dictionary is installed as the dimension's category mapping once, and the
+//! index buffer is then copied into the scratch buffer as bin indices.
+
+use wasm_bindgen::prelude::*;
+
+use crate::categories;
+use crate::columns::{bit, Quantizer};
+use crate::error::{ErrorKind, KernelError};
+use crate::half;
+use crate::temporal::{TimeUnit, DAY_MS};
+
+#[derive(Clone, Copy)]
+enum Primitive {
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Float16,
+    Float32,
+    Float64,
+    Bool,
+    /// Read as epoch milliseconds, so `min`/`max` are epoch ms too.
+    Timestamp(TimeUnit),
+    Date32,
+    Date64,
+}
+
+impl Primitive {
+    fn parse(name: &str) -> Result<Self, KernelError> {
+        Ok(match name {
+            "int8" => Primitive::Int8,
+            "int16" => Primitive::Int16,
+            "int32" => Primitive::Int32,
+            "int64" => Primitive::Int64,
+            "uint8" => Primitive::UInt8,
+            "uint16" => Primitive::UInt16,
+            "uint32" => Primitive::UInt32,
+            "uint64" => Primitive::UInt64,
+            "float16" => Primitive::Float16,
+            "float32" => Primitive::Float32,
+            "float64" => Primitive::Float64,
+            "bool" => Primitive::Bool,
+            "date32" => Primitive::Date32,
+            "date64" => Primitive::Date64,
+            _ => {
+                if let Some(unit) = name
+                    .strip_prefix("timestamp[")
+                    .and_then(|rest| rest.strip_suffix(']'))
+                    .and_then(TimeUnit::parse)
+                {
+                    return Ok(Primitive::Timestamp(unit));
+                }
+                return Err(KernelError::new(
+                    ErrorKind::Unsupported,
+                    format!("unsupported arrow buffer type {name:?}"),
+                ));
+            }
+        })
+    }
+
+    /// Bytes needed to hold `len` elements; bools are bit-packed.
+    fn byte_len(self, len: usize) -> Result<usize, KernelError> {
+        let width = match self {
+            Primitive::Bool => return Ok(len.div_ceil(8)),
+            Primitive::Int8 | Primitive::UInt8 => 1,
+            Primitive::Int16 | Primitive::UInt16 | Primitive::Float16 => 2,
+            Primitive::Int32 | Primitive::UInt32 | Primitive::Float32 | Primitive::Date32 => 4,
+            Primitive::Int64
+            | Primitive::UInt64
+            | Primitive::Float64
+            | Primitive::Timestamp(_)
+            | Primitive::Date64 => 8,
+        };
+        len.checked_mul(width).ok_or_else(|| out_of_range(len))
+    }
+
+    fn read(self, bytes: &[u8], index: usize) -> f64 {
+        fn le<const N: usize>(bytes: &[u8], index: usize) -> [u8; N] {
+            bytes[index * N..index * N + N].try_into().expect("N bytes")
+        }
+        match self {
+            Primitive::Int8 => f64::from(bytes[index] as i8),
+            Primitive::Int16 => f64::from(i16::from_le_bytes(le(bytes, index))),
+            Primitive::Int32 => f64::from(i32::from_le_bytes(le(bytes, index))),
+            Primitive::Int64 => i64::from_le_bytes(le(bytes, index)) as f64,
+            Primitive::UInt8 => f64::from(bytes[index]),
+            Primitive::UInt16 => f64::from(u16::from_le_bytes(le(bytes, index))),
+            Primitive::UInt32 => f64::from(u32::from_le_bytes(le(bytes, index))),
+            Primitive::UInt64 => u64::from_le_bytes(le(bytes, index)) as f64,
+            Primitive::Float16 => f64::from(half::to_f32(u16::from_le_bytes(le(bytes, index)))),
+            Primitive::Float32 => f64::from(f32::from_le_bytes(le(bytes, index))),
+            Primitive::Float64 => f64::from_le_bytes(le(bytes, index)),
+            Primitive::Bool => f64::from(u8::from(bit(bytes, index))),
+            Primitive::Timestamp(unit) => unit.to_epoch_ms(i64::from_le_bytes(le(bytes, index))),
+            Primitive::Date32 => f64::from(i32::from_le_bytes(le(bytes, index))) * DAY_MS,
+            Primitive::Date64 => i64::from_le_bytes(le(bytes, index)) as f64,
+        }
+    }
+}
+
+fn short_buffer(which: &'static str, needed: usize, available: usize) -> KernelError {
+    KernelError::new(
+        ErrorKind::InvalidArgument,
+        format!("arrow {which} buffer is too short"),
+    )
+    .with("needed", needed as f64)
+    .with("available", available as f64)
+}
+
+fn check_len(which: &'static str, bytes: &[u8], needed: usize) -> Result<(), KernelError> {
+    if bytes.len() < needed {
+        return Err(short_buffer(which, needed, bytes.len()));
+    }
+    Ok(())
+}
+
+/// A slice or buffer size past `usize`, which on wasm32 a bad `offset` or
+/// `len` reaches easily.
+fn out_of_range(rows: usize) -> KernelError {
+    KernelError::invalid_argument("arrow slice is out of range").with("rows", rows as f64)
+}
+
+/// Row after the slice `[offset, offset + len)`.
+fn slice_end(offset: usize, len: usize) -> Result<usize, KernelError> {
+    offset
+        .checked_add(len)
+        .ok_or_else(|| out_of_range(offset).with("len", len as f64))
+}
+
+fn check_validity(validity: Option<&[u8]>, end: usize) -> Result<(), KernelError> {
+    match validity {
+        Some(bits) => check_len("validity", bits, end.div_ceil(8)),
+        None => Ok(()),
+    }
+}
+
+/// Quantizes a fixed-width Arrow buffer (`arrowType` as reported by
+/// `columnType`, e.g. `"float64"`, or a temporal type: `"timestamp[s]"`
+/// through `"timestamp[ns]"`, `"date32"`, `"date64"`) into `binCount` bins over `[min, max]`,
+/// writing bin indices into the scratch buffer. Returns the row count.
+#[wasm_bindgen(js_name = binArrowNumeric)]
+#[allow(clippy::too_many_arguments)]
+pub fn bin_arrow_numeric(
+    arrow_type: &str,
+    values: &[u8],
+    validity: Option<Vec<u8>>,
+    offset: u32,
+    len: u32,
+    min: f64,
+    max: f64,
+    bin_count: u32,
+) -> Result<u32, KernelError> {
+    let primitive = Primitive::parse(arrow_type)?;
+    let quantizer = Quantizer::new(min, max, bin_count)?;
+    let (offset, len) = (offset as usize, len as usize);
+    let validity = validity.as_deref();
+    let end = slice_end(offset, len)?;
+    check_len("values", values, primitive.byte_len(end)?)?;
+    check_validity(validity, end)?;
+    crate::with_scratch(len, |scratch| {
+        for (index, slot) in scratch.iter_mut().enumerate() {
+            let row = offset + index;
+            let valid = validity.is_none_or(|bits| bit(bits, row));
+            *slot = quantizer.bin(primitive.read(values, row), valid);
+        }
+    })?;
+    Ok(len as u32)
+}
+
+/// Maps a `Utf8` (32-bit offsets) or `LargeUtf8` (64-bit offsets) buffer to
+/// category codes from `dimension`'s dictionary, writing them into the
+/// scratch buffer. Returns the row count.
+#[wasm_bindgen(js_name = binArrowUtf8)]
+#[allow(clippy::too_many_arguments)]
+pub fn bin_arrow_utf8(
+    offsets: &[u8],
+    data: &[u8],
+    validity: Option<Vec<u8>>,
+    offset: u32,
+    len: u32,
+    large: bool,
+    dimension: u32,
+    bin_count: u32,
+) -> Result<u32, KernelError> {
+    if bin_count == 0 || bin_count > u32::from(u16::MAX) + 1 {
+        return Err(KernelError::bad_bin_count(bin_count));
+    }
+    let (offset, len) = (offset as usize, len as usize);
+    let validity = validity.as_deref();
+    let width = if large { 8 } else { 4 };
+    let end = slice_end(offset, len)?;
+    let offset_bytes = end
+        .checked_add(1)
+        .and_then(|entries| entries.checked_mul(width))
+        .ok_or_else(|| out_of_range(end))?;
+    check_len("offsets", offsets, offset_bytes)?;
+    check_validity(validity, end)?;
+    let offset_at = offsets_reader(offsets, large);
+    check_len("data", data, offset_at(end))?;
+
+    categories::with_categories(dimension, |categories| {
+        crate::with_scratch(len, |scratch| {
+            for (index, slot) in scratch.iter_mut().enumerate() {
+                let row = offset + index;
+                let label = if validity.is_none_or(|bits| bit(bits, row)) {
+                    let (start, stop) = (offset_at(row), offset_at(row + 1));
+                    data.get(start..stop).ok_or_else(|| {
+                        KernelError::new(ErrorKind::InvalidArgument, "arrow offsets out of order")
+                            .with("row", row as f64)
+                    })?
+                } else {
+                    &[]
+                };
+                *slot = categories.code(label, bin_count);
+            }
+            Ok(len as u32)
+        })?
+    })
+}
+
+fn offsets_reader(offsets: &[u8], large: bool) -> impl Fn(usize) -> usize + '_ {
+    let width = if large { 8 } else { 4 };
+    move |index| {
+        let bytes = &offsets[index * width..index * width + width];
+        if large {
+            i64::from_le_bytes(bytes.try_into().expect("8 bytes")) as usize
+        } else {
+            i32::from_le_bytes(bytes.try_into().expect("4 bytes")) as usize
+        }
+    }
+}
+
+/// Installs an Arrow dictionary (`Utf8` or `LargeUtf8` buffers) as the
+/// category mapping of `dimension`: label `i` becomes bin `i`. Returns the
+/// number of labels.
+#[wasm_bindgen(js_name = setCategoryDictionary)]
+pub fn set_category_dictionary(
+    dimension: u32,
+    offsets: &[u8],
+    data: &[u8],
+    large: bool,
+) -> Result<u32, KernelError> {
+    let width = if large { 8 } else { 4 };
+    let labels = (offsets.len() / width).saturating_sub(1);
+    let offset_at = offsets_reader(offsets, large);
+    let mut slices = Vec::with_capacity(labels);
+    for index in 0..labels {
+        let label = data
+            .get(offset_at(index)..offset_at(index + 1))
+            .ok_or_else(|| {
+                KernelError::new(ErrorKind::InvalidArgument, "arrow offsets out of order")
+                    .with("row", index as f64)
+            })?;
+        slices.push(label);
+    }
+    categories::with_categories(dimension, |categories| categories.seed(slices.into_iter()))?;
+    Ok(labels as u32)
+}
+
+/// Writes a dictionary-encoded vector's index buffer (`indexType` such as
+/// `"int32"`) into the scratch buffer as bins for `dimension`, whose
+/// dictionary must have been installed with `setCategoryDictionary`. Null
+/// rows take the empty label's bin. Returns the row count.
+#[wasm_bindgen(js_name = binArrowDictionary)]
+#[allow(clippy::too_many_arguments)]
+pub fn bin_arrow_dictionary(
+    index_type: &str,
+    indices: &[u8],
+    validity: Option<Vec<u8>>,
+    offset: u32,
+    len: u32,
+    dimension: u32,
+    bin_count: u32,
+) -> Result<u32, KernelError> {
+    let primitive = Primitive::parse(index_type)?;
+    if matches!(
+        primitive,
+        Primitive::Float16
+            | Primitive::Float32
+            | Primitive::Float64
+            | Primitive::Bool
+            | Primitive::Timestamp(_)
+            | Primitive::Date32
+            | Primitive::Date64
+    ) {
+        return Err(KernelError::invalid_argument(
+            "dictionary indices must be integers",
+        ));
+    }
+    if bin_count == 0 || bin_count > u32::from(u16::MAX) + 1 {
+        return Err(KernelError::bad_bin_count(bin_count));
+    }
+    let (offset, len) = (offset as usize, len as usize);
+    let validity = validity.as_deref();
+    let end = slice_end(offset, len)?;
+    check_len("indices", indices, primitive.byte_len(end)?)?;
+    check_validity(validity, end)?;
+
+    categories::with_categories(dimension, |categories| {
+        let labels = categories.len() as f64;
+        crate::with_scratch(len, |scratch| {
+            for (index, slot) in scratch.iter_mut().enumerate() {
+                let row = offset + index;
+                if !validity.is_none_or(|bits| bit(bits, row)) {
+                    *slot = categories.code(&[], bin_count);
+                    continue;
+                }
+                let value = primitive.read(indices, row);
+                if !(0.0..labels).contains(&value) {
+                    return Err(KernelError::new(
+                        ErrorKind::InvalidArgument,
+                        "dictionary index out of range",
+                    )
+                    .with("row", row as f64)
+                    .with("index", value));
+                }
+                *slot = categories.code_for_index(value as u32, bin_count);
+            }
+            Ok(len as u32)
+        })?
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn f64_bytes(values: &[f64]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn numeric_slices_read_from_their_offset() {
+        let values = f64_bytes(&[9.0, 0.0, 0.5, 1.0]);
+        let rows = bin_arrow_numeric("float64", &values, Some(vec![0b1011]), 1, 3, 0.0, 1.0, 4);
+        assert_eq!(rows.unwrap(), 3);
+        let bins = crate::with_scratch(3, |scratch| scratch.to_vec()).unwrap();
+        assert_eq!((bins[0], bins[2]), (0, 3));
+    }
+
+    #[test]
+    fn slices_past_usize_are_rejected() {
+        assert!(slice_end(usize::MAX, 1).is_err());
+        assert!(Primitive::Float64.byte_len(usize::MAX / 4).is_err());
+        let values = f64_bytes(&[1.0]);
+        for (offset, len) in [(u32::MAX, u32::MAX), (1, u32::MAX), (u32::MAX, 0)] {
+            let error =
+                bin_arrow_numeric("float64", &values, None, offset, len, 0.0, 1.0, 4).unwrap_err();
+            assert_eq!(error.code(), ErrorKind::InvalidArgument as u32);
+            let offsets = [0u8; 8];
+            assert!(bin_arrow_utf8(&offsets, &[], None, offset, len, false, 1, 4).is_err());
+            assert!(bin_arrow_dictionary("int32", &[0; 4], None, offset, len, 1, 4).is_err());
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Per-dimension category dictionaries for string columns.
+//!
+//! Mirrors the worker's row ingest: labels get codes in first-seen order
+//! until the dimension's bin count is exhausted, after which every new label
+//! shares the last bin. Nulls are treated as the empty string.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::recovery;
+
+/// Labels a dictionary can hold: one per `u16` code.
+const MAX_LABELS: usize = u16::MAX as usize + 1;
+
+#[derive(Default)]
+pub(crate) struct Categories {
+    codes: HashMap<Vec<u8>, u16>,
+    labels: Vec<String>,
+}
+
+impl Categories {
+    /// Code for `label`, assigning the next free one if it is new.
+    pub(crate) fn code(&mut self, label: &[u8], bin_count: u32) -> u16 {
+        if let Some(&code) = self.codes.get(label) {
+            return code;
+        }
+        let code = if (self.labels.len() as u32) < bin_count {
+            self.labels
+                .push(String::from_utf8_lossy(label).into_owned());
+            (self.labels.len() - 1) as u16
+        } else {
+            (bin_count - 1) as u16
+        };
+        self.codes.insert(label.to_vec(), code);
+        code
+    }
+}
+
+impl Categories {
+    /// Replaces the dictionary with `labels`, coded by position. Used when
+    /// the source already carries a dictionary (Arrow dictionary encoding).
+    /// Fails, keeping the current dictionary, with more labels than codes.
+    pub(crate) fn seed<'a>(
+        &mut self,
+        labels: impl ExactSizeIterator<Item = &'a [u8]>,
+    ) -> Result<(), KernelError> {
+        if labels.len() > MAX_LABELS {
+            return Err(KernelError::invalid_argument(
+                "dictionary has more labels than category codes",
+            )
+            .with("labels", labels.len() as f64)
+            .with("limit", MAX_LABELS as f64));
+        }
+        self.codes.clear();
+        self.labels.clear();
+        for (index, label) in labels.enumerate() {
+            self.codes.entry(label.to_vec()).or_insert(index as u16);
+            self.labels
+                .push(String::from_utf8_lossy(label).into_owned());
+        }
+        Ok(())
+    }
+
+    /// Labels assigned so far.
+    pub(crate) fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Labels indexed by code.
+    pub(crate) fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Bin for a position in a seeded dictionary; positions past the bin
+    /// count share the last bin, like overflowing first-seen labels.
+    pub(crate) fn code_for_index(&self, index: u32, bin_count: u32) -> u16 {
+        index.min(bin_count - 1) as u16
+    }
+}
+
+thread_local! {
+    static CATEGORIES: RefCell<HashMap<u32, Categories>> = RefCell::new(HashMap::new());
+}
+
+/// Drops every dimension's categories; see `hardReset`.
+pub(crate) fn reset() {
+    CATEGORIES.with(|categories| recovery::reset_cell(categories, HashMap::new()));
+}
+
+/// Runs `update` against the dictionary for `dimension`, creating it empty.
+pub(crate) fn with_categories<T>(dimension: u32, update: impl FnOnce(&mut Categories) -> T) -> T {
+    CATEGORIES.with(|cell| update(cell.borrow_mut().entry(dimension).or_default()))
+}
+
+/// Labels assigned so far for `dimension`, indexed by code.
+#[wasm_bindgen(js_name = categoryLabels)]
+pub fn category_labels(dimension: u32) -> Vec<String> {
+    CATEGORIES.with(|cell| {
+        cell.borrow()
+            .get(&dimension)
+            .map(|categories| categories.labels.clone())
+            .unwrap_or_default()
+    })
+}
+
+/// Forgets the dictionary for `dimension`, or every dictionary when omitted.
+#[wasm_bindgen(js_name = resetCategories)]
+pub fn reset_categories(dimension: Option<u32>) {
+    CATEGORIES.with(|cell| {
+        let mut categories = cell.borrow_mut();
+        match dimension {
+            Some(dimension) => {
+                categories.remove(&dimension);
+            }
+            None => categories.clear(),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(count: usize) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|index| index.to_string().into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn seeding_fills_every_code() {
+        let labels = labels(MAX_LABELS);
+        let mut categories = Categories::default();
+        categories.seed(labels.iter().map(Vec::as_slice)).unwrap();
+        assert_eq!(categories.len(), MAX_LABELS);
+        assert_eq!(categories.code(b"65535", u32::from(u16::MAX) + 1), u16::MAX);
+        assert_eq!(categories.code(b"0", 4), 0);
+    }
+
+    #[test]
+    fn seeding_past_the_codes_keeps_the_dictionary() {
+        let mut categories = Categories::default();
+        categories.seed([&b"a"[..], b"b"].into_iter()).unwrap();
+        let labels = labels(MAX_LABELS + 1);
+        assert!(categories.seed(labels.iter().map(Vec::as_slice)).is_err());
+        assert_eq!(categories.labels(), ["a", "b"]);
+        assert_eq!(categories.code(b"b", 4), 1);
+    }
+}
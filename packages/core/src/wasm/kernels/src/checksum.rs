@@ -0,0 +1,348 @@
+//! Checksums for transferred and restored buffers.
+//!
+//! Chunks handed between workers or restored from OPFS snapshots are
+//! checked before use. `crc32` is the IEEE CRC (zlib, PNG, zip) for
+//! checksums recorded by other tools; `xxhash64` is XXH64, several times
+//! faster, for checksums the kernels write themselves. Both run over byte
+//! arrays, the scratch buffer, or a column in the store without copying it
+//! out of wasm.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, Values};
+use crate::error::KernelError;
+use crate::SCRATCH;
+
+/// `CRC_TABLES[k][byte]` is the CRC of `byte` followed by `k` zero bytes,
+/// for slicing-by-8.
+const CRC_TABLES: [[u32; 256]; 8] = crc_tables();
+
+const fn crc_tables() -> [[u32; 256]; 8] {
+    let mut tables = [[0; 256]; 8];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        tables[0][byte] = crc;
+        byte += 1;
+    }
+    let mut slice = 1;
+    while slice < 8 {
+        let mut byte = 0;
+        while byte < 256 {
+            let previous = tables[slice - 1][byte];
+            tables[slice][byte] = (previous >> 8) ^ tables[0][(previous & 0xff) as usize];
+            byte += 1;
+        }
+        slice += 1;
+    }
+    tables
+}
+
+/// Continues the CRC `crc` of earlier bytes over `bytes`.
+fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    let t = &CRC_TABLES;
+    let mut crc = !crc;
+    let mut blocks = bytes.chunks_exact(8);
+    for block in &mut blocks {
+        let one = u32::from_le_bytes([block[0], block[1], block[2], block[3]]) ^ crc;
+        let two = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+        crc = t[7][(one & 0xff) as usize]
+            ^ t[6][((one >> 8) & 0xff) as usize]
+            ^ t[5][((one >> 16) & 0xff) as usize]
+            ^ t[4][(one >> 24) as usize]
+            ^ t[3][(two & 0xff) as usize]
+            ^ t[2][((two >> 8) & 0xff) as usize]
+            ^ t[1][((two >> 16) & 0xff) as usize]
+            ^ t[0][(two >> 24) as usize];
+    }
+    for &byte in blocks.remainder() {
+        crc = t[0][((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+const PRIME_1: u64 = 0x9e37_79b1_85eb_ca87;
+const PRIME_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const PRIME_3: u64 = 0x1656_67b1_9e37_79f9;
+const PRIME_4: u64 = 0x85eb_ca77_c2b2_ae63;
+const PRIME_5: u64 = 0x27d4_eb2f_1656_67c5;
+const STRIPE: usize = 32;
+
+fn round(accumulator: u64, lane: u64) -> u64 {
+    accumulator
+        .wrapping_add(lane.wrapping_mul(PRIME_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME_1)
+}
+
+fn merge_round(hash: u64, accumulator: u64) -> u64 {
+    (hash ^ round(0, accumulator))
+        .wrapping_mul(PRIME_1)
+        .wrapping_add(PRIME_4)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap_or_default())
+}
+
+/// Streaming XXH64, so a column's buffers hash as one input.
+pub(crate) struct Xxh64 {
+    seed: u64,
+    lanes: [u64; 4],
+    pending: [u8; STRIPE],
+    pending_len: usize,
+    total: u64,
+}
+
+impl Xxh64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Xxh64 {
+            seed,
+            lanes: [
+                seed.wrapping_add(PRIME_1).wrapping_add(PRIME_2),
+                seed.wrapping_add(PRIME_2),
+                seed,
+                seed.wrapping_sub(PRIME_1),
+            ],
+            pending: [0; STRIPE],
+            pending_len: 0,
+            total: 0,
+        }
+    }
+
+    fn stripe(&mut self, stripe: &[u8]) {
+        for (lane, bytes) in self.lanes.iter_mut().zip(stripe.chunks_exact(8)) {
+            *lane = round(*lane, read_u64(bytes));
+        }
+    }
+
+    pub(crate) fn update(&mut self, mut bytes: &[u8]) {
+        self.total += bytes.len() as u64;
+        if self.pending_len > 0 {
+            let take = bytes.len().min(STRIPE - self.pending_len);
+            self.pending[self.pending_len..self.pending_len + take].copy_from_slice(&bytes[..take]);
+            self.pending_len += take;
+            bytes = &bytes[take..];
+            if self.pending_len < STRIPE {
+                return;
+            }
+            let pending = self.pending;
+            self.stripe(&pending);
+            self.pending_len = 0;
+        }
+        let mut stripes = bytes.chunks_exact(STRIPE);
+        for stripe in &mut stripes {
+            self.stripe(stripe);
+        }
+        let rest = stripes.remainder();
+        self.pending[..rest.len()].copy_from_slice(rest);
+        self.pending_len = rest.len();
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        let [v1, v2, v3, v4] = self.lanes;
+        let mut hash = if self.total >= STRIPE as u64 {
+            let hash = v1
+                .rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18));
+            self.lanes
+                .iter()
+                .fold(hash, |hash, &lane| merge_round(hash, lane))
+        } else {
+            self.seed.wrapping_add(PRIME_5)
+        };
+        hash = hash.wrapping_add(self.total);
+        let mut rest = &self.pending[..self.pending_len];
+        while rest.len() >= 8 {
+            hash = (hash ^ round(0, read_u64(rest)))
+                .rotate_left(27)
+                .wrapping_mul(PRIME_1)
+                .wrapping_add(PRIME_4);
+            rest = &rest[8..];
+        }
+        if rest.len() >= 4 {
+            let word = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+            hash = (hash ^ u64::from(word).wrapping_mul(PRIME_1))
+                .rotate_left(23)
+                .wrapping_mul(PRIME_2)
+                .wrapping_add(PRIME_3);
+            rest = &rest[4..];
+        }
+        for &byte in rest {
+            hash = (hash ^ u64::from(byte).wrapping_mul(PRIME_5))
+                .rotate_left(11)
+                .wrapping_mul(PRIME_1);
+        }
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME_2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME_3);
+        hash ^ (hash >> 32)
+    }
+}
+
+/// The in-memory bytes of `values`, which wasm lays out little-endian.
+fn bytes_of<T: Copy>(values: &[T]) -> &[u8] {
+    // SAFETY: only called with primitive integers and floats, which have
+    // no padding, so every byte of the slice is initialized.
+    unsafe { std::slice::from_raw_parts(values.as_ptr().cast(), std::mem::size_of_val(values)) }
+}
+
+/// Feeds the storage type and buffers of `values` to `hasher`.
+fn hash_values(values: &Values, hasher: &mut Xxh64) {
+    hasher.update(values.type_name().as_bytes());
+    match values {
+        Values::Int8(values) => hasher.update(bytes_of(values)),
+        Values::Int16(values) => hasher.update(bytes_of(values)),
+        Values::Int32(values) => hasher.update(bytes_of(values)),
+        Values::Int64(values) => hasher.update(bytes_of(values)),
+        Values::UInt8(values) | Values::Bool(values) => hasher.update(values),
+        Values::UInt16(values) | Values::Float16(values) => hasher.update(bytes_of(values)),
+        Values::UInt32(values) => hasher.update(bytes_of(values)),
+        Values::UInt64(values) => hasher.update(bytes_of(values)),
+        Values::Float32(values) => hasher.update(bytes_of(values)),
+        Values::Float64(values) => hasher.update(bytes_of(values)),
+        Values::Utf8 { offsets, data } => {
+            hasher.update(bytes_of(offsets));
+            hasher.update(data);
+        }
+        Values::Timestamp { millis, timezone } => {
+            hasher.update(bytes_of(millis));
+            hasher.update(timezone.as_deref().unwrap_or_default().as_bytes());
+        }
+        Values::Decimal128 { values, scale } => {
+            hasher.update(bytes_of(values));
+            hasher.update(&scale.to_le_bytes());
+        }
+        Values::Dictionary { indices, values } => {
+            hasher.update(bytes_of(indices));
+            hash_values(values, hasher);
+        }
+    }
+}
+
+/// CRC-32 (IEEE) of `bytes`; pass an earlier result as `previous` to
+/// continue it over the next chunk.
+#[wasm_bindgen]
+pub fn crc32(bytes: &[u8], previous: Option<u32>) -> u32 {
+    crc32_update(previous.unwrap_or(0), bytes)
+}
+
+/// XXH64 of `bytes` with `seed` (zero when omitted).
+#[wasm_bindgen]
+pub fn xxhash64(bytes: &[u8], seed: Option<u64>) -> u64 {
+    let mut hasher = Xxh64::new(seed.unwrap_or(0));
+    hasher.update(bytes);
+    hasher.finish()
+}
+
+/// XXH64 of the first `len` scratch entries, as little-endian bytes.
+#[wasm_bindgen(js_name = scratchChecksum)]
+pub fn scratch_checksum(len: u32) -> Result<u64, KernelError> {
+    SCRATCH.with(|cell| {
+        let scratch = cell.borrow();
+        let len = len as usize;
+        if len > scratch.len() {
+            return Err(
+                KernelError::scratch_overflow(len, scratch.len()).in_kernel("scratchChecksum")
+            );
+        }
+        Ok(xxhash64(bytes_of(&scratch[..len]), None))
+    })
+}
+
+/// XXH64 over the storage type, values and validity of the column behind
+/// `handle`; equal for columns with the same contents, whatever their name.
+#[wasm_bindgen(js_name = columnChecksum)]
+pub fn column_checksum(handle: u32) -> Result<u64, KernelError> {
+    columns::with_column(handle, |column| {
+        let mut hasher = Xxh64::new(0);
+        hasher.update(&(column.len as u64).to_le_bytes());
+        hash_values(&column.values, &mut hasher);
+        if let Some(validity) = &column.validity {
+            hasher.update(validity);
+        }
+        Ok(hasher.finish())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::Column;
+
+    #[test]
+    fn crc32_matches_known_answers() {
+        assert_eq!(crc32(b"", None), 0);
+        assert_eq!(crc32(b"123456789", None), 0xcbf4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog", None),
+            0x414f_a339
+        );
+    }
+
+    #[test]
+    fn xxhash64_matches_known_answers() {
+        assert_eq!(xxhash64(b"", None), 0xef46_db37_51d8_e999);
+        assert_eq!(xxhash64(b"abc", None), 0x44bc_2cf5_ad77_0999);
+        // 39 bytes: one full stripe, then the tail.
+        assert_eq!(
+            xxhash64(b"Nobody inspects the spammish repetition", None),
+            0xfbce_a83c_8a37_8bf1
+        );
+        assert_ne!(xxhash64(b"abc", Some(1)), xxhash64(b"abc", None));
+    }
+
+    #[test]
+    fn streaming_matches_a_single_update_at_every_split() {
+        let bytes: Vec<u8> = (0..100u32).map(|index| (index * 37 % 251) as u8).collect();
+        let crc = crc32(&bytes, None);
+        let hash = xxhash64(&bytes, Some(7));
+        for split in 0..=bytes.len() {
+            let (head, tail) = bytes.split_at(split);
+            assert_eq!(
+                crc32(tail, Some(crc32(head, None))),
+                crc,
+                "crc split {split}"
+            );
+            let mut hasher = Xxh64::new(7);
+            hasher.update(head);
+            hasher.update(tail);
+            assert_eq!(hasher.finish(), hash, "xxh64 split {split}");
+        }
+        // Many small updates straddling stripe boundaries.
+        let mut hasher = Xxh64::new(7);
+        for chunk in bytes.chunks(3) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finish(), hash);
+    }
+
+    #[test]
+    fn column_checksums_ignore_names_but_not_contents() {
+        let column = |name: &str, values: Vec<f64>| {
+            columns::register(Column {
+                name: name.to_owned(),
+                len: values.len(),
+                values: Values::Float64(values),
+                validity: None,
+            })
+        };
+        let a = column_checksum(column("a", vec![1.0, 2.0])).unwrap();
+        let b = column_checksum(column("b", vec![1.0, 2.0])).unwrap();
+        let c = column_checksum(column("a", vec![2.0, 1.0])).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+}
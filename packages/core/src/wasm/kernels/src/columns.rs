@@ -0,0 +1,543 @@
+//! Wasm-resident column store.
+//!
+//! Columns decoded inside the module (Arrow ingestion and friends) live here
+//! under opaque `u32` handles so dimension kernels can read them without the
+//! data ever round-tripping through JS objects. Values keep their source
+//! type; validity is an LSB-first bitmap where a set bit marks a valid row.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::categories;
+use crate::error::{ErrorKind, KernelError};
+use crate::half;
+use crate::recovery;
+
+/// Typed storage for one column.
+#[derive(Clone)]
+pub(crate) enum Values {
+    Int8(Vec<i8>),
+    Int16(Vec<i16>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    UInt8(Vec<u8>),
+    UInt16(Vec<u16>),
+    UInt32(Vec<u32>),
+    UInt64(Vec<u64>),
+    /// Half-precision floats as raw bit patterns, widened on read.
+    Float16(Vec<u16>),
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+    /// Bit-packed booleans, LSB first.
+    Bool(Vec<u8>),
+    /// Variable-length strings: `offsets` has `len + 1` entries into `data`.
+    Utf8 {
+        offsets: Vec<u32>,
+        data: Vec<u8>,
+    },
+    /// Instants as epoch milliseconds, converted from Arrow timestamp and
+    /// date types; `timezone` is the source's timezone metadata, if any.
+    Timestamp {
+        millis: Vec<f64>,
+        timezone: Option<String>,
+    },
+    /// Unscaled 128-bit decimals; the value is `unscaled / 10^scale`.
+    Decimal128 {
+        values: Vec<i128>,
+        scale: i32,
+    },
+    /// Dictionary-encoded strings: per-row `indices` into `values`, which is
+    /// always `Utf8`.
+    Dictionary {
+        indices: Vec<u32>,
+        values: Box<Values>,
+    },
+}
+
+impl Values {
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Values::Int8(_) => "int8",
+            Values::Int16(_) => "int16",
+            Values::Int32(_) => "int32",
+            Values::Int64(_) => "int64",
+            Values::UInt8(_) => "uint8",
+            Values::UInt16(_) => "uint16",
+            Values::UInt32(_) => "uint32",
+            Values::UInt64(_) => "uint64",
+            Values::Float16(_) => "float16",
+            Values::Float32(_) => "float32",
+            Values::Float64(_) => "float64",
+            Values::Bool(_) => "bool",
+            Values::Utf8 { .. } => "utf8",
+            Values::Timestamp { .. } => "timestamp",
+            Values::Decimal128 { .. } => "decimal128",
+            Values::Dictionary { .. } => "dictionary",
+        }
+    }
+
+    /// Whether `number` reads this storage, checked without touching a row
+    /// so empty columns pass.
+    pub(crate) fn is_numeric(&self) -> bool {
+        !matches!(self, Values::Utf8 { .. } | Values::Dictionary { .. })
+    }
+
+    /// Numeric view of row `index`; `None` for non-numeric columns.
+    pub(crate) fn number(&self, index: usize) -> Option<f64> {
+        Some(match self {
+            Values::Int8(values) => f64::from(values[index]),
+            Values::Int16(values) => f64::from(values[index]),
+            Values::Int32(values) => f64::from(values[index]),
+            Values::Int64(values) => values[index] as f64,
+            Values::UInt8(values) => f64::from(values[index]),
+            Values::UInt16(values) => f64::from(values[index]),
+            Values::UInt32(values) => f64::from(values[index]),
+            Values::UInt64(values) => values[index] as f64,
+            Values::Float16(values) => f64::from(half::to_f32(values[index])),
+            Values::Float32(values) => f64::from(values[index]),
+            Values::Float64(values) => values[index],
+            Values::Timestamp { millis, .. } => millis[index],
+            Values::Decimal128 { values, scale } => values[index] as f64 / 10f64.powi(*scale),
+            Values::Bool(bits) => f64::from(u8::from(bit(bits, index))),
+            Values::Utf8 { .. } | Values::Dictionary { .. } => return None,
+        })
+    }
+
+    /// Number of strings in `Utf8` storage.
+    pub(crate) fn label_count(&self) -> usize {
+        match self {
+            Values::Utf8 { offsets, .. } => offsets.len() - 1,
+            _ => 0,
+        }
+    }
+
+    /// Bytes of string `index`; `None` for non-string storage.
+    pub(crate) fn label(&self, index: usize) -> Option<&[u8]> {
+        match self {
+            Values::Utf8 { offsets, data } => {
+                Some(&data[offsets[index] as usize..offsets[index + 1] as usize])
+            }
+            Values::Dictionary { indices, values } => values.label(indices[index] as usize),
+            _ => None,
+        }
+    }
+
+    /// Rows `indices` of these values, in that order; every index must be in
+    /// range.
+    pub(crate) fn gather(&self, indices: &[u32]) -> Values {
+        match self {
+            Values::Int8(values) => Values::Int8(gather(values, indices)),
+            Values::Int16(values) => Values::Int16(gather(values, indices)),
+            Values::Int32(values) => Values::Int32(gather(values, indices)),
+            Values::Int64(values) => Values::Int64(gather(values, indices)),
+            Values::UInt8(values) => Values::UInt8(gather(values, indices)),
+            Values::UInt16(values) => Values::UInt16(gather(values, indices)),
+            Values::UInt32(values) => Values::UInt32(gather(values, indices)),
+            Values::UInt64(values) => Values::UInt64(gather(values, indices)),
+            Values::Float16(values) => Values::Float16(gather(values, indices)),
+            Values::Float32(values) => Values::Float32(gather(values, indices)),
+            Values::Float64(values) => Values::Float64(gather(values, indices)),
+            Values::Bool(bits) => Values::Bool(gather_bits(bits, indices)),
+            Values::Utf8 { offsets, data } => {
+                let mut gathered = Vec::with_capacity(indices.len() + 1);
+                gathered.push(0);
+                let mut bytes = Vec::new();
+                for &index in indices {
+                    let index = index as usize;
+                    bytes.extend_from_slice(
+                        &data[offsets[index] as usize..offsets[index + 1] as usize],
+                    );
+                    gathered.push(bytes.len() as u32);
+                }
+                Values::Utf8 {
+                    offsets: gathered,
+                    data: bytes,
+                }
+            }
+            Values::Timestamp { millis, timezone } => Values::Timestamp {
+                millis: gather(millis, indices),
+                timezone: timezone.clone(),
+            },
+            Values::Decimal128 { values, scale } => Values::Decimal128 {
+                values: gather(values, indices),
+                scale: *scale,
+            },
+            // The dictionary itself is shared by every row order.
+            Values::Dictionary {
+                indices: keys,
+                values,
+            } => Values::Dictionary {
+                indices: gather(keys, indices),
+                values: values.clone(),
+            },
+        }
+    }
+}
+
+pub(crate) fn bit(bits: &[u8], index: usize) -> bool {
+    bits[index >> 3] & (1 << (index & 7)) != 0
+}
+
+/// Elements `indices` of `values`, in that order.
+pub(crate) fn gather<T: Copy>(values: &[T], indices: &[u32]) -> Vec<T> {
+    indices
+        .iter()
+        .map(|&index| values[index as usize])
+        .collect()
+}
+
+/// Bits `indices` of an LSB-first bitmap, packed into a new one.
+pub(crate) fn gather_bits(bits: &[u8], indices: &[u32]) -> Vec<u8> {
+    let mut gathered = Bitmap::default();
+    for &index in indices {
+        gathered.push(bit(bits, index as usize));
+    }
+    gathered.bytes
+}
+
+/// Growable LSB-first bitmap.
+#[derive(Default)]
+pub(crate) struct Bitmap {
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) len: usize,
+}
+
+impl Bitmap {
+    pub(crate) fn push(&mut self, value: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if value {
+            self.bytes[self.len >> 3] |= 1 << (self.len & 7);
+        }
+        self.len += 1;
+    }
+
+    pub(crate) fn extend_constant(&mut self, value: bool, count: usize) {
+        for _ in 0..count {
+            self.push(value);
+        }
+    }
+
+    /// Appends `count` bits of `source`, starting at bit 0.
+    pub(crate) fn extend_from(&mut self, source: &[u8], count: usize) {
+        for index in 0..count {
+            self.push(bit(source, index));
+        }
+    }
+}
+
+pub(crate) struct Column {
+    pub(crate) name: String,
+    pub(crate) len: usize,
+    pub(crate) values: Values,
+    pub(crate) validity: Option<Vec<u8>>,
+}
+
+impl Column {
+    pub(crate) fn is_valid(&self, index: usize) -> bool {
+        self.validity.as_ref().is_none_or(|bits| bit(bits, index))
+    }
+}
+
+/// Linear `[min, max]` → bin mapping shared by the in-wasm binning kernels.
+#[derive(Clone, Copy)]
+pub(crate) struct Quantizer {
+    min: f64,
+    max: f64,
+    scale: f64,
+}
+
+impl Quantizer {
+    pub(crate) fn new(min: f64, max: f64, bin_count: u32) -> Result<Self, KernelError> {
+        if bin_count == 0 || bin_count > u32::from(u16::MAX) + 1 {
+            return Err(KernelError::bad_bin_count(bin_count));
+        }
+        let range = f64::from(bin_count - 1);
+        let scale = if max > min { range / (max - min) } else { 0.0 };
+        Ok(Quantizer { min, max, scale })
+    }
+
+    /// Nulls and non-finite values land in bin 0.
+    pub(crate) fn bin(&self, value: f64, valid: bool) -> u16 {
+        if valid && value.is_finite() && self.scale > 0.0 {
+            ((value.clamp(self.min, self.max) - self.min) * self.scale).round() as u16
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Default)]
+struct ColumnStore {
+    next_handle: u32,
+    columns: HashMap<u32, Column>,
+}
+
+thread_local! {
+    static COLUMNS: RefCell<ColumnStore> = RefCell::new(ColumnStore::default());
+}
+
+/// Drops every column; see `hardReset`.
+pub(crate) fn reset() {
+    COLUMNS.with(|store| recovery::reset_cell(store, ColumnStore::default()));
+}
+
+/// Registers a column and returns its handle.
+pub(crate) fn register(column: Column) -> u32 {
+    COLUMNS.with(|store| {
+        let mut store = store.borrow_mut();
+        store.next_handle = store.next_handle.wrapping_add(1).max(1);
+        let handle = store.next_handle;
+        store.columns.insert(handle, column);
+        handle
+    })
+}
+
+/// Runs `read` against the column behind `handle`.
+pub(crate) fn with_column<T>(
+    handle: u32,
+    read: impl FnOnce(&Column) -> Result<T, KernelError>,
+) -> Result<T, KernelError> {
+    COLUMNS.with(|store| {
+        let store = store.borrow();
+        let column = store
+            .columns
+            .get(&handle)
+            .ok_or_else(|| unknown_column(handle))?;
+        read(column)
+    })
+}
+
+fn unknown_column(handle: u32) -> KernelError {
+    KernelError::new(ErrorKind::InvalidArgument, "unknown column handle")
+        .with("handle", f64::from(handle))
+}
+
+#[wasm_bindgen(js_name = columnLength)]
+pub fn column_length(handle: u32) -> Result<u32, KernelError> {
+    with_column(handle, |column| Ok(column.len as u32))
+}
+
+#[wasm_bindgen(js_name = columnName)]
+pub fn column_name(handle: u32) -> Result<String, KernelError> {
+    with_column(handle, |column| Ok(column.name.clone()))
+}
+
+/// Storage type of the column, e.g. `"float64"` or `"utf8"`.
+#[wasm_bindgen(js_name = columnType)]
+pub fn column_type(handle: u32) -> Result<String, KernelError> {
+    with_column(handle, |column| Ok(column.values.type_name().to_owned()))
+}
+
+/// Timezone metadata of a timestamp column; `undefined` for naive
+/// timestamps and non-temporal columns.
+#[wasm_bindgen(js_name = columnTimezone)]
+pub fn column_timezone(handle: u32) -> Result<Option<String>, KernelError> {
+    with_column(handle, |column| match &column.values {
+        Values::Timestamp { timezone, .. } => Ok(timezone.clone()),
+        _ => Ok(None),
+    })
+}
+
+#[wasm_bindgen(js_name = columnNullCount)]
+pub fn column_null_count(handle: u32) -> Result<u32, KernelError> {
+    with_column(handle, |column| {
+        Ok((0..column.len)
+            .filter(|&index| !column.is_valid(index))
+            .count() as u32)
+    })
+}
+
+/// The first `rows` values of the numeric column behind `handle` as
+/// measure weights: nulls and NaNs weigh zero.
+pub(crate) fn measure(handle: u32, rows: usize) -> Result<Vec<f64>, KernelError> {
+    with_column(handle, |column| {
+        if column.len < rows {
+            return Err(KernelError::invalid_argument("measure column is too short")
+                .with("needed", rows as f64)
+                .with("available", column.len as f64));
+        }
+        Ok((0..rows)
+            .map(|row| match column.values.number(row) {
+                Some(value) if column.is_valid(row) && !value.is_nan() => value,
+                _ => 0.0,
+            })
+            .collect())
+    })
+}
+
+/// Frees the column. Unknown handles are ignored.
+#[wasm_bindgen(js_name = releaseColumn)]
+pub fn release_column(handle: u32) {
+    COLUMNS.with(|store| {
+        store.borrow_mut().columns.remove(&handle);
+    });
+}
+
+/// Quantizes a numeric column into `binCount` bins over `[min, max]` and
+/// writes the bin indices into the scratch buffer, ready for
+/// `accumulateScratch`. Mirrors the TS `quantize`: values are clamped and
+/// rounded; nulls and non-finite values land in bin 0. Returns the row count.
+#[wasm_bindgen(js_name = quantizeColumn)]
+pub fn quantize_column(
+    handle: u32,
+    min: f64,
+    max: f64,
+    bin_count: u32,
+) -> Result<u32, KernelError> {
+    let quantizer = Quantizer::new(min, max, bin_count)?;
+    with_column(handle, |column| {
+        if !column.values.is_numeric() {
+            return Err(KernelError::new(
+                ErrorKind::Unsupported,
+                "quantizeColumn requires a numeric column",
+            ));
+        }
+        crate::with_scratch(column.len, |scratch| {
+            for (index, slot) in scratch.iter_mut().enumerate() {
+                let value = column.values.number(index).unwrap_or(f64::NAN);
+                *slot = quantizer.bin(value, column.is_valid(index));
+            }
+        })?;
+        Ok(column.len as u32)
+    })
+}
+
+/// Writes category codes for a string or dictionary column into the scratch
+/// buffer, ready for `accumulateScratch`. Codes come from `dimension`'s
+/// category dictionary (see `categoryLabels`); for a dictionary-encoded
+/// column the Arrow dictionary becomes that mapping, so its indices are used
+/// as bins without decoding. Returns the row count.
+#[wasm_bindgen(js_name = categorizeColumn)]
+pub fn categorize_column(handle: u32, dimension: u32, bin_count: u32) -> Result<u32, KernelError> {
+    if bin_count == 0 || bin_count > u32::from(u16::MAX) + 1 {
+        return Err(KernelError::bad_bin_count(bin_count));
+    }
+    with_column(handle, |column| {
+        categories::with_categories(dimension, |categories| {
+            match &column.values {
+                Values::Dictionary { indices, values } => {
+                    let labels = values.label_count();
+                    categories
+                        .seed((0..labels).map(|index| values.label(index).unwrap_or_default()))?;
+                    crate::with_scratch(column.len, |scratch| {
+                        for (row, slot) in scratch.iter_mut().enumerate() {
+                            *slot = if column.is_valid(row) {
+                                categories.code_for_index(indices[row], bin_count)
+                            } else {
+                                categories.code(&[], bin_count)
+                            };
+                        }
+                    })?;
+                }
+                Values::Utf8 { .. } => crate::with_scratch(column.len, |scratch| {
+                    for (row, slot) in scratch.iter_mut().enumerate() {
+                        let label = if column.is_valid(row) {
+                            column.values.label(row).unwrap_or_default()
+                        } else {
+                            &[]
+                        };
+                        *slot = categories.code(label, bin_count);
+                    }
+                })?,
+                _ => {
+                    return Err(KernelError::new(
+                        ErrorKind::Unsupported,
+                        "categorizeColumn requires a string or dictionary column",
+                    ))
+                }
+            }
+            Ok(column.len as u32)
+        })
+    })
+}
+
+/// Column factories for the kernel tests.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::{register, Column, Values};
+
+    fn column(values: Values, len: usize, validity: Option<Vec<u8>>) -> u32 {
+        register(Column {
+            name: "x".to_owned(),
+            len,
+            values,
+            validity,
+        })
+    }
+
+    /// A `Float64` column, null where `validity` (LSB-first) has a clear bit.
+    pub(crate) fn floats(values: Vec<f64>, validity: Option<Vec<u8>>) -> u32 {
+        let len = values.len();
+        column(Values::Float64(values), len, validity)
+    }
+
+    /// An `Int32` column without nulls.
+    pub(crate) fn ints(values: Vec<i32>) -> u32 {
+        let len = values.len();
+        column(Values::Int32(values), len, None)
+    }
+
+    /// Key 7 in rows 0, 2, 4, ..., 98, then keys 100 to 148 once each.
+    pub(crate) fn skewed_keys() -> u32 {
+        ints(
+            (0..100)
+                .map(|row| if row % 2 == 0 { 7 } else { 100 + row / 2 })
+                .collect(),
+        )
+    }
+
+    /// `len` distinct floats starting at `start`, in a scrambled row order.
+    pub(crate) fn scrambled(start: usize, len: usize) -> u32 {
+        floats(
+            (0..len)
+                .map(|row| (start + row * 7919 % len) as f64)
+                .collect(),
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantizing_empty_columns_writes_nothing() {
+        let empty = register(Column {
+            name: "x".to_owned(),
+            len: 0,
+            values: Values::Bool(Vec::new()),
+            validity: None,
+        });
+        assert_eq!(quantize_column(empty, 0.0, 1.0, 4).unwrap(), 0);
+        let strings = register(Column {
+            name: "s".to_owned(),
+            len: 0,
+            values: Values::Utf8 {
+                offsets: vec![0],
+                data: Vec::new(),
+            },
+            validity: None,
+        });
+        let error = quantize_column(strings, 0.0, 1.0, 4).unwrap_err();
+        assert_eq!(error.code(), ErrorKind::Unsupported as u32);
+    }
+
+    #[test]
+    fn quantizing_clamps_and_sends_nulls_to_bin_zero() {
+        let handle = register(Column {
+            name: "x".to_owned(),
+            len: 4,
+            values: Values::Float64(vec![-5.0, 0.5, 1.0, 0.75]),
+            validity: Some(vec![0b0111]),
+        });
+        assert_eq!(quantize_column(handle, 0.0, 1.0, 4).unwrap(), 4);
+        let bins = crate::with_scratch(4, |scratch| scratch.to_vec()).unwrap();
+        assert_eq!(bins[0], 0);
+        assert_eq!(bins[2], 3);
+        assert_eq!(bins[3], 0);
+    }
+}
@@ -0,0 +1,178 @@
+//! Composite dimensions keyed on several columns.
+//!
+//! A dimension such as `[region, product]` gets one code per distinct tuple
+//! of part values, packed inside wasm instead of building joined key strings
+//! in JS. Each part keeps its own first-seen dictionary of values; tuples of
+//! part codes then get composite codes in first-seen order until the bin
+//! count is exhausted, after which every new tuple shares the last bin, as
+//! in `categorizeColumn`. Both persist across calls, so later batches of the
+//! same dimension keep earlier codes.
+//!
+//! String and dictionary parts are keyed by their labels, numeric parts by
+//! their shortest round-trip decimal form; nulls are the empty string.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::categories::Categories;
+use crate::columns::{self, Column};
+use crate::error::KernelError;
+use crate::recovery;
+
+#[derive(Default)]
+struct Composite {
+    parts: Vec<Categories>,
+    codes: HashMap<Vec<u16>, u16>,
+    /// Part codes of each composite code, in code order.
+    tuples: Vec<Vec<u16>>,
+}
+
+impl Composite {
+    fn code(&mut self, tuple: &[u16], bin_count: u32) -> u16 {
+        if let Some(&code) = self.codes.get(tuple) {
+            return code;
+        }
+        let code = if (self.tuples.len() as u32) < bin_count {
+            self.tuples.push(tuple.to_vec());
+            (self.tuples.len() - 1) as u16
+        } else {
+            (bin_count - 1) as u16
+        };
+        self.codes.insert(tuple.to_vec(), code);
+        code
+    }
+}
+
+thread_local! {
+    static COMPOSITES: RefCell<HashMap<u32, Composite>> = RefCell::new(HashMap::new());
+}
+
+/// Drops every composite dimension; see `hardReset`.
+pub(crate) fn reset() {
+    COMPOSITES.with(|composites| recovery::reset_cell(composites, HashMap::new()));
+}
+
+/// Part dictionaries use the whole `u16` code space; the composite bin count
+/// is what bounds the dimension.
+const PART_LIMIT: u32 = u16::MAX as u32 + 1;
+
+/// Key bytes of `row` in a part column.
+fn part_key(column: &Column, row: usize) -> Vec<u8> {
+    if !column.is_valid(row) {
+        return Vec::new();
+    }
+    match column.values.label(row) {
+        Some(label) => label.to_vec(),
+        None => column
+            .values
+            .number(row)
+            .map(|value| value.to_string().into_bytes())
+            .unwrap_or_default(),
+    }
+}
+
+/// Writes composite codes for the rows of the columns behind `handles`
+/// (the key parts, in order, of equal lengths) into the scratch buffer,
+/// ready for `accumulateScratch`. Codes come from `dimension`'s composite
+/// dictionary; see `compositeKeys`. Returns the row count.
+#[wasm_bindgen(js_name = categorizeComposite)]
+pub fn categorize_composite(
+    handles: &[u32],
+    dimension: u32,
+    bin_count: u32,
+) -> Result<u32, KernelError> {
+    if bin_count == 0 || bin_count > PART_LIMIT {
+        return Err(KernelError::bad_bin_count(bin_count));
+    }
+    if handles.is_empty() {
+        return Err(KernelError::invalid_argument(
+            "composite dimensions need at least one part",
+        ));
+    }
+    // Part codes per row, one part at a time.
+    let mut part_codes: Vec<Vec<u16>> = Vec::with_capacity(handles.len());
+    COMPOSITES.with(|composites| {
+        let mut composites = composites.borrow_mut();
+        let composite = composites.entry(dimension).or_default();
+        if !composite.parts.is_empty() && composite.parts.len() != handles.len() {
+            return Err(KernelError::invalid_state(
+                "composite dimension was built with a different number of parts",
+            )
+            .with("parts", composite.parts.len() as f64)
+            .with("handles", handles.len() as f64));
+        }
+        composite
+            .parts
+            .resize_with(handles.len(), Categories::default);
+        for (part, &handle) in handles.iter().enumerate() {
+            let codes = columns::with_column(handle, |column| {
+                if let Some(first) = part_codes.first() {
+                    if first.len() != column.len {
+                        return Err(KernelError::invalid_argument("part lengths differ")
+                            .with("expected", first.len() as f64)
+                            .with("actual", column.len as f64));
+                    }
+                }
+                let dictionary = &mut composite.parts[part];
+                Ok((0..column.len)
+                    .map(|row| dictionary.code(&part_key(column, row), PART_LIMIT))
+                    .collect())
+            })?;
+            part_codes.push(codes);
+        }
+        let rows = part_codes[0].len();
+        let mut tuple = vec![0u16; handles.len()];
+        crate::with_scratch(rows, |scratch| {
+            for (row, slot) in scratch.iter_mut().enumerate() {
+                for (code, codes) in tuple.iter_mut().zip(&part_codes) {
+                    *code = codes[row];
+                }
+                *slot = composite.code(&tuple, bin_count);
+            }
+        })?;
+        Ok(rows as u32)
+    })
+}
+
+/// Part codes of every composite code of `dimension`, flattened: entry
+/// `code * parts + part` is the code of part `part` in composite `code`,
+/// which indexes `compositePartLabels(dimension, part)`.
+#[wasm_bindgen(js_name = compositeKeys)]
+pub fn composite_keys(dimension: u32) -> Vec<u16> {
+    COMPOSITES.with(|composites| {
+        composites
+            .borrow()
+            .get(&dimension)
+            .map(|composite| composite.tuples.concat())
+            .unwrap_or_default()
+    })
+}
+
+/// Distinct values of part `part` of `dimension`, indexed by part code.
+#[wasm_bindgen(js_name = compositePartLabels)]
+pub fn composite_part_labels(dimension: u32, part: u32) -> Vec<String> {
+    COMPOSITES.with(|composites| {
+        composites
+            .borrow()
+            .get(&dimension)
+            .and_then(|composite| composite.parts.get(part as usize))
+            .map(|part| part.labels().to_vec())
+            .unwrap_or_default()
+    })
+}
+
+/// Forgets the composite dictionary of `dimension`, or every one when
+/// omitted.
+#[wasm_bindgen(js_name = resetComposite)]
+pub fn reset_composite(dimension: Option<u32>) {
+    COMPOSITES.with(|composites| {
+        let mut composites = composites.borrow_mut();
+        match dimension {
+            Some(dimension) => {
+                composites.remove(&dimension);
+            }
+            None => composites.clear(),
+        }
+    });
+}
@@ -0,0 +1,228 @@
+//! Decompression of compressed column payloads.
+//!
+//! The server ships large column chunks compressed. Decompressing them here
+//! writes the raw bytes straight into wasm memory and decodes them into the
+//! column store, instead of inflating them in JS and copying the result back
+//! in.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, Column, Values};
+use crate::error::{ErrorKind, KernelError};
+use crate::memory;
+
+fn malformed(codec: &str, detail: impl std::fmt::Display) -> KernelError {
+    KernelError::new(
+        ErrorKind::MalformedInput,
+        format!("malformed {codec} payload: {detail}"),
+    )
+}
+
+/// Decompresses the zstd frames in `src`, appending the content to `out`.
+/// Concatenated frames decode back to back and skippable frames are ignored,
+/// as the format allows.
+pub(crate) fn zstd_decompress(mut src: &[u8], out: &mut Vec<u8>) -> Result<(), KernelError> {
+    use ruzstd::decoding::errors::{FrameDecoderError, ReadFrameHeaderError};
+    use ruzstd::decoding::{BlockDecodingStrategy, FrameDecoder};
+
+    // Bounds how much decoded data sits in the decoder's window buffer
+    // before it is drained into `out`.
+    const STEP: usize = 1 << 20;
+    let mut decoder = FrameDecoder::new();
+    while !src.is_empty() {
+        match decoder.init(&mut src) {
+            Ok(()) => {}
+            Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                length,
+                ..
+            })) => {
+                src = src
+                    .get(length as usize..)
+                    .ok_or_else(|| malformed("zstd", "truncated skippable frame"))?;
+                continue;
+            }
+            Err(error) => return Err(malformed("zstd", error)),
+        }
+        // A declared content size is checked against the budget up front
+        // and holds the frame to it; frames without one (reported as 0) are
+        // checked a step at a time as they decode.
+        let declared = decoder.content_size();
+        memory::check_budget(usize::try_from(declared).unwrap_or(usize::MAX))?;
+        let start = out.len();
+        loop {
+            if declared == 0 {
+                memory::check_budget(STEP)?;
+            }
+            decoder
+                .decode_blocks(&mut src, BlockDecodingStrategy::UptoBytes(STEP))
+                .map_err(|error| malformed("zstd", error))?;
+            decoder
+                .collect_to_writer(&mut *out)
+                .map_err(|error| malformed("zstd", error))?;
+            if declared > 0 && (out.len() - start) as u64 > declared {
+                return Err(malformed("zstd", "frame exceeds its declared content size"));
+            }
+            if decoder.is_finished() {
+                break;
+            }
+        }
+        if let Some(expected) = decoder.get_checksum_from_data() {
+            if decoder.get_calculated_checksum() != Some(expected) {
+                return Err(malformed("zstd", "content checksum mismatch"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decodes raw little-endian values of `value_type` (`"int8"` through
+/// `"uint64"`, `"float16"` through `"float64"`), returning them with their count.
+fn decode_values(value_type: &str, bytes: &[u8]) -> Result<(Values, usize), KernelError> {
+    fn fixed<T, const N: usize>(
+        bytes: &[u8],
+        decode: impl Fn([u8; N]) -> T,
+    ) -> Result<Vec<T>, KernelError> {
+        if !bytes.len().is_multiple_of(N) {
+            return Err(KernelError::invalid_argument(
+                "decompressed length is not a multiple of the value width",
+            )
+            .with("length", bytes.len() as f64)
+            .with("width", N as f64));
+        }
+        Ok(bytes
+            .chunks_exact(N)
+            .map(|chunk| decode(chunk.try_into().expect("chunk of N bytes")))
+            .collect())
+    }
+
+    let values = match value_type {
+        "int8" => Values::Int8(fixed(bytes, i8::from_le_bytes)?),
+        "int16" => Values::Int16(fixed(bytes, i16::from_le_bytes)?),
+        "int32" => Values::Int32(fixed(bytes, i32::from_le_bytes)?),
+        "int64" => Values::Int64(fixed(bytes, i64::from_le_bytes)?),
+        "uint8" => Values::UInt8(bytes.to_vec()),
+        "uint16" => Values::UInt16(fixed(bytes, u16::from_le_bytes)?),
+        "uint32" => Values::UInt32(fixed(bytes, u32::from_le_bytes)?),
+        "uint64" => Values::UInt64(fixed(bytes, u64::from_le_bytes)?),
+        "float16" => Values::Float16(fixed(bytes, u16::from_le_bytes)?),
+        "float32" => Values::Float32(fixed(bytes, f32::from_le_bytes)?),
+        "float64" => Values::Float64(fixed(bytes, f64::from_le_bytes)?),
+        other => {
+            return Err(KernelError::new(
+                ErrorKind::Unsupported,
+                format!("unsupported column value type {other:?}"),
+            ))
+        }
+    };
+    let width = match &values {
+        Values::Int8(_) | Values::UInt8(_) => 1,
+        Values::Int16(_) | Values::UInt16(_) | Values::Float16(_) => 2,
+        Values::Int32(_) | Values::UInt32(_) | Values::Float32(_) => 4,
+        _ => 8,
+    };
+    Ok((values, bytes.len() / width))
+}
+
+/// Decompresses a zstd-compressed column of raw little-endian values and
+/// registers it as a column named `name`, returning its handle.
+/// `valueType` is a column type name such as `"int32"` or `"float64"`; the
+/// row count follows from the decompressed length. All rows are valid.
+#[wasm_bindgen(js_name = ingestZstdColumn)]
+pub fn ingest_zstd_column(
+    name: String,
+    value_type: &str,
+    compressed: &[u8],
+) -> Result<u32, KernelError> {
+    let mut raw = Vec::new();
+    zstd_decompress(compressed, &mut raw)?;
+    // The decoded values are a second copy of the raw bytes.
+    memory::check_budget(raw.len())?;
+    let (values, len) = decode_values(value_type, &raw)?;
+    kernel_log!(
+        Debug,
+        "compression",
+        "ingested zstd column",
+        name = name.as_str(),
+        compressed = compressed.len(),
+        decompressed = raw.len()
+    );
+    Ok(columns::register(Column {
+        name,
+        len,
+        values,
+        validity: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+    /// A zstd frame of raw blocks holding `content`, declaring `declared` as
+    /// its content size (when given, under 256 bytes) or a 1 KiB window.
+    fn frame(content: &[u8], declared: Option<u8>) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        match declared {
+            Some(size) => out.extend_from_slice(&[0x20, size]),
+            None => out.extend_from_slice(&[0x00, 0x00]),
+        }
+        let mut blocks = content.chunks(1024).peekable();
+        if blocks.peek().is_none() {
+            out.extend_from_slice(&[0x01, 0x00, 0x00]);
+        }
+        while let Some(block) = blocks.next() {
+            let last = u32::from(blocks.peek().is_none());
+            let header = ((block.len() as u32) << 3) | last;
+            out.extend_from_slice(&header.to_le_bytes()[..3]);
+            out.extend_from_slice(block);
+        }
+        out
+    }
+
+    #[test]
+    fn decodes_concatenated_and_skippable_frames() {
+        let mut src = frame(b"hello ", Some(6));
+        // A skippable frame with four bytes of user data.
+        src.extend_from_slice(&[0x50, 0x2a, 0x4d, 0x18, 4, 0, 0, 0, 1, 2, 3, 4]);
+        let long: Vec<u8> = (0..3000).map(|index| index as u8).collect();
+        src.extend(frame(&long, None));
+        let mut out = Vec::new();
+        zstd_decompress(&src, &mut out).unwrap();
+        assert_eq!(&out[..6], b"hello ");
+        assert_eq!(&out[6..], &long[..]);
+    }
+
+    #[test]
+    fn rejects_truncated_and_oversized_frames() {
+        let src = frame(b"abcdefgh", Some(8));
+        for len in [3, 5, src.len() - 1] {
+            assert!(zstd_decompress(&src[..len], &mut Vec::new()).is_err());
+        }
+        // Content past the declared size.
+        let src = frame(b"abcdefgh", Some(4));
+        let error = zstd_decompress(&src, &mut Vec::new()).unwrap_err();
+        assert!(error.message().contains("declared content size"));
+        // Truncated skippable frame.
+        let src = [0x50, 0x2a, 0x4d, 0x18, 9, 0, 0, 0, 1];
+        assert!(zstd_decompress(&src, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn ingests_raw_values() {
+        let raw: Vec<u8> = [1i32, -2, 3]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let handle = ingest_zstd_column("x".to_owned(), "int32", &frame(&raw, Some(12))).unwrap();
+        columns::with_column(handle, |column| {
+            assert_eq!(column.len, 3);
+            assert!(matches!(&column.values, Values::Int32(values) if values == &[1, -2, 3]));
+            Ok(())
+        })
+        .unwrap();
+        let error = ingest_zstd_column("x".to_owned(), "int32", &frame(&raw[..10], None));
+        assert!(error.is_err());
+    }
+}
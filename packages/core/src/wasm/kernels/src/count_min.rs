@@ -0,0 +1,263 @@
+//! Count-Min sketches for approximate key frequencies.
+//!
+//! "Top domains" over a column with millions of distinct keys, in
+//! `width * depth` counters instead of a hash map of every key. Each of the
+//! `depth` rows of counters maps a key to one counter (double hashing off
+//! one key hash) and a key's estimate is the smallest of its counters, so
+//! estimates never undercount and overcount by at most `e * total / width`
+//! with probability `1 - e^-depth`.
+//!
+//! Keys are identified by row, so queries and results work for every column
+//! type: point queries take rows of a column with the same key type, and
+//! heavy hitters come back as one representative row per key.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::columns;
+use crate::error::KernelError;
+use crate::hash;
+use crate::memory;
+use crate::recovery;
+
+struct CountMin {
+    width: usize,
+    depth: usize,
+    /// `depth` rows of `width` counters.
+    counters: Vec<u32>,
+    total: u32,
+    /// Column and mask the sketch was built from, for heavy hitters.
+    handle: u32,
+    mask: Option<Vec<u8>>,
+}
+
+/// Counter index of `hash` in each of `depth` rows of `width` counters.
+fn cells(hash: u64, width: usize, depth: usize) -> impl Iterator<Item = usize> {
+    hash::probes(hash, depth)
+        .enumerate()
+        .map(move |(row, probe)| row * width + (probe % width as u64) as usize)
+}
+
+impl CountMin {
+    fn estimate(&self, hash: u64) -> u32 {
+        cells(hash, self.width, self.depth)
+            .map(|cell| self.counters[cell])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Overcount bound of every estimate, with probability `1 - e^-depth`.
+    fn error_bound(&self) -> u32 {
+        (std::f64::consts::E * f64::from(self.total) / self.width as f64).ceil() as u32
+    }
+}
+
+thread_local! {
+    static SKETCHES: RefCell<HashMap<u32, CountMin>> = RefCell::new(HashMap::new());
+}
+
+/// Drops every sketch; see `hardReset`.
+pub(crate) fn reset() {
+    SKETCHES.with(|sketches| recovery::reset_cell(sketches, HashMap::new()));
+}
+
+fn with_sketch<T>(
+    sketch: u32,
+    read: impl FnOnce(&CountMin) -> Result<T, KernelError>,
+) -> Result<T, KernelError> {
+    SKETCHES.with(|sketches| {
+        let sketches = sketches.borrow();
+        let count_min = sketches.get(&sketch).ok_or_else(|| {
+            KernelError::invalid_state("no count-min sketch").with("sketch", f64::from(sketch))
+        })?;
+        read(count_min)
+    })
+}
+
+/// Keys with their estimated counts and error bounds.
+#[wasm_bindgen]
+pub struct HeavyHitters {
+    pub(crate) rows: Vec<u32>,
+    pub(crate) counts: Vec<u32>,
+    pub(crate) errors: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl HeavyHitters {
+    /// A row holding each key, largest count first.
+    #[wasm_bindgen(getter)]
+    pub fn rows(&self) -> Vec<u32> {
+        self.rows.clone()
+    }
+
+    /// Estimated count of each key.
+    #[wasm_bindgen(getter)]
+    pub fn counts(&self) -> Vec<u32> {
+        self.counts.clone()
+    }
+
+    /// Most each count may overstate the true count.
+    #[wasm_bindgen(getter)]
+    pub fn errors(&self) -> Vec<u32> {
+        self.errors.clone()
+    }
+}
+
+/// Builds (or rebuilds) count-min sketch `sketch` over the keys of the
+/// column behind `handle`, counting the rows set in `mask` (an LSB-first
+/// row bitmap in the layout's `activeMask` format; every row when omitted)
+/// in `depth` rows of `width` counters. Returns the rows counted.
+#[wasm_bindgen(js_name = buildCountMin)]
+pub fn build_count_min(
+    sketch: u32,
+    handle: u32,
+    width: u32,
+    depth: u32,
+    mask: Option<Vec<u8>>,
+) -> Result<u32, KernelError> {
+    if width == 0 || depth == 0 {
+        return Err(
+            KernelError::invalid_argument("count-min sketches need counters")
+                .with("width", f64::from(width))
+                .with("depth", f64::from(depth)),
+        );
+    }
+    let (width, depth) = (width as usize, depth as usize);
+    let bytes = width.checked_mul(depth);
+    let Some(bytes) = bytes.and_then(|len| len.checked_mul(std::mem::size_of::<u32>())) else {
+        return Err(
+            KernelError::invalid_argument("count-min sketch is too large")
+                .with("width", width as f64)
+                .with("depth", depth as f64),
+        );
+    };
+    memory::check_budget(bytes)?;
+    let hashes = hash::row_hashes(handle, mask.as_deref())?;
+    let mut count_min = CountMin {
+        width,
+        depth,
+        counters: vec![0; width * depth],
+        total: hashes.len() as u32,
+        handle,
+        mask,
+    };
+    for &(_, hash) in &hashes {
+        for cell in cells(hash, width, depth) {
+            count_min.counters[cell] += 1;
+        }
+    }
+    SKETCHES.with(|sketches| sketches.borrow_mut().insert(sketch, count_min));
+    Ok(hashes.len() as u32)
+}
+
+/// Estimated counts in `sketch` of the keys at `rows` of the column behind
+/// `handle`, which must hold keys of the sketched column's type.
+#[wasm_bindgen(js_name = countMinEstimate)]
+pub fn count_min_estimate(sketch: u32, handle: u32, rows: &[u32]) -> Result<Vec<u32>, KernelError> {
+    let hashes = columns::with_column(handle, |column| {
+        let mut key = Vec::new();
+        rows.iter()
+            .map(|&row| {
+                if row as usize >= column.len {
+                    return Err(KernelError::invalid_argument("row out of range")
+                        .with("row", f64::from(row))
+                        .with("length", column.len as f64));
+                }
+                Ok(hash::key_hash(column, row as usize, &mut key))
+            })
+            .collect::<Result<Vec<u64>, KernelError>>()
+    })?;
+    with_sketch(sketch, |count_min| {
+        Ok(hashes
+            .iter()
+            .map(|&hash| count_min.estimate(hash))
+            .collect())
+    })
+}
+
+/// Keys of `sketch` whose estimated count reaches `fraction` of the rows
+/// counted, largest first. Every key at or above the fraction is returned;
+/// keys somewhat below it may be too, within the error bound. Rescans the
+/// sketched column.
+#[wasm_bindgen(js_name = countMinHeavyHitters)]
+pub fn count_min_heavy_hitters(sketch: u32, fraction: f64) -> Result<HeavyHitters, KernelError> {
+    if !(fraction > 0.0 && fraction <= 1.0) {
+        return Err(
+            KernelError::invalid_argument("fraction must be in (0, 1]").with("fraction", fraction)
+        );
+    }
+    with_sketch(sketch, |count_min| {
+        let threshold = (fraction * f64::from(count_min.total)).ceil() as u32;
+        let mut found: HashMap<u64, (u32, u32)> = HashMap::new();
+        for (row, hash) in hash::row_hashes(count_min.handle, count_min.mask.as_deref())? {
+            if found.contains_key(&hash) {
+                continue;
+            }
+            let estimate = count_min.estimate(hash);
+            if estimate >= threshold {
+                found.insert(hash, (row, estimate));
+            }
+        }
+        let mut hitters: Vec<(u32, u32)> = found.into_values().collect();
+        hitters.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let error = count_min.error_bound();
+        Ok(HeavyHitters {
+            errors: vec![error; hitters.len()],
+            rows: hitters.iter().map(|&(row, _)| row).collect(),
+            counts: hitters.iter().map(|&(_, count)| count).collect(),
+        })
+    })
+}
+
+/// Drops count-min sketch `sketch`, or every one when omitted.
+#[wasm_bindgen(js_name = releaseCountMin)]
+pub fn release_count_min(sketch: Option<u32>) {
+    SKETCHES.with(|sketches| match sketch {
+        Some(sketch) => {
+            sketches.borrow_mut().remove(&sketch);
+        }
+        None => sketches.borrow_mut().clear(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::skewed_keys as keys;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn estimates_never_undercount() {
+        let handle = keys();
+        assert_eq!(build_count_min(1, handle, 64, 4, None).unwrap(), 100);
+        let estimates = count_min_estimate(1, handle, &[0, 1, 3]).unwrap();
+        let bound = with_sketch(1, |count_min| Ok(count_min.error_bound())).unwrap();
+        assert!(estimates[0] >= 50 && estimates[0] <= 50 + bound);
+        assert!(estimates[1] >= 1 && estimates[2] >= 1);
+        assert!(count_min_estimate(1, handle, &[100]).is_err());
+    }
+
+    #[test]
+    fn heavy_hitters_respect_the_mask() {
+        let handle = keys();
+        build_count_min(2, handle, 256, 5, None).unwrap();
+        let hitters = count_min_heavy_hitters(2, 0.4).unwrap();
+        assert_eq!(hitters.rows(), [0]);
+        assert!(hitters.counts()[0] >= 50);
+        // Only odd rows: every key appears once.
+        build_count_min(2, handle, 256, 5, Some(vec![0xaa; 13])).unwrap();
+        let hitters = count_min_heavy_hitters(2, 0.4).unwrap();
+        assert!(hitters.rows().is_empty());
+        assert!(count_min_heavy_hitters(2, 0.0).is_err());
+    }
+
+    #[test]
+    fn oversized_sketches_are_rejected() {
+        let handle = keys();
+        let error = build_count_min(3, handle, u32::MAX, u32::MAX, None).unwrap_err();
+        assert_eq!(error.code(), ErrorKind::InvalidArgument as u32);
+        assert!(build_count_min(3, handle, 0, 4, None).is_err());
+        assert!(with_sketch(3, |_| Ok(())).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+//! CUSUM change detection.
+//!
+//! Answers "did the level shift?" over a binned series (e.g. per-interval
+//! means from `resample`). The two-sided tabular CUSUM accumulates
+//! deviations above `target + slack` and below `target - slack`, clamped at
+//! zero, so noise around the target cancels out while a sustained shift
+//! keeps growing. A sum past the threshold marks a change point, and both
+//! sums restart there so later shifts are caught too.
+//!
+//! NaN values (empty bins) leave the sums unchanged.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+
+/// Cumulative deviation series with the detected change points.
+#[wasm_bindgen]
+pub struct Cusum {
+    upper: Vec<f64>,
+    lower: Vec<f64>,
+    change_points: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl Cusum {
+    /// Accumulated upward deviation after each value.
+    #[wasm_bindgen(getter)]
+    pub fn upper(&self) -> Vec<f64> {
+        self.upper.clone()
+    }
+
+    /// Accumulated downward deviation after each value, as a positive sum.
+    #[wasm_bindgen(getter)]
+    pub fn lower(&self) -> Vec<f64> {
+        self.lower.clone()
+    }
+
+    /// Indices where either sum crossed the threshold, ascending.
+    #[wasm_bindgen(getter = changePoints)]
+    pub fn change_points(&self) -> Vec<u32> {
+        self.change_points.clone()
+    }
+}
+
+/// Two-sided CUSUM of `values` around `target` (the mean of the non-NaN
+/// values when omitted), ignoring deviations up to `slack` and flagging a
+/// change once a sum exceeds `threshold`. Reported sums are taken before the
+/// restart, so the crossing stays visible.
+#[wasm_bindgen]
+pub fn cusum(
+    values: &[f64],
+    target: Option<f64>,
+    slack: f64,
+    threshold: f64,
+) -> Result<Cusum, KernelError> {
+    if !slack.is_finite() || slack < 0.0 {
+        return Err(
+            KernelError::invalid_argument("slack must be non-negative").with("slack", slack)
+        );
+    }
+    if threshold.is_nan() || threshold <= 0.0 {
+        return Err(KernelError::invalid_argument("threshold must be positive")
+            .with("threshold", threshold));
+    }
+    let target = target.unwrap_or_else(|| {
+        let (sum, count) = values
+            .iter()
+            .filter(|value| !value.is_nan())
+            .fold((0.0, 0u32), |(sum, count), &value| (sum + value, count + 1));
+        if count == 0 {
+            0.0
+        } else {
+            sum / f64::from(count)
+        }
+    });
+    if !target.is_finite() {
+        return Err(KernelError::invalid_argument("target must be finite").with("target", target));
+    }
+    let mut result = Cusum {
+        upper: Vec::with_capacity(values.len()),
+        lower: Vec::with_capacity(values.len()),
+        change_points: Vec::new(),
+    };
+    let (mut upper, mut lower) = (0.0f64, 0.0f64);
+    for (at, &value) in values.iter().enumerate() {
+        if !value.is_nan() {
+            upper = (upper + value - target - slack).max(0.0);
+            lower = (lower + target - slack - value).max(0.0);
+        }
+        result.upper.push(upper);
+        result.lower.push(lower);
+        if upper > threshold || lower > threshold {
+            result.change_points.push(at as u32);
+            upper = 0.0;
+            lower = 0.0;
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,327 @@
+//! Decimal128 measures.
+//!
+//! Per-bin sum/min/max over 128-bit decimals using exact `i128` arithmetic.
+//! Rows are grouped by a caller-supplied bin per row (the grouping
+//! dimension's bin indices); rows that are null or whose bin is out of range
+//! are skipped, like dropped bins in the histogram kernels. Results are
+//! rescaled to the caller's `resultScale`, rounding half away from zero when
+//! the scale shrinks, and exposed both as exact decimal strings and as
+//! approximate `f64`s.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, bit, Values};
+use crate::error::{ErrorKind, KernelError};
+
+/// Largest scale an `i128` power of ten can represent.
+const MAX_SCALE: u32 = 38;
+
+fn overflow(what: &'static str) -> KernelError {
+    KernelError::new(
+        ErrorKind::Overflow,
+        format!("decimal {what} overflows 128 bits"),
+    )
+}
+
+fn pow10(exponent: u32) -> Result<i128, KernelError> {
+    10i128
+        .checked_pow(exponent)
+        .ok_or_else(|| overflow("scale"))
+}
+
+/// Converts `value` from scale `from` to scale `to`.
+pub(crate) fn rescale(value: i128, from: i32, to: i32) -> Result<i128, KernelError> {
+    match to - from {
+        0 => Ok(value),
+        diff if diff > 0 => value
+            .checked_mul(pow10(diff as u32)?)
+            .ok_or_else(|| overflow("rescale")),
+        diff => {
+            let divisor = pow10(diff.unsigned_abs())?;
+            let quotient = value / divisor;
+            let remainder = value % divisor;
+            Ok(if remainder.unsigned_abs() * 2 >= divisor.unsigned_abs() {
+                quotient + value.signum()
+            } else {
+                quotient
+            })
+        }
+    }
+}
+
+/// Formats an unscaled integer at `scale` as a decimal string.
+pub(crate) fn format(value: i128, scale: i32) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let sign = if value < 0 { "-" } else { "" };
+    if scale <= 0 {
+        let zeros = "0".repeat(scale.unsigned_abs() as usize);
+        return if value == 0 {
+            "0".to_owned()
+        } else {
+            format!("{sign}{digits}{zeros}")
+        };
+    }
+    let scale = scale as usize;
+    let padded = format!("{digits:0>width$}", width = scale + 1);
+    let (whole, fraction) = padded.split_at(padded.len() - scale);
+    format!("{sign}{whole}.{fraction}")
+}
+
+/// Per-bin decimal aggregates at a fixed result scale.
+#[wasm_bindgen]
+pub struct DecimalAggregates {
+    scale: i32,
+    counts: Vec<u32>,
+    sums: Vec<i128>,
+    mins: Vec<Option<i128>>,
+    maxs: Vec<Option<i128>>,
+}
+
+#[wasm_bindgen]
+impl DecimalAggregates {
+    #[wasm_bindgen(getter)]
+    pub fn scale(&self) -> i32 {
+        self.scale
+    }
+
+    /// Non-null rows per bin.
+    #[wasm_bindgen(getter)]
+    pub fn counts(&self) -> Vec<u32> {
+        self.counts.clone()
+    }
+
+    /// Exact sums as decimal strings.
+    #[wasm_bindgen(js_name = sumStrings)]
+    pub fn sum_strings(&self) -> Vec<String> {
+        self.sums
+            .iter()
+            .map(|&sum| format(sum, self.scale))
+            .collect()
+    }
+
+    /// Exact minimums; empty bins yield an empty string.
+    #[wasm_bindgen(js_name = minStrings)]
+    pub fn min_strings(&self) -> Vec<String> {
+        self.strings(&self.mins)
+    }
+
+    /// Exact maximums; empty bins yield an empty string.
+    #[wasm_bindgen(js_name = maxStrings)]
+    pub fn max_strings(&self) -> Vec<String> {
+        self.strings(&self.maxs)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sums(&self) -> Vec<f64> {
+        self.sums.iter().map(|&sum| self.approximate(sum)).collect()
+    }
+
+    /// Exact sums as a `BigInt64Array` of unscaled values (`sum * 10^scale`);
+    /// fails with `Overflow` when one leaves the 64-bit range.
+    #[wasm_bindgen(js_name = unscaledSums)]
+    pub fn unscaled_sums(&self) -> Result<Vec<i64>, KernelError> {
+        self.sums
+            .iter()
+            .enumerate()
+            .map(|(bin, &sum)| {
+                i64::try_from(sum).map_err(|_| {
+                    KernelError::new(ErrorKind::Overflow, "decimal sum exceeds 64 bits")
+                        .with("bin", bin as f64)
+                })
+            })
+            .collect()
+    }
+
+    /// Approximate minimums; `NaN` for empty bins.
+    #[wasm_bindgen(getter)]
+    pub fn mins(&self) -> Vec<f64> {
+        self.numbers(&self.mins)
+    }
+
+    /// Approximate maximums; `NaN` for empty bins.
+    #[wasm_bindgen(getter)]
+    pub fn maxs(&self) -> Vec<f64> {
+        self.numbers(&self.maxs)
+    }
+}
+
+impl DecimalAggregates {
+    fn approximate(&self, value: i128) -> f64 {
+        value as f64 / 10f64.powi(self.scale)
+    }
+
+    fn strings(&self, values: &[Option<i128>]) -> Vec<String> {
+        values
+            .iter()
+            .map(|value| value.map_or_else(String::new, |value| format(value, self.scale)))
+            .collect()
+    }
+
+    fn numbers(&self, values: &[Option<i128>]) -> Vec<f64> {
+        values
+            .iter()
+            .map(|value| value.map_or(f64::NAN, |value| self.approximate(value)))
+            .collect()
+    }
+}
+
+/// Folds `value(row)` into per-bin aggregates keyed by `bins[row]`.
+fn aggregate(
+    bins: &[u16],
+    bin_count: u32,
+    scale: i32,
+    result_scale: u32,
+    value: impl Fn(usize) -> Option<i128>,
+) -> Result<DecimalAggregates, KernelError> {
+    if bin_count == 0 || bin_count > u32::from(u16::MAX) + 1 {
+        return Err(KernelError::bad_bin_count(bin_count));
+    }
+    if result_scale > MAX_SCALE {
+        return Err(
+            KernelError::invalid_argument("resultScale must be at most 38")
+                .with("resultScale", f64::from(result_scale)),
+        );
+    }
+    let bins_len = bin_count as usize;
+    let mut counts = vec![0u32; bins_len];
+    let mut sums = vec![0i128; bins_len];
+    let mut mins = vec![None; bins_len];
+    let mut maxs = vec![None; bins_len];
+    for (row, &bin) in bins.iter().enumerate() {
+        let bin = bin as usize;
+        let Some(value) = value(row) else { continue };
+        if bin >= bins_len {
+            continue;
+        }
+        counts[bin] += 1;
+        sums[bin] = sums[bin]
+            .checked_add(value)
+            .ok_or_else(|| overflow("sum"))?;
+        mins[bin] = Some(mins[bin].map_or(value, |min: i128| min.min(value)));
+        maxs[bin] = Some(maxs[bin].map_or(value, |max: i128| max.max(value)));
+    }
+
+    let to = result_scale as i32;
+    let rescale_all = |values: Vec<Option<i128>>| {
+        values
+            .into_iter()
+            .map(|value| value.map(|value| rescale(value, scale, to)).transpose())
+            .collect::<Result<Vec<_>, _>>()
+    };
+    Ok(DecimalAggregates {
+        scale: to,
+        counts,
+        sums: sums
+            .into_iter()
+            .map(|sum| rescale(sum, scale, to))
+            .collect::<Result<_, _>>()?,
+        mins: rescale_all(mins)?,
+        maxs: rescale_all(maxs)?,
+    })
+}
+
+/// Aggregates a raw Arrow `Decimal128` buffer (16-byte little-endian
+/// values at `scale`) by `bins`, one bin index per row. `offset` is the
+/// Arrow `Data` offset.
+#[wasm_bindgen(js_name = aggregateDecimal128)]
+#[allow(clippy::too_many_arguments)]
+pub fn aggregate_decimal128(
+    values: &[u8],
+    validity: Option<Vec<u8>>,
+    offset: u32,
+    scale: i32,
+    bins: &[u16],
+    bin_count: u32,
+    result_scale: u32,
+) -> Result<DecimalAggregates, KernelError> {
+    let offset = offset as usize;
+    // Unchecked, a bad offset wraps past the length check on wasm32.
+    let end = offset.checked_add(bins.len());
+    let Some(needed) = end.and_then(|end| end.checked_mul(16)) else {
+        return Err(KernelError::invalid_argument("arrow slice is out of range")
+            .with("offset", offset as f64)
+            .with("rows", bins.len() as f64));
+    };
+    if values.len() < needed {
+        return Err(
+            KernelError::invalid_argument("arrow values buffer is too short")
+                .with("needed", needed as f64)
+                .with("available", values.len() as f64),
+        );
+    }
+    if validity
+        .as_ref()
+        .is_some_and(|bits| bits.len() < (needed / 16).div_ceil(8))
+    {
+        return Err(KernelError::invalid_argument(
+            "arrow validity buffer is too short",
+        ));
+    }
+    aggregate(bins, bin_count, scale, result_scale, |row| {
+        let row = offset + row;
+        if !validity.as_ref().is_none_or(|bits| bit(bits, row)) {
+            return None;
+        }
+        let raw = values[row * 16..row * 16 + 16]
+            .try_into()
+            .expect("16 bytes");
+        Some(i128::from_le_bytes(raw))
+    })
+}
+
+/// Aggregates a `decimal128` column from the column store by `bins`.
+#[wasm_bindgen(js_name = aggregateDecimalColumn)]
+pub fn aggregate_decimal_column(
+    handle: u32,
+    bins: &[u16],
+    bin_count: u32,
+    result_scale: u32,
+) -> Result<DecimalAggregates, KernelError> {
+    columns::with_column(handle, |column| {
+        let Values::Decimal128 { values, scale } = &column.values else {
+            return Err(KernelError::new(
+                ErrorKind::Unsupported,
+                "aggregateDecimalColumn requires a decimal128 column",
+            ));
+        };
+        if bins.len() > column.len {
+            return Err(KernelError::invalid_argument("more bins than column rows")
+                .with("bins", bins.len() as f64)
+                .with("rows", column.len as f64));
+        }
+        aggregate(bins, bin_count, *scale, result_scale, |row| {
+            column.is_valid(row).then(|| values[row])
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimals(values: &[i128]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn arrow_decimals_aggregate_from_their_offset() {
+        let values = decimals(&[999, 125, 250, 5]);
+        let result = aggregate_decimal128(&values, None, 1, 2, &[0, 1, 0], 2, 2).unwrap();
+        assert_eq!(result.counts(), [2, 1]);
+        assert_eq!(result.sum_strings(), ["1.30", "2.50"]);
+    }
+
+    #[test]
+    fn offsets_past_usize_are_rejected() {
+        let values = decimals(&[1]);
+        let bins = [0u16; 4];
+        for offset in [u32::MAX, u32::MAX - 2] {
+            let error = aggregate_decimal128(&values, None, offset, 0, &bins, 1, 0).err();
+            assert_eq!(error.unwrap().code(), ErrorKind::InvalidArgument as u32);
+        }
+        assert!(aggregate_decimal128(&values, Some(Vec::new()), 0, 0, &bins[..1], 1, 0).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! Sparse changed-bin output.
+//!
+//! After a filter toggle most bins keep their value, so instead of dense
+//! arrays these kernels return `(bin, delta)` pairs for the bins that
+//! changed, letting the TS layer patch chart data in place rather than diff
+//! whole arrays.
+//!
+//! The same shape serves as the sparse output mode of the histogram
+//! kernels: wide, mostly empty histograms cost more to copy out and scan
+//! as dense `Uint32Array`s than to accumulate, so the `Sparse` variants of
+//! `accumulateBins`, `accumulateScratch` and `accumulateBatch` return the
+//! nonzero bins only, each count in `countDeltas`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+
+/// Changed bins, ascending, with their count and sum deltas aligned.
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct BinChanges {
+    pub(crate) bins: Vec<u32>,
+    pub(crate) count_deltas: Vec<i32>,
+    pub(crate) sum_deltas: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl BinChanges {
+    #[wasm_bindgen(getter)]
+    pub fn bins(&self) -> Vec<u32> {
+        self.bins.clone()
+    }
+
+    #[wasm_bindgen(getter = countDeltas)]
+    pub fn count_deltas(&self) -> Vec<i32> {
+        self.count_deltas.clone()
+    }
+
+    /// Sum deltas aligned with `bins`; empty when the group has no measure.
+    #[wasm_bindgen(getter = sumDeltas)]
+    pub fn sum_deltas(&self) -> Vec<f64> {
+        self.sum_deltas.clone()
+    }
+}
+
+/// The bins of `counts` that received rows, each with its count as delta.
+fn nonzero(counts: &[u32]) -> BinChanges {
+    let mut changes = BinChanges::default();
+    for (bin, &count) in counts.iter().enumerate() {
+        if count > 0 {
+            changes.bins.push(bin as u32);
+            changes.count_deltas.push(count as i32);
+        }
+    }
+    changes
+}
+
+/// `accumulateBins` returning only the bins that received rows, each with
+/// its row count as the delta (the caller applies the sign for activations
+/// or deactivations).
+#[wasm_bindgen(js_name = accumulateBinsSparse)]
+pub fn accumulate_bins_sparse(
+    bins: &[u16],
+    bin_count: u32,
+    dimension: Option<u32>,
+) -> Result<BinChanges, KernelError> {
+    crate::accumulate_bins_with("accumulateBinsSparse", bins, bin_count, dimension, nonzero)
+}
+
+/// `accumulateScratch` returning only the bins that received rows, as
+/// `accumulateBinsSparse` does.
+#[wasm_bindgen(js_name = accumulateScratchSparse)]
+pub fn accumulate_scratch_sparse(
+    len: u32,
+    bin_count: u32,
+    dimension: Option<u32>,
+) -> Result<BinChanges, KernelError> {
+    crate::accumulate_scratch_with(
+        "accumulateScratchSparse",
+        len as usize,
+        bin_count,
+        dimension,
+        nonzero,
+    )
+}
+
+/// `accumulateBatch` with a `BinChanges` of the nonzero bins per request
+/// in place of each `Uint32Array`.
+#[wasm_bindgen(js_name = accumulateBatchSparse)]
+pub fn accumulate_batch_sparse(requests: &[u32]) -> Result<js_sys::Array, KernelError> {
+    crate::accumulate_batch_with("accumulateBatchSparse", requests, |counts| {
+        nonzero(counts).into()
+    })
+}
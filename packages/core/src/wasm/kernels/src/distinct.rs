@@ -0,0 +1,91 @@
+//! Distinct-row masks.
+//!
+//! Marks the first row of every distinct combination of key columns so
+//! duplicate events can be dropped from all groups by and-ing the result
+//! into the active mask, without deduplicating the dataset in JS. Each row's
+//! key is encoded as bytes and looked up in a hash set; the encoding is
+//! exact (64-bit integers and decimals are not squeezed through `f64`), so
+//! distinct keys never collide.
+//!
+//! Nulls equal each other, as do NaNs; `-0` equals `0`.
+
+use std::collections::HashSet;
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, Column, Values};
+use crate::error::KernelError;
+
+/// Appends the key bytes of `row` in `column` to `key`.
+pub(crate) fn encode(column: &Column, row: usize, key: &mut Vec<u8>) {
+    if !column.is_valid(row) {
+        key.push(0);
+        return;
+    }
+    key.push(1);
+    match &column.values {
+        Values::Int64(values) => key.extend_from_slice(&values[row].to_le_bytes()),
+        Values::UInt64(values) => key.extend_from_slice(&values[row].to_le_bytes()),
+        Values::Decimal128 { values, .. } => key.extend_from_slice(&values[row].to_le_bytes()),
+        values => match values.label(row) {
+            Some(label) => {
+                key.extend_from_slice(&(label.len() as u32).to_le_bytes());
+                key.extend_from_slice(label);
+            }
+            None => {
+                let value = values.number(row).unwrap_or(f64::NAN);
+                let bits = if value.is_nan() {
+                    f64::NAN.to_bits()
+                } else if value == 0.0 {
+                    0
+                } else {
+                    value.to_bits()
+                };
+                key.extend_from_slice(&bits.to_le_bytes());
+            }
+        },
+    }
+}
+
+/// Key bytes of every row over the columns behind `handles`, which must be
+/// at least one and of equal lengths.
+pub(crate) fn row_keys(entry: &str, handles: &[u32]) -> Result<Vec<Vec<u8>>, KernelError> {
+    let Some(&first) = handles.first() else {
+        return Err(KernelError::invalid_argument(format!(
+            "{entry} needs at least one key"
+        )));
+    };
+    let len = columns::with_column(first, |column| Ok(column.len))?;
+    let mut keys = vec![Vec::new(); len];
+    for &handle in handles {
+        columns::with_column(handle, |column| {
+            if column.len != len {
+                return Err(KernelError::invalid_argument("key lengths differ")
+                    .with("expected", len as f64)
+                    .with("actual", column.len as f64));
+            }
+            for (row, key) in keys.iter_mut().enumerate() {
+                encode(column, row, key);
+            }
+            Ok(())
+        })?;
+    }
+    Ok(keys)
+}
+
+/// Returns an LSB-first row bitmap, in the layout's `activeMask` format,
+/// with the bit set for the first row of every distinct combination of the
+/// columns behind `handles` (equal lengths). And it into the active mask to
+/// count each distinct event once.
+#[wasm_bindgen(js_name = distinctRows)]
+pub fn distinct_rows(handles: &[u32]) -> Result<Vec<u8>, KernelError> {
+    let keys = row_keys("distinctRows", handles)?;
+    let len = keys.len();
+    let mut mask = vec![0u8; len.div_ceil(8)];
+    let mut seen = HashSet::with_capacity(len);
+    for (row, key) in keys.into_iter().enumerate() {
+        if seen.insert(key) {
+            mask[row >> 3] |= 1 << (row & 7);
+        }
+    }
+    Ok(mask)
+}
@@ -0,0 +1,288 @@
+//! Parquet-style integer encodings.
+//!
+//! The RLE/bit-packed hybrid and delta-binary-packed decoders, shared by the
+//! Parquet page reader and exported on their own because our wire format
+//! uses the same encodings for timestamp and id columns. Both follow the
+//! Parquet specification byte for byte, including its little-endian,
+//! LSB-first bit packing.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{ErrorKind, KernelError};
+use crate::memory;
+
+fn malformed(what: &str) -> KernelError {
+    KernelError::new(
+        ErrorKind::MalformedInput,
+        format!("malformed encoded integers: {what}"),
+    )
+}
+
+/// Reads an unsigned LEB128 varint at `*pos`, advancing past it.
+fn read_uleb128(bytes: &[u8], pos: &mut usize) -> Result<u64, KernelError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes
+            .get(*pos)
+            .ok_or_else(|| malformed("truncated varint"))?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(malformed("varint too long"))
+}
+
+fn read_zigzag(bytes: &[u8], pos: &mut usize) -> Result<i64, KernelError> {
+    let raw = read_uleb128(bytes, pos)?;
+    Ok((raw >> 1) as i64 ^ -((raw & 1) as i64))
+}
+
+/// Reserves room in `out` for `declared` more values, a count read from
+/// untrusted input: the budget is checked for all of them, but at most
+/// `encodable` (what the remaining input could hold bit-packed) are
+/// reserved up front, so a lying header cannot trigger a huge allocation.
+fn reserve<T>(out: &mut Vec<T>, declared: usize, encodable: usize) -> Result<(), KernelError> {
+    memory::check_budget(declared.saturating_mul(std::mem::size_of::<T>()))?;
+    out.try_reserve(declared.min(encodable))
+        .map_err(|_| KernelError::new(ErrorKind::OutOfMemory, "cannot reserve decoded values"))
+}
+
+/// Value `index` of a run packed LSB first at `bit_width` (at most 64) bits;
+/// the caller guarantees the run covers it.
+fn unpack(packed: &[u8], index: usize, bit_width: u32) -> u64 {
+    let start = index * bit_width as usize;
+    let mut window = 0u128;
+    let bytes = packed[start / 8..].iter().take(9);
+    for (shift, byte) in bytes.enumerate() {
+        window |= u128::from(*byte) << (shift * 8);
+    }
+    let mask = (1u128 << bit_width) - 1;
+    ((window >> (start % 8)) & mask) as u64
+}
+
+/// Decodes `count` values of the RLE/bit-packed hybrid encoding, appending
+/// them to `out`. Returns the number of bytes consumed.
+pub(crate) fn decode_hybrid(
+    bytes: &[u8],
+    bit_width: u32,
+    count: usize,
+    out: &mut Vec<u32>,
+) -> Result<usize, KernelError> {
+    if bit_width > 32 {
+        return Err(malformed("bit width above 32"));
+    }
+    let value_bytes = bit_width.div_ceil(8) as usize;
+    let target = out
+        .len()
+        .checked_add(count)
+        .ok_or_else(|| malformed("value count too large"))?;
+    reserve(out, count, bytes.len().saturating_mul(8))?;
+    let mut pos = 0;
+    while out.len() < target {
+        let header = read_uleb128(bytes, &mut pos)?;
+        if header & 1 == 0 {
+            let run = usize::try_from(header >> 1).unwrap_or(usize::MAX);
+            let raw = bytes
+                .get(pos..)
+                .and_then(|rest| rest.get(..value_bytes))
+                .ok_or_else(|| malformed("truncated rle run"))?;
+            let value = raw
+                .iter()
+                .rev()
+                .fold(0u32, |acc, &byte| (acc << 8) | u32::from(byte));
+            out.extend(std::iter::repeat_n(value, run.min(target - out.len())));
+            pos += value_bytes;
+        } else {
+            let groups =
+                usize::try_from(header >> 1).map_err(|_| malformed("bit-packed run too long"))?;
+            // A group of 8 values takes `bit_width` bytes.
+            let len = groups
+                .checked_mul(bit_width as usize)
+                .ok_or_else(|| malformed("bit-packed run too long"))?;
+            let values = groups.saturating_mul(8);
+            let packed = bytes
+                .get(pos..)
+                .and_then(|rest| rest.get(..len))
+                .ok_or_else(|| malformed("truncated bit-packed run"))?;
+            let take = values.min(target - out.len());
+            out.extend((0..take).map(|index| unpack(packed, index, bit_width) as u32));
+            pos += len;
+        }
+    }
+    Ok(pos)
+}
+
+/// Decodes a delta-binary-packed stream (header, then blocks of
+/// miniblocks), appending its values to `out`. Arithmetic wraps as the
+/// specification requires, so 32-bit streams decode correctly when
+/// truncated by the caller. Returns the number of bytes consumed.
+pub(crate) fn decode_delta_binary_packed(
+    bytes: &[u8],
+    out: &mut Vec<i64>,
+) -> Result<usize, KernelError> {
+    let mut pos = 0;
+    let mut header = || {
+        let value = read_uleb128(bytes, &mut pos)?;
+        usize::try_from(value).map_err(|_| malformed("header field too large"))
+    };
+    let (block_size, miniblocks, total) = (header()?, header()?, header()?);
+    let mut value = read_zigzag(bytes, &mut pos)?;
+    if block_size == 0
+        || miniblocks == 0
+        || !block_size.is_multiple_of(128)
+        || !(block_size / miniblocks).is_multiple_of(32)
+    {
+        return Err(malformed("invalid delta block layout"));
+    }
+    let per_miniblock = block_size / miniblocks;
+    if total == 0 {
+        return Ok(pos);
+    }
+    // Every block of `block_size` values takes at least its minimum delta
+    // and one width byte per miniblock.
+    let encodable = (bytes.len() - pos)
+        .saturating_div(miniblocks + 1)
+        .saturating_mul(block_size)
+        .saturating_add(1);
+    reserve(out, total, encodable)?;
+    out.push(value);
+    let mut remaining = total - 1;
+    while remaining > 0 {
+        let min_delta = read_zigzag(bytes, &mut pos)?;
+        let widths = bytes
+            .get(pos..)
+            .and_then(|rest| rest.get(..miniblocks))
+            .ok_or_else(|| malformed("truncated miniblock widths"))?;
+        pos += miniblocks;
+        for &width in widths {
+            if remaining == 0 {
+                break;
+            }
+            let width = u32::from(width);
+            if width > 64 {
+                return Err(malformed("bit width above 64"));
+            }
+            let len = per_miniblock
+                .checked_mul(width as usize)
+                .ok_or_else(|| malformed("miniblock too long"))?
+                / 8;
+            let packed = bytes
+                .get(pos..)
+                .and_then(|rest| rest.get(..len))
+                .ok_or_else(|| malformed("truncated miniblock"))?;
+            let take = per_miniblock.min(remaining);
+            for index in 0..take {
+                let delta = min_delta.wrapping_add(unpack(packed, index, width) as i64);
+                value = value.wrapping_add(delta);
+                out.push(value);
+            }
+            remaining -= take;
+            pos += len;
+        }
+    }
+    Ok(pos)
+}
+
+/// Decodes `count` values of the RLE/bit-packed hybrid encoding (no length
+/// prefix) at `bitWidth` bits each, up to 32.
+#[wasm_bindgen(js_name = decodeRleHybrid)]
+pub fn decode_rle_hybrid(
+    bytes: &[u8],
+    bit_width: u32,
+    count: u32,
+) -> Result<Vec<u32>, KernelError> {
+    let mut out = Vec::new();
+    decode_hybrid(bytes, bit_width, count as usize, &mut out)?;
+    Ok(out)
+}
+
+/// Decodes a delta-binary-packed stream into a `BigInt64Array`.
+#[wasm_bindgen(js_name = decodeDeltaBinaryPacked)]
+pub fn decode_delta_binary_packed_values(bytes: &[u8]) -> Result<Vec<i64>, KernelError> {
+    let mut out = Vec::new();
+    decode_delta_binary_packed(bytes, &mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hybrid_decodes_bit_packed_and_rle_runs() {
+        // The specification's example: 0..=7 bit-packed at 3 bits, then a
+        // run of five 7s.
+        let bytes = [0x03, 0x88, 0xc6, 0xfa, 0x0a, 0x07];
+        let values = decode_rle_hybrid(&bytes, 3, 13).unwrap();
+        assert_eq!(values, [0, 1, 2, 3, 4, 5, 6, 7, 7, 7, 7, 7, 7]);
+    }
+
+    #[test]
+    fn hybrid_stops_at_count_inside_a_run() {
+        let mut out = Vec::new();
+        let used = decode_hybrid(&[0x0a, 0x07, 0xff], 3, 2, &mut out).unwrap();
+        assert_eq!(out, [7, 7]);
+        assert_eq!(used, 2);
+    }
+
+    #[test]
+    fn hybrid_rejects_truncated_input() {
+        assert!(decode_rle_hybrid(&[], 3, 1).is_err());
+        assert!(decode_rle_hybrid(&[0x80], 3, 1).is_err());
+        assert!(decode_rle_hybrid(&[0x03, 0x88], 3, 8).is_err());
+        assert!(decode_rle_hybrid(&[0x0a], 16, 1).is_err());
+    }
+
+    #[test]
+    fn hybrid_oversized_count_fails_without_allocating_it() {
+        let error = decode_rle_hybrid(&[0x03, 0x88, 0xc6, 0xfa], 3, u32::MAX).unwrap_err();
+        assert_eq!(error.code(), ErrorKind::MalformedInput as u32);
+        // A bit-packed header claiming more groups than addressable.
+        let huge = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert!(decode_rle_hybrid(&huge, 8, 16).is_err());
+    }
+
+    #[test]
+    fn delta_decodes_the_specification_examples() {
+        // 1, 2, 3, 4, 5: every delta equals the minimum, so widths are 0.
+        let bytes = [0x80, 0x01, 0x04, 0x05, 0x02, 0x02, 0x00, 0x00, 0x00, 0x00];
+        assert_eq!(
+            decode_delta_binary_packed_values(&bytes).unwrap(),
+            [1, 2, 3, 4, 5]
+        );
+        // 7, 5, 3, 1, 2, 3, 4, 5: min delta -2, deltas 0,0,0,3,3,3,3 at 2 bits.
+        let mut bytes = vec![0x80, 0x01, 0x04, 0x08, 0x0e, 0x03, 0x02, 0x00, 0x00, 0x00];
+        let mut packed = [0u8; 8];
+        for (index, delta) in [0u8, 0, 0, 3, 3, 3, 3].into_iter().enumerate() {
+            packed[index * 2 / 8] |= delta << (index * 2 % 8);
+        }
+        bytes.extend_from_slice(&packed);
+        assert_eq!(
+            decode_delta_binary_packed_values(&bytes).unwrap(),
+            [7, 5, 3, 1, 2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn delta_rejects_truncated_and_malformed_headers() {
+        for len in 0..4 {
+            assert!(decode_delta_binary_packed_values(&[0x80, 0x01, 0x04, 0x05][..len]).is_err());
+        }
+        // Block size not a multiple of 128.
+        assert!(decode_delta_binary_packed_values(&[0x40, 0x04, 0x01, 0x00]).is_err());
+        // Body missing after the header.
+        assert!(decode_delta_binary_packed_values(&[0x80, 0x01, 0x04, 0x05, 0x02]).is_err());
+    }
+
+    #[test]
+    fn delta_oversized_total_fails_without_allocating_it() {
+        // Total of 2^62 values followed by a single block.
+        let mut bytes = vec![0x80, 0x01, 0x04];
+        bytes.extend_from_slice(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x40]);
+        bytes.extend_from_slice(&[0x02, 0x02, 0x00, 0x00, 0x00, 0x00]);
+        let error = decode_delta_binary_packed_values(&bytes).unwrap_err();
+        assert_ne!(error.code(), ErrorKind::OutOfMemory as u32);
+    }
+}
@@ -0,0 +1,178 @@
+//! Structured errors returned by the exported kernels.
+//!
+//! Every fallible entry point rejects with a [`KernelError`] rather than a
+//! bare string. It reaches JS as an instance of the `KernelError` subclass
+//! of `Error`, with a stack trace and the fields `kind` (and its alias
+//! `code`), `kernel` (the entry point, where it names itself), `location`
+//! (the source line that raised it) and `context` (a plain object of numeric
+//! fields such as lengths and bin counts). Telemetry can aggregate on those
+//! without parsing messages.
+
+use std::fmt;
+use std::panic::Location;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(inline_js = "
+export class KernelError extends Error {
+  constructor(message, kind, kernel, location, context) {
+    super(message);
+    this.name = 'KernelError';
+    this.kind = kind;
+    this.code = kind;
+    this.kernel = kernel;
+    this.location = location;
+    this.context = context;
+  }
+}
+
+export function kernelError(message, kind, kernel, location, context) {
+  return new KernelError(message, kind, kernel, location, context);
+}
+")]
+extern "C" {
+    #[wasm_bindgen(js_name = kernelError)]
+    fn kernel_error(
+        message: &str,
+        kind: ErrorKind,
+        kernel: Option<String>,
+        location: String,
+        context: JsValue,
+    ) -> JsValue;
+}
+
+/// Stable numeric error codes. Values are part of the JS contract; append new
+/// kinds rather than renumbering.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// `binCount` was zero or otherwise unusable for the requested kernel.
+    BadBinCount = 1,
+    /// A length or range extends past the scratch buffer.
+    ScratchOverflow = 2,
+    /// Reserved for abandoning a long-running operation at the caller's
+    /// request. No kernel returns it yet; the code is kept so the numbering
+    /// stays stable.
+    Cancelled = 3,
+    /// Malformed arguments, e.g. a descriptor list with the wrong stride.
+    InvalidArgument = 4,
+    /// The call is not valid in the current module state.
+    InvalidState = 5,
+    /// Encoded input (IPC stream, page, buffer) is truncated or inconsistent.
+    MalformedInput = 6,
+    /// The input is well-formed but uses a feature the kernels do not handle.
+    Unsupported = 7,
+    /// Exact arithmetic (decimal sums, rescaling, checked bin counts) left
+    /// its integer range.
+    Overflow = 8,
+    /// Linear memory could not grow to fit an allocation, or the allocation
+    /// would exceed the memory budget.
+    OutOfMemory = 9,
+}
+
+#[derive(Clone, Debug)]
+pub struct KernelError {
+    kind: ErrorKind,
+    message: String,
+    kernel: Option<&'static str>,
+    location: &'static Location<'static>,
+    context: Vec<(&'static str, f64)>,
+}
+
+impl KernelError {
+    #[track_caller]
+    pub(crate) fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        KernelError {
+            kind,
+            message: message.into(),
+            kernel: None,
+            location: Location::caller(),
+            context: Vec::new(),
+        }
+    }
+
+    /// Names the entry point that failed, e.g. `"accumulateBins"`.
+    pub(crate) fn in_kernel(mut self, kernel: &'static str) -> Self {
+        self.kernel = Some(kernel);
+        self
+    }
+
+    /// Attaches a numeric context field.
+    pub(crate) fn with(mut self, key: &'static str, value: f64) -> Self {
+        self.context.push((key, value));
+        self
+    }
+
+    #[track_caller]
+    pub(crate) fn bad_bin_count(bin_count: u32) -> Self {
+        KernelError::new(
+            ErrorKind::BadBinCount,
+            "bin_count must be greater than zero",
+        )
+        .with("binCount", f64::from(bin_count))
+    }
+
+    #[track_caller]
+    pub(crate) fn scratch_overflow(requested: usize, available: usize) -> Self {
+        KernelError::new(ErrorKind::ScratchOverflow, "scratch length exceeded")
+            .with("requested", requested as f64)
+            .with("available", available as f64)
+    }
+
+    #[track_caller]
+    pub(crate) fn invalid_argument(message: impl Into<String>) -> Self {
+        KernelError::new(ErrorKind::InvalidArgument, message)
+    }
+
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    #[track_caller]
+    pub(crate) fn invalid_state(message: impl Into<String>) -> Self {
+        KernelError::new(ErrorKind::InvalidState, message)
+    }
+}
+
+impl KernelError {
+    pub(crate) fn code(&self) -> u32 {
+        self.kind as u32
+    }
+
+    pub(crate) fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    /// Plain object of numeric context fields, e.g. `{ requested, available }`.
+    fn context(&self) -> JsValue {
+        use js_sys::{Object, Reflect};
+        let object = Object::new();
+        for &(key, value) in &self.context {
+            let _ = Reflect::set(&object, &JsValue::from_str(key), &JsValue::from_f64(value));
+        }
+        object.into()
+    }
+}
+
+impl From<KernelError> for JsValue {
+    fn from(error: KernelError) -> JsValue {
+        kernel_error(
+            &error.message,
+            error.kind,
+            error.kernel.map(str::to_string),
+            format!("{}:{}", error.location.file(), error.location.line()),
+            error.context(),
+        )
+    }
+}
+
+impl fmt::Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(kernel) = self.kernel {
+            write!(f, "{kernel}: ")?;
+        }
+        write!(f, "{:?}: {}", self.kind, self.message)?;
+        for (key, value) in &self.context {
+            write!(f, " {key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for KernelError {}
@@ -0,0 +1,92 @@
+//! Exponentially weighted moving averages.
+//!
+//! Trend lines for monitoring views: each value pulls the average toward
+//! itself by `alpha`, and the first value seeds it (pandas'
+//! `ewm(adjust=False)`). Over irregular times the pull scales with elapsed
+//! time, `1 - (1 - alpha)^(dt / interval)`, so a point after a long silence
+//! counts for more than one right behind the last.
+//!
+//! Missing values (NaN) are handled per `EwmaGap`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::rolling::check_times;
+
+/// What a missing value does to the average and the output.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EwmaGap {
+    /// Outputs NaN; the average carries on past the gap.
+    Skip = 0,
+    /// Outputs the average so far, drawing the line through the gap.
+    Hold = 1,
+    /// Outputs NaN and restarts the average at the next value.
+    Reset = 2,
+}
+
+/// Smooths `values`; `alpha_after(at, last)` is the weight of the value at
+/// `at` when the average last took in the value at `last`.
+fn smooth(values: &[f64], gap: EwmaGap, alpha_after: impl Fn(usize, usize) -> f64) -> Vec<f64> {
+    let mut out = Vec::with_capacity(values.len());
+    // The average and the position of the value it last took in.
+    let mut state: Option<(f64, usize)> = None;
+    for (at, &value) in values.iter().enumerate() {
+        if value.is_nan() {
+            out.push(match gap {
+                EwmaGap::Hold => state.map_or(f64::NAN, |(average, _)| average),
+                EwmaGap::Skip => f64::NAN,
+                EwmaGap::Reset => {
+                    state = None;
+                    f64::NAN
+                }
+            });
+            continue;
+        }
+        let average = match state {
+            Some((average, last)) => average + alpha_after(at, last) * (value - average),
+            None => value,
+        };
+        state = Some((average, at));
+        out.push(average);
+    }
+    out
+}
+
+fn check_alpha(alpha: f64) -> Result<(), KernelError> {
+    if !(alpha > 0.0 && alpha <= 1.0) {
+        return Err(KernelError::invalid_argument("alpha must be in (0, 1]").with("alpha", alpha));
+    }
+    Ok(())
+}
+
+/// EWMA of evenly spaced `values` with smoothing factor `alpha`.
+#[wasm_bindgen(js_name = ewmaByCount)]
+pub fn ewma_by_count(values: &[f64], alpha: f64, gap: EwmaGap) -> Result<Vec<f64>, KernelError> {
+    check_alpha(alpha)?;
+    Ok(smooth(values, gap, |_, _| alpha))
+}
+
+/// EWMA of `values` at ascending `times` (numbers or epoch milliseconds),
+/// where `alpha` is the weight of a value arriving `interval` after the
+/// previous one.
+#[wasm_bindgen(js_name = ewmaByTime)]
+pub fn ewma_by_time(
+    times: &[f64],
+    values: &[f64],
+    alpha: f64,
+    interval: f64,
+    gap: EwmaGap,
+) -> Result<Vec<f64>, KernelError> {
+    check_alpha(alpha)?;
+    if interval.is_nan() || interval <= 0.0 {
+        return Err(
+            KernelError::invalid_argument("interval must be positive").with("interval", interval)
+        );
+    }
+    check_times("ewmaByTime", times, values)?;
+    let keep = 1.0 - alpha;
+    Ok(smooth(values, gap, |at, last| {
+        1.0 - keep.powf((times[at] - times[last]) / interval)
+    }))
+}
@@ -0,0 +1,358 @@
+//! Column extents.
+//!
+//! Axis scales need each dimension's `[min, max]`, which ingestion used to
+//! find with a JS reduce over the freshly decoded values. These kernels scan
+//! the values inside wasm instead, two `f64` or four `f32` lanes per step
+//! where `simd128` is available and parity is off. Nulls and NaNs are
+//! skipped; a validity bitmap is honoured a byte at a time, so runs of
+//! eight valid rows still take the vector path.
+
+use wasm_bindgen::prelude::*;
+
+#[cfg(target_feature = "simd128")]
+use std::arch::wasm32::{
+    f32x4_eq, f32x4_extract_lane, f32x4_pmax, f32x4_pmin, f32x4_splat, f64x2_eq,
+    f64x2_extract_lane, f64x2_pmax, f64x2_pmin, f64x2_splat, i32x4_extract_lane, i32x4_splat,
+    i32x4_sub, i64x2_extract_lane, i64x2_splat, i64x2_sub, v128, v128_load,
+};
+
+use crate::columns::{self, bit, Values};
+use crate::error::{ErrorKind, KernelError};
+
+/// Smallest and largest value of a scan, and how many values it saw.
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub struct Extent {
+    min: f64,
+    max: f64,
+    count: u32,
+}
+
+#[wasm_bindgen]
+impl Extent {
+    /// NaN when no value was seen.
+    #[wasm_bindgen(getter)]
+    pub fn min(&self) -> f64 {
+        if self.count == 0 {
+            f64::NAN
+        } else {
+            self.min
+        }
+    }
+
+    /// NaN when no value was seen.
+    #[wasm_bindgen(getter)]
+    pub fn max(&self) -> f64 {
+        if self.count == 0 {
+            f64::NAN
+        } else {
+            self.max
+        }
+    }
+
+    /// Values that were neither null nor NaN.
+    #[wasm_bindgen(getter)]
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+}
+
+impl Extent {
+    const EMPTY: Extent = Extent {
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+        count: 0,
+    };
+
+    fn push(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        if value < self.min {
+            self.min = value;
+        }
+        if value > self.max {
+            self.max = value;
+        }
+        self.count += 1;
+    }
+
+    fn merge(&mut self, other: Extent) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+    }
+}
+
+/// Extent of `values` with no nulls.
+fn dense_scalar<T: Copy + Into<f64>>(values: &[T]) -> Extent {
+    let mut extent = Extent::EMPTY;
+    for &value in values {
+        extent.push(value.into());
+    }
+    extent
+}
+
+#[cfg(target_feature = "simd128")]
+fn dense_f64(values: &[f64]) -> Extent {
+    if !crate::parity::simd_allowed() {
+        return dense_scalar(values);
+    }
+    const LANES: usize = 2;
+    let whole = values.len() - values.len() % LANES;
+    let mut low = f64x2_splat(f64::INFINITY);
+    let mut high = f64x2_splat(f64::NEG_INFINITY);
+    let mut seen = i64x2_splat(0);
+    // SAFETY: every load stays within the first `whole` elements. `pmin`
+    // and `pmax` keep the running lane when the new one is NaN, and the
+    // all-ones `eq` mask of a non-NaN lane subtracts as one.
+    unsafe {
+        for index in (0..whole).step_by(LANES) {
+            let lanes = v128_load(values.as_ptr().add(index) as *const v128);
+            low = f64x2_pmin(low, lanes);
+            high = f64x2_pmax(high, lanes);
+            seen = i64x2_sub(seen, f64x2_eq(lanes, lanes));
+        }
+    }
+    let mut extent = Extent {
+        min: f64x2_extract_lane::<0>(low).min(f64x2_extract_lane::<1>(low)),
+        max: f64x2_extract_lane::<0>(high).max(f64x2_extract_lane::<1>(high)),
+        count: (i64x2_extract_lane::<0>(seen) + i64x2_extract_lane::<1>(seen)) as u32,
+    };
+    extent.merge(dense_scalar(&values[whole..]));
+    extent
+}
+
+#[cfg(not(target_feature = "simd128"))]
+fn dense_f64(values: &[f64]) -> Extent {
+    dense_scalar(values)
+}
+
+#[cfg(target_feature = "simd128")]
+fn dense_f32(values: &[f32]) -> Extent {
+    if !crate::parity::simd_allowed() {
+        return dense_scalar(values);
+    }
+    const LANES: usize = 4;
+    let whole = values.len() - values.len() % LANES;
+    let mut low = f32x4_splat(f32::INFINITY);
+    let mut high = f32x4_splat(f32::NEG_INFINITY);
+    let mut seen = i32x4_splat(0);
+    // SAFETY: as in `dense_f64`.
+    unsafe {
+        for index in (0..whole).step_by(LANES) {
+            let lanes = v128_load(values.as_ptr().add(index) as *const v128);
+            low = f32x4_pmin(low, lanes);
+            high = f32x4_pmax(high, lanes);
+            seen = i32x4_sub(seen, f32x4_eq(lanes, lanes));
+        }
+    }
+    let min = [
+        f32x4_extract_lane::<0>(low),
+        f32x4_extract_lane::<1>(low),
+        f32x4_extract_lane::<2>(low),
+        f32x4_extract_lane::<3>(low),
+    ];
+    let max = [
+        f32x4_extract_lane::<0>(high),
+        f32x4_extract_lane::<1>(high),
+        f32x4_extract_lane::<2>(high),
+        f32x4_extract_lane::<3>(high),
+    ];
+    let seen = [
+        i32x4_extract_lane::<0>(seen),
+        i32x4_extract_lane::<1>(seen),
+        i32x4_extract_lane::<2>(seen),
+        i32x4_extract_lane::<3>(seen),
+    ];
+    let mut extent = Extent {
+        min: f64::from(min.into_iter().fold(f32::INFINITY, f32::min)),
+        max: f64::from(max.into_iter().fold(f32::NEG_INFINITY, f32::max)),
+        count: seen.into_iter().map(|lane| lane as u32).sum(),
+    };
+    extent.merge(dense_scalar(&values[whole..]));
+    extent
+}
+
+#[cfg(not(target_feature = "simd128"))]
+fn dense_f32(values: &[f32]) -> Extent {
+    dense_scalar(values)
+}
+
+/// Extent of the rows of `values` set in `validity` (all rows when `None`),
+/// fully valid bytes of the bitmap going through `dense`.
+fn scan<T: Copy + Into<f64>>(
+    values: &[T],
+    validity: Option<&[u8]>,
+    dense: fn(&[T]) -> Extent,
+) -> Extent {
+    let Some(bits) = validity else {
+        return dense(values);
+    };
+    let mut extent = Extent::EMPTY;
+    for (byte, rows) in values.chunks(8).enumerate() {
+        match bits[byte] {
+            0 => {}
+            0xff if rows.len() == 8 => extent.merge(dense(rows)),
+            mask => {
+                for (offset, &value) in rows.iter().enumerate() {
+                    if mask & (1 << offset) != 0 {
+                        extent.push(value.into());
+                    }
+                }
+            }
+        }
+    }
+    extent
+}
+
+fn check_validity(validity: Option<&[u8]>, len: usize) -> Result<(), KernelError> {
+    match validity {
+        Some(bits) if bits.len() < len.div_ceil(8) => Err(KernelError::invalid_argument(
+            "validity bitmap is too short",
+        )
+        .with("needed", len.div_ceil(8) as f64)
+        .with("available", bits.len() as f64)),
+        _ => Ok(()),
+    }
+}
+
+/// `[min, max]` of a `Float64Array`, skipping NaNs and the rows cleared in
+/// `validity` (an LSB-first bitmap, as in Arrow).
+#[wasm_bindgen(js_name = extentF64)]
+pub fn extent_f64(values: &[f64], validity: Option<Vec<u8>>) -> Result<Extent, KernelError> {
+    check_validity(validity.as_deref(), values.len())?;
+    Ok(scan(values, validity.as_deref(), dense_f64))
+}
+
+/// `extentF64` for `Float32Array`.
+#[wasm_bindgen(js_name = extentF32)]
+pub fn extent_f32(values: &[f32], validity: Option<Vec<u8>>) -> Result<Extent, KernelError> {
+    check_validity(validity.as_deref(), values.len())?;
+    Ok(scan(values, validity.as_deref(), dense_f32))
+}
+
+/// `[min, max]` of the numeric column behind `handle`, skipping nulls and
+/// NaNs. Timestamps report epoch milliseconds.
+#[wasm_bindgen(js_name = columnExtent)]
+pub fn column_extent(handle: u32) -> Result<Extent, KernelError> {
+    columns::with_column(handle, |column| {
+        let validity = column.validity.as_deref();
+        Ok(match &column.values {
+            Values::Float64(values) | Values::Timestamp { millis: values, .. } => {
+                scan(values, validity, dense_f64)
+            }
+            Values::Float32(values) => scan(values, validity, dense_f32),
+            Values::Utf8 { .. } | Values::Dictionary { .. } => {
+                return Err(KernelError::new(
+                    ErrorKind::Unsupported,
+                    "columnExtent requires a numeric column",
+                ))
+            }
+            values => {
+                let mut extent = Extent::EMPTY;
+                for row in 0..column.len {
+                    if validity.is_none_or(|bits| bit(bits, row)) {
+                        extent.push(values.number(row).unwrap_or(f64::NAN));
+                    }
+                }
+                extent
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::Column;
+    use crate::parity::set_simd_parity;
+
+    /// Values with NaNs, infinities and signed zeros in every lane position.
+    fn values(len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|row| match row % 7 {
+                0 => f64::NAN,
+                1 => -0.0,
+                2 if row % 3 == 0 => f64::INFINITY,
+                _ => (row * 37 % 23) as f64 - 11.5,
+            })
+            .collect()
+    }
+
+    /// Row by row, without going through `scan`.
+    fn reference(values: &[f64], validity: Option<&[u8]>) -> (f64, f64, u32) {
+        let valid = values
+            .iter()
+            .enumerate()
+            .filter(|&(row, value)| validity.is_none_or(|bits| bit(bits, row)) && !value.is_nan())
+            .map(|(_, &value)| value);
+        valid.fold((f64::NAN, f64::NAN, 0), |(min, max, count), value| {
+            (value.min(min), value.max(max), count + 1)
+        })
+    }
+
+    fn triple(extent: Extent) -> (f64, f64, u32) {
+        (extent.min(), extent.max(), extent.count())
+    }
+
+    fn assert_same(actual: (f64, f64, u32), expected: (f64, f64, u32)) {
+        assert_eq!(actual.2, expected.2);
+        for (a, b) in [(actual.0, expected.0), (actual.1, expected.1)] {
+            assert!(
+                a == b || (a.is_nan() && b.is_nan()),
+                "{actual:?} != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn vector_and_scalar_paths_agree() {
+        for len in 0..=35 {
+            let values = values(len);
+            let narrow: Vec<f32> = values.iter().map(|&value| value as f32).collect();
+            let validity: Vec<u8> = (0..len.div_ceil(8))
+                .map(|byte| [0xff, 0x00, 0xa5][byte % 3])
+                .collect();
+            for parity in [false, true] {
+                set_simd_parity(parity);
+                for bits in [None, Some(validity.as_slice())] {
+                    let expected = reference(&values, bits);
+                    let owned = bits.map(<[u8]>::to_vec);
+                    assert_same(
+                        triple(extent_f64(&values, owned.clone()).unwrap()),
+                        expected,
+                    );
+                    assert_same(triple(extent_f32(&narrow, owned).unwrap()), expected);
+                }
+            }
+        }
+        set_simd_parity(false);
+    }
+
+    #[test]
+    fn short_validity_is_rejected() {
+        assert!(extent_f64(&[1.0; 9], Some(vec![0xff])).is_err());
+        let empty = extent_f32(&[], None).unwrap();
+        assert!(empty.min().is_nan() && empty.max().is_nan());
+        assert_eq!(empty.count(), 0);
+    }
+
+    #[test]
+    fn column_extents_skip_nulls() {
+        let ints = columns::register(Column {
+            name: "i".to_owned(),
+            len: 4,
+            values: Values::Int32(vec![5, -9, 2, 40]),
+            validity: Some(vec![0b0111]),
+        });
+        assert_eq!(triple(column_extent(ints).unwrap()), (-9.0, 5.0, 3));
+        let floats = columns::register(Column {
+            name: "f".to_owned(),
+            len: 3,
+            values: Values::Float64(vec![f64::NAN, 2.0, -1.0]),
+            validity: None,
+        });
+        assert_eq!(triple(column_extent(floats).unwrap()), (-1.0, 2.0, 2));
+    }
+}
@@ -0,0 +1,308 @@
+//! Fenwick trees for filtered range aggregates.
+//!
+//! A range tree lays a dimension's rows out in sorted-index order and keeps
+//! two Fenwick (binary indexed) trees over those positions: one counting
+//! active rows and one summing their weights. Turning a row on or off as
+//! other filters change costs `O(log n)`, and so does the count and sum of
+//! active rows in any value range, so a brush can be re-aggregated on every
+//! pointer move without scanning the dimension.
+//!
+//! Trees are built from the dimension's sorted index and do not follow
+//! later appends; rebuild after adding rows. Sums are kept in `f64` and
+//! accumulate rounding as rows toggle, which a rebuild also clears.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::{AddAssign, Sub};
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, bit};
+use crate::error::KernelError;
+use crate::recovery;
+use crate::sorted_index;
+
+/// Rows absent from the index (nulls, NaNs) have no position.
+const UNINDEXED: u32 = u32::MAX;
+
+/// Prefix sums over positions, updatable one position at a time.
+struct Fenwick<T> {
+    /// One-based: `tree[i]` covers positions `(i - lowbit(i), i]`.
+    tree: Vec<T>,
+}
+
+impl<T: Copy + Default + AddAssign + Sub<Output = T>> Fenwick<T> {
+    /// Builds the tree over `values` in `O(n)`.
+    fn new(values: impl IntoIterator<Item = T>) -> Self {
+        let mut tree = vec![T::default()];
+        tree.extend(values);
+        for index in 1..tree.len() {
+            let parent = index + (index & index.wrapping_neg());
+            if parent < tree.len() {
+                let value = tree[index];
+                tree[parent] += value;
+            }
+        }
+        Fenwick { tree }
+    }
+
+    fn add(&mut self, position: usize, delta: T) {
+        let mut index = position + 1;
+        while index < self.tree.len() {
+            self.tree[index] += delta;
+            index += index & index.wrapping_neg();
+        }
+    }
+
+    /// Sum over positions `[0, end)`.
+    fn prefix(&self, end: usize) -> T {
+        let mut total = T::default();
+        let mut index = end;
+        while index > 0 {
+            total += self.tree[index];
+            index &= index - 1;
+        }
+        total
+    }
+
+    /// Sum over positions `[start, end)`.
+    fn range(&self, start: usize, end: usize) -> T {
+        self.prefix(end) - self.prefix(start)
+    }
+}
+
+struct RangeTree {
+    /// Sorted-index values, to map value ranges onto positions.
+    values: Vec<f64>,
+    /// Index position of each row, or [`UNINDEXED`].
+    positions: Vec<u32>,
+    weights: Vec<f64>,
+    active: Vec<bool>,
+    counts: Fenwick<i64>,
+    sums: Fenwick<f64>,
+}
+
+thread_local! {
+    static TREES: RefCell<HashMap<u32, RangeTree>> = RefCell::new(HashMap::new());
+}
+
+/// Drops every range tree; see `hardReset`.
+pub(crate) fn reset() {
+    TREES.with(|trees| recovery::reset_cell(trees, HashMap::new()));
+}
+
+/// Builds (or rebuilds) the range tree of `dimension` from its sorted index
+/// (`buildSortedIndex`). `weights`, when given, is the handle of a numeric
+/// column with one value per row whose sum is tracked (nulls and NaNs weigh
+/// zero); without it every row weighs one. `mask`, an LSB-first row bitmap
+/// in the layout's `activeMask` format, sets which rows start active (all
+/// when omitted). Returns the number of indexed rows.
+#[wasm_bindgen(js_name = buildRangeTree)]
+pub fn build_range_tree(
+    dimension: u32,
+    weights: Option<u32>,
+    mask: Option<Vec<u8>>,
+) -> Result<u32, KernelError> {
+    let (values, rows) = sorted_index::with_index(dimension, |index| {
+        Ok((index.values.clone(), index.rows.clone()))
+    })?;
+    let row_count = rows.iter().map(|&row| row as usize + 1).max().unwrap_or(0);
+    if let Some(mask) = &mask {
+        if mask.len() < row_count.div_ceil(8) {
+            return Err(KernelError::invalid_argument("mask is too short")
+                .with("needed", row_count.div_ceil(8) as f64)
+                .with("available", mask.len() as f64));
+        }
+    }
+    let weights: Vec<f64> = match weights {
+        Some(handle) => columns::with_column(handle, |column| {
+            if column.len < row_count {
+                return Err(KernelError::invalid_argument("weight column is too short")
+                    .with("needed", row_count as f64)
+                    .with("available", column.len as f64));
+            }
+            Ok(rows
+                .iter()
+                .map(|&row| {
+                    let row = row as usize;
+                    match column.values.number(row) {
+                        Some(value) if column.is_valid(row) && !value.is_nan() => value,
+                        _ => 0.0,
+                    }
+                })
+                .collect())
+        })?,
+        None => vec![1.0; rows.len()],
+    };
+    let mut positions = vec![UNINDEXED; row_count];
+    for (position, &row) in rows.iter().enumerate() {
+        positions[row as usize] = position as u32;
+    }
+    let active: Vec<bool> = rows
+        .iter()
+        .map(|&row| mask.as_ref().is_none_or(|mask| bit(mask, row as usize)))
+        .collect();
+    let tree = RangeTree {
+        counts: Fenwick::new(active.iter().map(|&on| i64::from(on))),
+        sums: Fenwick::new(
+            active
+                .iter()
+                .zip(&weights)
+                .map(|(&on, &weight)| if on { weight } else { 0.0 }),
+        ),
+        values,
+        positions,
+        weights,
+        active,
+    };
+    let len = rows.len() as u32;
+    TREES.with(|trees| trees.borrow_mut().insert(dimension, tree));
+    Ok(len)
+}
+
+fn with_tree<T>(
+    dimension: u32,
+    run: impl FnOnce(&mut RangeTree) -> Result<T, KernelError>,
+) -> Result<T, KernelError> {
+    TREES.with(|trees| {
+        let mut trees = trees.borrow_mut();
+        let tree = trees.get_mut(&dimension).ok_or_else(|| {
+            KernelError::invalid_state("no range tree for dimension")
+                .with("dimension", f64::from(dimension))
+        })?;
+        run(tree)
+    })
+}
+
+/// Marks `rows` active (or inactive) in the range tree of `dimension`.
+/// Rows already in that state, and rows the index left out (nulls, NaNs,
+/// rows past the indexed ones), are skipped.
+/// Returns how many rows changed state.
+#[wasm_bindgen(js_name = updateRangeTree)]
+pub fn update_range_tree(dimension: u32, rows: &[u32], active: bool) -> Result<u32, KernelError> {
+    with_tree(dimension, |tree| {
+        let mut changed = 0;
+        for &row in rows {
+            let position = tree.positions.get(row as usize).copied();
+            let position = match position {
+                Some(position) if position != UNINDEXED => position as usize,
+                _ => continue,
+            };
+            if tree.active[position] == active {
+                continue;
+            }
+            tree.active[position] = active;
+            let weight = tree.weights[position];
+            if active {
+                tree.counts.add(position, 1);
+                tree.sums.add(position, weight);
+            } else {
+                tree.counts.add(position, -1);
+                tree.sums.add(position, -weight);
+            }
+            changed += 1;
+        }
+        Ok(changed)
+    })
+}
+
+/// Count and weight sum of the active rows with values in `[lo, hi)`, as a
+/// two-element array.
+#[wasm_bindgen(js_name = rangeTreeAggregate)]
+pub fn range_tree_aggregate(dimension: u32, lo: f64, hi: f64) -> Result<Vec<f64>, KernelError> {
+    with_tree(dimension, |tree| {
+        let (start, end) = sorted_index::value_range(&tree.values, lo, hi);
+        Ok(vec![
+            tree.counts.range(start, end) as f64,
+            tree.sums.range(start, end),
+        ])
+    })
+}
+
+/// Drops the range tree of `dimension`, or every tree when omitted.
+#[wasm_bindgen(js_name = releaseRangeTree)]
+pub fn release_range_tree(dimension: Option<u32>) {
+    TREES.with(|trees| match dimension {
+        Some(dimension) => {
+            trees.borrow_mut().remove(&dimension);
+        }
+        None => trees.borrow_mut().clear(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::floats;
+    use crate::sorted_index::build_sorted_index;
+
+    const ROWS: usize = 200;
+
+    #[test]
+    fn prefix_sums_follow_point_updates() {
+        let mut tree = Fenwick::new([3i64, 1, 4, 1, 5, 9, 2]);
+        assert_eq!(tree.prefix(7), 25);
+        assert_eq!(tree.range(2, 5), 10);
+        tree.add(3, 6);
+        assert_eq!(tree.range(2, 5), 16);
+        assert_eq!(tree.range(0, 0), 0);
+    }
+
+    #[test]
+    fn range_aggregates_match_a_full_rescan() {
+        // Every 13th key is NaN and left out of the index.
+        let keys: Vec<f64> = (0..ROWS)
+            .map(|row| match row % 13 {
+                0 => f64::NAN,
+                _ => (row * 29 % 40) as f64,
+            })
+            .collect();
+        let weights: Vec<f64> = (0..ROWS).map(|row| (row % 5) as f64 + 0.5).collect();
+        build_sorted_index(1, floats(keys.clone(), None)).unwrap();
+        let mask = vec![0x33; ROWS.div_ceil(8)];
+        let indexed = build_range_tree(1, Some(floats(weights.clone(), None)), Some(mask));
+        assert_eq!(indexed.unwrap() as usize, ROWS - ROWS.div_ceil(13));
+        let mut active: Vec<bool> = (0..ROWS).map(|row| row % 4 < 2).collect();
+
+        let ranges = [
+            (0.0, 40.0),
+            (10.0, 20.0),
+            (f64::NEG_INFINITY, 5.0),
+            (39.0, f64::INFINITY),
+            (17.0, 17.0),
+        ];
+        let steps: [(Vec<u32>, bool); 4] = [
+            ((0..ROWS as u32).step_by(3).collect(), true),
+            ((0..ROWS as u32).step_by(2).collect(), false),
+            ((50..150).collect(), true),
+            ((0..ROWS as u32 + 10).collect(), false),
+        ];
+        for (step, (rows, on)) in steps.into_iter().enumerate() {
+            let flips = rows
+                .iter()
+                .map(|&row| row as usize)
+                .filter(|&row| row < ROWS && !keys[row].is_nan() && active[row] != on)
+                .count();
+            assert_eq!(
+                update_range_tree(1, &rows, on).unwrap() as usize,
+                flips,
+                "step {step}"
+            );
+            for &row in rows.iter().filter(|&&row| (row as usize) < ROWS) {
+                active[row as usize] = on;
+            }
+            for (lo, hi) in ranges {
+                let inside: Vec<usize> = (0..ROWS)
+                    .filter(|&row| active[row] && lo <= keys[row] && keys[row] < hi)
+                    .collect();
+                let sum: f64 = inside.iter().map(|&row| weights[row]).sum();
+                assert_eq!(
+                    range_tree_aggregate(1, lo, hi).unwrap(),
+                    [inside.len() as f64, sum],
+                    "step {step}, [{lo}, {hi})"
+                );
+            }
+        }
+        release_range_tree(Some(1));
+        assert!(range_tree_aggregate(1, 0.0, 1.0).is_err());
+    }
+}
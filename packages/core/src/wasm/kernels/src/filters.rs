@@ -0,0 +1,627 @@
+//! Filter state and `groupAll` reductions.
+//!
+//! crossfilter's filter model kept inside wasm: each row carries one bit per
+//! filtered dimension, set while that dimension's filter excludes it, and a
+//! row is active when no bit is set. Changing one dimension's filter touches
+//! only that bit, and the rows whose overall state flips are handed to every
+//! `groupAll` reduction, so `groupAllValue` stays current in `O(changed
+//! rows)` instead of rescanning, matching `groupAll().value()`.
+//!
+//! `filterRange`, `filterExact`, `filterList` and `filterAll` mirror
+//! crossfilter's dimension filters over the dimension's sorted index. While
+//! a dimension moves between value filters only the index positions whose
+//! membership differs are visited, so dragging a brush costs the rows it
+//! sweeps over; changed rows also move through every reducer attached with
+//! `attachReducer`, and each call returns those reducers' bin deltas. Nulls
+//! and NaNs fail every value filter and pass `filterAll`, as in crossfilter.
+//!
+//! Up to 32 dimensions can hold a filter at once; a dimension keeps its bit
+//! until the state is reset.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, bit};
+use crate::delta::BinChanges;
+use crate::error::{ErrorKind, KernelError};
+use crate::recovery;
+use crate::reducer;
+use crate::sorted_index::{self, value_range, SortedIndex};
+use crate::summation::Sum;
+
+const MAX_FILTERED: usize = 32;
+
+/// Reduction computed by `groupAllValue`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reduce {
+    Count = 0,
+    Sum = 1,
+    Mean = 2,
+}
+
+/// Running count and measure sum over the active rows.
+struct Total {
+    /// Measure value per row (zero for nulls and NaNs); `None` counts only.
+    values: Option<Vec<f64>>,
+    count: u32,
+    sum: Sum,
+}
+
+impl Total {
+    fn apply(&mut self, row: usize, add: bool) {
+        let value = self.values.as_ref().map_or(0.0, |values| values[row]);
+        if add {
+            self.count += 1;
+            self.sum.add(value);
+        } else {
+            self.count -= 1;
+            self.sum.add(-value);
+        }
+    }
+}
+
+/// A dimension's value filter.
+enum Predicate {
+    /// Values in `[lo, hi)`.
+    Range(f64, f64),
+    /// Values equal to one of these, ascending and distinct.
+    Exact(Vec<f64>),
+}
+
+impl Predicate {
+    /// Ascending, disjoint position ranges `[start, end)` of `index` that
+    /// pass.
+    fn positions(&self, index: &SortedIndex) -> Vec<(usize, usize)> {
+        let values = &index.values;
+        let spans = match self {
+            Predicate::Range(lo, hi) => vec![value_range(values, *lo, *hi)],
+            Predicate::Exact(exact) => exact
+                .iter()
+                .map(|&value| {
+                    let start = values.partition_point(|&v| v < value);
+                    (
+                        start,
+                        start + values[start..].partition_point(|&v| v <= value),
+                    )
+                })
+                .collect(),
+        };
+        spans
+            .into_iter()
+            .filter(|&(start, end)| start < end)
+            .collect()
+    }
+}
+
+/// Whether `position` lies in one of the ascending `spans`.
+fn covers(spans: &[(usize, usize)], position: usize) -> bool {
+    let at = spans.partition_point(|&(_, end)| end <= position);
+    spans.get(at).is_some_and(|&(start, _)| start <= position)
+}
+
+/// Position ranges whose membership differs between `old` and `new`, with
+/// whether they pass under `new`.
+fn changed_spans(old: &[(usize, usize)], new: &[(usize, usize)]) -> Vec<(usize, usize, bool)> {
+    let mut bounds: Vec<usize> = old
+        .iter()
+        .chain(new)
+        .flat_map(|&(start, end)| [start, end])
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+    bounds
+        .windows(2)
+        .filter_map(|pair| {
+            let passes = covers(new, pair[0]);
+            (covers(old, pair[0]) != passes).then_some((pair[0], pair[1], passes))
+        })
+        .collect()
+}
+
+#[derive(Default)]
+pub(crate) struct FilterState {
+    /// Per row, the bits of the dimensions whose filter excludes it.
+    failing: Vec<u32>,
+    /// Bit of each dimension that has held a filter.
+    slots: HashMap<u32, u32>,
+    /// `groupAll` totals by measure handle (`None` for plain counts).
+    totals: HashMap<Option<u32>, Total>,
+    /// Value filter of each dimension filtered through `filterRange`,
+    /// `filterExact` or `filterList`.
+    predicates: HashMap<u32, Predicate>,
+}
+
+impl FilterState {
+    pub(crate) fn rows(&self) -> usize {
+        self.failing.len()
+    }
+
+    /// Bit of `dimension`, or zero when it never held a filter.
+    fn flag(&self, dimension: u32) -> u32 {
+        self.slots.get(&dimension).map_or(0, |&slot| 1 << slot)
+    }
+
+    fn slot(&mut self, dimension: u32) -> Result<u32, KernelError> {
+        if let Some(&slot) = self.slots.get(&dimension) {
+            return Ok(1 << slot);
+        }
+        if self.slots.len() == MAX_FILTERED {
+            return Err(KernelError::new(
+                ErrorKind::Unsupported,
+                "at most 32 dimensions can be filtered at once",
+            )
+            .with("dimension", f64::from(dimension)));
+        }
+        let slot = self.slots.len() as u32;
+        self.slots.insert(dimension, slot);
+        Ok(1 << slot)
+    }
+
+    /// Sets whether `dimension`'s filter passes each row of `rows`, moving
+    /// rows of attached reducers along; returns how many rows changed
+    /// overall state.
+    pub(crate) fn set_rows(
+        &mut self,
+        dimension: u32,
+        rows: impl IntoIterator<Item = (usize, bool)>,
+    ) -> Result<u32, KernelError> {
+        let flag = self.slot(dimension)?;
+        let mut changed = 0;
+        let mut flipped = Vec::new();
+        for (row, passes) in rows {
+            let old = self.failing[row];
+            let new = if passes { old & !flag } else { old | flag };
+            if old == new {
+                continue;
+            }
+            self.failing[row] = new;
+            flipped.push(row as u32);
+            if (old == 0) != (new == 0) {
+                changed += 1;
+                for total in self.totals.values_mut() {
+                    total.apply(row, new == 0);
+                }
+            }
+        }
+        reducer::follow_filters(flag, &flipped, &self.failing, |dimension| {
+            self.flag(dimension)
+        });
+        Ok(changed)
+    }
+
+    fn is_active(&self, row: usize) -> bool {
+        self.failing[row] == 0
+    }
+
+    /// Whether `row` passes every filter but `dimension`'s.
+    pub(crate) fn passes_except(&self, row: usize, dimension: u32) -> bool {
+        self.failing[row] & !self.flag(dimension) == 0
+    }
+
+    /// Test for rows passing every filter but those of `dimensions`.
+    pub(crate) fn passes_except_all(&self, dimensions: &[u32]) -> impl Fn(usize) -> bool + '_ {
+        let ignored = dimensions
+            .iter()
+            .fold(0, |flags, &dimension| flags | self.flag(dimension));
+        move |row| self.failing[row] & !ignored == 0
+    }
+
+    /// Replaces `dimension`'s filter with `predicate` over its sorted
+    /// `index`; returns how many rows changed overall state.
+    fn filter(
+        &mut self,
+        dimension: u32,
+        index: &SortedIndex,
+        predicate: Predicate,
+    ) -> Result<u32, KernelError> {
+        let rows = self.rows();
+        let check = |row: u32| {
+            if row as usize >= rows {
+                return Err(KernelError::invalid_state(
+                    "sorted index has rows past the filter state",
+                )
+                .with("row", f64::from(row))
+                .with("rows", rows as f64));
+            }
+            Ok(row as usize)
+        };
+        let new = predicate.positions(index);
+        let changed = match self.predicates.get(&dimension) {
+            Some(old) => {
+                let mut updates = Vec::new();
+                for (start, end, passes) in changed_spans(&old.positions(index), &new) {
+                    for &row in &index.rows[start..end] {
+                        updates.push((check(row)?, passes));
+                    }
+                }
+                self.set_rows(dimension, updates)?
+            }
+            None => {
+                let mut passes = vec![false; rows];
+                for &(start, end) in &new {
+                    for &row in &index.rows[start..end] {
+                        passes[check(row)?] = true;
+                    }
+                }
+                self.set_rows(dimension, passes.into_iter().enumerate())?
+            }
+        };
+        self.predicates.insert(dimension, predicate);
+        Ok(changed)
+    }
+}
+
+thread_local! {
+    static FILTERS: RefCell<FilterState> = RefCell::new(FilterState::default());
+}
+
+/// Clears every filter; see `hardReset`.
+pub(crate) fn reset() {
+    FILTERS.with(|filters| recovery::reset_cell(filters, FilterState::default()));
+}
+
+pub(crate) fn with_filters<T>(
+    update: impl FnOnce(&mut FilterState) -> Result<T, KernelError>,
+) -> Result<T, KernelError> {
+    FILTERS.with(|filters| update(&mut filters.borrow_mut()))
+}
+
+/// Clears every filter and sizes the state for `rowCount` rows, all active.
+/// `groupAll` totals are dropped and rebuilt on their next read.
+#[wasm_bindgen(js_name = resetFilters)]
+pub fn reset_filters(row_count: u32) {
+    FILTERS.with(|filters| {
+        *filters.borrow_mut() = FilterState {
+            failing: vec![0; row_count as usize],
+            ..FilterState::default()
+        };
+    });
+}
+
+/// Replaces `dimension`'s filter with `mask`, an LSB-first row bitmap in
+/// the layout's `activeMask` format whose set bits pass; `None` clears the
+/// filter. Returns how many rows changed overall state.
+#[wasm_bindgen(js_name = setFilterMask)]
+pub fn set_filter_mask(dimension: u32, mask: Option<Vec<u8>>) -> Result<u32, KernelError> {
+    with_filters(|filters| {
+        let rows = filters.rows();
+        if let Some(mask) = &mask {
+            if mask.len() < rows.div_ceil(8) {
+                return Err(KernelError::invalid_argument("mask is too short")
+                    .with("needed", rows.div_ceil(8) as f64)
+                    .with("available", mask.len() as f64));
+            }
+        }
+        let passes = |row| mask.as_ref().is_none_or(|mask| bit(mask, row));
+        filters.predicates.remove(&dimension);
+        filters.set_rows(dimension, (0..rows).map(|row| (row, passes(row))))
+    })
+}
+
+/// Outcome of a dimension filter change.
+#[wasm_bindgen]
+pub struct FilterChanges {
+    rows: u32,
+    groups: Vec<u32>,
+    changes: Vec<BinChanges>,
+}
+
+#[wasm_bindgen]
+impl FilterChanges {
+    /// Rows whose overall filter state flipped.
+    #[wasm_bindgen(getter = changedRows)]
+    pub fn changed_rows(&self) -> u32 {
+        self.rows
+    }
+
+    /// Attached groups with changed bins, ascending.
+    #[wasm_bindgen(getter)]
+    pub fn groups(&self) -> Vec<u32> {
+        self.groups.clone()
+    }
+
+    /// Changed bins of `groups[index]`, as `reducerChanges` reports them.
+    pub fn changes(&self, index: u32) -> Option<BinChanges> {
+        self.changes.get(index as usize).cloned()
+    }
+}
+
+fn filter_changes(rows: u32) -> FilterChanges {
+    let (groups, changes) = reducer::attached_changes().into_iter().unzip();
+    FilterChanges {
+        rows,
+        groups,
+        changes,
+    }
+}
+
+fn apply_filter(dimension: u32, predicate: Predicate) -> Result<FilterChanges, KernelError> {
+    let rows = sorted_index::with_index(dimension, |index| {
+        with_filters(|filters| filters.filter(dimension, index, predicate))
+    })?;
+    Ok(filter_changes(rows))
+}
+
+/// `dimension.filterRange([lo, hi])`: keeps rows whose value in
+/// `dimension`'s sorted index lies in `[lo, hi)`.
+#[wasm_bindgen(js_name = filterRange)]
+pub fn filter_range(dimension: u32, lo: f64, hi: f64) -> Result<FilterChanges, KernelError> {
+    apply_filter(dimension, Predicate::Range(lo, hi))
+}
+
+/// `dimension.filterExact(value)`: keeps rows whose value equals `value`.
+#[wasm_bindgen(js_name = filterExact)]
+pub fn filter_exact(dimension: u32, value: f64) -> Result<FilterChanges, KernelError> {
+    filter_list(dimension, &[value])
+}
+
+/// Keeps rows whose value equals any of `values`.
+#[wasm_bindgen(js_name = filterList)]
+pub fn filter_list(dimension: u32, values: &[f64]) -> Result<FilterChanges, KernelError> {
+    let mut values: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+    values.sort_unstable_by(f64::total_cmp);
+    values.dedup_by(|a, b| a == b);
+    apply_filter(dimension, Predicate::Exact(values))
+}
+
+/// `dimension.filterAll()`: clears `dimension`'s filter, however it was
+/// set.
+#[wasm_bindgen(js_name = filterAll)]
+pub fn filter_all(dimension: u32) -> Result<FilterChanges, KernelError> {
+    let rows = with_filters(|filters| {
+        filters.predicates.remove(&dimension);
+        if !filters.slots.contains_key(&dimension) {
+            return Ok(0);
+        }
+        let rows = filters.rows();
+        filters.set_rows(dimension, (0..rows).map(|row| (row, true)))
+    })?;
+    Ok(filter_changes(rows))
+}
+
+/// The combined filter as an LSB-first row bitmap: set bits are rows that
+/// pass every dimension's filter.
+#[wasm_bindgen(js_name = filterMask)]
+pub fn filter_mask() -> Vec<u8> {
+    FILTERS.with(|filters| {
+        let filters = filters.borrow();
+        let mut mask = vec![0u8; filters.rows().div_ceil(8)];
+        for row in (0..filters.rows()).filter(|&row| filters.is_active(row)) {
+            mask[row >> 3] |= 1 << (row & 7);
+        }
+        mask
+    })
+}
+
+/// `groupAll().value()` over the rows passing every filter: their count, or
+/// the sum or mean of the numeric column behind `measure` (nulls and NaNs
+/// add zero to sums but still count). The first read for a measure scans
+/// the rows; later filter changes keep it current incrementally.
+#[wasm_bindgen(js_name = groupAllValue)]
+pub fn group_all_value(reduce: Reduce, measure: Option<u32>) -> Result<f64, KernelError> {
+    if reduce != Reduce::Count && measure.is_none() {
+        return Err(KernelError::invalid_argument(
+            "sum and mean reductions need a measure column",
+        ));
+    }
+    let key = if reduce == Reduce::Count {
+        None
+    } else {
+        measure
+    };
+    with_filters(|filters| {
+        if !filters.totals.contains_key(&key) {
+            let rows = filters.rows();
+            let values = key
+                .map(|handle| columns::measure(handle, rows))
+                .transpose()?;
+            let mut total = Total {
+                values,
+                count: 0,
+                sum: Sum::new(),
+            };
+            for row in (0..rows).filter(|&row| filters.is_active(row)) {
+                total.apply(row, true);
+            }
+            filters.totals.insert(key, total);
+        }
+        let total = &filters.totals[&key];
+        Ok(match reduce {
+            Reduce::Count => f64::from(total.count),
+            Reduce::Sum => total.sum.value(),
+            Reduce::Mean if total.count == 0 => f64::NAN,
+            Reduce::Mean => total.sum.value() / f64::from(total.count),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::floats;
+    use crate::reducer::{attach_reducer, build_reducer, reducer_counts};
+    use crate::sorted_index::build_sorted_index;
+
+    const ROWS: usize = 300;
+
+    /// A dimension filter as crossfilter states it, checked row by row.
+    enum Model {
+        All,
+        Range(f64, f64),
+        List(Vec<f64>),
+    }
+
+    impl Model {
+        fn passes(&self, value: f64) -> bool {
+            match self {
+                Model::All => true,
+                Model::Range(lo, hi) => *lo <= value && value < *hi,
+                Model::List(values) => values.contains(&value),
+            }
+        }
+    }
+
+    /// Values of dimension 1 (NaN every 17th row) and dimension 2.
+    fn dimension_values() -> [Vec<f64>; 2] {
+        let first = (0..ROWS)
+            .map(|row| match row % 17 {
+                0 => f64::NAN,
+                _ => (row * 37 % 50) as f64,
+            })
+            .collect();
+        let second = (0..ROWS).map(|row| (row % 7) as f64).collect();
+        [first, second]
+    }
+
+    #[test]
+    fn span_changes_cover_only_flipped_positions() {
+        assert_eq!(
+            changed_spans(&[(0, 5)], &[(3, 8)]),
+            [(0, 3, false), (5, 8, true)]
+        );
+        assert_eq!(changed_spans(&[(2, 4), (6, 9)], &[(2, 9)]), [(4, 6, true)]);
+        assert!(changed_spans(&[(1, 3)], &[(1, 3)]).is_empty());
+        assert_eq!(changed_spans(&[], &[(4, 5)]), [(4, 5, true)]);
+    }
+
+    #[test]
+    fn incremental_filters_match_a_full_rescan() {
+        let values = dimension_values();
+        reset_filters(ROWS as u32);
+        for (dimension, values) in (1..).zip(&values) {
+            build_sorted_index(dimension, floats(values.clone(), None)).unwrap();
+        }
+        // A group over dimension 2, attached so it ignores that dimension's
+        // filter.
+        let bins: Vec<u16> = values[1].iter().map(|&value| value as u16).collect();
+        build_reducer(9, &bins, 7, None, None).unwrap();
+        attach_reducer(9, 2).unwrap();
+        let mut reported = reducer_counts(9).unwrap();
+
+        let mut models = [Model::All, Model::All];
+        let mut active = vec![true; ROWS];
+        let steps: Vec<(u32, Model)> = vec![
+            (1, Model::Range(10.0, 30.0)),
+            (1, Model::Range(15.0, 35.0)),
+            (1, Model::Range(5.0, 12.0)),
+            (2, Model::List(vec![3.0])),
+            (1, Model::List(vec![7.0])),
+            (1, Model::Range(0.0, 50.0)),
+            (2, Model::Range(2.0, 5.0)),
+            (1, Model::All),
+            (1, Model::Range(40.0, 45.0)),
+            (2, Model::All),
+            (1, Model::List(vec![1.0, 11.0, 49.0])),
+            (1, Model::Range(f64::NEG_INFINITY, f64::INFINITY)),
+            (1, Model::All),
+        ];
+        for (step, (dimension, model)) in steps.into_iter().enumerate() {
+            let changes = match &model {
+                Model::All => filter_all(dimension),
+                Model::Range(lo, hi) => filter_range(dimension, *lo, *hi),
+                Model::List(values) => filter_list(dimension, values),
+            }
+            .unwrap();
+            models[dimension as usize - 1] = model;
+
+            let passes = |row: usize, skip: Option<usize>| {
+                (0..2).all(|d| Some(d) == skip || models[d].passes(values[d][row]))
+            };
+            let expected: Vec<bool> = (0..ROWS).map(|row| passes(row, None)).collect();
+            let flipped = (0..ROWS)
+                .filter(|&row| active[row] != expected[row])
+                .count();
+            assert_eq!(changes.changed_rows() as usize, flipped, "step {step}");
+            active = expected;
+            let mask = filter_mask();
+            assert!(
+                (0..ROWS).all(|row| bit(&mask, row) == active[row]),
+                "step {step}"
+            );
+            let count = active.iter().filter(|&&active| active).count();
+            assert_eq!(group_all_value(Reduce::Count, None).unwrap(), count as f64);
+
+            let mut counts = vec![0; 7];
+            for row in (0..ROWS).filter(|&row| passes(row, Some(1))) {
+                counts[bins[row] as usize] += 1;
+            }
+            assert_eq!(reducer_counts(9).unwrap(), counts, "step {step}");
+            // The reported deltas carry the previous counts to the new ones.
+            for index in 0..changes.groups().len() as u32 {
+                let delta = changes.changes(index).unwrap();
+                for (&bin, &count_delta) in delta.bins.iter().zip(&delta.count_deltas) {
+                    let count = &mut reported[bin as usize];
+                    *count = (*count as i32 + count_delta) as u32;
+                }
+            }
+            assert_eq!(reported, counts, "step {step}");
+        }
+        assert_eq!(filter_all(5).unwrap().changed_rows(), 0);
+    }
+
+    #[test]
+    fn group_all_totals_match_a_full_rescan() {
+        const ROWS: usize = 64;
+        // Rows 3, 13, 23, ... are NaN and every 9th row is null; both add
+        // zero.
+        let measure: Vec<f64> = (0..ROWS)
+            .map(|row| {
+                if row % 10 == 3 {
+                    f64::NAN
+                } else {
+                    row as f64 * 0.5
+                }
+            })
+            .collect();
+        let validity: Vec<u8> = (0..ROWS / 8)
+            .map(|byte| {
+                (0..8)
+                    .filter(|&b| (byte * 8 + b) % 9 != 0)
+                    .fold(0, |m, b| m | 1 << b)
+            })
+            .collect();
+        let values: Vec<f64> = (0..ROWS)
+            .map(|row| match measure[row] {
+                value if row % 9 == 0 || value.is_nan() => 0.0,
+                value => value,
+            })
+            .collect();
+        let handle = floats(measure, Some(validity));
+        reset_filters(ROWS as u32);
+        assert!(group_all_value(Reduce::Sum, None).is_err());
+
+        let mut masks = [[0xffu8; ROWS / 8], [0xff; ROWS / 8]];
+        let steps: [(u32, u64); 6] = [
+            (1, 0x0f0f_0f0f_0f0f_0f0f),
+            (2, 0xffff_0000_ffff_0000),
+            (1, 0xf0f0_f0f0_f0f0_f0f0),
+            (2, 0),
+            (2, u64::MAX),
+            (1, 0x8421_8421_8421_8421),
+        ];
+        for (step, (dimension, mask)) in steps.into_iter().enumerate() {
+            let mask = mask.to_le_bytes();
+            masks[dimension as usize - 1] = mask;
+            set_filter_mask(dimension, Some(mask.to_vec())).unwrap();
+            let active: Vec<usize> = (0..ROWS)
+                .filter(|&row| masks.iter().all(|mask| bit(mask, row)))
+                .collect();
+            let sum: f64 = active.iter().map(|&row| values[row]).sum();
+            let count = active.len() as f64;
+            assert_eq!(group_all_value(Reduce::Count, None).unwrap(), count);
+            assert_eq!(
+                group_all_value(Reduce::Sum, Some(handle)).unwrap(),
+                sum,
+                "step {step}"
+            );
+            let mean = group_all_value(Reduce::Mean, Some(handle)).unwrap();
+            if active.is_empty() {
+                assert!(mean.is_nan());
+            } else {
+                assert_eq!(mean, sum / count, "step {step}");
+            }
+        }
+    }
+}
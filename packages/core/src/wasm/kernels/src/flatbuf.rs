@@ -0,0 +1,339 @@
+//! Minimal FlatBuffers reader and writer.
+//!
+//! Only what the Arrow IPC metadata needs: tables with scalar, string, table,
+//! union and vector fields. Every read is bounds-checked and reports
+//! malformed input as a [`KernelError`] instead of panicking, since the bytes
+//! come straight from the network. The writer lays tables out front to back
+//! (vtable, table, then children) so every `uoffset_t` points forward.
+
+use crate::error::{ErrorKind, KernelError};
+
+fn malformed(what: &str) -> KernelError {
+    KernelError::new(
+        ErrorKind::MalformedInput,
+        format!("malformed flatbuffer: {what}"),
+    )
+}
+
+fn read_bytes<const N: usize>(buf: &[u8], pos: usize) -> Result<[u8; N], KernelError> {
+    buf.get(pos..pos + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| malformed("read out of bounds"))
+}
+
+pub(crate) fn read_u8(buf: &[u8], pos: usize) -> Result<u8, KernelError> {
+    Ok(read_bytes::<1>(buf, pos)?[0])
+}
+
+pub(crate) fn read_u16(buf: &[u8], pos: usize) -> Result<u16, KernelError> {
+    Ok(u16::from_le_bytes(read_bytes(buf, pos)?))
+}
+
+pub(crate) fn read_i32(buf: &[u8], pos: usize) -> Result<i32, KernelError> {
+    Ok(i32::from_le_bytes(read_bytes(buf, pos)?))
+}
+
+pub(crate) fn read_u32(buf: &[u8], pos: usize) -> Result<u32, KernelError> {
+    Ok(u32::from_le_bytes(read_bytes(buf, pos)?))
+}
+
+pub(crate) fn read_i64(buf: &[u8], pos: usize) -> Result<i64, KernelError> {
+    Ok(i64::from_le_bytes(read_bytes(buf, pos)?))
+}
+
+/// Follows a `uoffset_t` stored at `pos`.
+fn follow(buf: &[u8], pos: usize) -> Result<usize, KernelError> {
+    let target = pos.checked_add(read_u32(buf, pos)? as usize);
+    target
+        .filter(|&target| target < buf.len())
+        .ok_or_else(|| malformed("offset out of bounds"))
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    vtable: usize,
+    vtable_len: usize,
+}
+
+impl<'a> Table<'a> {
+    /// Reads the root table of a finished buffer.
+    pub(crate) fn root(buf: &'a [u8]) -> Result<Self, KernelError> {
+        Table::at(buf, follow(buf, 0)?)
+    }
+
+    fn at(buf: &'a [u8], pos: usize) -> Result<Self, KernelError> {
+        let vtable = pos as i64 - i64::from(read_i32(buf, pos)?);
+        if vtable < 0 {
+            return Err(malformed("vtable out of bounds"));
+        }
+        let vtable = vtable as usize;
+        let vtable_len = read_u16(buf, vtable)? as usize;
+        Ok(Table {
+            buf,
+            pos,
+            vtable,
+            vtable_len,
+        })
+    }
+
+    /// Absolute position of field `id`, or `None` when it is absent.
+    fn field(&self, id: usize) -> Result<Option<usize>, KernelError> {
+        let entry = 4 + id * 2;
+        if entry + 2 > self.vtable_len {
+            return Ok(None);
+        }
+        let offset = read_u16(self.buf, self.vtable + entry)? as usize;
+        Ok((offset != 0).then_some(self.pos + offset))
+    }
+
+    pub(crate) fn u8_or(&self, id: usize, default: u8) -> Result<u8, KernelError> {
+        self.field(id)?
+            .map_or(Ok(default), |pos| read_u8(self.buf, pos))
+    }
+
+    pub(crate) fn bool_or(&self, id: usize, default: bool) -> Result<bool, KernelError> {
+        Ok(self.u8_or(id, u8::from(default))? != 0)
+    }
+
+    pub(crate) fn i16_or(&self, id: usize, default: i16) -> Result<i16, KernelError> {
+        self.field(id)?
+            .map_or(Ok(default), |pos| Ok(read_u16(self.buf, pos)? as i16))
+    }
+
+    pub(crate) fn u32_or(&self, id: usize, default: u32) -> Result<u32, KernelError> {
+        self.field(id)?
+            .map_or(Ok(default), |pos| read_u32(self.buf, pos))
+    }
+
+    pub(crate) fn f64_or(&self, id: usize, default: f64) -> Result<f64, KernelError> {
+        self.field(id)?.map_or(Ok(default), |pos| {
+            Ok(f64::from_le_bytes(read_bytes(self.buf, pos)?))
+        })
+    }
+
+    pub(crate) fn i32_or(&self, id: usize, default: i32) -> Result<i32, KernelError> {
+        self.field(id)?
+            .map_or(Ok(default), |pos| read_i32(self.buf, pos))
+    }
+
+    pub(crate) fn i64_or(&self, id: usize, default: i64) -> Result<i64, KernelError> {
+        self.field(id)?
+            .map_or(Ok(default), |pos| read_i64(self.buf, pos))
+    }
+
+    pub(crate) fn table(&self, id: usize) -> Result<Option<Table<'a>>, KernelError> {
+        match self.field(id)? {
+            Some(pos) => Ok(Some(Table::at(self.buf, follow(self.buf, pos)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn string(&self, id: usize) -> Result<Option<&'a str>, KernelError> {
+        let Some(bytes) = self.byte_vector(id)? else {
+            return Ok(None);
+        };
+        std::str::from_utf8(bytes)
+            .map(Some)
+            .map_err(|_| malformed("invalid utf-8 string"))
+    }
+
+    fn byte_vector(&self, id: usize) -> Result<Option<&'a [u8]>, KernelError> {
+        let Some(pos) = self.field(id)? else {
+            return Ok(None);
+        };
+        let start = follow(self.buf, pos)?;
+        let len = read_u32(self.buf, start)? as usize;
+        self.buf
+            .get(start + 4..(start + 4).saturating_add(len))
+            .map(Some)
+            .ok_or_else(|| malformed("vector out of bounds"))
+    }
+
+    /// Vector of tables.
+    pub(crate) fn tables(&self, id: usize) -> Result<Vec<Table<'a>>, KernelError> {
+        let Some(pos) = self.field(id)? else {
+            return Ok(Vec::new());
+        };
+        let start = follow(self.buf, pos)?;
+        let len = read_u32(self.buf, start)? as usize;
+        (0..len)
+            .map(|index| {
+                let element = start + 4 + index * 4;
+                Table::at(self.buf, follow(self.buf, element)?)
+            })
+            .collect()
+    }
+
+    /// Vector of fixed-size structs, returned as raw `len * stride` bytes.
+    pub(crate) fn structs(&self, id: usize, stride: usize) -> Result<&'a [u8], KernelError> {
+        let Some(pos) = self.field(id)? else {
+            return Ok(&[]);
+        };
+        let start = follow(self.buf, pos)?;
+        let len = read_u32(self.buf, start)? as usize;
+        self.buf
+            .get(start + 4..(start + 4).saturating_add(len.saturating_mul(stride)))
+            .ok_or_else(|| malformed("struct vector out of bounds"))
+    }
+}
+
+/// Field value for [`TableBuilder`].
+pub(crate) enum Value {
+    U8(u8),
+    Bool(bool),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    Table(TableBuilder),
+    String(String),
+    Tables(Vec<TableBuilder>),
+    /// Vector of fixed-size structs or scalars, already encoded; elements are
+    /// 8-aligned.
+    Structs {
+        count: usize,
+        bytes: Vec<u8>,
+    },
+}
+
+impl Value {
+    /// Inline size (and alignment) inside the table.
+    fn inline_size(&self) -> usize {
+        match self {
+            Value::U8(_) | Value::Bool(_) => 1,
+            Value::I16(_) | Value::U16(_) => 2,
+            Value::I64(_) => 8,
+            Value::I32(_)
+            | Value::U32(_)
+            | Value::Table(_)
+            | Value::String(_)
+            | Value::Tables(_)
+            | Value::Structs { .. } => 4,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct TableBuilder {
+    fields: Vec<(u16, Value)>,
+}
+
+impl TableBuilder {
+    pub(crate) fn new() -> Self {
+        TableBuilder::default()
+    }
+
+    pub(crate) fn add(mut self, id: u16, value: Value) -> Self {
+        self.fields.push((id, value));
+        self
+    }
+
+    /// Serializes this table as the root of a finished buffer, padded to a
+    /// multiple of 8 bytes.
+    pub(crate) fn finish(&self) -> Vec<u8> {
+        let mut buf = vec![0; 8];
+        let root = write_table(&mut buf, self);
+        patch_u32(&mut buf, 0, root as u32);
+        pad_to(&mut buf, 8);
+        buf
+    }
+}
+
+fn pad_to(buf: &mut Vec<u8>, align: usize) {
+    buf.resize(buf.len().next_multiple_of(align), 0);
+}
+
+fn patch_u32(buf: &mut [u8], pos: usize, value: u32) {
+    buf[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn patch_u16(buf: &mut [u8], pos: usize, value: u16) {
+    buf[pos..pos + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_table(buf: &mut Vec<u8>, table: &TableBuilder) -> usize {
+    let slots = table
+        .fields
+        .iter()
+        .map(|(id, _)| usize::from(*id) + 1)
+        .max()
+        .unwrap_or(0);
+    pad_to(buf, 2);
+    let vtable = buf.len();
+    buf.resize(vtable + 4 + slots * 2, 0);
+    patch_u16(buf, vtable, (4 + slots * 2) as u16);
+
+    pad_to(buf, 8);
+    let start = buf.len();
+    buf.extend_from_slice(&((start - vtable) as i32).to_le_bytes());
+
+    // Widest fields first keeps padding to a minimum.
+    let mut order: Vec<&(u16, Value)> = table.fields.iter().collect();
+    order.sort_by_key(|(_, value)| std::cmp::Reverse(value.inline_size()));
+    let mut deferred = Vec::new();
+    for (id, value) in order {
+        pad_to(buf, value.inline_size());
+        let pos = buf.len();
+        match value {
+            Value::U8(byte) => buf.push(*byte),
+            Value::Bool(flag) => buf.push(u8::from(*flag)),
+            Value::I16(short) => buf.extend_from_slice(&short.to_le_bytes()),
+            Value::U16(short) => buf.extend_from_slice(&short.to_le_bytes()),
+            Value::I32(int) => buf.extend_from_slice(&int.to_le_bytes()),
+            Value::U32(int) => buf.extend_from_slice(&int.to_le_bytes()),
+            Value::I64(long) => buf.extend_from_slice(&long.to_le_bytes()),
+            _ => {
+                buf.extend_from_slice(&[0; 4]);
+                deferred.push((pos, value));
+            }
+        }
+        patch_u16(buf, vtable + 4 + usize::from(*id) * 2, (pos - start) as u16);
+    }
+    let table_size = buf.len() - start;
+    patch_u16(buf, vtable + 2, table_size as u16);
+
+    for (pos, value) in deferred {
+        let target = write_offset_target(buf, value);
+        patch_u32(buf, pos, (target - pos) as u32);
+    }
+    start
+}
+
+fn write_offset_target(buf: &mut Vec<u8>, value: &Value) -> usize {
+    match value {
+        Value::Table(table) => write_table(buf, table),
+        Value::String(text) => {
+            pad_to(buf, 4);
+            let pos = buf.len();
+            buf.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            buf.extend_from_slice(text.as_bytes());
+            buf.push(0);
+            pos
+        }
+        Value::Tables(tables) => {
+            pad_to(buf, 4);
+            let pos = buf.len();
+            buf.extend_from_slice(&(tables.len() as u32).to_le_bytes());
+            buf.resize(pos + 4 + tables.len() * 4, 0);
+            for (index, table) in tables.iter().enumerate() {
+                let slot = pos + 4 + index * 4;
+                let target = write_table(buf, table);
+                patch_u32(buf, slot, (target - slot) as u32);
+            }
+            pos
+        }
+        Value::Structs { count, bytes } => {
+            // The length prefix sits just before the 8-aligned elements.
+            pad_to(buf, 8);
+            buf.extend_from_slice(&[0; 4]);
+            let pos = buf.len();
+            buf.extend_from_slice(&(*count as u32).to_le_bytes());
+            buf.extend_from_slice(bytes);
+            pos
+        }
+        _ => unreachable!("scalars are written inline"),
+    }
+}
@@ -0,0 +1,81 @@
+//! Applying row orders.
+//!
+//! Gathers rows by index, typically a permutation from `argsort*` or
+//! `sortColumns`, so reordering data after a sort stays inside wasm instead
+//! of indexing element by element in JS. Any index list works, including a
+//! prefix of a permutation (top-K) or repeated rows. Indices are checked up
+//! front, so a bad one fails the call before anything is written.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, gather, Column};
+use crate::error::KernelError;
+
+fn check_indices(indices: &[u32], len: usize) -> Result<(), KernelError> {
+    match indices.iter().find(|&&index| index as usize >= len) {
+        Some(&index) => Err(KernelError::invalid_argument("gather index out of range")
+            .with("index", f64::from(index))
+            .with("length", len as f64)),
+        None => Ok(()),
+    }
+}
+
+fn gather_checked<T: Copy>(values: &[T], indices: &[u32]) -> Result<Vec<T>, KernelError> {
+    check_indices(indices, values.len())?;
+    Ok(gather(values, indices))
+}
+
+/// Registers, for each column behind `handles`, a new column holding rows
+/// `indices` in that order (same name and type, validity carried along) and
+/// returns the new handles. The source columns are left untouched.
+#[wasm_bindgen(js_name = gatherColumns)]
+pub fn gather_columns(handles: &[u32], indices: &[u32]) -> Result<Vec<u32>, KernelError> {
+    let gathered = handles
+        .iter()
+        .map(|&handle| {
+            columns::with_column(handle, |column| {
+                check_indices(indices, column.len)?;
+                Ok(Column {
+                    name: column.name.clone(),
+                    len: indices.len(),
+                    values: column.values.gather(indices),
+                    validity: column
+                        .validity
+                        .as_ref()
+                        .map(|bits| columns::gather_bits(bits, indices)),
+                })
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(gathered.into_iter().map(columns::register).collect())
+}
+
+/// Returns `values[indices[i]]` for each `i`.
+#[wasm_bindgen(js_name = gatherU16)]
+pub fn gather_u16(values: &[u16], indices: &[u32]) -> Result<Vec<u16>, KernelError> {
+    gather_checked(values, indices)
+}
+
+/// `gatherU16` for `Uint32Array`.
+#[wasm_bindgen(js_name = gatherU32)]
+pub fn gather_u32(values: &[u32], indices: &[u32]) -> Result<Vec<u32>, KernelError> {
+    gather_checked(values, indices)
+}
+
+/// `gatherU16` for `Int32Array`.
+#[wasm_bindgen(js_name = gatherI32)]
+pub fn gather_i32(values: &[i32], indices: &[u32]) -> Result<Vec<i32>, KernelError> {
+    gather_checked(values, indices)
+}
+
+/// `gatherU16` for `Float32Array`.
+#[wasm_bindgen(js_name = gatherF32)]
+pub fn gather_f32(values: &[f32], indices: &[u32]) -> Result<Vec<f32>, KernelError> {
+    gather_checked(values, indices)
+}
+
+/// `gatherU16` for `Float64Array`.
+#[wasm_bindgen(js_name = gatherF64)]
+pub fn gather_f64(values: &[f64], indices: &[u32]) -> Result<Vec<f64>, KernelError> {
+    gather_checked(values, indices)
+}
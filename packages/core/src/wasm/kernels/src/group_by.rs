@@ -0,0 +1,187 @@
+//! Hash aggregation over composite keys.
+//!
+//! Crosstabs group by several dimensions at once, and the dense bin-index
+//! model (one slot per bin) stops fitting when the product of the bin
+//! counts runs into the millions while only a few thousand combinations
+//! occur. This groups rows by up to four bin columns, packing each row's
+//! bins into one `u64` key, in an open-addressing table (linear probing,
+//! power-of-two capacity, at most half full), and returns only the
+//! combinations that occur.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns::bit;
+use crate::error::KernelError;
+use crate::hash::mix;
+use crate::summation::Sums;
+
+/// Most bin columns a key packs.
+const MAX_WIDTH: u32 = 4;
+const EMPTY: u32 = u32::MAX;
+
+/// Open-addressing map from packed key to group index.
+struct GroupTable {
+    /// Group index per slot, `EMPTY` when free.
+    slots: Vec<u32>,
+    /// Packed key per group, in first-seen order.
+    keys: Vec<u64>,
+}
+
+impl GroupTable {
+    fn new() -> Self {
+        GroupTable {
+            slots: vec![EMPTY; 64],
+            keys: Vec::new(),
+        }
+    }
+
+    /// Index of `key`'s group, adding the group when new.
+    fn group(&mut self, key: u64) -> usize {
+        let mask = self.slots.len() - 1;
+        let mut slot = mix(key) as usize & mask;
+        loop {
+            match self.slots[slot] {
+                EMPTY => break,
+                group if self.keys[group as usize] == key => return group as usize,
+                _ => slot = (slot + 1) & mask,
+            }
+        }
+        let group = self.keys.len();
+        self.slots[slot] = group as u32;
+        self.keys.push(key);
+        if self.keys.len() * 2 > self.slots.len() {
+            self.grow();
+        }
+        group
+    }
+
+    fn grow(&mut self) {
+        self.slots = vec![EMPTY; self.slots.len() * 2];
+        let mask = self.slots.len() - 1;
+        for (group, &key) in self.keys.iter().enumerate() {
+            let mut slot = mix(key) as usize & mask;
+            while self.slots[slot] != EMPTY {
+                slot = (slot + 1) & mask;
+            }
+            self.slots[slot] = group as u32;
+        }
+    }
+}
+
+/// Occurring key combinations with their aggregates, ascending by key.
+#[wasm_bindgen]
+pub struct SparseGroups {
+    width: u32,
+    keys: Vec<u16>,
+    counts: Vec<u32>,
+    sums: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl SparseGroups {
+    /// Bins per key.
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Each group's bins, `width` per group.
+    #[wasm_bindgen(getter)]
+    pub fn keys(&self) -> Vec<u16> {
+        self.keys.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn counts(&self) -> Vec<u32> {
+        self.counts.clone()
+    }
+
+    /// Measure sums aligned with `counts` (nulls and NaNs skipped); empty
+    /// without a measure.
+    #[wasm_bindgen(getter)]
+    pub fn sums(&self) -> Vec<f64> {
+        self.sums.clone()
+    }
+}
+
+/// Groups the rows set in `mask` (an LSB-first row bitmap in the layout's
+/// `activeMask` format such as `filterMask()`; every row when omitted) by
+/// their bins in `bins`, which holds `width` (1 to 4) bins per row, row
+/// after row. Counts the rows of each occurring combination and sums
+/// `measure` (one value per row) when given.
+#[wasm_bindgen(js_name = groupByKeys)]
+pub fn group_by_keys(
+    bins: &[u16],
+    width: u32,
+    measure: Option<Vec<f64>>,
+    mask: Option<Vec<u8>>,
+) -> Result<SparseGroups, KernelError> {
+    if width == 0 || width > MAX_WIDTH {
+        return Err(
+            KernelError::invalid_argument("keys must pack between 1 and 4 bins")
+                .with("width", f64::from(width)),
+        );
+    }
+    if !bins.len().is_multiple_of(width as usize) {
+        return Err(KernelError::invalid_argument("bins do not fill whole rows")
+            .with("length", bins.len() as f64)
+            .with("width", f64::from(width)));
+    }
+    let rows = bins.len() / width as usize;
+    if let Some(measure) = &measure {
+        if measure.len() != rows {
+            return Err(KernelError::invalid_argument("measure length differs")
+                .with("expected", rows as f64)
+                .with("actual", measure.len() as f64));
+        }
+    }
+    if let Some(mask) = &mask {
+        if mask.len() < rows.div_ceil(8) {
+            return Err(KernelError::invalid_argument("mask is too short")
+                .with("needed", rows.div_ceil(8) as f64)
+                .with("available", mask.len() as f64));
+        }
+    }
+    let mut table = GroupTable::new();
+    let mut counts: Vec<u32> = Vec::new();
+    let mut sums = Sums::new(0);
+    for (row, key) in bins.chunks_exact(width as usize).enumerate() {
+        if mask.as_ref().is_some_and(|mask| !bit(mask, row)) {
+            continue;
+        }
+        // First bin most significant, so packed order is key order.
+        let packed = key
+            .iter()
+            .fold(0u64, |packed, &bin| packed << 16 | u64::from(bin));
+        let group = table.group(packed);
+        if group == counts.len() {
+            counts.push(0);
+            sums.push();
+        }
+        counts[group] += 1;
+        if let Some(value) = measure.as_ref().map(|measure| measure[row]) {
+            if !value.is_nan() {
+                sums.add(group, value);
+            }
+        }
+    }
+    let mut order: Vec<usize> = (0..counts.len()).collect();
+    order.sort_unstable_by_key(|&group| table.keys[group]);
+    let shift = |index: u32| 16 * (width - 1 - index);
+    Ok(SparseGroups {
+        width,
+        keys: order
+            .iter()
+            .flat_map(|&group| {
+                let packed = table.keys[group];
+                (0..width).map(move |index| (packed >> shift(index)) as u16)
+            })
+            .collect(),
+        counts: order.iter().map(|&group| counts[group]).collect(),
+        sums: if measure.is_some() {
+            order.iter().map(|&group| sums.get(group)).collect()
+        } else {
+            Vec::new()
+        },
+    })
+}
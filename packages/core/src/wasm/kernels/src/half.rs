@@ -0,0 +1,81 @@
+//! IEEE 754 half-precision (`f16`) values.
+//!
+//! Embedding and sensor columns are often stored as `f16` to halve their
+//! memory. Such columns keep their raw 16-bit patterns and are widened to
+//! `f32` only when read. Widening is exact: rebasing the exponent is a
+//! multiplication by a power of two, which also normalizes subnormals, and
+//! infinities and NaNs (payload included) map to their `f32` counterparts.
+
+use wasm_bindgen::prelude::*;
+
+#[cfg(target_feature = "simd128")]
+use std::arch::wasm32::{
+    f32x4_mul, u32x4_eq, u32x4_extend_high_u16x8, u32x4_extend_low_u16x8, u32x4_shl, u32x4_splat,
+    v128, v128_and, v128_bitselect, v128_load, v128_or, v128_store,
+};
+
+/// `2^112`: the bias difference between `f32` (127) and `f16` (15) exponents.
+const REBIAS: u32 = (112 + 127) << 23;
+const EXPONENT: u16 = 0x7c00;
+
+/// Widens one `f16` bit pattern to `f32`.
+pub(crate) fn to_f32(bits: u16) -> f32 {
+    let sign = u32::from(bits & 0x8000) << 16;
+    let shifted = u32::from(bits & 0x7fff) << 13;
+    let magnitude = if bits & EXPONENT == EXPONENT {
+        f32::from_bits(shifted | 0x7f80_0000)
+    } else {
+        f32::from_bits(shifted) * f32::from_bits(REBIAS)
+    };
+    f32::from_bits(magnitude.to_bits() | sign)
+}
+
+/// Widens `src` into `out`, which must be at least as long, eight values
+/// per step where `simd128` is available and parity is off.
+pub(crate) fn widen(src: &[u16], out: &mut [f32]) {
+    let out = &mut out[..src.len()];
+    #[cfg(target_feature = "simd128")]
+    let done = if crate::parity::simd_allowed() {
+        const LANES: usize = 8;
+        let whole = src.len() - src.len() % LANES;
+        // SAFETY: every load and store stays within the first `whole`
+        // elements of `src` and `out`, which have equal lengths.
+        unsafe {
+            for index in (0..whole).step_by(LANES) {
+                let halves = v128_load(src.as_ptr().add(index) as *const v128);
+                let dst = out.as_mut_ptr().add(index) as *mut v128;
+                v128_store(dst, widen_lanes(u32x4_extend_low_u16x8(halves)));
+                v128_store(dst.add(1), widen_lanes(u32x4_extend_high_u16x8(halves)));
+            }
+        }
+        whole
+    } else {
+        0
+    };
+    #[cfg(not(target_feature = "simd128"))]
+    let done = 0;
+    for (slot, &bits) in out[done..].iter_mut().zip(&src[done..]) {
+        *slot = to_f32(bits);
+    }
+}
+
+/// [`to_f32`] on four zero-extended bit patterns.
+#[cfg(target_feature = "simd128")]
+fn widen_lanes(bits: v128) -> v128 {
+    let sign = u32x4_shl(v128_and(bits, u32x4_splat(0x8000)), 16);
+    let shifted = u32x4_shl(v128_and(bits, u32x4_splat(0x7fff)), 13);
+    let exponent = u32::from(EXPONENT);
+    let special = u32x4_eq(v128_and(bits, u32x4_splat(exponent)), u32x4_splat(exponent));
+    let finite = f32x4_mul(shifted, u32x4_splat(REBIAS));
+    let infinite = v128_or(shifted, u32x4_splat(0x7f80_0000));
+    v128_or(v128_bitselect(infinite, finite, special), sign)
+}
+
+/// Widens raw `f16` bit patterns (e.g. an Arrow `halffloat` buffer viewed
+/// as `Uint16Array`) to a `Float32Array`.
+#[wasm_bindgen(js_name = decodeFloat16)]
+pub fn decode_float16(bits: &[u16]) -> Vec<f32> {
+    let mut out = vec![0.0; bits.len()];
+    widen(bits, &mut out);
+    out
+}
@@ -0,0 +1,212 @@
+//! Stable key hashes for sketches.
+//!
+//! Sketches are serialized and merged across calls, workers and releases,
+//! so their hashes must not depend on the standard library's hasher (whose
+//! algorithm may change). Keys are encoded exactly as in `distinctRows` and
+//! hashed with FNV-1a, then run through MurmurHash3's 64-bit finalizer so
+//! every output bit depends on every input bit.
+//!
+//! `hashRows` exposes a per-row hash over several columns, for
+//! deduplication, sampling by hash and A/B bucketing that stays consistent
+//! across sessions. Those hashes are seeded XXH64 of the same key bytes, so
+//! one seed per experiment gives independent buckets.
+
+use wasm_bindgen::prelude::*;
+
+use crate::bigint;
+use crate::checksum::xxhash64;
+use crate::columns::{self, bit, Column};
+use crate::distinct::{encode, row_keys};
+use crate::error::KernelError;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// MurmurHash3's `fmix64`.
+pub(crate) fn mix(mut hash: u64) -> u64 {
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+/// `count` probe hashes derived from `hash` by double hashing
+/// (Kirsch-Mitzenmacher), for structures that need several independent
+/// positions per key.
+pub(crate) fn probes(hash: u64, count: usize) -> impl Iterator<Item = u64> {
+    let step = mix(hash ^ 0x9e37_79b9_7f4a_7c15) | 1;
+    (0..count as u64).map(move |probe| hash.wrapping_add(probe.wrapping_mul(step)))
+}
+
+pub(crate) fn hash_bytes(bytes: &[u8]) -> u64 {
+    mix(bytes.iter().fold(FNV_OFFSET, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    }))
+}
+
+/// Hash of the key of `row` in `column`; nulls share one hash, as do NaNs.
+pub(crate) fn key_hash(column: &Column, row: usize, key: &mut Vec<u8>) -> u64 {
+    key.clear();
+    encode(column, row, key);
+    hash_bytes(key)
+}
+
+/// Key hashes of the rows of the column behind `handle` set in `mask` (an
+/// LSB-first row bitmap in the layout's `activeMask` format; every row when
+/// omitted), as `(row, hash)` pairs.
+pub(crate) fn row_hashes(handle: u32, mask: Option<&[u8]>) -> Result<Vec<(u32, u64)>, KernelError> {
+    columns::with_column(handle, |column| {
+        if let Some(mask) = mask {
+            if mask.len() < column.len.div_ceil(8) {
+                return Err(KernelError::invalid_argument("mask is too short")
+                    .with("needed", column.len.div_ceil(8) as f64)
+                    .with("available", mask.len() as f64));
+            }
+        }
+        let mut key = Vec::new();
+        Ok((0..column.len)
+            .filter(|&row| mask.is_none_or(|mask| bit(mask, row)))
+            .map(|row| (row as u32, key_hash(column, row, &mut key)))
+            .collect())
+    })
+}
+
+/// 64-bit row hashes, one per row.
+#[wasm_bindgen]
+pub struct RowHashes {
+    hashes: Vec<u64>,
+}
+
+#[wasm_bindgen]
+impl RowHashes {
+    /// The hashes as a `BigUint64Array`.
+    #[wasm_bindgen(getter)]
+    pub fn hashes(&self) -> Vec<u64> {
+        self.hashes.clone()
+    }
+
+    /// High words of `hashes`, for engines without BigInt arrays.
+    #[wasm_bindgen(getter)]
+    pub fn high(&self) -> Vec<u32> {
+        bigint::high_words(&self.hashes)
+    }
+
+    /// Low words of `hashes`.
+    #[wasm_bindgen(getter)]
+    pub fn low(&self) -> Vec<u32> {
+        bigint::low_words(&self.hashes)
+    }
+}
+
+/// Hash of every row over the columns behind `handles` (equal lengths),
+/// with `seed` (zero when omitted). Rows with equal keys hash equal: nulls
+/// equal each other, as do NaNs, and `-0` equals `0`.
+#[wasm_bindgen(js_name = hashRows)]
+pub fn hash_rows(handles: &[u32], seed: Option<u64>) -> Result<RowHashes, KernelError> {
+    let keys = row_keys("hashRows", handles)?;
+    Ok(RowHashes {
+        hashes: keys.iter().map(|key| xxhash64(key, seed)).collect(),
+    })
+}
+
+/// Bucket of every row, `hash % bucketCount`, from `hashRows` with `seed`:
+/// a stable assignment of rows to `bucketCount` groups (A/B cohorts, or one
+/// bucket in `n` as a `1/n` sample).
+#[wasm_bindgen(js_name = hashBuckets)]
+pub fn hash_buckets(
+    handles: &[u32],
+    bucket_count: u32,
+    seed: Option<u64>,
+) -> Result<Vec<u32>, KernelError> {
+    if bucket_count == 0 {
+        return Err(KernelError::invalid_argument(
+            "bucketCount must be positive",
+        ));
+    }
+    let keys = row_keys("hashBuckets", handles)?;
+    Ok(keys
+        .iter()
+        .map(|key| (xxhash64(key, seed) % u64::from(bucket_count)) as u32)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::floats;
+
+    #[test]
+    fn key_hashes_are_pinned_to_fnv_and_fmix() {
+        assert_eq!(mix(0), 0);
+        assert_eq!(hash_bytes(b""), mix(FNV_OFFSET));
+        // FNV-1a 64 of "a".
+        assert_eq!(hash_bytes(b"a"), mix(0xaf63_dc4c_8601_ec8c));
+        let probes: Vec<u64> = probes(42, 3).collect();
+        assert_eq!(probes.len(), 3);
+        assert_eq!(probes[0], 42);
+        assert_eq!(
+            probes[2].wrapping_sub(probes[1]),
+            probes[1].wrapping_sub(42)
+        );
+        assert_eq!(probes[1].wrapping_sub(42) & 1, 1);
+    }
+
+    #[test]
+    fn equal_keys_hash_equal() {
+        let nan = f64::from_bits(0x7ff8_0000_0000_0001);
+        // Rows 5 and 6 are null.
+        let values = vec![0.0, -0.0, f64::NAN, nan, 1.0, 0.0, 5.0];
+        let handle = floats(values, Some(vec![0x1f]));
+        let hashes = hash_rows(&[handle], None).unwrap().hashes();
+        assert_eq!(hashes[0], hashes[1]);
+        assert_eq!(hashes[2], hashes[3]);
+        assert_eq!(hashes[5], hashes[6]);
+        assert_ne!(hashes[0], hashes[4]);
+        assert_ne!(hashes[0], hashes[5]);
+        assert_ne!(hashes[0], hashes[2]);
+        let mut key = Vec::new();
+        let sketch = row_hashes(handle, None).unwrap();
+        columns::with_column(handle, |column| {
+            assert_eq!(key_hash(column, 1, &mut key), sketch[0].1);
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn seeds_and_columns_change_row_hashes() {
+        let a = floats(vec![1.0, 1.0, 2.0], None);
+        let b = floats(vec![3.0, 4.0, 3.0], None);
+        let unseeded = hash_rows(&[a], None).unwrap().hashes();
+        assert_eq!(hash_rows(&[a], Some(0)).unwrap().hashes(), unseeded);
+        let seeded = hash_rows(&[a], Some(7)).unwrap().hashes();
+        assert_eq!(seeded[0], seeded[1]);
+        assert_ne!(seeded[0], unseeded[0]);
+        let pairs = hash_rows(&[a, b], None).unwrap();
+        assert_ne!(pairs.hashes()[0], pairs.hashes()[1]);
+        let high = u64::from(pairs.high()[2]) << 32;
+        assert_eq!(high | u64::from(pairs.low()[2]), pairs.hashes()[2]);
+        let short = floats(vec![1.0], None);
+        assert!(hash_rows(&[a, short], None).is_err());
+        assert!(hash_rows(&[], None).is_err());
+    }
+
+    #[test]
+    fn buckets_are_hashes_modulo_the_count() {
+        let values: Vec<f64> = (0..4_000).map(f64::from).collect();
+        let handle = floats(values, None);
+        let hashes = hash_rows(&[handle], Some(3)).unwrap().hashes();
+        let buckets = hash_buckets(&[handle], 4, Some(3)).unwrap();
+        let mut sizes = [0; 4];
+        for (&bucket, &hash) in buckets.iter().zip(&hashes) {
+            assert_eq!(u64::from(bucket), hash % 4);
+            sizes[bucket as usize] += 1;
+        }
+        assert!(
+            sizes.iter().all(|&size| (900..1_100).contains(&size)),
+            "{sizes:?}"
+        );
+        assert!(hash_buckets(&[handle], 0, None).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! Histogram results as one object.
+//!
+//! `accumulateBins` returns bare counts, leaving the call's metrics to a
+//! separate `takeMetrics` and its strategy to the invocation history. The
+//! `Result` variants return a `HistogramResult` carrying all three, so
+//! kernels with several outputs need no tuple-of-arrays convention the TS
+//! layer must remember. The outputs stay in wasm until read: each getter
+//! builds its JS value on access, and a caller that only wants the counts
+//! never materializes the metrics.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::metrics::{Metrics, METRICS};
+use crate::{Strategy, WORKSPACE};
+
+/// Outputs of one histogram call.
+#[wasm_bindgen]
+pub struct HistogramResult {
+    counts: Vec<u32>,
+    metrics: Metrics,
+    strategy: Strategy,
+}
+
+#[wasm_bindgen]
+impl HistogramResult {
+    /// Rows per bin.
+    #[wasm_bindgen(getter)]
+    pub fn counts(&self) -> Vec<u32> {
+        self.counts.clone()
+    }
+
+    /// Metrics of this call alone; zero when collection is off.
+    #[wasm_bindgen(getter)]
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    /// Strategy the call ran, never `Auto`.
+    #[wasm_bindgen(getter)]
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+}
+
+impl HistogramResult {
+    /// Collects the outputs of the accumulation that produced `counts`.
+    fn new(counts: Vec<u32>) -> Self {
+        HistogramResult {
+            counts,
+            metrics: METRICS.with(|metrics| metrics.borrow().clone()),
+            strategy: WORKSPACE
+                .with(|workspace| workspace.borrow().resolved)
+                .unwrap_or(Strategy::Sharded),
+        }
+    }
+}
+
+/// `accumulateBins` returning a [`HistogramResult`].
+#[wasm_bindgen(js_name = accumulateBinsResult)]
+pub fn accumulate_bins_result(
+    bins: &[u16],
+    bin_count: u32,
+    dimension: Option<u32>,
+) -> Result<HistogramResult, KernelError> {
+    let counts = crate::accumulate_bins_with(
+        "accumulateBinsResult",
+        bins,
+        bin_count,
+        dimension,
+        <[u32]>::to_vec,
+    )?;
+    Ok(HistogramResult::new(counts))
+}
+
+/// `accumulateScratch` returning a [`HistogramResult`].
+#[wasm_bindgen(js_name = accumulateScratchResult)]
+pub fn accumulate_scratch_result(
+    len: u32,
+    bin_count: u32,
+    dimension: Option<u32>,
+) -> Result<HistogramResult, KernelError> {
+    let counts = crate::accumulate_scratch_with(
+        "accumulateScratchResult",
+        len as usize,
+        bin_count,
+        dimension,
+        <[u32]>::to_vec,
+    )?;
+    Ok(HistogramResult::new(counts))
+}
@@ -0,0 +1,158 @@
+//! Ring buffer of recent kernel invocations.
+//!
+//! Keeps the last few calls (entry point, input size, bin count, duration,
+//! resolved strategy) so field bug reports can include what the wasm layer
+//! actually executed. Recording is off until `setInvocationHistory` gives
+//! the buffer a capacity, so accumulation neither reads the clock nor
+//! touches the buffer by default. The buffer is allocated once when its
+//! capacity is set, so recording stays allocation-free on the hot path.
+
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+
+use crate::recovery;
+use crate::Strategy;
+
+const DEFAULT_CAPACITY: usize = 0;
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Invocation {
+    kernel: &'static str,
+    rows: usize,
+    bin_count: usize,
+    dimension: Option<u32>,
+    strategy: &'static str,
+    duration_ms: f64,
+}
+
+struct History {
+    capacity: usize,
+    entries: VecDeque<Invocation>,
+}
+
+thread_local! {
+    static HISTORY: RefCell<History> = RefCell::new(History {
+        capacity: DEFAULT_CAPACITY,
+        entries: VecDeque::with_capacity(DEFAULT_CAPACITY),
+    });
+}
+
+/// Clears the history and restores its default capacity; see `hardReset`.
+pub(crate) fn reset() {
+    HISTORY.with(|history| {
+        recovery::reset_cell(
+            history,
+            History {
+                capacity: DEFAULT_CAPACITY,
+                entries: VecDeque::with_capacity(DEFAULT_CAPACITY),
+            },
+        )
+    });
+}
+
+pub(crate) fn enabled() -> bool {
+    HISTORY.with(|history| history.borrow().capacity > 0)
+}
+
+pub(crate) fn record(
+    kernel: &'static str,
+    rows: usize,
+    bin_count: usize,
+    dimension: Option<u32>,
+    strategy: Strategy,
+    duration_ms: f64,
+) {
+    HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        if history.capacity == 0 {
+            return;
+        }
+        if history.entries.len() == history.capacity {
+            history.entries.pop_front();
+        }
+        history.entries.push_back(Invocation {
+            kernel,
+            rows,
+            bin_count,
+            dimension,
+            strategy: strategy_name(strategy),
+            duration_ms,
+        });
+    });
+}
+
+fn strategy_name(strategy: Strategy) -> &'static str {
+    match strategy {
+        Strategy::Auto => "auto",
+        Strategy::Scalar => "scalar",
+        Strategy::Sharded => "sharded",
+        Strategy::Sorted => "sorted",
+        Strategy::Unrolled => "unrolled",
+    }
+}
+
+/// Sets how many invocations are retained (default 0, recording off); zero
+/// disables recording again. Shrinking drops the oldest entries.
+#[wasm_bindgen(js_name = setInvocationHistory)]
+pub fn set_invocation_history(capacity: u32) {
+    HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        let capacity = capacity as usize;
+        while history.entries.len() > capacity {
+            history.entries.pop_front();
+        }
+        let additional = capacity.saturating_sub(history.entries.len());
+        history.entries.reserve_exact(additional);
+        history.capacity = capacity;
+    });
+}
+
+/// Returns the retained invocations, oldest first, as plain objects:
+/// `{ kernel, rows, binCount, dimension, strategy, durationMs }`.
+#[wasm_bindgen(js_name = recentInvocations)]
+pub fn recent_invocations() -> Result<JsValue, JsValue> {
+    HISTORY.with(|history| {
+        let history = history.borrow();
+        let entries: Vec<Invocation> = history.entries.iter().copied().collect();
+        serde_wasm_bindgen::to_value(&entries).map_err(JsValue::from)
+    })
+}
+
+/// `recentInvocations()` encoded as MessagePack.
+#[cfg(feature = "msgpack")]
+#[wasm_bindgen(js_name = recentInvocationsMsgpack)]
+pub fn recent_invocations_msgpack() -> Result<Vec<u8>, crate::error::KernelError> {
+    HISTORY.with(|history| crate::msgpack::encode(&history.borrow().entries))
+}
+
+#[wasm_bindgen(js_name = clearInvocations)]
+pub fn clear_invocations() {
+    HISTORY.with(|history| history.borrow_mut().entries.clear());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retained() -> usize {
+        HISTORY.with(|history| history.borrow().entries.len())
+    }
+
+    #[test]
+    fn recording_is_opt_in() {
+        assert!(!enabled());
+        crate::accumulate_bins_with("test", &[0, 1], 2, None, |_| ()).unwrap();
+        assert_eq!(retained(), 0);
+        set_invocation_history(2);
+        for _ in 0..3 {
+            crate::accumulate_bins_with("test", &[0, 1], 2, None, |_| ()).unwrap();
+        }
+        assert_eq!(retained(), 2);
+        reset();
+        assert!(!enabled());
+        assert_eq!(retained(), 0);
+    }
+}
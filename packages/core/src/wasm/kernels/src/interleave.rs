@@ -0,0 +1,69 @@
+//! Interleaved vertex buffers.
+//!
+//! Kernels return one array per output (struct of arrays), while WebGL and
+//! WebGPU draw from a single buffer holding each vertex's attributes side by
+//! side. `interleaveF32` packs the outputs into that layout in one pass, so
+//! a chart uploads the result directly instead of interleaving in JS. Every
+//! attribute becomes one `f32`, which holds integers such as counts and
+//! color indices exactly up to 2^24.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::memory;
+
+/// Writes `columns[c][row]` to `out[row * stride + c]`, leaving the padding
+/// slots past the last column untouched.
+fn interleave(columns: &[Vec<f32>], stride: usize, out: &mut [f32]) {
+    for (offset, column) in columns.iter().enumerate() {
+        for (vertex, &value) in out[offset..].chunks_mut(stride).zip(column) {
+            vertex[0] = value;
+        }
+    }
+}
+
+/// Packs the typed arrays in `columns`, which must have equal lengths, into
+/// one `Float32Array` with each row's values adjacent, in column order. A
+/// `stride` (in floats) beyond the column count leaves zeroed padding after
+/// each vertex, e.g. to align vertices to 16 bytes.
+#[wasm_bindgen(js_name = interleaveF32)]
+pub fn interleave_f32(
+    columns: js_sys::Array,
+    stride: Option<u32>,
+) -> Result<Vec<f32>, KernelError> {
+    let count = columns.length() as usize;
+    if count == 0 {
+        return Err(KernelError::invalid_argument(
+            "interleaveF32 needs at least one column",
+        ));
+    }
+    let stride = stride.map_or(count, |stride| stride as usize);
+    if stride < count {
+        return Err(
+            KernelError::invalid_argument("stride shorter than a vertex")
+                .with("stride", stride as f64)
+                .with("columns", count as f64),
+        );
+    }
+    let columns = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            if !js_sys::ArrayBuffer::is_view(&column) {
+                return Err(KernelError::invalid_argument("column is not a typed array")
+                    .with("column", index as f64));
+            }
+            Ok(js_sys::Float32Array::new(&column).to_vec())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let rows = columns[0].len();
+    if let Some(column) = columns.iter().find(|column| column.len() != rows) {
+        return Err(KernelError::invalid_argument("column lengths differ")
+            .with("expected", rows as f64)
+            .with("actual", column.len() as f64));
+    }
+    memory::check_budget(rows.saturating_mul(stride).saturating_mul(4))?;
+    let mut out = vec![0.0; rows * stride];
+    interleave(&columns, stride, &mut out);
+    Ok(out)
+}
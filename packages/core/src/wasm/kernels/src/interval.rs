@@ -0,0 +1,240 @@
+//! Interval indexes for interval-valued dimensions.
+//!
+//! Rows that span a time range (a session, a booking) are indexed by their
+//! `[start, end]` pair. Intervals are sorted by start and laid out as the
+//! leaves of a tree whose nodes hold the largest end below them. An overlap
+//! query for `[lo, hi]` binary-searches the intervals starting at or before
+//! `hi`, then walks only the subtrees whose largest end reaches `lo`, so it
+//! costs `O(log n)` per matching row instead of a scan. Stabbing ("active at
+//! `t`") is the overlap with `[t, t]`.
+//!
+//! Both ends are inclusive. Rows with a null or NaN end, or that end before
+//! they start, are left out since no query can match them.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, Column};
+use crate::error::{ErrorKind, KernelError};
+use crate::recovery;
+use crate::sort;
+
+struct IntervalIndex {
+    starts: Vec<f64>,
+    rows: Vec<u32>,
+    /// Implicit tree over the intervals in start order: node `1` is the
+    /// root, `2i` and `2i + 1` the children of `i`, and leaf `leaves + i`
+    /// is interval `i`. Each node holds the largest end beneath it.
+    max_end: Vec<f64>,
+    leaves: usize,
+}
+
+impl IntervalIndex {
+    fn new(starts: Vec<f64>, ends: &[f64], rows: Vec<u32>) -> Self {
+        let leaves = starts.len().next_power_of_two();
+        let mut max_end = vec![f64::NEG_INFINITY; 2 * leaves];
+        max_end[leaves..leaves + ends.len()].copy_from_slice(ends);
+        for node in (1..leaves).rev() {
+            max_end[node] = max_end[2 * node].max(max_end[2 * node + 1]);
+        }
+        IntervalIndex {
+            starts,
+            rows,
+            max_end,
+            leaves,
+        }
+    }
+
+    /// Rows whose interval overlaps `[lo, hi]`, in ascending row order.
+    fn overlaps(&self, lo: f64, hi: f64) -> Vec<u32> {
+        let mut found = Vec::new();
+        if lo.is_nan() || hi.is_nan() || self.rows.is_empty() {
+            return found;
+        }
+        let candidates = self.starts.partition_point(|&start| start <= hi);
+        let mut stack = vec![(1, 0, self.leaves)];
+        while let Some((node, first, last)) = stack.pop() {
+            if first >= candidates || self.max_end[node] < lo {
+                continue;
+            }
+            if node >= self.leaves {
+                found.push(self.rows[first]);
+                continue;
+            }
+            let middle = (first + last) / 2;
+            stack.push((2 * node + 1, middle, last));
+            stack.push((2 * node, first, middle));
+        }
+        found.sort_unstable();
+        found
+    }
+}
+
+thread_local! {
+    static INTERVALS: RefCell<HashMap<u32, IntervalIndex>> = RefCell::new(HashMap::new());
+}
+
+/// Drops every interval index; see `hardReset`.
+pub(crate) fn reset() {
+    INTERVALS.with(|intervals| recovery::reset_cell(intervals, HashMap::new()));
+}
+
+fn numbers(column: &Column, role: &str) -> Result<Vec<f64>, KernelError> {
+    if !column.values.is_numeric() {
+        return Err(KernelError::new(
+            ErrorKind::Unsupported,
+            format!("interval {role} column must be numeric"),
+        ));
+    }
+    Ok((0..column.len)
+        .map(|row| match column.values.number(row) {
+            Some(value) if column.is_valid(row) => value,
+            _ => f64::NAN,
+        })
+        .collect())
+}
+
+/// Builds (or rebuilds) the interval index of `dimension` from the numeric
+/// columns behind `startHandle` and `endHandle`, which must have equal
+/// lengths. Returns the number of indexed rows.
+#[wasm_bindgen(js_name = buildIntervalIndex)]
+pub fn build_interval_index(
+    dimension: u32,
+    start_handle: u32,
+    end_handle: u32,
+) -> Result<u32, KernelError> {
+    let starts = columns::with_column(start_handle, |column| numbers(column, "start"))?;
+    let ends = columns::with_column(end_handle, |column| numbers(column, "end"))?;
+    if starts.len() != ends.len() {
+        return Err(
+            KernelError::invalid_argument("interval column lengths differ")
+                .with("starts", starts.len() as f64)
+                .with("ends", ends.len() as f64),
+        );
+    }
+    // `end >= start` is false for NaN on either side.
+    let mut order: Vec<u32> = (0..starts.len() as u32)
+        .filter(|&row| ends[row as usize] >= starts[row as usize])
+        .collect();
+    let keys: Vec<u64> = starts.iter().map(|&start| sort::f64_key(start)).collect();
+    sort::sort_index_permutation(&keys, &mut order);
+    let sorted_ends: Vec<f64> = order.iter().map(|&row| ends[row as usize]).collect();
+    let index = IntervalIndex::new(
+        order.iter().map(|&row| starts[row as usize]).collect(),
+        &sorted_ends,
+        order,
+    );
+    let len = index.rows.len() as u32;
+    INTERVALS.with(|intervals| intervals.borrow_mut().insert(dimension, index));
+    Ok(len)
+}
+
+fn with_intervals<T>(
+    dimension: u32,
+    read: impl FnOnce(&IntervalIndex) -> T,
+) -> Result<T, KernelError> {
+    INTERVALS.with(|intervals| {
+        let intervals = intervals.borrow();
+        let index = intervals.get(&dimension).ok_or_else(|| {
+            KernelError::invalid_state("no interval index for dimension")
+                .with("dimension", f64::from(dimension))
+        })?;
+        Ok(read(index))
+    })
+}
+
+/// Rows whose interval overlaps `[lo, hi]` (both ends inclusive), in
+/// ascending row order. NaN bounds match nothing.
+#[wasm_bindgen(js_name = intervalOverlaps)]
+pub fn interval_overlaps(dimension: u32, lo: f64, hi: f64) -> Result<Vec<u32>, KernelError> {
+    with_intervals(dimension, |index| index.overlaps(lo, hi))
+}
+
+/// Rows whose interval contains `at`, in ascending row order.
+#[wasm_bindgen(js_name = intervalStab)]
+pub fn interval_stab(dimension: u32, at: f64) -> Result<Vec<u32>, KernelError> {
+    with_intervals(dimension, |index| index.overlaps(at, at))
+}
+
+/// Drops the interval index of `dimension`, or every index when omitted.
+#[wasm_bindgen(js_name = releaseIntervalIndex)]
+pub fn release_interval_index(dimension: Option<u32>) {
+    INTERVALS.with(|intervals| match dimension {
+        Some(dimension) => {
+            intervals.borrow_mut().remove(&dimension);
+        }
+        None => intervals.borrow_mut().clear(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::floats;
+
+    #[test]
+    fn empty_columns_build_empty_indexes() {
+        let empty = floats(Vec::new(), None);
+        assert_eq!(build_interval_index(1, empty, empty).unwrap(), 0);
+        assert!(interval_overlaps(1, f64::NEG_INFINITY, f64::INFINITY)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn overlaps_match_a_brute_force_scan() {
+        const ROWS: usize = 150;
+        // Every 11th start is null and every 7th interval ends before it
+        // starts; neither can match.
+        let starts: Vec<f64> = (0..ROWS).map(|row| (row * 37 % 100) as f64).collect();
+        let ends: Vec<f64> = (0..ROWS)
+            .map(|row| match row % 7 {
+                0 => starts[row] - 1.0,
+                _ => starts[row] + (row * 13 % 25) as f64,
+            })
+            .collect();
+        let validity = (0..ROWS.div_ceil(8))
+            .map(|byte| {
+                (0..8)
+                    .filter(|&b| (byte * 8 + b) % 11 != 0)
+                    .fold(0u8, |mask, b| mask | 1 << b)
+            })
+            .collect();
+        let indexed = build_interval_index(
+            2,
+            floats(starts.clone(), Some(validity)),
+            floats(ends.clone(), None),
+        );
+        let valid = |row: usize| !row.is_multiple_of(11) && ends[row] >= starts[row];
+        assert_eq!(
+            indexed.unwrap() as usize,
+            (0..ROWS).filter(|&row| valid(row)).count()
+        );
+
+        let queries = [
+            (0.0, 0.0),
+            (10.0, 20.0),
+            (50.5, 50.5),
+            (99.0, 200.0),
+            (-5.0, -1.0),
+            (f64::NEG_INFINITY, f64::INFINITY),
+            (30.0, 20.0),
+        ];
+        for (lo, hi) in queries {
+            let expected: Vec<u32> = (0..ROWS)
+                .filter(|&row| valid(row) && starts[row] <= hi && ends[row] >= lo)
+                .map(|row| row as u32)
+                .collect();
+            assert_eq!(
+                interval_overlaps(2, lo, hi).unwrap(),
+                expected,
+                "[{lo}, {hi}]"
+            );
+            if lo == hi {
+                assert_eq!(interval_stab(2, lo).unwrap(), expected);
+            }
+        }
+        assert!(interval_overlaps(2, f64::NAN, 1.0).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,416 @@
+//! Hash joins over `u32` keys.
+//!
+//! Small lookups (country names for country ids, customer segments for
+//! orders) join inside wasm instead of going back to the server. The build
+//! phase indexes a dimension table's key column once and keeps it resident;
+//! each probe streams a fact key column past it and emits the matching
+//! `(fact row, dimension row)` pairs of the inner join, from which payload
+//! columns of the dimension table are gathered.
+//!
+//! The index groups the dimension rows by key in one array, with a hash map
+//! from key to its span, so a key repeated across the dimension table joins
+//! to every one of its rows without a list per key.
+//!
+//! When both sides are already ordered by key (time-indexed datasets
+//! aligned for a comparison chart), a merge join walks them side by side
+//! instead: no index to build, sequential reads, and only the output is
+//! allocated.
+//!
+//! A semi-join only asks whether a row's key occurs in a key set ("orders
+//! of the selected customers"), so it answers with a row bitmap that goes
+//! straight into `setFilterMask` and composes with the other filters.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use wasm_bindgen::prelude::*;
+
+use crate::columns::bit;
+use crate::error::KernelError;
+use crate::gather::gather_columns;
+use crate::recovery;
+
+struct JoinTable {
+    /// Dimension rows grouped by key, ascending within a key.
+    rows: Vec<u32>,
+    /// Span of each key's rows in `rows`.
+    spans: HashMap<u32, (u32, u32)>,
+}
+
+thread_local! {
+    static TABLES: RefCell<HashMap<u32, JoinTable>> = RefCell::new(HashMap::new());
+}
+
+/// Drops every join table; see `hardReset`.
+pub(crate) fn reset() {
+    TABLES.with(|tables| recovery::reset_cell(tables, HashMap::new()));
+}
+
+fn check_mask(mask: Option<&[u8]>, rows: usize) -> Result<(), KernelError> {
+    match mask {
+        Some(mask) if mask.len() < rows.div_ceil(8) => {
+            Err(KernelError::invalid_argument("mask is too short")
+                .with("needed", rows.div_ceil(8) as f64)
+                .with("available", mask.len() as f64))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Matched row pairs of an inner join.
+#[wasm_bindgen]
+pub struct JoinedRows {
+    fact_rows: Vec<u32>,
+    dimension_rows: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl JoinedRows {
+    /// Fact row of each match, ascending.
+    #[wasm_bindgen(getter = factRows)]
+    pub fn fact_rows(&self) -> Vec<u32> {
+        self.fact_rows.clone()
+    }
+
+    /// Dimension row of each match; a fact row matching several dimension
+    /// rows repeats, with its dimension rows ascending.
+    #[wasm_bindgen(getter = dimensionRows)]
+    pub fn dimension_rows(&self) -> Vec<u32> {
+        self.dimension_rows.clone()
+    }
+
+    /// Registers, for each dimension table column behind `handles`, a new
+    /// column holding its value for each match (in match order) and returns
+    /// the new handles, as `gatherColumns` with `dimensionRows`.
+    pub fn gather(&self, handles: &[u32]) -> Result<Vec<u32>, KernelError> {
+        gather_columns(handles, &self.dimension_rows)
+    }
+}
+
+/// Builds (or rebuilds) join table `table` over the dimension key column
+/// `keys`, one key per dimension row. Returns the number of distinct keys.
+#[wasm_bindgen(js_name = buildJoinTable)]
+pub fn build_join_table(table: u32, keys: &[u32]) -> u32 {
+    let mut rows: Vec<u32> = (0..keys.len() as u32).collect();
+    rows.sort_by_key(|&row| keys[row as usize]);
+    let mut spans = HashMap::new();
+    let mut start = 0;
+    for group in rows.chunk_by(|&a, &b| keys[a as usize] == keys[b as usize]) {
+        let end = start + group.len() as u32;
+        spans.insert(keys[group[0] as usize], (start, end));
+        start = end;
+    }
+    let distinct = spans.len() as u32;
+    TABLES.with(|tables| tables.borrow_mut().insert(table, JoinTable { rows, spans }));
+    distinct
+}
+
+/// Inner join of the fact key column `keys` against join table `table`,
+/// over the fact rows set in `mask` (an LSB-first row bitmap in the
+/// layout's `activeMask` format such as `filterMask()`; every row when
+/// omitted).
+#[wasm_bindgen(js_name = hashJoin)]
+pub fn hash_join(
+    table: u32,
+    keys: &[u32],
+    mask: Option<Vec<u8>>,
+) -> Result<JoinedRows, KernelError> {
+    check_mask(mask.as_deref(), keys.len())?;
+    TABLES.with(|tables| {
+        let tables = tables.borrow();
+        let join = tables.get(&table).ok_or_else(|| {
+            KernelError::invalid_state("no join table").with("table", f64::from(table))
+        })?;
+        let mut joined = JoinedRows {
+            fact_rows: Vec::new(),
+            dimension_rows: Vec::new(),
+        };
+        for (row, key) in keys.iter().enumerate() {
+            if mask.as_ref().is_some_and(|mask| !bit(mask, row)) {
+                continue;
+            }
+            let Some(&(start, end)) = join.spans.get(key) else {
+                continue;
+            };
+            let matches = &join.rows[start as usize..end as usize];
+            joined
+                .fact_rows
+                .extend(std::iter::repeat_n(row as u32, matches.len()));
+            joined.dimension_rows.extend_from_slice(matches);
+        }
+        Ok(joined)
+    })
+}
+
+/// Matched row pairs of a merge join.
+#[wasm_bindgen]
+pub struct MergedRows {
+    left_rows: Vec<u32>,
+    right_rows: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl MergedRows {
+    /// Left row of each match, in key order.
+    #[wasm_bindgen(getter = leftRows)]
+    pub fn left_rows(&self) -> Vec<u32> {
+        self.left_rows.clone()
+    }
+
+    /// Right row of each match; a left row matching several right rows
+    /// repeats, once per right row in their order.
+    #[wasm_bindgen(getter = rightRows)]
+    pub fn right_rows(&self) -> Vec<u32> {
+        self.right_rows.clone()
+    }
+}
+
+/// Rows of `keys` in key order: `order` when given, otherwise the rows as
+/// they are. `order` may list only some rows (`sortedIndexRows` leaves out
+/// nulls and NaNs); the rest match nothing. Checks that the keys ascend up
+/// to the first NaN and returns the rows before it.
+fn sorted_rows(keys: &[f64], order: Option<Vec<u32>>) -> Result<Vec<u32>, KernelError> {
+    let rows = match order {
+        Some(order) => {
+            let mut listed = vec![0u8; keys.len().div_ceil(8)];
+            for &row in &order {
+                let index = row as usize;
+                if index >= keys.len() {
+                    return Err(KernelError::invalid_argument("order row out of range")
+                        .with("row", f64::from(row))
+                        .with("length", keys.len() as f64));
+                }
+                if bit(&listed, index) {
+                    return Err(KernelError::invalid_argument("order repeats a row")
+                        .with("row", f64::from(row)));
+                }
+                listed[index >> 3] |= 1 << (index & 7);
+            }
+            order
+        }
+        None => (0..keys.len() as u32).collect(),
+    };
+    let end = rows
+        .iter()
+        .position(|&row| keys[row as usize].is_nan())
+        .unwrap_or(rows.len());
+    if let Some(position) = rows[..end]
+        .windows(2)
+        .position(|pair| keys[pair[1] as usize] < keys[pair[0] as usize])
+    {
+        return Err(KernelError::invalid_argument("keys are not sorted")
+            .with("position", (position + 1) as f64));
+    }
+    Ok(rows[..end].to_vec())
+}
+
+/// Inner join of `leftKeys` with `rightKeys` on equal keys by merging, for
+/// inputs already in ascending key order, either as stored or through
+/// `leftOrder` / `rightOrder`: rows in ascending key order, each at most
+/// once. An order may cover only some rows, so `sortedIndexRows` of a
+/// dimension's sorted index joins directly (keeping full `f64` precision,
+/// which `argsortF32` loses on millisecond timestamps); `argsortU32`,
+/// `argsortI32` and `argsortF32` permutations work too. NaN keys match
+/// nothing and must come last, as those orders place them.
+#[wasm_bindgen(js_name = mergeJoin)]
+pub fn merge_join(
+    left_keys: &[f64],
+    right_keys: &[f64],
+    left_order: Option<Vec<u32>>,
+    right_order: Option<Vec<u32>>,
+) -> Result<MergedRows, KernelError> {
+    let left = sorted_rows(left_keys, left_order)?;
+    let right = sorted_rows(right_keys, right_order)?;
+    let mut merged = MergedRows {
+        left_rows: Vec::new(),
+        right_rows: Vec::new(),
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        let key = left_keys[left[i] as usize];
+        let other = right_keys[right[j] as usize];
+        if key < other {
+            i += 1;
+        } else if other < key {
+            j += 1;
+        } else {
+            // Cross the runs of equal keys on both sides.
+            let run = right[j..]
+                .iter()
+                .take_while(|&&row| right_keys[row as usize] == key)
+                .count();
+            while i < left.len() && left_keys[left[i] as usize] == key {
+                merged.left_rows.extend(std::iter::repeat_n(left[i], run));
+                merged.right_rows.extend_from_slice(&right[j..j + run]);
+                i += 1;
+            }
+            j += run;
+        }
+    }
+    Ok(merged)
+}
+
+/// Row bitmap (LSB-first, in the layout's `activeMask` format, ready for
+/// `setFilterMask`) of the rows whose key in `keys` occurs in `keySet`,
+/// limited to the rows set in `mask` when given.
+#[wasm_bindgen(js_name = semiJoinMask)]
+pub fn semi_join_mask(
+    keys: &[u32],
+    key_set: &[u32],
+    mask: Option<Vec<u8>>,
+) -> Result<Vec<u8>, KernelError> {
+    check_mask(mask.as_deref(), keys.len())?;
+    let set: HashSet<u32> = key_set.iter().copied().collect();
+    let mut kept = vec![0u8; keys.len().div_ceil(8)];
+    for (row, key) in keys.iter().enumerate() {
+        if set.contains(key) && mask.as_ref().is_none_or(|mask| bit(mask, row)) {
+            kept[row >> 3] |= 1 << (row & 7);
+        }
+    }
+    Ok(kept)
+}
+
+/// Drops join table `table`, or every one when omitted.
+#[wasm_bindgen(js_name = releaseJoinTable)]
+pub fn release_join_table(table: Option<u32>) {
+    TABLES.with(|tables| match table {
+        Some(table) => {
+            tables.borrow_mut().remove(&table);
+        }
+        None => tables.borrow_mut().clear(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::floats;
+    use crate::sorted_index::{build_sorted_index, sorted_index_length, sorted_index_rows};
+
+    /// Every `(left, right)` pair with equal, non-NaN keys, left rows in key
+    /// order as the merge emits them.
+    fn nested_loop(left: &[f64], right: &[f64]) -> Vec<(u32, u32)> {
+        let mut pairs = Vec::new();
+        for (i, &key) in left.iter().enumerate() {
+            for (j, &other) in right.iter().enumerate() {
+                if key == other {
+                    pairs.push((i as u32, j as u32));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| {
+            left[a.0 as usize]
+                .total_cmp(&left[b.0 as usize])
+                .then(a.cmp(b))
+        });
+        pairs
+    }
+
+    fn pairs(merged: &MergedRows) -> Vec<(u32, u32)> {
+        merged
+            .left_rows()
+            .into_iter()
+            .zip(merged.right_rows())
+            .collect()
+    }
+
+    /// Rows of a sorted index over `keys`, which leaves out nulls and NaNs.
+    fn index_rows(dimension: u32, keys: &[f64]) -> Vec<u32> {
+        let handle = floats(keys.to_vec(), None);
+        build_sorted_index(dimension, handle).unwrap();
+        let len = sorted_index_length(dimension).unwrap();
+        sorted_index_rows(dimension, 0, len).unwrap()
+    }
+
+    #[test]
+    fn hash_joins_match_a_nested_loop() {
+        // Keys 0..8 with 3 and 5 repeated; fact keys 0..12 miss some.
+        let dimension_keys = [4, 3, 0, 5, 7, 3, 1, 5, 6, 2, 3];
+        let fact_keys: Vec<u32> = (0..60).map(|row| row * 7 % 12).collect();
+        assert_eq!(build_join_table(1, &dimension_keys), 8);
+        let mask = vec![0xb6; 8];
+        let joined = hash_join(1, &fact_keys, Some(mask.clone())).unwrap();
+        let mut expected = Vec::new();
+        for (fact, &key) in fact_keys.iter().enumerate() {
+            for (dimension, &other) in dimension_keys.iter().enumerate() {
+                if key == other && bit(&mask, fact) {
+                    expected.push((fact as u32, dimension as u32));
+                }
+            }
+        }
+        let pairs: Vec<(u32, u32)> = joined
+            .fact_rows()
+            .into_iter()
+            .zip(joined.dimension_rows())
+            .collect();
+        assert_eq!(pairs, expected);
+
+        let payload: Vec<f64> = (0..dimension_keys.len()).map(|row| row as f64).collect();
+        let gathered = joined.gather(&[floats(payload, None)]).unwrap();
+        let values = crate::columns::with_column(gathered[0], |column| {
+            Ok((0..column.len)
+                .map(|row| column.values.number(row).unwrap())
+                .collect::<Vec<_>>())
+        })
+        .unwrap();
+        let dimension_rows: Vec<f64> = expected.iter().map(|&(_, row)| f64::from(row)).collect();
+        assert_eq!(values, dimension_rows);
+
+        assert!(hash_join(1, &fact_keys, Some(vec![0; 7])).is_err());
+        release_join_table(Some(1));
+        assert!(hash_join(1, &fact_keys, None).is_err());
+    }
+
+    #[test]
+    fn merge_joins_sorted_index_rows() {
+        // Millisecond timestamps `f32` cannot tell apart, and NaNs.
+        let base = 1.7e12;
+        let left = [base + 3.0, f64::NAN, base + 1.0, base + 2.0, base + 1.0];
+        let right = [base + 1.0, base + 2.0, f64::NAN, base + 5.0, base + 1.0];
+        let (left_order, right_order) = (index_rows(1, &left), index_rows(2, &right));
+        assert_eq!(left_order.len(), 4);
+        let merged = merge_join(&left, &right, Some(left_order), Some(right_order)).unwrap();
+        assert_eq!(pairs(&merged), nested_loop(&left, &right));
+        assert_eq!(merged.left_rows().len(), 5);
+    }
+
+    #[test]
+    fn merge_joins_match_a_nested_loop() {
+        let left: Vec<f64> = (0..40).map(|row| f64::from(row * 7 % 11)).collect();
+        let right: Vec<f64> = (0..25).map(|row| f64::from(row * 3 % 13)).collect();
+        let order = |keys: &[f64]| {
+            let mut rows: Vec<u32> = (0..keys.len() as u32).collect();
+            rows.sort_by(|&a, &b| keys[a as usize].total_cmp(&keys[b as usize]));
+            rows
+        };
+        let merged = merge_join(&left, &right, Some(order(&left)), Some(order(&right))).unwrap();
+        assert_eq!(pairs(&merged), nested_loop(&left, &right));
+    }
+
+    #[test]
+    fn stored_and_argsort_orders_match_a_nested_loop() {
+        // Ascending as stored, with runs on both sides and trailing NaNs.
+        let left = [1.0, 1.0, 2.0, 4.0, 4.0, 4.0, 9.0, f64::NAN];
+        let right = [0.0, 1.0, 4.0, 4.0, 5.0, 9.0, 9.0, f64::NAN, f64::NAN];
+        let merged = merge_join(&left, &right, None, None).unwrap();
+        assert_eq!(pairs(&merged), nested_loop(&left, &right));
+
+        let left: Vec<f64> = (0..30).map(|row| f64::from(row * 11 % 9)).collect();
+        let right: Vec<f64> = (0..20).map(|row| f64::from(row * 5 % 7)).collect();
+        let argsort = |keys: &[f64]| {
+            let keys: Vec<f32> = keys.iter().map(|&key| key as f32).collect();
+            crate::sort::argsort_f32(&keys, false)
+        };
+        let merged = merge_join(&left, &right, Some(argsort(&left)), Some(argsort(&right)));
+        assert_eq!(pairs(&merged.unwrap()), nested_loop(&left, &right));
+    }
+
+    #[test]
+    fn orders_must_be_sorted_in_range_and_distinct() {
+        let keys = [1.0, 2.0, 3.0];
+        assert!(merge_join(&keys, &keys, Some(vec![1, 0]), None).is_err());
+        assert!(merge_join(&keys, &keys, Some(vec![0, 3]), None).is_err());
+        assert!(merge_join(&keys, &keys, Some(vec![0, 0]), None).is_err());
+        let merged = merge_join(&keys, &keys, Some(vec![2]), None).unwrap();
+        assert_eq!(pairs(&merged), [(2, 2)]);
+    }
+}
@@ -0,0 +1,142 @@
+//! Group results with their bin keys.
+//!
+//! Materializes each bin's key next to its aggregates, so the TS layer gets
+//! render-ready `(key, count, sum)` tuples instead of joining bin indices
+//! back to values. Numeric keys are the value a bin's rows round to under
+//! `quantizeColumn` (its center), which for temporal dimensions is epoch
+//! milliseconds; categorical keys are the dimension's category labels.
+
+use wasm_bindgen::prelude::*;
+
+use crate::categories;
+use crate::error::KernelError;
+
+/// How a group's bins map back to keys.
+#[wasm_bindgen]
+pub struct GroupKeys {
+    source: KeySource,
+}
+
+enum KeySource {
+    /// Bins from `quantizeColumn` over `[min, max]`.
+    Range { min: f64, max: f64 },
+    /// Bins from `categorizeColumn` for a dimension.
+    Categories(u32),
+}
+
+#[wasm_bindgen]
+impl GroupKeys {
+    /// Keys for bins quantized over `[min, max]` (numbers or epoch
+    /// milliseconds).
+    pub fn numeric(min: f64, max: f64) -> GroupKeys {
+        GroupKeys {
+            source: KeySource::Range { min, max },
+        }
+    }
+
+    /// Keys for bins coded by `dimension`'s category dictionary.
+    pub fn categories(dimension: u32) -> GroupKeys {
+        GroupKeys {
+            source: KeySource::Categories(dimension),
+        }
+    }
+}
+
+/// Bins with keys and aggregates aligned.
+#[wasm_bindgen]
+pub struct KeyedGroups {
+    bins: Vec<u32>,
+    keys: Vec<f64>,
+    labels: Vec<String>,
+    counts: Vec<u32>,
+    sums: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl KeyedGroups {
+    #[wasm_bindgen(getter)]
+    pub fn bins(&self) -> Vec<u32> {
+        self.bins.clone()
+    }
+
+    /// Numeric keys; empty for categorical groups.
+    #[wasm_bindgen(getter)]
+    pub fn keys(&self) -> Vec<f64> {
+        self.keys.clone()
+    }
+
+    /// Category labels; empty for numeric groups.
+    #[wasm_bindgen(getter)]
+    pub fn labels(&self) -> Vec<String> {
+        self.labels.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn counts(&self) -> Vec<u32> {
+        self.counts.clone()
+    }
+
+    /// Sums aligned with `bins`; empty when the group has no measure.
+    #[wasm_bindgen(getter)]
+    pub fn sums(&self) -> Vec<f64> {
+        self.sums.clone()
+    }
+}
+
+impl KeyedGroups {
+    /// Keys the bins of `counts` (and `sums`, when not empty), leaving out
+    /// empty bins when `skip_empty`.
+    pub(crate) fn new(counts: &[u32], sums: &[f64], keys: &GroupKeys, skip_empty: bool) -> Self {
+        let bins: Vec<u32> = (0..counts.len() as u32)
+            .filter(|&bin| !skip_empty || counts[bin as usize] > 0)
+            .collect();
+        let (keys, labels) = match keys.source {
+            KeySource::Range { min, max } => {
+                let step = if counts.len() > 1 && max > min {
+                    (max - min) / (counts.len() - 1) as f64
+                } else {
+                    0.0
+                };
+                let keys = bins.iter().map(|&bin| min + f64::from(bin) * step);
+                (keys.collect(), Vec::new())
+            }
+            KeySource::Categories(dimension) => {
+                let all = categories::category_labels(dimension);
+                let labels = bins
+                    .iter()
+                    .map(|&bin| all.get(bin as usize).cloned().unwrap_or_default());
+                (Vec::new(), labels.collect())
+            }
+        };
+        KeyedGroups {
+            counts: bins.iter().map(|&bin| counts[bin as usize]).collect(),
+            sums: if sums.is_empty() {
+                Vec::new()
+            } else {
+                bins.iter().map(|&bin| sums[bin as usize]).collect()
+            },
+            bins,
+            keys,
+            labels,
+        }
+    }
+}
+
+/// `accumulateBins` with each bin's key alongside its count, leaving out
+/// empty bins when `skipEmpty`.
+#[wasm_bindgen(js_name = accumulateBinsKeyed)]
+pub fn accumulate_bins_keyed(
+    bins: &[u16],
+    bin_count: u32,
+    dimension: Option<u32>,
+    keys: &GroupKeys,
+    skip_empty: bool,
+) -> Result<KeyedGroups, KernelError> {
+    crate::accumulate_bins_with(
+        "accumulateBinsKeyed",
+        bins,
+        bin_count,
+        dimension,
+        |counts| KeyedGroups::new(counts, &[], keys, skip_empty),
+    )
+}
@@ -0,0 +1,343 @@
+//! KLL quantile sketches.
+//!
+//! The bounded-error alternative to t-digest: a KLL sketch keeps a stack of
+//! compactors, level `h` holding items that each stand for `2^h` values.
+//! A full level is sorted and every other item (from a random offset) is
+//! promoted, so the rank error of any query is bounded with high
+//! probability regardless of the distribution (`kllRankError`), where
+//! t-digest's error depends on the data. Capacities shrink by 2/3 per level
+//! below the top, so a sketch holds about `3k` items.
+//!
+//! Sketches serialize little-endian: `k` and the level count (`u32`), the
+//! value count (`u64`), minimum and maximum (`f64`), then per level its
+//! length (`u32`) and items (`f64`). Merging stacks the levels of both and
+//! compacts, so sketches built per worker or per chunk combine.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::random::{self, Rng};
+use crate::select::{check_quantiles, masked_values};
+
+const DEFAULT_K: u32 = 200;
+const MIN_K: u32 = 8;
+const MAX_K: u32 = 65_535;
+const HEADER: usize = 32;
+
+struct Kll {
+    k: u32,
+    count: u64,
+    min: f64,
+    max: f64,
+    /// Items per level; level `h` items weigh `2^h`.
+    levels: Vec<Vec<f64>>,
+    /// Picks the offset of each compaction, from the `setRandomSeed` seed.
+    coin: Rng,
+}
+
+impl Kll {
+    fn new(k: u32) -> Self {
+        Kll {
+            k,
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            levels: vec![Vec::new()],
+            coin: random::generator(None),
+        }
+    }
+
+    fn capacity(&self, level: usize) -> usize {
+        let depth = (self.levels.len() - 1 - level) as i32;
+        (f64::from(self.k) * (2.0f64 / 3.0).powi(depth))
+            .ceil()
+            .max(2.0) as usize
+    }
+
+    fn is_full(&self) -> bool {
+        let size: usize = self.levels.iter().map(Vec::len).sum();
+        let capacity: usize = (0..self.levels.len())
+            .map(|level| self.capacity(level))
+            .sum();
+        size >= capacity
+    }
+
+    /// Compacts the lowest level at capacity into the one above.
+    fn compact(&mut self) {
+        let Some(level) =
+            (0..self.levels.len()).find(|&level| self.levels[level].len() >= self.capacity(level))
+        else {
+            return;
+        };
+        if level + 1 == self.levels.len() {
+            self.levels.push(Vec::new());
+        }
+        let mut items = std::mem::take(&mut self.levels[level]);
+        // An odd item out stays behind.
+        if items.len() % 2 == 1 {
+            self.levels[level].push(items.pop().expect("odd length"));
+        }
+        items.sort_unstable_by(f64::total_cmp);
+        let offset = (self.coin.next() & 1) as usize;
+        let promoted = items.into_iter().skip(offset).step_by(2);
+        self.levels[level + 1].extend(promoted);
+    }
+
+    fn insert(&mut self, value: f64) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.levels[0].push(value);
+        if self.is_full() {
+            self.compact();
+        }
+    }
+
+    /// Summed weight of the held items, or `None` on overflow. Compaction
+    /// promotes an even number of items, so this always equals `count`.
+    fn weight(&self) -> Option<u64> {
+        self.levels
+            .iter()
+            .enumerate()
+            .try_fold(0u64, |total, (level, items)| {
+                let weight = (items.len() as u64).checked_mul(1u64.checked_shl(level as u32)?)?;
+                total.checked_add(weight)
+            })
+    }
+
+    fn quantile(&self, q: f64) -> f64 {
+        if self.count == 0 {
+            return f64::NAN;
+        }
+        if q == 0.0 {
+            return self.min;
+        }
+        if q == 1.0 {
+            return self.max;
+        }
+        let mut weighted: Vec<(f64, u64)> = self
+            .levels
+            .iter()
+            .enumerate()
+            .flat_map(|(level, items)| items.iter().map(move |&item| (item, 1u64 << level)))
+            .collect();
+        weighted.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        // Item weights sum to `count`, checked when deserializing.
+        let target = q * self.count as f64;
+        let mut seen = 0u64;
+        for (item, weight) in weighted {
+            seen = seen.saturating_add(weight);
+            if seen as f64 >= target {
+                return item;
+            }
+        }
+        self.max
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER);
+        bytes.extend_from_slice(&self.k.to_le_bytes());
+        bytes.extend_from_slice(&(self.levels.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.count.to_le_bytes());
+        bytes.extend_from_slice(&self.min.to_le_bytes());
+        bytes.extend_from_slice(&self.max.to_le_bytes());
+        for items in &self.levels {
+            bytes.extend_from_slice(&(items.len() as u32).to_le_bytes());
+            for item in items {
+                bytes.extend_from_slice(&item.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, KernelError> {
+        let invalid = || {
+            KernelError::invalid_argument("KLL sketch is malformed")
+                .with("length", bytes.len() as f64)
+        };
+        let mut at = 0;
+        let mut take = |len: usize| {
+            let slice = bytes.get(at..at + len).ok_or_else(invalid)?;
+            at += len;
+            Ok::<&[u8], KernelError>(slice)
+        };
+        let word = |slice: &[u8]| u32::from_le_bytes(slice.try_into().expect("4 bytes"));
+        let double = |slice: &[u8]| f64::from_le_bytes(slice.try_into().expect("8 bytes"));
+        let k = word(take(4)?);
+        check_k(k)?;
+        let level_count = word(take(4)?) as usize;
+        let count = u64::from_le_bytes(take(8)?.try_into().expect("8 bytes"));
+        let (min, max) = (double(take(8)?), double(take(8)?));
+        if level_count == 0 || level_count > 64 {
+            return Err(invalid());
+        }
+        let mut levels = Vec::with_capacity(level_count);
+        for _ in 0..level_count {
+            let len = word(take(4)?) as usize;
+            let items = take(len.checked_mul(8).ok_or_else(invalid)?)?;
+            levels.push(items.chunks_exact(8).map(double).collect());
+        }
+        if take(1).is_ok() {
+            return Err(invalid());
+        }
+        let sketch = Kll {
+            k,
+            count,
+            min,
+            max,
+            levels,
+            coin: random::generator(None),
+        };
+        // Items must account for exactly `count` values, within extremes
+        // that bound every one of them.
+        if sketch.weight() != Some(count) {
+            return Err(invalid().with("count", count as f64));
+        }
+        let mut items = sketch.levels.iter().flatten();
+        if count > 0 && !(min <= max && items.all(|&item| min <= item && item <= max)) {
+            return Err(invalid());
+        }
+        Ok(sketch)
+    }
+}
+
+fn check_k(k: u32) -> Result<(), KernelError> {
+    if !(MIN_K..=MAX_K).contains(&k) {
+        return Err(
+            KernelError::invalid_argument("k must be between 8 and 65535").with("k", f64::from(k)),
+        );
+    }
+    Ok(())
+}
+
+/// Serialized KLL sketch of the numeric column behind `handle` over the
+/// rows set in `mask` (an LSB-first row bitmap in the layout's `activeMask`
+/// format such as `filterMask()`; every row when omitted), skipping nulls
+/// and NaNs. `k` (8 to 65535, default 200) trades size for accuracy.
+#[wasm_bindgen(js_name = kllSketch)]
+pub fn kll_sketch(
+    handle: u32,
+    k: Option<u32>,
+    mask: Option<Vec<u8>>,
+) -> Result<Vec<u8>, KernelError> {
+    let k = k.unwrap_or(DEFAULT_K);
+    check_k(k)?;
+    let mut sketch = Kll::new(k);
+    for value in masked_values(handle, mask.as_deref(), "kllSketch")? {
+        sketch.insert(value);
+    }
+    Ok(sketch.to_bytes())
+}
+
+/// The sketch of the union of the values behind sketches `a` and `b`, at
+/// the smaller of their `k`s.
+#[wasm_bindgen(js_name = kllMerge)]
+pub fn kll_merge(a: &[u8], b: &[u8]) -> Result<Vec<u8>, KernelError> {
+    let (mut merged, b) = (Kll::from_bytes(a)?, Kll::from_bytes(b)?);
+    merged.k = merged.k.min(b.k);
+    merged.count = merged
+        .count
+        .checked_add(b.count)
+        .ok_or_else(|| KernelError::invalid_argument("merged KLL sketch counts too many values"))?;
+    merged.min = merged.min.min(b.min);
+    merged.max = merged.max.max(b.max);
+    if merged.levels.len() < b.levels.len() {
+        merged.levels.resize_with(b.levels.len(), Vec::new);
+    }
+    for (level, items) in b.levels.into_iter().enumerate() {
+        merged.levels[level].extend(items);
+    }
+    while merged.is_full() {
+        merged.compact();
+    }
+    Ok(merged.to_bytes())
+}
+
+/// Estimated `quantiles` (each in `[0, 1]`) of the values behind `sketch`;
+/// NaN for an empty sketch. Quantile 0 and 1 are the exact minimum and
+/// maximum; others are values the sketch holds.
+#[wasm_bindgen(js_name = kllQuantiles)]
+pub fn kll_quantiles(sketch: &[u8], quantiles: &[f64]) -> Result<Vec<f64>, KernelError> {
+    check_quantiles(quantiles)?;
+    let sketch = Kll::from_bytes(sketch)?;
+    Ok(quantiles.iter().map(|&q| sketch.quantile(q)).collect())
+}
+
+/// Normalized rank error a sketch with parameter `k` stays within with 99%
+/// confidence, from the Apache DataSketches fit `2.296 / k^0.9723`.
+#[wasm_bindgen(js_name = kllRankError)]
+pub fn kll_rank_error(k: u32) -> Result<f64, KernelError> {
+    check_k(k)?;
+    Ok(2.296 / f64::from(k).powf(0.9723))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::scrambled;
+
+    /// Largest gap between each requested quantile and the normalized rank
+    /// of its estimate among `0..len`.
+    fn worst_rank_error(sketch: &[u8], len: usize) -> f64 {
+        let quantiles: Vec<f64> = (1..100).map(|percent| f64::from(percent) / 100.0).collect();
+        let estimates = kll_quantiles(sketch, &quantiles).unwrap();
+        quantiles
+            .iter()
+            .zip(estimates)
+            .map(|(&q, estimate)| ((estimate + 1.0) / len as f64 - q).abs())
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn quantiles_stay_within_the_rank_error() {
+        let len = 20_000;
+        let sketch = kll_sketch(scrambled(0, len), Some(200), None).unwrap();
+        assert!(sketch.len() < HEADER + 64 * 4 + 4 * 200 * 8);
+        assert!(worst_rank_error(&sketch, len) <= kll_rank_error(200).unwrap());
+        let exact = kll_quantiles(&sketch, &[0.0, 1.0]).unwrap();
+        assert_eq!(exact, [0.0, (len - 1) as f64]);
+    }
+
+    #[test]
+    fn merged_sketches_stay_within_the_rank_error() {
+        let (half, len) = (7_000, 14_000);
+        let a = kll_sketch(scrambled(0, half), Some(200), None).unwrap();
+        let b = kll_sketch(scrambled(half, half), Some(100), None).unwrap();
+        let merged = kll_merge(&a, &b).unwrap();
+        let decoded = Kll::from_bytes(&merged).unwrap();
+        assert_eq!((decoded.k, decoded.count), (100, len as u64));
+        assert!(worst_rank_error(&merged, len) <= kll_rank_error(100).unwrap());
+        let exact = kll_quantiles(&merged, &[0.0, 1.0]).unwrap();
+        assert_eq!(exact, [0.0, (len - 1) as f64]);
+    }
+
+    #[test]
+    fn inconsistent_counts_are_rejected() {
+        let sketch = kll_sketch(scrambled(0, 1_000), Some(8), None).unwrap();
+        assert!(Kll::from_bytes(&sketch).is_ok());
+        for count in [0u64, 999, 1_001, u64::MAX] {
+            let mut forged = sketch.clone();
+            forged[8..16].copy_from_slice(&count.to_le_bytes());
+            assert!(kll_quantiles(&forged, &[0.5]).is_err(), "count {count}");
+        }
+        // Two items at level 63 overflow the weight.
+        let mut forged = Vec::new();
+        forged.extend_from_slice(&8u32.to_le_bytes());
+        forged.extend_from_slice(&64u32.to_le_bytes());
+        forged.extend_from_slice(&u64::MAX.to_le_bytes());
+        forged.extend_from_slice(&0.0f64.to_le_bytes());
+        forged.extend_from_slice(&1.0f64.to_le_bytes());
+        for level in 0..64 {
+            let len = if level == 63 { 2u32 } else { 0 };
+            forged.extend_from_slice(&len.to_le_bytes());
+            for _ in 0..len {
+                forged.extend_from_slice(&0.5f64.to_le_bytes());
+            }
+        }
+        assert!(kll_quantiles(&forged, &[0.5]).is_err());
+        // An item outside the extremes.
+        let mut forged = sketch;
+        forged[24..32].copy_from_slice(&1.0f64.to_le_bytes());
+        assert!(kll_quantiles(&forged, &[0.5]).is_err());
+    }
+}
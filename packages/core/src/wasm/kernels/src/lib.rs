@@ -6,22 +6,348 @@
 //! mirrors the interface the TypeScript layer expects, so we can swap in a
 //! future SIMD-enabled version without touching the higher-level plumbing.
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 
+#[macro_use]
+mod trace;
+#[macro_use]
+mod log;
+mod anomaly;
+mod arrow;
+mod arrow_export;
+mod autocorrelation;
+mod bigint;
+mod bits;
+mod bloom;
+mod buffers;
+mod categories;
+mod checksum;
+mod columns;
+mod composite;
+#[cfg(feature = "zstd")]
+mod compression;
+mod count_min;
+mod cusum;
+mod decimal;
+mod delta;
+mod distinct;
+mod encodings;
+mod error;
+mod ewma;
+mod extent;
+mod fenwick;
+mod filters;
+mod flatbuf;
+mod gather;
+mod group_by;
+mod half;
+mod hash;
+mod histogram;
+mod history;
+mod hll;
+mod interleave;
+mod interval;
+mod join;
+mod kde;
+mod keyed;
+mod kll;
+mod lz4;
+mod m4;
+mod memory;
+mod metrics;
+mod minhash;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+mod ordered;
+mod overflow;
+mod packed;
+mod parity;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod pivot;
+mod prefix;
+mod protocol;
+mod random;
+mod rebin;
+mod recovery;
+mod reducer;
+mod resample;
+mod reservoir;
+mod results;
+mod rolling;
+#[cfg(feature = "threads")]
+mod sample_sort;
+mod scratch;
+mod select;
+mod shared;
+mod sort;
+mod sorted_index;
+mod space_saving;
+mod stream;
+mod summation;
+mod tdigest;
+mod temporal;
+#[cfg(feature = "parquet")]
+mod thrift;
+mod unique;
+mod validate;
+
+pub use anomaly::{bin_z_scores, BinScores};
+pub use arrow::{
+    abort_arrow_ingest, begin_arrow_ingest, finish_arrow_ingest, ingest_arrow_stream,
+    push_arrow_chunk,
+};
+pub use arrow_export::encode_groups_arrow;
+pub use autocorrelation::autocorrelation;
+pub use bits::{bool_column_mask, pack_bools, unpack_bools};
+pub use bloom::{build_bloom_filter, probe_bloom_filter};
+pub use buffers::{
+    bin_arrow_dictionary, bin_arrow_numeric, bin_arrow_utf8, set_category_dictionary,
+};
+pub use categories::{category_labels, reset_categories};
+pub use checksum::{column_checksum, crc32, scratch_checksum, xxhash64};
+pub use columns::{
+    categorize_column, column_length, column_name, column_null_count, column_timezone, column_type,
+    quantize_column, release_column,
+};
+pub use composite::{categorize_composite, composite_keys, composite_part_labels, reset_composite};
+#[cfg(feature = "zstd")]
+pub use compression::ingest_zstd_column;
+pub use count_min::{
+    build_count_min, count_min_estimate, count_min_heavy_hitters, release_count_min, HeavyHitters,
+};
+pub use cusum::{cusum, Cusum};
+pub use decimal::{aggregate_decimal128, aggregate_decimal_column, DecimalAggregates};
+pub use delta::{
+    accumulate_batch_sparse, accumulate_bins_sparse, accumulate_scratch_sparse, BinChanges,
+};
+pub use distinct::distinct_rows;
+pub use encodings::{decode_delta_binary_packed_values, decode_rle_hybrid};
+pub use error::{ErrorKind, KernelError};
+pub use ewma::{ewma_by_count, ewma_by_time, EwmaGap};
+pub use extent::{column_extent, extent_f32, extent_f64, Extent};
+pub use fenwick::{build_range_tree, range_tree_aggregate, release_range_tree, update_range_tree};
+pub use filters::{
+    filter_all, filter_exact, filter_list, filter_mask, filter_range, group_all_value,
+    reset_filters, set_filter_mask, FilterChanges, Reduce,
+};
+pub use gather::{gather_columns, gather_f32, gather_f64, gather_i32, gather_u16, gather_u32};
+pub use group_by::{group_by_keys, SparseGroups};
+pub use half::decode_float16;
+pub use hash::{hash_buckets, hash_rows, RowHashes};
+pub use histogram::{accumulate_bins_result, accumulate_scratch_result, HistogramResult};
+#[cfg(feature = "msgpack")]
+pub use history::recent_invocations_msgpack;
+pub use history::{clear_invocations, recent_invocations, set_invocation_history};
+pub use hll::{hll_estimate, hll_merge, hll_sketch};
+pub use interleave::interleave_f32;
+pub use interval::{
+    build_interval_index, interval_overlaps, interval_stab, release_interval_index,
+};
+pub use join::{
+    build_join_table, hash_join, merge_join, release_join_table, semi_join_mask, JoinedRows,
+    MergedRows,
+};
+pub use kde::{smooth_histogram, DensityKernel};
+pub use keyed::{accumulate_bins_keyed, GroupKeys, KeyedGroups};
+pub use kll::{kll_merge, kll_quantiles, kll_rank_error, kll_sketch};
+pub use log::{log_level, set_log_level, set_log_sink, LogLevel};
+pub use lz4::decompress_lz4_block;
+pub use m4::m4_downsample;
+pub use memory::{
+    memory_budget, memory_stats, reserve_memory, reset_memory_peak, set_memory_budget, MemoryStats,
+};
+use metrics::METRICS;
+pub use metrics::{
+    reset_metrics, set_dropped_sample_limit, set_metrics_enabled, take_metrics, FlushSizes,
+    KernelTimings, Metrics,
+};
+pub use minhash::{minhash_signatures, minhash_similarity};
+#[cfg(feature = "msgpack")]
+pub use msgpack::encode_groups_msgpack;
+pub use ordered::{
+    accumulate_bins_ordered, accumulate_bins_top, accumulate_scratch_top, GroupOrder, OrderedGroups,
+};
+pub use overflow::{accumulate_bins_checked, CheckedCounts, OverflowMode};
+pub use packed::{accumulate_batch_packed, accumulate_batch_sparse_packed};
+pub use parity::{set_simd_parity, simd_parity};
+#[cfg(feature = "parquet")]
+pub use parquet::ingest_parquet_column;
+pub use pivot::{pivot_table, PivotTable};
+pub use prefix::{empirical_cdf, prefix_sum};
+pub use protocol::execute;
+pub use random::{random_seed, set_random_seed};
+pub use rebin::{rebin_counts, rebin_scratch, remap_bins, remap_counts, remap_scratch};
+pub use recovery::{hard_reset, is_poisoned};
+pub use reducer::{
+    attach_reducer, build_reducer, reducer_add, reducer_changes, reducer_counts, reducer_keyed,
+    reducer_ordered, reducer_remove, reducer_sums, release_reducer,
+};
+pub use resample::{resample, FillPolicy, ResampleReduce};
+pub use reservoir::{reservoir_sample, stratified_sample, StratifiedSample};
+pub use results::{accumulate_bins_view, accumulate_scratch_view, release_result, ResultView};
+pub use rolling::{rolling_by_count, rolling_by_time, RollingReduce};
+pub use scratch::{
+    is_view_valid, scratch_decay, scratch_view, set_scratch_decay, trim_scratch, ScratchView,
+};
+pub use select::{exact_quantiles, top_rows};
+pub use shared::{accumulate_bins_shared, accumulate_scratch_shared};
+pub use sort::{argsort_f32, argsort_i32, argsort_u32, sort_columns};
+pub use sorted_index::{
+    append_sorted_index, build_sorted_index, release_sorted_index, set_sorted_index_mask,
+    sorted_index_bottom, sorted_index_count, sorted_index_length, sorted_index_lower_bound,
+    sorted_index_range, sorted_index_rows, sorted_index_top, sorted_index_upper_bound,
+    sorted_index_values,
+};
+pub use space_saving::space_saving;
+pub use stream::{
+    begin_row_stream, push_row_chunk, release_row_stream, row_stream_counts, StreamCounts,
+};
+pub use summation::{set_summation, summation, Summation};
+pub use tdigest::{tdigest_merge, tdigest_quantiles, tdigest_sketch};
+#[cfg(feature = "tracing")]
+pub use trace::init_tracing;
+pub use unique::{unique_values, UniqueValues};
+pub use validate::{set_strict_mode, strict_mode, take_diagnostics, Diagnostics};
+
 #[cfg(target_feature = "simd128")]
 use std::arch::wasm32::{u16x8_extract_lane, v128_load};
 
 thread_local! {
-    static SCRATCH: RefCell<Vec<u16>> = RefCell::new(Vec::new());
-    static METRICS: RefCell<Metrics> = RefCell::new(Metrics::default());
+    static SCRATCH: RefCell<Vec<u16>> = const { RefCell::new(Vec::new()) };
+    static STRATEGY: Cell<Strategy> = const { Cell::new(Strategy::Sharded) };
+    #[cfg(feature = "unchecked")]
+    static UNCHECKED_BINS: Cell<bool> = const { Cell::new(false) };
+    static SHARD_SLOT_LIMIT: Cell<Option<usize>> = const { Cell::new(None) };
+    static CALIBRATION: RefCell<HashMap<u32, Calibration>> = RefCell::new(HashMap::new());
+    static WORKSPACE: RefCell<Workspace> = RefCell::new(Workspace::default());
+}
+
+/// Drops the scratch buffer, workspace and calibrations and restores the
+/// accumulation settings; see `hardReset`.
+fn reset() {
+    SCRATCH.with(|scratch| recovery::reset_cell(scratch, Vec::new()));
+    WORKSPACE.with(|workspace| recovery::reset_cell(workspace, Workspace::default()));
+    CALIBRATION.with(|calibration| recovery::reset_cell(calibration, HashMap::new()));
+    STRATEGY.with(|strategy| strategy.set(Strategy::Sharded));
+    #[cfg(feature = "unchecked")]
+    UNCHECKED_BINS.with(|unchecked| unchecked.set(false));
+    SHARD_SLOT_LIMIT.with(|limit| limit.set(None));
+}
+
+/// Buffers reused across kernel calls so the steady-state brush loop performs
+/// no heap allocations. Counts are keyed by the caller's dimension (`None` for
+/// unkeyed calls) because the TS layer reads one dimension's result before
+/// requesting the next, but distinct dimensions usually differ in width.
+/// Growth of any buffer is reported through the `allocations` metric.
+#[derive(Default)]
+struct Workspace {
+    counts: HashMap<Option<u32>, Vec<u32>>,
+    input: Vec<u16>,
+    kernel: KernelScratch,
+    /// Strategy the latest accumulation ran, with `Auto` resolved.
+    resolved: Option<Strategy>,
+}
+
+/// Per-strategy working memory shared by every dimension.
+#[derive(Default)]
+struct KernelScratch {
+    shard_cache: ShardCache,
+    lane_caches: [ShardCache; UNROLL_LANES],
+    sorted: Vec<u16>,
+    /// Throwaway counts for calibration re-runs.
+    trial: Vec<u32>,
+}
+
+/// Accumulation strategy used by the histogram kernels.
+///
+/// * `Scalar` – plain per-row increments straight into the counts array.
+/// * `Sharded` – routes writes through the shard cache (SIMD lane extraction
+///   when `simd128` is available and parity is off). This is the historical
+///   default.
+/// * `Sorted` – sorts a copy of the input and counts runs, which wins on
+///   heavily skewed inputs where most rows land in a handful of bins.
+/// * `Unrolled` – four-way unrolled scalar loop feeding independent shard
+///   caches, breaking the serial increment chain on devices without `simd128`.
+/// * `Auto` – times the concrete strategies on a sample of the input and uses
+///   the fastest for the remainder of the call (see [`accumulate_auto`]).
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    Auto = 0,
+    Scalar = 1,
+    Sharded = 2,
+    Sorted = 3,
+    Unrolled = 4,
+}
+
+/// Rows timed per strategy during calibration. Large enough to rise above the
+/// coarsened `performance.now` resolution in cross-origin isolated workers.
+const CALIBRATION_CHUNK: usize = 16_384;
+/// Calls shorter than this skip calibration; the sample would dominate the work.
+const CALIBRATION_MIN_ROWS: usize = CALIBRATION_CHUNK * (CALIBRATED_STRATEGIES.len() + 1);
+/// Samples faster than this are re-timed on their chunk, into throwaway
+/// counts, until they add up to it, so a coarse clock does not turn every
+/// sample into a 0 ms tie.
+const CALIBRATION_MIN_MS: f64 = 1.0;
+/// Most timed runs of one sample chunk.
+const CALIBRATION_MAX_RUNS: u32 = 64;
+const CALIBRATED_STRATEGIES: [Strategy; 4] = [
+    Strategy::Scalar,
+    Strategy::Sharded,
+    Strategy::Sorted,
+    Strategy::Unrolled,
+];
+
+/// Cached calibration outcome for one dimension. The bin count is kept so a
+/// dimension that is rebuilt with a different width gets re-measured.
+#[derive(Clone, Copy)]
+struct Calibration {
+    bin_count: usize,
+    strategy: Strategy,
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+/// Millisecond timestamp used for calibration. Falls back to `Instant` on
+/// native targets so the kernels can be exercised outside the browser.
+#[cfg(target_arch = "wasm32")]
+fn now_ms() -> f64 {
+    performance_now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_ms() -> f64 {
+    use std::time::Instant;
+    thread_local! {
+        static ORIGIN: Instant = Instant::now();
+    }
+    ORIGIN.with(|origin| origin.elapsed().as_secs_f64() * 1_000.0)
+}
+
+/// Timestamp for metrics only; skips the clock read when collection is off.
+#[cfg(target_feature = "simd128")]
+fn metrics_now() -> f64 {
+    if metrics::enabled() {
+        now_ms()
+    } else {
+        0.0
+    }
 }
 
 /// Initialise panic hook so Rust panics surface as readable messages in the
-/// browser/devtools console rather than silently trapping.
+/// browser/devtools console rather than silently trapping, and mark the
+/// module poisoned (see `isPoisoned`).
 #[wasm_bindgen]
 pub fn init_panic_hook() {
-    console_error_panic_hook::set_once();
+    recovery::install_hook();
 }
 
 /// Computes per-bin counts for the provided bin index stream.
@@ -37,111 +363,684 @@ pub fn init_panic_hook() {
 /// The returned `Uint32Array` owns its data inside the WebAssembly linear
 /// memory. JavaScript can read it immediately (e.g. via `new Uint32Array(result)`)
 /// and then drop the reference; the next invocation reuses the allocation on the
-/// Rust side.
+/// Rust side. The view goes stale when the buffer moves or memory grows; use
+/// `scratchView` to detect that.
 #[wasm_bindgen(js_name = scratchBuffer)]
-pub fn scratch_buffer(size: u32) -> js_sys::Uint16Array {
+pub fn scratch_buffer(size: u32) -> Result<js_sys::Uint16Array, KernelError> {
+    scratch::request(size as usize * 2);
     SCRATCH.with(|cell| {
         let mut scratch = cell.borrow_mut();
         let size = size as usize;
-        if scratch.len() < size {
-            scratch.resize(size, 0);
-        }
-        unsafe { js_sys::Uint16Array::view(&scratch[..size]) }
+        scratch::grow(&mut scratch, size)?;
+        Ok(unsafe { js_sys::Uint16Array::view(&scratch[..size]) })
     })
 }
 
-#[wasm_bindgen(js_name = accumulateScratch)]
-pub fn accumulate_scratch(len: u32, bin_count: u32) -> Result<js_sys::Uint32Array, JsValue> {
+/// Grows the scratch buffer to at least `len` entries and hands the first
+/// `len` to `fill`, for kernels that produce bin indices inside wasm. Fails
+/// when growing would exceed the memory budget.
+pub(crate) fn with_scratch<T>(
+    len: usize,
+    fill: impl FnOnce(&mut [u16]) -> T,
+) -> Result<T, KernelError> {
+    scratch::request(len * 2);
     SCRATCH.with(|cell| {
-        let scratch = cell.borrow();
-        let len = len as usize;
+        let mut scratch = cell.borrow_mut();
+        scratch::grow(&mut scratch, len)?;
+        Ok(fill(&mut scratch[..len]))
+    })
+}
+
+/// Hands the first `len` scratch entries to `update` in place, for kernels
+/// that rewrite bin indices already there.
+pub(crate) fn with_scratch_mut<T>(
+    len: usize,
+    update: impl FnOnce(&mut [u16]) -> T,
+) -> Result<T, KernelError> {
+    SCRATCH.with(|cell| {
+        let mut scratch = cell.borrow_mut();
         if len > scratch.len() {
-            return Err(JsValue::from_str("scratch length exceeded"));
+            return Err(KernelError::scratch_overflow(len, scratch.len()));
         }
-        accumulate_slice(&scratch[..len], bin_count)
+        Ok(update(&mut scratch[..len]))
     })
 }
 
+/// Accumulates the first `len` scratch entries. `dimension` is an optional
+/// caller-chosen key under which `Strategy::Auto` caches its calibration
+/// decision; calls without a key re-calibrate whenever the input is large
+/// enough to be worth measuring.
+#[wasm_bindgen(js_name = accumulateScratch)]
+pub fn accumulate_scratch(
+    len: u32,
+    bin_count: u32,
+    dimension: Option<u32>,
+) -> Result<js_sys::Uint32Array, KernelError> {
+    accumulate_scratch_with(
+        "accumulateScratch",
+        len as usize,
+        bin_count,
+        dimension,
+        |counts| js_sys::Uint32Array::from(counts),
+    )
+}
+
 #[wasm_bindgen(js_name = accumulateBins)]
 pub fn accumulate_bins(
     bins: &js_sys::Uint16Array,
     bin_count: u32,
-) -> Result<js_sys::Uint32Array, JsValue> {
-    let data = bins.to_vec();
-    accumulate_slice(&data, bin_count)
+    dimension: Option<u32>,
+) -> Result<js_sys::Uint32Array, KernelError> {
+    scratch::request(bins.length() as usize * 2);
+    begin_call();
+    WORKSPACE.with(|workspace| {
+        let mut workspace = workspace.borrow_mut();
+        let len = bins.length() as usize;
+        memory::check_budget(len.saturating_sub(workspace.input.capacity()) * 2)?;
+        let mut input = std::mem::take(&mut workspace.input);
+        prepare_buffer(&mut input, len);
+        bins.copy_to(&mut input);
+        let result = accumulate_slice(
+            "accumulateBins",
+            &input,
+            bin_count,
+            dimension,
+            &mut workspace,
+        )
+        .map(js_sys::Uint32Array::from);
+        workspace.input = input;
+        result
+    })
 }
 
-fn accumulate_slice(data: &[u16], bin_count: u32) -> Result<js_sys::Uint32Array, JsValue> {
-    let bin_count = bin_count as usize;
-    if bin_count == 0 {
-        return Err(JsValue::from_str("bin_count must be greater than zero"));
+/// `accumulateScratch` for in-wasm callers: runs `read` on the counts
+/// instead of copying them into a JS array.
+pub(crate) fn accumulate_scratch_with<T>(
+    entry: &'static str,
+    len: usize,
+    bin_count: u32,
+    dimension: Option<u32>,
+    read: impl FnOnce(&[u32]) -> T,
+) -> Result<T, KernelError> {
+    SCRATCH.with(|cell| {
+        let scratch = cell.borrow();
+        if len > scratch.len() {
+            return Err(KernelError::scratch_overflow(len, scratch.len()).in_kernel(entry));
+        }
+        begin_call();
+        WORKSPACE.with(|workspace| {
+            let mut workspace = workspace.borrow_mut();
+            accumulate_slice(entry, &scratch[..len], bin_count, dimension, &mut workspace).map(read)
+        })
+    })
+}
+
+/// `accumulateBins` for in-wasm callers; see [`accumulate_scratch_with`].
+pub(crate) fn accumulate_bins_with<T>(
+    entry: &'static str,
+    bins: &[u16],
+    bin_count: u32,
+    dimension: Option<u32>,
+    read: impl FnOnce(&[u32]) -> T,
+) -> Result<T, KernelError> {
+    begin_call();
+    WORKSPACE.with(|workspace| {
+        let mut workspace = workspace.borrow_mut();
+        accumulate_slice(entry, bins, bin_count, dimension, &mut workspace).map(read)
+    })
+}
+
+/// Words per descriptor accepted by [`accumulate_batch`].
+const BATCH_STRIDE: usize = 4;
+/// Descriptor `dimension` value marking a request without a dimension key.
+const UNKEYED_DIMENSION: u32 = u32::MAX;
+
+/// Accumulates several scratch ranges in a single call so an interaction that
+/// touches many small groups pays the wasm-bindgen boundary cost once.
+///
+/// `requests` is a flat list of `(offset, len, binCount, dimension)`
+/// quadruples describing ranges of the scratch buffer; `dimension` may be
+/// `0xFFFFFFFF` for unkeyed requests. The result array holds one
+/// `Uint32Array` per descriptor, in order. Metrics cover the whole batch.
+#[wasm_bindgen(js_name = accumulateBatch)]
+pub fn accumulate_batch(requests: &[u32]) -> Result<js_sys::Array, KernelError> {
+    accumulate_batch_with("accumulateBatch", requests, |counts| {
+        js_sys::Uint32Array::from(counts).into()
+    })
+}
+
+/// `accumulateBatch` with each result produced by `read` from the counts.
+pub(crate) fn accumulate_batch_with(
+    entry: &'static str,
+    requests: &[u32],
+    mut read: impl FnMut(&[u32]) -> JsValue,
+) -> Result<js_sys::Array, KernelError> {
+    let results = js_sys::Array::new_with_length((requests.len() / BATCH_STRIDE) as u32);
+    visit_batch(entry, requests, |index, counts| {
+        results.set(index as u32, read(counts))
+    })?;
+    Ok(results)
+}
+
+/// Accumulates each `accumulateBatch` request in turn and hands `visit` its
+/// index and counts.
+pub(crate) fn visit_batch(
+    entry: &'static str,
+    requests: &[u32],
+    mut visit: impl FnMut(usize, &[u32]),
+) -> Result<(), KernelError> {
+    if !requests.len().is_multiple_of(BATCH_STRIDE) {
+        return Err(KernelError::invalid_argument(
+            "batch requests must be (offset, len, binCount, dimension) quadruples",
+        )
+        .with("length", requests.len() as f64));
     }
+    SCRATCH.with(|cell| {
+        let scratch = cell.borrow();
+        begin_call();
+        WORKSPACE.with(|workspace| {
+            let mut workspace = workspace.borrow_mut();
+            for (index, request) in requests.chunks_exact(BATCH_STRIDE).enumerate() {
+                let (offset, len) = (request[0] as usize, request[1] as usize);
+                // Unchecked, the sum wraps on wasm32 and passes the check.
+                let Some(end) = offset.checked_add(len).filter(|&end| end <= scratch.len()) else {
+                    return Err(KernelError::scratch_overflow(
+                        offset.saturating_add(len),
+                        scratch.len(),
+                    )
+                    .in_kernel(entry)
+                    .with("request", index as f64));
+                };
+                let dimension = (request[3] != UNKEYED_DIMENSION).then_some(request[3]);
+                let counts = accumulate_slice(
+                    entry,
+                    &scratch[offset..end],
+                    request[2],
+                    dimension,
+                    &mut workspace,
+                )?;
+                visit(index, counts);
+            }
+            Ok(())
+        })
+    })
+}
 
-    let mut counts = vec![0u32; bin_count];
+/// Selects the accumulation strategy for subsequent calls.
+#[wasm_bindgen(js_name = setStrategy)]
+pub fn set_strategy(strategy: Strategy) {
+    STRATEGY.with(|cell| cell.set(strategy));
+}
+
+/// Opts the accumulation kernels into skipping their per-row bin range
+/// checks, for callers that validate bins before handing them over. The
+/// caller guarantees every bin is below its call's `binCount`: an
+/// out-of-range bin then corrupts memory instead of being dropped. Strict
+/// mode (`setStrictMode`) still validates first. Only built with the
+/// `unchecked` feature.
+#[cfg(feature = "unchecked")]
+#[wasm_bindgen(js_name = setUncheckedBins)]
+pub fn set_unchecked_bins(enabled: bool) {
+    UNCHECKED_BINS.with(|cell| cell.set(enabled));
+}
+
+fn unchecked_bins() -> bool {
+    #[cfg(feature = "unchecked")]
+    {
+        UNCHECKED_BINS.with(Cell::get)
+    }
+    #[cfg(not(feature = "unchecked"))]
+    {
+        false
+    }
+}
 
+/// Returns the strategy `Auto` settled on for `dimension`, if it has been
+/// calibrated.
+#[wasm_bindgen(js_name = calibratedStrategy)]
+pub fn calibrated_strategy(dimension: u32) -> Option<Strategy> {
+    CALIBRATION.with(|cache| cache.borrow().get(&dimension).map(|entry| entry.strategy))
+}
+
+/// Drops cached calibration decisions so the next `Auto` call re-measures.
+/// Passing no dimension clears every entry.
+#[wasm_bindgen(js_name = resetCalibration)]
+pub fn reset_calibration(dimension: Option<u32>) {
+    CALIBRATION.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        match dimension {
+            Some(dimension) => {
+                cache.remove(&dimension);
+            }
+            None => cache.clear(),
+        }
+    });
+}
+
+/// Resets per-call metrics. Called by every exported entry point before it
+/// touches the workspace so buffer growth is attributed to the right call.
+fn begin_call() {
     METRICS.with(|metrics| metrics.borrow_mut().reset());
+}
+
+fn record_allocation() {
+    metrics::record(|metrics| metrics.allocations += 1);
+}
+
+/// Resizes `buffer` to `len` zeroed entries, recording an allocation only when
+/// the retained capacity is insufficient.
+fn prepare_buffer<T: Copy + Default>(buffer: &mut Vec<T>, len: usize) {
+    if buffer.capacity() < len {
+        record_allocation();
+    }
+    buffer.clear();
+    buffer.resize(len, T::default());
+}
+
+fn accumulate_slice<'w>(
+    entry: &'static str,
+    data: &[u16],
+    bin_count: u32,
+    dimension: Option<u32>,
+    workspace: &'w mut Workspace,
+) -> Result<&'w [u32], KernelError> {
+    if bin_count == 0 {
+        return Err(KernelError::bad_bin_count(bin_count).in_kernel(entry));
+    }
+    let bin_count = bin_count as usize;
+    validate::strict(entry, |report| {
+        let outside = data
+            .iter()
+            .enumerate()
+            .filter(|&(_, &bin)| bin as usize >= bin_count);
+        report.check("binsInRange", outside.map(|(index, _)| index));
+    })?;
+
+    let Workspace {
+        counts,
+        kernel,
+        resolved: last_resolved,
+        ..
+    } = workspace;
+    let counts = counts.entry(dimension).or_insert_with(|| {
+        record_allocation();
+        Vec::new()
+    });
+    prepare_buffer(counts, bin_count);
+
+    let strategy = STRATEGY.with(Cell::get);
+    let _span = kernel_span!(
+        "accumulate",
+        rows = data.len(),
+        bin_count,
+        ?strategy,
+        ?dimension
+    );
+    let recording = history::enabled();
+    let timed = metrics::enabled() || recording;
+    let started = if timed { now_ms() } else { 0.0 };
+    let resolved = if unchecked_bins() {
+        dispatch::<false>(strategy, data, counts, dimension, kernel)
+    } else {
+        dispatch::<true>(strategy, data, counts, dimension, kernel)
+    };
+    let elapsed = if timed { now_ms() - started } else { 0.0 };
+    if recording {
+        history::record(entry, data.len(), bin_count, dimension, resolved, elapsed);
+    }
+    *last_resolved = Some(resolved);
+
+    if metrics::enabled() {
+        record_dropped_bins(data, counts);
+    }
+    metrics::record(|metrics| {
+        metrics.invocations += 1;
+        metrics.timing.kernel_ms += elapsed;
+        metrics.finalise();
+    });
+
+    Ok(counts.as_slice())
+}
 
+/// Every strategy skips rows whose bin falls outside `counts`. The deficit
+/// between rows in and rows counted gives the number lost; the input is only
+/// rescanned for `(index, bin)` samples in that (rare) case.
+fn record_dropped_bins(data: &[u16], counts: &[u32]) {
+    let counted: u64 = counts.iter().map(|&count| u64::from(count)).sum();
+    let dropped = (data.len() as u64).saturating_sub(counted);
+    if dropped == 0 {
+        return;
+    }
+    let sample_limit = metrics::dropped_sample_limit();
+    metrics::record(|metrics| {
+        metrics.dropped_bins += dropped;
+        let room = sample_limit.saturating_sub(metrics.dropped_samples.len());
+        let offenders = data
+            .iter()
+            .enumerate()
+            .filter(|&(_, &bin)| bin as usize >= counts.len())
+            .take(room)
+            .map(|(index, &bin)| [index as u32, u32::from(bin)]);
+        metrics.dropped_samples.extend(offenders);
+    });
+    kernel_log!(
+        Warn,
+        "accumulate",
+        "dropped rows with out-of-range bins",
+        dropped = dropped as f64,
+        bin_count = counts.len(),
+    );
+}
+
+/// Runs `strategy`, resolving `Auto`. The strategies are generic over
+/// `CHECKED`: without it they index `counts` without bounds checks, which
+/// is only sound when every bin is below `counts.len()`.
+fn dispatch<const CHECKED: bool>(
+    strategy: Strategy,
+    data: &[u16],
+    counts: &mut [u32],
+    dimension: Option<u32>,
+    kernel: &mut KernelScratch,
+) -> Strategy {
+    match strategy {
+        Strategy::Auto => accumulate_auto::<CHECKED>(data, counts, dimension, kernel),
+        strategy => {
+            run_strategy::<CHECKED>(strategy, data, counts, kernel);
+            strategy
+        }
+    }
+}
+
+fn run_strategy<const CHECKED: bool>(
+    strategy: Strategy,
+    data: &[u16],
+    counts: &mut [u32],
+    kernel: &mut KernelScratch,
+) {
+    match strategy {
+        Strategy::Scalar => accumulate_direct::<CHECKED>(data, counts),
+        Strategy::Sorted => accumulate_sorted::<CHECKED>(data, counts, &mut kernel.sorted),
+        Strategy::Unrolled => accumulate_unrolled::<CHECKED>(data, counts, &mut kernel.lane_caches),
+        Strategy::Sharded | Strategy::Auto => {
+            accumulate_sharded::<CHECKED>(data, counts, &mut kernel.shard_cache)
+        }
+    }
+}
+
+/// The calibrated strategy with the lowest per-run time in `sample_ms`, and
+/// that time. Ties, e.g. samples still below the clock's resolution, go to
+/// `Sharded`, the historical default.
+fn fastest(sample_ms: &[f64; CALIBRATED_STRATEGIES.len()]) -> (Strategy, f64) {
+    let sharded = CALIBRATED_STRATEGIES
+        .iter()
+        .position(|&strategy| strategy == Strategy::Sharded)
+        .unwrap_or_default();
+    let mut best = (Strategy::Sharded, sample_ms[sharded]);
+    for (&strategy, &elapsed) in CALIBRATED_STRATEGIES.iter().zip(sample_ms) {
+        if elapsed < best.1 {
+            best = (strategy, elapsed);
+        }
+    }
+    best
+}
+
+/// Calibrating strategy. Each concrete strategy processes one consecutive
+/// chunk of the input into the shared counts, so the sample contributes to the
+/// result rather than being thrown away (re-runs of samples too quick to
+/// time go to throwaway counts); the fastest strategy then handles the
+/// remainder. With a `dimension` key the winner is cached and later
+/// calls skip straight to it. Returns the strategy that processed the bulk of
+/// the input.
+fn accumulate_auto<const CHECKED: bool>(
+    data: &[u16],
+    counts: &mut [u32],
+    dimension: Option<u32>,
+    kernel: &mut KernelScratch,
+) -> Strategy {
+    let cached = dimension.and_then(|dimension| {
+        CALIBRATION.with(|cache| {
+            cache
+                .borrow()
+                .get(&dimension)
+                .filter(|entry| entry.bin_count == counts.len())
+                .map(|entry| entry.strategy)
+        })
+    });
+    if let Some(strategy) = cached {
+        kernel_log!(
+            Trace,
+            "strategy",
+            "using cached calibration",
+            strategy = format!("{strategy:?}")
+        );
+        run_strategy::<CHECKED>(strategy, data, counts, kernel);
+        return strategy;
+    }
+    if data.len() < CALIBRATION_MIN_ROWS {
+        kernel_log!(
+            Debug,
+            "strategy",
+            "input too short to calibrate; falling back to sharded",
+            rows = data.len(),
+            min_rows = CALIBRATION_MIN_ROWS,
+        );
+        accumulate_sharded::<CHECKED>(data, counts, &mut kernel.shard_cache);
+        return Strategy::Sharded;
+    }
+
+    let _span = kernel_span!("calibrate", rows = data.len(), ?dimension);
+    // Taken out so the strategies can borrow the rest of the scratch.
+    let mut trial = std::mem::take(&mut kernel.trial);
+    let mut trial_ready = false;
+    let mut sample_ms = [0.0; CALIBRATED_STRATEGIES.len()];
+    for (index, &strategy) in CALIBRATED_STRATEGIES.iter().enumerate() {
+        let start = index * CALIBRATION_CHUNK;
+        let chunk = &data[start..start + CALIBRATION_CHUNK];
+        let started = now_ms();
+        run_strategy::<CHECKED>(strategy, chunk, counts, kernel);
+        let mut elapsed = now_ms() - started;
+        let mut runs = 1;
+        while elapsed < CALIBRATION_MIN_MS && runs < CALIBRATION_MAX_RUNS {
+            if !trial_ready {
+                // Over budget, the sample keeps its single (coarse) timing.
+                let grow = counts.len().saturating_sub(trial.capacity());
+                if memory::check_budget(grow * 4).is_err() {
+                    break;
+                }
+                prepare_buffer(&mut trial, counts.len());
+                trial_ready = true;
+            }
+            let started = now_ms();
+            run_strategy::<CHECKED>(strategy, chunk, &mut trial, kernel);
+            elapsed += now_ms() - started;
+            runs += 1;
+        }
+        sample_ms[index] = elapsed / f64::from(runs);
+        kernel_event!(
+            ?strategy,
+            elapsed_ms = sample_ms[index],
+            runs,
+            "calibration sample"
+        );
+    }
+    kernel.trial = trial;
+    let (best, best_ms) = fastest(&sample_ms);
+    kernel_event!(strategy = ?best, "calibrated");
+    kernel_log!(
+        Info,
+        "strategy",
+        "calibrated",
+        strategy = format!("{best:?}"),
+        sample_ms = best_ms,
+        bin_count = counts.len(),
+        cached = dimension.is_some(),
+    );
+
+    let rest = &data[CALIBRATED_STRATEGIES.len() * CALIBRATION_CHUNK..];
+    run_strategy::<CHECKED>(best, rest, counts, kernel);
+
+    if let Some(dimension) = dimension {
+        CALIBRATION.with(|cache| {
+            cache.borrow_mut().insert(
+                dimension,
+                Calibration {
+                    bin_count: counts.len(),
+                    strategy: best,
+                },
+            );
+        });
+    }
+    best
+}
+
+fn accumulate_sharded<const CHECKED: bool>(
+    data: &[u16],
+    counts: &mut [u32],
+    cache: &mut ShardCache,
+) {
     #[cfg(target_feature = "simd128")]
-    {
-        accumulate_simd(data, &mut counts);
+    if parity::simd_allowed() {
+        accumulate_simd::<CHECKED>(data, counts, cache);
+        return;
     }
 
-    #[cfg(not(target_feature = "simd128"))]
-    {
-        accumulate_scalar(data, &mut counts);
+    accumulate_scalar::<CHECKED>(data, counts, cache);
+}
+
+fn accumulate_direct<const CHECKED: bool>(data: &[u16], counts: &mut [u32]) {
+    for &bin in data {
+        if let Some(target) = bin_mut::<CHECKED>(counts, bin as usize) {
+            *target += 1;
+        }
     }
+}
 
-    METRICS.with(|metrics| metrics.borrow_mut().finalise());
+/// `counts[bin]`, or `None` when out of range. Unchecked, `bin` must be in
+/// range: callers opt in through `setUncheckedBins`, whose contract is
+/// exactly that.
+#[inline(always)]
+fn bin_mut<const CHECKED: bool>(counts: &mut [u32], bin: usize) -> Option<&mut u32> {
+    if CHECKED {
+        counts.get_mut(bin)
+    } else {
+        debug_assert!(bin < counts.len());
+        // SAFETY: unchecked accumulation is only enabled by callers that
+        // guarantee every bin is below the bin count (`setUncheckedBins`).
+        Some(unsafe { counts.get_unchecked_mut(bin) })
+    }
+}
 
-    Ok(js_sys::Uint32Array::from(counts.as_slice()))
+/// Sorts a copy of the input and adds each run length in one write. Sorting is
+/// `O(n log n)` but the counting pass touches each distinct bin once, which
+/// pays off when a few bins absorb most rows.
+fn accumulate_sorted<const CHECKED: bool>(data: &[u16], counts: &mut [u32], sorted: &mut Vec<u16>) {
+    if sorted.capacity() < data.len() {
+        record_allocation();
+    }
+    sorted.clear();
+    sorted.extend_from_slice(data);
+    sorted.sort_unstable();
+    let mut index = 0;
+    while index < sorted.len() {
+        let bin = sorted[index];
+        let mut end = index + 1;
+        while end < sorted.len() && sorted[end] == bin {
+            end += 1;
+        }
+        if let Some(target) = bin_mut::<CHECKED>(counts, bin as usize) {
+            *target += (end - index) as u32;
+        }
+        index = end;
+    }
 }
 
 #[cfg(target_feature = "simd128")]
-fn accumulate_simd(data: &[u16], counts: &mut [u32]) {
+fn accumulate_simd<const CHECKED: bool>(data: &[u16], counts: &mut [u32], cache: &mut ShardCache) {
     let (shard_bits, shard_size) = shard_params(counts.len());
     let shard_slots = shard_slot_count(counts.len());
-    let mut cache = ShardCache::new(shard_bits, shard_size, shard_slots);
+    cache.configure(shard_bits, shard_size, shard_slots);
     let mut index = 0;
     const LANES: usize = 8;
 
+    let simd_started = metrics_now();
     unsafe {
         while index + LANES <= data.len() {
             let lane = v128_load(data.as_ptr().add(index) as *const _);
             for i in 0..LANES {
-                cache.increment(u16x8_extract_lane(lane, i as u8) as usize, counts);
+                cache.increment::<CHECKED>(u16x8_extract_lane(lane, i as u8) as usize, counts);
             }
             index += LANES;
         }
     }
+    let tail_started = metrics_now();
 
     for &bin in &data[index..] {
-        cache.increment(bin as usize, counts);
+        cache.increment::<CHECKED>(bin as usize, counts);
     }
+    let tail_finished = metrics_now();
 
-    cache.flush_all(counts);
-}
+    metrics::record(|metrics| {
+        metrics.timing.simd_ms += tail_started - simd_started;
+        metrics.timing.tail_ms += tail_finished - tail_started;
+    });
 
-#[cfg(target_feature = "simd128")]
-#[allow(dead_code)]
-fn accumulate_scalar(data: &[u16], counts: &mut [u32]) {
-    accumulate_scalar_common(data, counts);
+    cache.flush_all(counts);
 }
 
-#[cfg(not(target_feature = "simd128"))]
-fn accumulate_scalar(data: &[u16], counts: &mut [u32]) {
-    accumulate_scalar_common(data, counts);
+fn accumulate_scalar<const CHECKED: bool>(
+    data: &[u16],
+    counts: &mut [u32],
+    cache: &mut ShardCache,
+) {
+    accumulate_scalar_common::<CHECKED>(data, counts, cache);
 }
 
-fn accumulate_scalar_common(data: &[u16], counts: &mut [u32]) {
+fn accumulate_scalar_common<const CHECKED: bool>(
+    data: &[u16],
+    counts: &mut [u32],
+    cache: &mut ShardCache,
+) {
     let (shard_bits, shard_size) = shard_params(counts.len());
     let shard_slots = shard_slot_count(counts.len());
-    let mut cache = ShardCache::new(shard_bits, shard_size, shard_slots);
+    cache.configure(shard_bits, shard_size, shard_slots);
     for &bin in data {
-        cache.increment(bin as usize, counts);
+        cache.increment::<CHECKED>(bin as usize, counts);
     }
     cache.flush_all(counts);
 }
 
+/// Independent accumulators used by `Strategy::Unrolled`.
+const UNROLL_LANES: usize = 4;
+
+/// Scalar accumulation with four independent shard caches. Consecutive rows go
+/// to different caches so their increments do not serialise on the same store
+/// slot; every cache flushes into `counts`, which merges the partial results.
+fn accumulate_unrolled<const CHECKED: bool>(
+    data: &[u16],
+    counts: &mut [u32],
+    caches: &mut [ShardCache; UNROLL_LANES],
+) {
+    let (shard_bits, shard_size) = shard_params(counts.len());
+    let shard_slots = shard_slot_count(counts.len());
+    for cache in caches.iter_mut() {
+        cache.configure(shard_bits, shard_size, shard_slots);
+    }
+    let [a, b, c, d] = caches;
+    let mut chunks = data.chunks_exact(UNROLL_LANES);
+    for chunk in &mut chunks {
+        a.increment::<CHECKED>(chunk[0] as usize, counts);
+        b.increment::<CHECKED>(chunk[1] as usize, counts);
+        c.increment::<CHECKED>(chunk[2] as usize, counts);
+        d.increment::<CHECKED>(chunk[3] as usize, counts);
+    }
+    for &bin in chunks.remainder() {
+        a.increment::<CHECKED>(bin as usize, counts);
+    }
+    for cache in [a, b, c, d] {
+        cache.flush_all(counts);
+    }
+}
+
 fn shard_params(len: usize) -> (usize, usize) {
     match len {
         n if n <= 256 => (0, n.max(1)),
@@ -162,62 +1061,98 @@ fn shard_slot_count(len: usize) -> usize {
     } else {
         len.saturating_sub(1) >> shard_bits
     } + 1;
-    let cap = match len {
+    let cap = SHARD_SLOT_LIMIT.with(Cell::get).unwrap_or(match len {
         n if n <= 2048 => 8,
         n if n <= 16384 => 16,
         _ => 32,
-    };
+    });
     shard_count.min(cap).max(1)
 }
 
+/// Largest slot count the shard map can address (it stores `slot + 1`).
+const MAX_SHARD_SLOTS: usize = u16::MAX as usize;
+
+/// Overrides the width-based slot cap so very wide histograms can keep every
+/// shard resident instead of rotating through evictions. Passing no limit
+/// restores the heuristic. Values are clamped to `1..=65535`.
+#[wasm_bindgen(js_name = setShardSlotLimit)]
+pub fn set_shard_slot_limit(limit: Option<u32>) {
+    let limit = limit.map(|limit| (limit as usize).clamp(1, MAX_SHARD_SLOTS));
+    SHARD_SLOT_LIMIT.with(|cell| cell.set(limit));
+}
+
 /// Small cache that groups histogram writes into shard-local buffers. Each slot
 /// tracks one high-order shard of the histogram and accumulates its counts in a
 /// contiguous slice so we only touch the backing array when the shard rotates
 /// out of the cache.
-#[derive(Clone)]
+#[derive(Clone, Default)]
 struct ShardSlot {
     id: Option<usize>,
     used: bool,
 }
 
+#[derive(Default)]
 struct ShardCache {
     shard_bits: usize,
     shard_size: usize,
     slots: Vec<ShardSlot>,
-    shard_map: Vec<u8>,
+    shard_map: Vec<u16>,
     store: Vec<u32>,
     next_evict: usize,
     mask: usize,
 }
 
 impl ShardCache {
-    fn new(shard_bits: usize, shard_size: usize, slot_count: usize) -> Self {
-        let slot_count = slot_count.max(1);
+    /// Prepares the cache for a histogram with the given shard geometry. The
+    /// cache is left clean by `flush_all`, so an unchanged geometry is reused
+    /// as-is; otherwise the existing buffers are resized in place.
+    fn configure(&mut self, shard_bits: usize, shard_size: usize, slot_count: usize) {
+        let slot_count = slot_count.clamp(1, MAX_SHARD_SLOTS);
+        if self.shard_bits == shard_bits
+            && self.shard_size == shard_size
+            && self.slots.len() == slot_count
+        {
+            return;
+        }
         let mask = if shard_bits == 0 {
             usize::MAX
         } else {
             (1usize << shard_bits) - 1
         };
-        let shard_map_size = if shard_bits == 0 { 1 } else { 1 << (16 - shard_bits) };
-        ShardCache {
-            shard_bits,
-            shard_size,
-            slots: vec![
-                ShardSlot {
-                    id: None,
-                    used: false
-                };
-                slot_count
-            ],
-            shard_map: vec![0; shard_map_size],
-            store: vec![0u32; shard_size * slot_count],
-            next_evict: 0,
-            mask,
+        let shard_map_size = if shard_bits == 0 {
+            1
+        } else {
+            1 << (16 - shard_bits)
+        };
+        let store_size = shard_size * slot_count;
+        kernel_log!(
+            Debug,
+            "shard",
+            "configured shard cache",
+            shard_bits = shard_bits,
+            shard_size = shard_size,
+            slots = slot_count,
+        );
+        if self.slots.capacity() < slot_count
+            || self.shard_map.capacity() < shard_map_size
+            || self.store.capacity() < store_size
+        {
+            record_allocation();
         }
+        self.shard_bits = shard_bits;
+        self.shard_size = shard_size;
+        self.slots.clear();
+        self.slots.resize(slot_count, ShardSlot::default());
+        self.shard_map.clear();
+        self.shard_map.resize(shard_map_size, 0);
+        self.store.clear();
+        self.store.resize(store_size, 0);
+        self.next_evict = 0;
+        self.mask = mask;
     }
 
-    fn increment(&mut self, bin: usize, counts: &mut [u32]) {
-        if bin >= counts.len() {
+    fn increment<const CHECKED: bool>(&mut self, bin: usize, counts: &mut [u32]) {
+        if CHECKED && bin >= counts.len() {
             return;
         }
         let shard_idx = if self.shard_bits == 0 {
@@ -235,7 +1170,7 @@ impl ShardCache {
             let base = slot_index * self.shard_size + local_index;
             self.store[base] += 1;
             self.slots[slot_index].used = true;
-        } else if let Some(target) = counts.get_mut(bin) {
+        } else if let Some(target) = bin_mut::<CHECKED>(counts, bin) {
             *target += 1;
         }
     }
@@ -254,7 +1189,7 @@ impl ShardCache {
             slot.id = Some(shard_idx);
             slot.used = false;
             if shard_idx < self.shard_map.len() {
-                self.shard_map[shard_idx] = (slot_index + 1) as u8;
+                self.shard_map[shard_idx] = (slot_index + 1) as u16;
             }
             return slot_index;
         }
@@ -270,7 +1205,7 @@ impl ShardCache {
         self.slots[slot_index].id = Some(shard_idx);
         self.slots[slot_index].used = false;
         if shard_idx < self.shard_map.len() {
-            self.shard_map[shard_idx] = (slot_index + 1) as u8;
+            self.shard_map[shard_idx] = (slot_index + 1) as u16;
         }
         self.next_evict = (slot_index + 1) % self.slots.len();
         slot_index
@@ -306,11 +1241,13 @@ impl ShardCache {
         self.slots[slot_index].used = false;
 
         if bins_written > 0 {
-            METRICS.with(|metrics| {
-                let mut metrics = metrics.borrow_mut();
+            metrics::record(|metrics| {
                 metrics.flushes += 1;
                 metrics.bins += u64::from(bins_written);
                 metrics.rows += rows_written;
+                metrics
+                    .flush_sizes
+                    .record(u64::from(bins_written), rows_written);
                 match reason {
                     FlushReason::Evict => metrics.evicts += 1,
                     FlushReason::Final => metrics.final_flushes += 1,
@@ -344,65 +1281,183 @@ enum FlushReason {
     Final,
 }
 
-#[derive(Default)]
-struct Metrics {
-    flushes: u64,
-    evicts: u64,
-    final_flushes: u64,
-    bins: u64,
-    rows: u64,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDE_BINS: usize = 65_536;
 
-impl Metrics {
-    fn reset(&mut self) {
-        *self = Metrics::default();
+    fn wide_input() -> Vec<u16> {
+        // Stride through every shard so each one is touched repeatedly.
+        (0..WIDE_BINS * 3)
+            .map(|i| ((i * 7_919) % WIDE_BINS) as u16)
+            .collect()
     }
 
-    fn finalise(&mut self) {
-        // no-op placeholder for future derived fields
+    fn reference_counts(data: &[u16], bin_count: usize) -> Vec<u32> {
+        let mut counts = vec![0u32; bin_count];
+        for &bin in data {
+            counts[bin as usize] += 1;
+        }
+        counts
     }
-}
 
-#[wasm_bindgen(js_name = resetMetrics)]
-pub fn reset_metrics() {
-    METRICS.with(|metrics| metrics.borrow_mut().reset());
-}
+    #[cfg(feature = "metrics")]
+    fn take_evictions() -> u64 {
+        METRICS.with(|metrics| {
+            let mut metrics = metrics.borrow_mut();
+            let evicts = metrics.evicts;
+            metrics.reset();
+            evicts
+        })
+    }
 
-#[wasm_bindgen(js_name = takeMetrics)]
-pub fn take_metrics() -> JsValue {
-    use js_sys::Object;
-    use js_sys::Reflect;
-    use wasm_bindgen::JsValue;
+    #[test]
+    fn wide_histograms_match_reference_for_every_strategy() {
+        let data = wide_input();
+        let expected = reference_counts(&data, WIDE_BINS);
+        for strategy in [
+            Strategy::Scalar,
+            Strategy::Sharded,
+            Strategy::Sorted,
+            Strategy::Unrolled,
+        ] {
+            let mut kernel = KernelScratch::default();
+            let mut counts = vec![0u32; WIDE_BINS];
+            run_strategy::<true>(strategy, &data, &mut counts, &mut kernel);
+            assert_eq!(counts, expected, "{strategy:?}");
+        }
+    }
 
-    METRICS.with(|metrics| {
-        let mut metrics = metrics.borrow_mut();
-        let result = Object::new();
-        let _ = Reflect::set(
-            &result,
-            &JsValue::from_str("flushes"),
-            &JsValue::from_f64(metrics.flushes as f64),
-        );
-        let _ = Reflect::set(
-            &result,
-            &JsValue::from_str("evictions"),
-            &JsValue::from_f64(metrics.evicts as f64),
-        );
-        let _ = Reflect::set(
-            &result,
-            &JsValue::from_str("finalFlushes"),
-            &JsValue::from_f64(metrics.final_flushes as f64),
-        );
-        let _ = Reflect::set(
-            &result,
-            &JsValue::from_str("bins"),
-            &JsValue::from_f64(metrics.bins as f64),
-        );
-        let _ = Reflect::set(
-            &result,
-            &JsValue::from_str("rows"),
-            &JsValue::from_f64(metrics.rows as f64),
-        );
-        metrics.reset();
-        JsValue::from(result)
-    })
+    #[test]
+    fn auto_counts_every_row_once_and_reuses_its_calibration() {
+        let data: Vec<u16> = (0..CALIBRATION_MIN_ROWS * 2)
+            .map(|i| ((i * 31) % 1_000) as u16)
+            .collect();
+        let expected = reference_counts(&data, 1_000);
+        let mut kernel = KernelScratch::default();
+        let mut counts = vec![0u32; 1_000];
+        let chosen = dispatch::<true>(Strategy::Auto, &data, &mut counts, Some(7), &mut kernel);
+        assert_eq!(counts, expected);
+        assert!(CALIBRATED_STRATEGIES.contains(&chosen));
+        assert_eq!(calibrated_strategy(7), Some(chosen));
+
+        // A cached decision is used as is.
+        CALIBRATION.with(|cache| {
+            cache.borrow_mut().insert(
+                7,
+                Calibration {
+                    bin_count: 1_000,
+                    strategy: Strategy::Sorted,
+                },
+            )
+        });
+        counts.fill(0);
+        let chosen = dispatch::<true>(Strategy::Auto, &data, &mut counts, Some(7), &mut kernel);
+        assert_eq!(chosen, Strategy::Sorted);
+        assert_eq!(counts, expected);
+
+        // A different bin count re-measures.
+        let mut wider = vec![0u32; 2_000];
+        let chosen = dispatch::<true>(Strategy::Auto, &data, &mut wider, Some(7), &mut kernel);
+        assert_eq!(wider[..1_000], expected[..]);
+        let entry = CALIBRATION.with(|cache| cache.borrow().get(&7).copied());
+        assert!(entry.is_some_and(|entry| entry.bin_count == 2_000 && entry.strategy == chosen));
+        reset_calibration(Some(7));
+        assert_eq!(calibrated_strategy(7), None);
+    }
+
+    #[test]
+    #[cfg(feature = "metrics")]
+    fn calibration_reuses_its_scratch() {
+        let data: Vec<u16> = (0..CALIBRATION_MIN_ROWS)
+            .map(|i| (i % 500) as u16)
+            .collect();
+        let mut kernel = KernelScratch::default();
+        let mut counts = vec![0u32; 500];
+        dispatch::<true>(Strategy::Auto, &data, &mut counts, None, &mut kernel);
+        assert!(kernel.trial.is_empty() || kernel.trial.len() == 500);
+
+        reset_metrics();
+        counts.fill(0);
+        dispatch::<true>(Strategy::Auto, &data, &mut counts, None, &mut kernel);
+        assert_eq!(METRICS.with(|metrics| metrics.borrow().allocations), 0);
+        assert_eq!(counts, reference_counts(&data, 500));
+    }
+
+    #[test]
+    fn auto_skips_calibration_for_short_inputs() {
+        let data: Vec<u16> = (0..1_000).map(|i| (i % 10) as u16).collect();
+        let mut kernel = KernelScratch::default();
+        let mut counts = vec![0u32; 10];
+        let chosen = dispatch::<true>(Strategy::Auto, &data, &mut counts, Some(8), &mut kernel);
+        assert_eq!(chosen, Strategy::Sharded);
+        assert_eq!(counts, reference_counts(&data, 10));
+        assert_eq!(calibrated_strategy(8), None);
+    }
+
+    #[test]
+    fn calibration_ties_keep_sharded() {
+        assert_eq!(fastest(&[0.0; 4]).0, Strategy::Sharded);
+        assert_eq!(fastest(&[0.5, 0.5, 0.5, 0.5]).0, Strategy::Sharded);
+        assert_eq!(fastest(&[0.2, 0.3, 0.1, 0.4]), (Strategy::Sorted, 0.1));
+        assert_eq!(fastest(&[0.1, 0.3, 0.2, 0.1]).0, Strategy::Scalar);
+    }
+
+    #[test]
+    #[cfg(feature = "metrics")]
+    fn raised_slot_limit_keeps_every_wide_shard_resident() {
+        let data = wide_input();
+        let mut kernel = KernelScratch::default();
+        let mut counts = vec![0u32; WIDE_BINS];
+
+        set_shard_slot_limit(None);
+        reset_metrics();
+        run_strategy::<true>(Strategy::Sharded, &data, &mut counts, &mut kernel);
+        let evicts = take_evictions();
+        assert!(evicts > 0, "default cap should rotate shards");
+
+        set_shard_slot_limit(Some(64));
+        counts.fill(0);
+        run_strategy::<true>(Strategy::Sharded, &data, &mut counts, &mut kernel);
+        let evicts = take_evictions();
+        set_shard_slot_limit(None);
+        assert_eq!(evicts, 0);
+        assert_eq!(counts, reference_counts(&data, WIDE_BINS));
+    }
+
+    #[test]
+    #[cfg(feature = "metrics")]
+    fn shard_map_addresses_more_than_255_slots() {
+        let data = wide_input();
+        let mut cache = ShardCache::default();
+        // 64-bin shards give 1024 shards across the u16 range.
+        cache.configure(6, 64, 1024);
+        let mut counts = vec![0u32; WIDE_BINS];
+        reset_metrics();
+        for &bin in &data {
+            cache.increment::<true>(bin as usize, &mut counts);
+        }
+        cache.flush_all(&mut counts);
+        let evicts = take_evictions();
+        assert_eq!(evicts, 0);
+        assert_eq!(counts, reference_counts(&data, WIDE_BINS));
+    }
+
+    #[test]
+    fn batch_ranges_past_the_scratch_are_rejected() {
+        with_scratch(8, |scratch| {
+            scratch.copy_from_slice(&[0, 1, 1, 2, 3, 3, 3, 0])
+        })
+        .unwrap();
+        let mut seen = Vec::new();
+        let requests = [0, 4, 4, UNKEYED_DIMENSION, 4, 4, 4, UNKEYED_DIMENSION];
+        visit_batch("test", &requests, |_, counts| seen.push(counts.to_vec())).unwrap();
+        assert_eq!(seen, [vec![1, 2, 1, 0], vec![1, 0, 0, 3]]);
+        for request in [[4, 5], [u32::MAX, u32::MAX], [u32::MAX, 1]] {
+            let requests = [request[0], request[1], 4, UNKEYED_DIMENSION];
+            let error = visit_batch("test", &requests, |_, _| ()).unwrap_err();
+            assert_eq!(error.code(), ErrorKind::ScratchOverflow as u32);
+        }
+    }
 }
@@ -0,0 +1,192 @@
+//! Leveled diagnostics controlled from JavaScript.
+//!
+//! Logging is off by default. `setLogLevel` raises the threshold and
+//! `setLogSink` optionally redirects records to a JS callback; without a sink
+//! records go to the matching `console` method. Each record carries a
+//! `target` (the subsystem), a fixed `message`, and a `fields` object, so
+//! callers can filter on structure rather than parse strings. Fields are only
+//! materialised when the level is enabled, keeping disabled logging to a
+//! single `Cell` read.
+
+use std::cell::{Cell, RefCell};
+use wasm_bindgen::prelude::*;
+
+use crate::recovery;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+thread_local! {
+    static LEVEL: Cell<LogLevel> = const { Cell::new(LogLevel::Off) };
+    static SINK: RefCell<Option<js_sys::Function>> = const { RefCell::new(None) };
+}
+
+/// Turns logging off and drops the sink; see `hardReset`.
+pub(crate) fn reset() {
+    LEVEL.with(|level| level.set(LogLevel::Off));
+    SINK.with(|sink| recovery::reset_cell(sink, None));
+}
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console, js_name = error)]
+    fn console_error(message: &str, fields: &JsValue);
+    #[wasm_bindgen(js_namespace = console, js_name = warn)]
+    fn console_warn(message: &str, fields: &JsValue);
+    #[wasm_bindgen(js_namespace = console, js_name = info)]
+    fn console_info(message: &str, fields: &JsValue);
+    #[wasm_bindgen(js_namespace = console, js_name = debug)]
+    fn console_debug(message: &str, fields: &JsValue);
+}
+
+/// Sets the most verbose level that will be emitted. `Off` disables logging.
+#[wasm_bindgen(js_name = setLogLevel)]
+pub fn set_log_level(level: LogLevel) {
+    LEVEL.with(|cell| cell.set(level));
+}
+
+#[wasm_bindgen(js_name = logLevel)]
+pub fn log_level() -> LogLevel {
+    LEVEL.with(Cell::get)
+}
+
+/// Routes records to `sink(record)` where `record` is
+/// `{ level, target, message, fields }`. Passing nothing restores console
+/// output.
+#[wasm_bindgen(js_name = setLogSink)]
+pub fn set_log_sink(sink: Option<js_sys::Function>) {
+    SINK.with(|cell| *cell.borrow_mut() = sink);
+}
+
+#[inline]
+pub(crate) fn enabled(level: LogLevel) -> bool {
+    level != LogLevel::Off && level <= LEVEL.with(Cell::get)
+}
+
+/// Value attached to a log record field.
+pub(crate) enum FieldValue {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+impl From<usize> for FieldValue {
+    fn from(value: usize) -> Self {
+        FieldValue::Number(value as f64)
+    }
+}
+
+impl From<u32> for FieldValue {
+    fn from(value: u32) -> Self {
+        FieldValue::Number(f64::from(value))
+    }
+}
+
+impl From<f64> for FieldValue {
+    fn from(value: f64) -> Self {
+        FieldValue::Number(value)
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        FieldValue::Bool(value)
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::Text(value.to_owned())
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        FieldValue::Text(value)
+    }
+}
+
+impl From<FieldValue> for JsValue {
+    fn from(value: FieldValue) -> Self {
+        match value {
+            FieldValue::Number(number) => JsValue::from_f64(number),
+            FieldValue::Text(text) => JsValue::from_str(&text),
+            FieldValue::Bool(flag) => JsValue::from_bool(flag),
+        }
+    }
+}
+
+fn level_name(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Off => "off",
+        LogLevel::Error => "error",
+        LogLevel::Warn => "warn",
+        LogLevel::Info => "info",
+        LogLevel::Debug => "debug",
+        LogLevel::Trace => "trace",
+    }
+}
+
+/// Delivers one record. Use through `kernel_log!`, which checks the level
+/// before building fields.
+pub(crate) fn emit(
+    level: LogLevel,
+    target: &str,
+    message: &str,
+    fields: Vec<(&'static str, FieldValue)>,
+) {
+    use js_sys::{Object, Reflect};
+
+    let object = Object::new();
+    for (key, value) in fields {
+        let _ = Reflect::set(&object, &JsValue::from_str(key), &value.into());
+    }
+    let fields = JsValue::from(object);
+
+    let delivered = SINK.with(|sink| {
+        let sink = sink.borrow();
+        let Some(sink) = sink.as_ref() else {
+            return false;
+        };
+        let record = Object::new();
+        let _ = Reflect::set(&record, &"level".into(), &level_name(level).into());
+        let _ = Reflect::set(&record, &"target".into(), &target.into());
+        let _ = Reflect::set(&record, &"message".into(), &message.into());
+        let _ = Reflect::set(&record, &"fields".into(), &fields);
+        let _ = sink.call1(&JsValue::NULL, &record);
+        true
+    });
+    if delivered {
+        return;
+    }
+
+    let text = format!("[crossfilterx:{target}] {message}");
+    match level {
+        LogLevel::Error => console_error(&text, &fields),
+        LogLevel::Warn => console_warn(&text, &fields),
+        LogLevel::Info => console_info(&text, &fields),
+        LogLevel::Debug | LogLevel::Trace | LogLevel::Off => console_debug(&text, &fields),
+    }
+}
+
+/// `kernel_log!(Debug, "strategy", "calibrated", strategy = name, rows = n)`
+macro_rules! kernel_log {
+    ($level:ident, $target:literal, $message:literal $(, $key:ident = $value:expr)* $(,)?) => {
+        if $crate::log::enabled($crate::log::LogLevel::$level) {
+            $crate::log::emit(
+                $crate::log::LogLevel::$level,
+                $target,
+                $message,
+                vec![$((stringify!($key), $crate::log::FieldValue::from($value))),*],
+            );
+        }
+    };
+}
@@ -0,0 +1,174 @@
+//! LZ4 block decompression.
+//!
+//! Worker snapshots and transfer buffers are LZ4 block-compressed (raw
+//! blocks, no frame header): they shrink 4–6x and decompress at memory
+//! speed. The block format carries no length, so the caller passes the
+//! uncompressed size recorded alongside the block; the output is allocated
+//! once and every copy is bounds-checked against it.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{ErrorKind, KernelError};
+use crate::memory;
+
+const MIN_MATCH: usize = 4;
+
+fn malformed(what: &str) -> KernelError {
+    KernelError::new(
+        ErrorKind::MalformedInput,
+        format!("malformed lz4 block: {what}"),
+    )
+}
+
+/// Extends a 4-bit length field that saturated at 15 with the following
+/// `255`-continued bytes.
+fn read_length(src: &[u8], pos: &mut usize, nibble: usize) -> Result<usize, KernelError> {
+    let mut len = nibble;
+    if nibble == 15 {
+        loop {
+            let byte = *src.get(*pos).ok_or_else(|| malformed("truncated length"))?;
+            *pos += 1;
+            len += usize::from(byte);
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+    Ok(len)
+}
+
+/// Decompresses one LZ4 block, appending exactly `len` bytes to `out`.
+/// Matches may only reach back into this block's own output.
+pub(crate) fn decompress_block(
+    src: &[u8],
+    len: usize,
+    out: &mut Vec<u8>,
+) -> Result<(), KernelError> {
+    let base = out.len();
+    let end = base
+        .checked_add(len)
+        .ok_or_else(|| malformed("declared length too large"))?;
+    memory::check_budget(len)?;
+    out.try_reserve_exact(len)
+        .map_err(|_| KernelError::new(ErrorKind::OutOfMemory, "cannot reserve lz4 output"))?;
+    let mut pos = 0;
+    while pos < src.len() {
+        let token = src[pos];
+        pos += 1;
+
+        let literals = read_length(src, &mut pos, usize::from(token >> 4))?;
+        let bytes = src
+            .get(pos..)
+            .and_then(|rest| rest.get(..literals))
+            .ok_or_else(|| malformed("truncated literals"))?;
+        if literals > end - out.len() {
+            return Err(malformed("output exceeds the declared length"));
+        }
+        out.extend_from_slice(bytes);
+        pos += literals;
+        // The last sequence is literals only.
+        if pos == src.len() {
+            break;
+        }
+
+        let offset = src
+            .get(pos..pos + 2)
+            .map(|bytes| usize::from(u16::from_le_bytes([bytes[0], bytes[1]])))
+            .ok_or_else(|| malformed("truncated match offset"))?;
+        pos += 2;
+        let length = read_length(src, &mut pos, usize::from(token & 15))? + MIN_MATCH;
+        if offset == 0 || offset > out.len() - base {
+            return Err(malformed("match offset out of range"));
+        }
+        if length > end - out.len() {
+            return Err(malformed("output exceeds the declared length"));
+        }
+        let start = out.len() - offset;
+        if offset >= length {
+            out.extend_from_within(start..start + length);
+        } else {
+            // Overlapping match: the copy repeats the last `offset` bytes.
+            for index in start..start + length {
+                out.push(out[index]);
+            }
+        }
+    }
+    if out.len() != end {
+        return Err(malformed("output shorter than the declared length")
+            .with("expected", len as f64)
+            .with("actual", (out.len() - base) as f64));
+    }
+    Ok(())
+}
+
+/// Decompresses a raw LZ4 block (no frame header) whose uncompressed size
+/// is `uncompressedLength`, as recorded next to it in the snapshot or
+/// transfer buffer. Fails with `MalformedInput` if the block does not
+/// decode to exactly that many bytes.
+#[wasm_bindgen(js_name = decompressLz4Block)]
+pub fn decompress_lz4_block(
+    compressed: &[u8],
+    uncompressed_length: u32,
+) -> Result<Vec<u8>, KernelError> {
+    let mut out = Vec::new();
+    decompress_block(compressed, uncompressed_length as usize, &mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decompress(src: &[u8], len: u32) -> Result<Vec<u8>, KernelError> {
+        decompress_lz4_block(src, len)
+    }
+
+    #[test]
+    fn copies_literals_and_matches() {
+        // "abcd" then a non-overlapping match of 4 at offset 4, then "x".
+        let src = [0x40, b'a', b'b', b'c', b'd', 0x04, 0x00, 0x10, b'x'];
+        assert_eq!(decompress(&src, 9).unwrap(), b"abcdabcdx");
+        // Extended lengths: 20 literals, then a match of 4 + 15 + 3.
+        let mut src = vec![0xff, 5];
+        src.extend(b'a'..b'a' + 20);
+        src.extend_from_slice(&[20, 0, 3, 0x00]);
+        let mut expected: Vec<u8> = (b'a'..b'a' + 20).collect();
+        expected.extend(b'a'..b'a' + 20);
+        expected.extend(b'a'..b'a' + 2);
+        assert_eq!(decompress(&src, 42).unwrap(), expected);
+    }
+
+    #[test]
+    fn overlapping_matches_repeat_the_window() {
+        // Offset 1 repeats a single byte.
+        let src = [0x15, b'a', 0x01, 0x00, 0x10, b'b'];
+        assert_eq!(decompress(&src, 11).unwrap(), b"aaaaaaaaaab");
+        // Offset 2 repeats a pair, ending mid-pair.
+        let src = [0x23, b'a', b'b', 0x02, 0x00, 0x00];
+        assert_eq!(decompress(&src, 9).unwrap(), b"ababababa");
+    }
+
+    #[test]
+    fn truncated_blocks_are_malformed() {
+        let src = [0xf5, 2, b'a', b'b', b'c', b'd', b'e', b'f', b'g', b'h'];
+        let src = [&src[..], &[b'i'; 9], &[0x01, 0x00, 0x10, b'z']].concat();
+        assert!(decompress(&src, 27).is_ok());
+        for len in 1..src.len() {
+            let error = decompress(&src[..len], 27).unwrap_err();
+            assert_eq!(error.code(), ErrorKind::MalformedInput as u32);
+        }
+    }
+
+    #[test]
+    fn declared_length_is_enforced() {
+        let src = [0x40, b'a', b'b', b'c', b'd', 0x04, 0x00, 0x10, b'x'];
+        assert!(decompress(&src, 8).is_err());
+        assert!(decompress(&src, 10).is_err());
+        // Offsets of zero or before the block.
+        assert!(decompress(&[0x10, b'a', 0x00, 0x00, 0x00], 5).is_err());
+        assert!(decompress(&[0x10, b'a', 0x02, 0x00, 0x00], 5).is_err());
+        // Output already in the vector is outside the block's window.
+        let mut out = b"prefix".to_vec();
+        assert!(decompress_block(&[0x00, 0x01, 0x00, 0x00], 4, &mut out).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! Linear memory and allocator statistics.
+//!
+//! A thin counting wrapper around the system allocator tracks live bytes and
+//! their high-water mark, so the host can spot leaks and decide when to
+//! recycle the worker. Linear memory size is read straight from the wasm
+//! `memory.size` instruction; it only ever grows, so its high-water mark is
+//! the current size.
+//!
+//! Memory grows on demand, one allocation at a time, and every growth
+//! detaches the JS views into it. `reserveMemory` grows it once up front
+//! instead: it allocates the estimated size through the allocator and frees
+//! it again. Wasm memory never shrinks, so the freed block stays with the
+//! allocator and serves the allocations that follow without further growth.
+//!
+//! On low-end devices the host can cap live bytes with `setMemoryBudget`.
+//! Entry points that allocate in proportion to their input (ingestion, the
+//! scratch buffer, accumulation input copies) check the budget first and
+//! fail with an `OutOfMemory` error giving the requested and available
+//! bytes, rather than growing until an allocation aborts the module, so the
+//! app can sample or use coarser bins instead. The check is an estimate
+//! made before the work starts; smaller allocations are not checked.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use wasm_bindgen::prelude::*;
+
+use crate::error::{ErrorKind, KernelError};
+
+const WASM_PAGE_BYTES: usize = 65_536;
+
+struct CountingAllocator;
+
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static DEALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+/// Live-byte cap; `usize::MAX` when unset.
+static BUDGET: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+impl CountingAllocator {
+    fn grew(size: usize) {
+        let live = LIVE_BYTES.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK_LIVE_BYTES.fetch_max(live, Ordering::Relaxed);
+    }
+
+    fn shrank(size: usize) {
+        LIVE_BYTES.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            Self::grew(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            Self::grew(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        Self::shrank(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let next = System.realloc(ptr, layout, new_size);
+        if !next.is_null() {
+            if new_size >= layout.size() {
+                Self::grew(new_size - layout.size());
+            } else {
+                Self::shrank(layout.size() - new_size);
+            }
+        }
+        next
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn memory_pages() -> usize {
+    core::arch::wasm32::memory_size(0)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn memory_pages() -> usize {
+    0
+}
+
+/// Snapshot returned by `memoryStats()`. Byte counts are `number`s; they are
+/// bounded by the 4 GiB wasm32 address space.
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub struct MemoryStats {
+    pages: usize,
+    live_bytes: usize,
+    peak_live_bytes: usize,
+    allocations: usize,
+    deallocations: usize,
+}
+
+#[wasm_bindgen]
+impl MemoryStats {
+    /// Current linear memory size in 64 KiB pages.
+    #[wasm_bindgen(getter)]
+    pub fn pages(&self) -> f64 {
+        self.pages as f64
+    }
+
+    /// Current linear memory size in bytes.
+    #[wasm_bindgen(getter = memoryBytes)]
+    pub fn memory_bytes(&self) -> f64 {
+        (self.pages * WASM_PAGE_BYTES) as f64
+    }
+
+    /// Bytes currently handed out by the allocator.
+    #[wasm_bindgen(getter = liveBytes)]
+    pub fn live_bytes(&self) -> f64 {
+        self.live_bytes as f64
+    }
+
+    /// Highest `liveBytes` seen since start-up or the last `resetMemoryPeak`.
+    #[wasm_bindgen(getter = peakLiveBytes)]
+    pub fn peak_live_bytes(&self) -> f64 {
+        self.peak_live_bytes as f64
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn allocations(&self) -> f64 {
+        self.allocations as f64
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn deallocations(&self) -> f64 {
+        self.deallocations as f64
+    }
+}
+
+#[wasm_bindgen(js_name = memoryStats)]
+pub fn memory_stats() -> MemoryStats {
+    MemoryStats {
+        pages: memory_pages(),
+        live_bytes: LIVE_BYTES.load(Ordering::Relaxed),
+        peak_live_bytes: PEAK_LIVE_BYTES.load(Ordering::Relaxed),
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        deallocations: DEALLOCATIONS.load(Ordering::Relaxed),
+    }
+}
+
+/// Restarts the live-bytes high-water mark from the current value, e.g. after
+/// ingestion completes, so later peaks reflect interactive use only.
+#[wasm_bindgen(js_name = resetMemoryPeak)]
+pub fn reset_memory_peak() {
+    PEAK_LIVE_BYTES.store(LIVE_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+/// Makes sure `bytes` more can be allocated without growing linear memory,
+/// growing it now if needed, and returns the memory size in bytes. Call it
+/// before ingestion with the estimated dataset size, and before creating
+/// views, which growth detaches. The block counts toward `peakLiveBytes`
+/// until the next `resetMemoryPeak`.
+#[wasm_bindgen(js_name = reserveMemory)]
+pub fn reserve_memory(bytes: f64) -> Result<f64, KernelError> {
+    if !(0.0..=u32::MAX as f64).contains(&bytes) {
+        return Err(KernelError::invalid_argument("bytes out of range").with("bytes", bytes));
+    }
+    check_budget(bytes as usize)?;
+    let mut block: Vec<u8> = Vec::new();
+    block.try_reserve_exact(bytes as usize).map_err(|_| {
+        KernelError::new(ErrorKind::OutOfMemory, "linear memory cannot grow that far")
+            .with("requested", bytes)
+            .with("memoryBytes", (memory_pages() * WASM_PAGE_BYTES) as f64)
+    })?;
+    drop(block);
+    Ok((memory_pages() * WASM_PAGE_BYTES) as f64)
+}
+
+/// Lifts the memory budget; see `hardReset`.
+pub(crate) fn reset() {
+    BUDGET.store(usize::MAX, Ordering::Relaxed);
+}
+
+/// Fails with `OutOfMemory` when allocating `requested` more bytes would
+/// take live bytes past the budget.
+pub(crate) fn check_budget(requested: usize) -> Result<(), KernelError> {
+    let budget = BUDGET.load(Ordering::Relaxed);
+    if budget == usize::MAX {
+        return Ok(());
+    }
+    let available = budget.saturating_sub(LIVE_BYTES.load(Ordering::Relaxed));
+    if requested <= available {
+        return Ok(());
+    }
+    Err(
+        KernelError::new(ErrorKind::OutOfMemory, "memory budget exceeded")
+            .with("requested", requested as f64)
+            .with("available", available as f64)
+            .with("budget", budget as f64),
+    )
+}
+
+/// Caps live bytes at `bytes` for the checked entry points; omitted lifts
+/// the cap. Memory already live is kept even when over the new budget.
+#[wasm_bindgen(js_name = setMemoryBudget)]
+pub fn set_memory_budget(bytes: Option<f64>) -> Result<(), KernelError> {
+    let budget = match bytes {
+        None => usize::MAX,
+        Some(bytes) if (0.0..=u32::MAX as f64).contains(&bytes) => bytes as usize,
+        Some(bytes) => {
+            return Err(KernelError::invalid_argument("bytes out of range").with("bytes", bytes))
+        }
+    };
+    BUDGET.store(budget, Ordering::Relaxed);
+    Ok(())
+}
+
+#[wasm_bindgen(js_name = memoryBudget)]
+pub fn memory_budget() -> Option<f64> {
+    let budget = BUDGET.load(Ordering::Relaxed);
+    (budget != usize::MAX).then_some(budget as f64)
+}
@@ -0,0 +1,277 @@
+//! Kernel instrumentation shared by the histogram entry points.
+//!
+//! Counters accumulate in a thread-local [`Metrics`] value between
+//! `resetMetrics` and `takeMetrics`. The snapshot handed to JavaScript is an
+//! exported class, so the generated TypeScript declarations give callers
+//! compile-time checked fields. Schema (JS names):
+//!
+//! | field            | meaning                                             |
+//! |------------------|-----------------------------------------------------|
+//! | `flushes`        | shard flushes that wrote at least one bin           |
+//! | `evictions`      | flushes caused by rotating a shard out of the cache |
+//! | `finalFlushes`   | flushes performed at the end of a call              |
+//! | `bins`           | bins written by flushes                             |
+//! | `rows`           | rows carried by flushes                             |
+//! | `allocations`    | workspace buffers that had to grow                  |
+//! | `droppedBins`    | rows skipped because their bin was out of range     |
+//! | `droppedSamples` | first offending rows as `[index, bin]` pairs        |
+//! | `rowOffset`      | row stream position of the call's first row         |
+//! | `invocations`    | accumulation kernels executed                       |
+//! | `timing`         | [`KernelTimings`] section                           |
+//! | `flushSizes`     | [`FlushSizes`] section                              |
+//!
+//! New sections are added as nested classes rather than new top-level
+//! fields so existing readers keep working. `toJSON()` produces a plain
+//! object with the same shape for logging and merging.
+//!
+//! Collection can be switched off at runtime with `setMetricsEnabled(false)`,
+//! or compiled out entirely by building without the default `metrics`
+//! feature; either way the hot path skips the thread-local borrow.
+
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use wasm_bindgen::prelude::*;
+
+use crate::recovery;
+
+thread_local! {
+    pub(crate) static METRICS: RefCell<Metrics> = RefCell::new(Metrics::default());
+    static ENABLED: Cell<bool> = const { Cell::new(true) };
+    static DROPPED_SAMPLE_LIMIT: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Clears the metrics and restores their settings; see `hardReset`.
+pub(crate) fn reset() {
+    METRICS.with(|metrics| recovery::reset_cell(metrics, Metrics::default()));
+    ENABLED.with(|enabled| enabled.set(true));
+    DROPPED_SAMPLE_LIMIT.with(|limit| limit.set(0));
+}
+
+/// Whether kernels should record metrics for the current call.
+#[inline]
+pub(crate) fn enabled() -> bool {
+    cfg!(feature = "metrics") && ENABLED.with(Cell::get)
+}
+
+/// Applies `update` to the live metrics when collection is enabled.
+#[inline]
+pub(crate) fn record(update: impl FnOnce(&mut Metrics)) {
+    if enabled() {
+        METRICS.with(|metrics| update(&mut metrics.borrow_mut()));
+    }
+}
+
+/// Turns metrics collection on or off. Has no effect in builds compiled
+/// without the `metrics` feature, where collection is always off.
+#[wasm_bindgen(js_name = setMetricsEnabled)]
+pub fn set_metrics_enabled(enabled: bool) {
+    ENABLED.with(|cell| cell.set(enabled));
+}
+
+/// Number of out-of-range `(index, bin)` pairs retained per metrics period.
+/// Zero (the default) only counts drops.
+#[wasm_bindgen(js_name = setDroppedSampleLimit)]
+pub fn set_dropped_sample_limit(limit: u32) {
+    DROPPED_SAMPLE_LIMIT.with(|cell| cell.set(limit as usize));
+}
+
+pub(crate) fn dropped_sample_limit() -> usize {
+    DROPPED_SAMPLE_LIMIT.with(Cell::get)
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metrics {
+    pub(crate) flushes: u64,
+    #[serde(rename = "evictions")]
+    pub(crate) evicts: u64,
+    pub(crate) final_flushes: u64,
+    pub(crate) bins: u64,
+    pub(crate) rows: u64,
+    pub(crate) allocations: u64,
+    pub(crate) invocations: u64,
+    pub(crate) dropped_bins: u64,
+    /// `[row index within the call's input, bin]` for the first drops.
+    pub(crate) dropped_samples: Vec<[u32; 2]>,
+    /// Logical row number of the call's first row when it pushed a row
+    /// stream chunk, else zero.
+    pub(crate) row_offset: u64,
+    pub(crate) timing: KernelTimings,
+    pub(crate) flush_sizes: FlushSizes,
+}
+
+/// Wall-clock breakdown of kernel execution, in milliseconds.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KernelTimings {
+    /// Time spent inside accumulation kernels.
+    pub(crate) kernel_ms: f64,
+    /// Portion of `kernel_ms` spent in the vectorised main loop (SIMD builds).
+    pub(crate) simd_ms: f64,
+    /// Portion of `kernel_ms` spent in the scalar tail after the SIMD loop.
+    pub(crate) tail_ms: f64,
+}
+
+/// Number of power-of-two buckets in the flush size histograms.
+const FLUSH_SIZE_BUCKETS: usize = 16;
+
+/// Distribution of shard flush sizes. Bucket `i` counts flushes whose size
+/// falls in `[2^i, 2^(i+1))`; the last bucket is open-ended. Lots of flushes
+/// in the low buckets mean the shard cache is rotating before it can batch.
+#[wasm_bindgen]
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlushSizes {
+    bins_per_flush: [u64; FLUSH_SIZE_BUCKETS],
+    rows_per_flush: [u64; FLUSH_SIZE_BUCKETS],
+}
+
+impl FlushSizes {
+    pub(crate) fn record(&mut self, bins: u64, rows: u64) {
+        self.bins_per_flush[size_bucket(bins)] += 1;
+        self.rows_per_flush[size_bucket(rows)] += 1;
+    }
+}
+
+fn size_bucket(size: u64) -> usize {
+    (size.max(1).ilog2() as usize).min(FLUSH_SIZE_BUCKETS - 1)
+}
+
+impl Metrics {
+    pub(crate) fn reset(&mut self) {
+        *self = Metrics::default();
+    }
+
+    pub(crate) fn finalise(&mut self) {
+        // no-op placeholder for future derived fields
+    }
+}
+
+// Counters are surfaced as `number` rather than `bigint`; they stay well below
+// 2^53 for any realistic session.
+#[wasm_bindgen]
+impl Metrics {
+    #[wasm_bindgen(getter)]
+    pub fn flushes(&self) -> f64 {
+        self.flushes as f64
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn evictions(&self) -> f64 {
+        self.evicts as f64
+    }
+
+    #[wasm_bindgen(getter = finalFlushes)]
+    pub fn final_flushes(&self) -> f64 {
+        self.final_flushes as f64
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn bins(&self) -> f64 {
+        self.bins as f64
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn rows(&self) -> f64 {
+        self.rows as f64
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn allocations(&self) -> f64 {
+        self.allocations as f64
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn invocations(&self) -> f64 {
+        self.invocations as f64
+    }
+
+    #[wasm_bindgen(getter = droppedBins)]
+    pub fn dropped_bins(&self) -> f64 {
+        self.dropped_bins as f64
+    }
+
+    /// Flattened `[index, bin, index, bin, ...]` pairs.
+    #[wasm_bindgen(getter = droppedSamples)]
+    pub fn dropped_samples(&self) -> Vec<u32> {
+        self.dropped_samples.iter().flatten().copied().collect()
+    }
+
+    /// Add to a `droppedSamples` index for its row in the stream.
+    #[wasm_bindgen(getter = rowOffset)]
+    pub fn row_offset(&self) -> f64 {
+        self.row_offset as f64
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn timing(&self) -> KernelTimings {
+        self.timing
+    }
+
+    #[wasm_bindgen(getter = flushSizes)]
+    pub fn flush_sizes(&self) -> FlushSizes {
+        self.flush_sizes.clone()
+    }
+
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(self).map_err(JsValue::from)
+    }
+
+    /// The `toJSON()` object encoded as MessagePack.
+    #[cfg(feature = "msgpack")]
+    #[wasm_bindgen(js_name = toMsgpack)]
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, crate::error::KernelError> {
+        crate::msgpack::encode(self)
+    }
+}
+
+#[wasm_bindgen]
+impl KernelTimings {
+    #[wasm_bindgen(getter = kernelMs)]
+    pub fn kernel_ms(&self) -> f64 {
+        self.kernel_ms
+    }
+
+    #[wasm_bindgen(getter = simdMs)]
+    pub fn simd_ms(&self) -> f64 {
+        self.simd_ms
+    }
+
+    #[wasm_bindgen(getter = tailMs)]
+    pub fn tail_ms(&self) -> f64 {
+        self.tail_ms
+    }
+}
+
+#[wasm_bindgen]
+impl FlushSizes {
+    #[wasm_bindgen(getter = binsPerFlush)]
+    pub fn bins_per_flush(&self) -> Vec<f64> {
+        self.bins_per_flush
+            .iter()
+            .map(|&count| count as f64)
+            .collect()
+    }
+
+    #[wasm_bindgen(getter = rowsPerFlush)]
+    pub fn rows_per_flush(&self) -> Vec<f64> {
+        self.rows_per_flush
+            .iter()
+            .map(|&count| count as f64)
+            .collect()
+    }
+}
+
+#[wasm_bindgen(js_name = resetMetrics)]
+pub fn reset_metrics() {
+    METRICS.with(|metrics| metrics.borrow_mut().reset());
+}
+
+/// Returns the metrics gathered since the last reset and clears them.
+#[wasm_bindgen(js_name = takeMetrics)]
+pub fn take_metrics() -> Metrics {
+    METRICS.with(|metrics| std::mem::take(&mut *metrics.borrow_mut()))
+}
@@ -0,0 +1,117 @@
+//! MinHash signatures of per-bin key sets.
+//!
+//! "Similar segments" compares the sets of keys (users, devices) behind
+//! each bin of a dimension by Jaccard similarity, without exporting the
+//! sets. Each bin keeps, per hash function, the smallest hash of its keys;
+//! the share of positions where two signatures agree estimates the Jaccard
+//! similarity of their sets, with standard error about
+//! `sqrt(J (1 - J) / hashes)`.
+//!
+//! Hash functions are derived from each key's hash (see `distinctRows` for
+//! key equality) and truncated to 32 bits, so signatures fit a
+//! `Uint32Array`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, bit};
+use crate::error::KernelError;
+use crate::hash;
+
+const EMPTY: u32 = u32::MAX;
+
+/// MinHash signatures, `hashes` entries per bin, of the keys in the column
+/// behind `handle` grouped by `bins` (each row's bin, below `binCount`),
+/// over the rows set in `mask` (an LSB-first row bitmap in the layout's
+/// `activeMask` format; every row when omitted). Entry `bin * hashes + i`
+/// is the bin's minimum under hash `i`; bins without rows hold `0xffffffff`
+/// throughout.
+#[wasm_bindgen(js_name = minhashSignatures)]
+pub fn minhash_signatures(
+    handle: u32,
+    bins: &[u16],
+    bin_count: u32,
+    hashes: u32,
+    mask: Option<Vec<u8>>,
+) -> Result<Vec<u32>, KernelError> {
+    if bin_count == 0 || bin_count > u32::from(u16::MAX) + 1 {
+        return Err(KernelError::bad_bin_count(bin_count));
+    }
+    if hashes == 0 {
+        return Err(KernelError::invalid_argument(
+            "signatures need at least one hash",
+        ));
+    }
+    let width = hashes as usize;
+    let mut signatures = vec![EMPTY; bin_count as usize * width];
+    columns::with_column(handle, |column| {
+        if column.len != bins.len() {
+            return Err(
+                KernelError::invalid_argument("bins and keys lengths differ")
+                    .with("bins", bins.len() as f64)
+                    .with("keys", column.len as f64),
+            );
+        }
+        if let Some(mask) = &mask {
+            if mask.len() < column.len.div_ceil(8) {
+                return Err(KernelError::invalid_argument("mask is too short")
+                    .with("needed", column.len.div_ceil(8) as f64)
+                    .with("available", mask.len() as f64));
+            }
+        }
+        let mut key = Vec::new();
+        for (row, &bin) in bins.iter().enumerate() {
+            if mask.as_ref().is_some_and(|mask| !bit(mask, row)) {
+                continue;
+            }
+            if u32::from(bin) >= bin_count {
+                return Err(KernelError::invalid_argument("bin out of range")
+                    .with("bin", f64::from(bin))
+                    .with("binCount", f64::from(bin_count)));
+            }
+            let start = bin as usize * width;
+            let signature = &mut signatures[start..start + width];
+            let probes = hash::probes(hash::key_hash(column, row, &mut key), width);
+            for (slot, probe) in signature.iter_mut().zip(probes) {
+                *slot = (*slot).min((hash::mix(probe) >> 32) as u32);
+            }
+        }
+        Ok(())
+    })?;
+    Ok(signatures)
+}
+
+/// Estimated Jaccard similarity between bin `bin` and every bin, from
+/// `signatures` as returned by `minhashSignatures` with `hashes` entries per
+/// bin. NaN where either bin had no rows.
+#[wasm_bindgen(js_name = minhashSimilarity)]
+pub fn minhash_similarity(
+    signatures: &[u32],
+    hashes: u32,
+    bin: u32,
+) -> Result<Vec<f64>, KernelError> {
+    let width = hashes as usize;
+    if width == 0 || !signatures.len().is_multiple_of(width) {
+        return Err(
+            KernelError::invalid_argument("signatures are not a multiple of hashes")
+                .with("length", signatures.len() as f64)
+                .with("hashes", f64::from(hashes)),
+        );
+    }
+    let Some(target) = signatures.chunks_exact(width).nth(bin as usize) else {
+        return Err(KernelError::invalid_argument("bin out of range")
+            .with("bin", f64::from(bin))
+            .with("bins", (signatures.len() / width) as f64));
+    };
+    let is_empty = |signature: &[u32]| signature.iter().all(|&entry| entry == EMPTY);
+    let target_empty = is_empty(target);
+    Ok(signatures
+        .chunks_exact(width)
+        .map(|signature| {
+            if target_empty || is_empty(signature) {
+                return f64::NAN;
+            }
+            let agree = signature.iter().zip(target).filter(|(a, b)| a == b).count();
+            agree as f64 / width as f64
+        })
+        .collect())
+}
@@ -0,0 +1,48 @@
+//! MessagePack encoding of results and metrics.
+//!
+//! Structs encode as maps keyed by their camelCase field names (the same
+//! shape `toJSON()` produces), so the bytes can be posted to another worker
+//! or sent over a WebSocket and decoded without a schema.
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use wasm_bindgen::prelude::*;
+
+use crate::arrow_export::{self, ExportColumn};
+use crate::error::{ErrorKind, KernelError};
+
+pub(crate) fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, KernelError> {
+    rmp_serde::to_vec_named(value).map_err(|err| {
+        KernelError::new(
+            ErrorKind::InvalidState,
+            format!("msgpack encoding failed: {err}"),
+        )
+    })
+}
+
+/// Columns in order, as a map from column name to array.
+struct ColumnMap<'a>(&'a [(String, ExportColumn)]);
+
+impl Serialize for ColumnMap<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, column) in self.0 {
+            map.serialize_entry(name, column)?;
+        }
+        map.end()
+    }
+}
+
+/// MessagePack counterpart of `encodeGroupsArrow`: a map with `key`,
+/// `count` and one entry per aggregate, each an array of `counts.length`
+/// values.
+#[wasm_bindgen(js_name = encodeGroupsMsgpack)]
+pub fn encode_groups_msgpack(
+    keys: &[f64],
+    counts: &[u32],
+    labels: Option<Vec<String>>,
+    aggregate_names: Vec<String>,
+    aggregates: &[f64],
+) -> Result<Vec<u8>, KernelError> {
+    let columns = arrow_export::group_columns(keys, counts, labels, aggregate_names, aggregates)?;
+    encode(&ColumnMap(&columns))
+}
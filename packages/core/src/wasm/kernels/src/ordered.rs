@@ -0,0 +1,176 @@
+//! Group results ordered by an aggregate.
+//!
+//! The `group.order` / `group.top` equivalent: bins are ranked by count or
+//! sum inside wasm and returned with their aggregates already permuted, so a
+//! chart sorted by value does not re-sort in JS on every interaction. Bins
+//! that tie keep ascending bin order in either direction.
+//!
+//! A `limit` well below the bin count (the top categories of a wide
+//! categorical dimension) is served from a heap of the best `limit` bins in
+//! one pass over the aggregates, instead of ranking every bin.
+
+use std::collections::BinaryHeap;
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::sort;
+
+/// Limits below `bins / TOP_HEAP_RATIO` take the heap path.
+const TOP_HEAP_RATIO: usize = 16;
+
+/// Aggregate that ranks the bins.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupOrder {
+    Count = 0,
+    Sum = 1,
+}
+
+/// Bins in rank order with their aggregates aligned.
+#[wasm_bindgen]
+pub struct OrderedGroups {
+    bins: Vec<u32>,
+    counts: Vec<u32>,
+    sums: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl OrderedGroups {
+    /// Bin indices, best ranked first.
+    #[wasm_bindgen(getter)]
+    pub fn bins(&self) -> Vec<u32> {
+        self.bins.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn counts(&self) -> Vec<u32> {
+        self.counts.clone()
+    }
+
+    /// Sums aligned with `bins`; empty when the group has no measure.
+    #[wasm_bindgen(getter)]
+    pub fn sums(&self) -> Vec<f64> {
+        self.sums.clone()
+    }
+}
+
+impl OrderedGroups {
+    /// Ranks the bins of `counts` (and `sums`, when not empty) by `by`,
+    /// keeping the first `limit` when given.
+    pub(crate) fn new(
+        counts: &[u32],
+        sums: &[f64],
+        by: GroupOrder,
+        descending: bool,
+        limit: Option<u32>,
+    ) -> Result<Self, KernelError> {
+        let keys: Vec<u64> = match by {
+            GroupOrder::Count => counts.iter().map(|&count| u64::from(count)).collect(),
+            GroupOrder::Sum if sums.len() == counts.len() => {
+                sums.iter().map(|&sum| sort::f64_key(sum)).collect()
+            }
+            GroupOrder::Sum => {
+                return Err(KernelError::invalid_argument(
+                    "ordering by sum needs a sum per bin",
+                ))
+            }
+        };
+        let keys: Vec<u64> = if descending {
+            keys.into_iter().map(|key| !key).collect()
+        } else {
+            keys
+        };
+        let bins = match limit {
+            Some(limit) if (limit as usize) < keys.len() / TOP_HEAP_RATIO => {
+                top(&keys, limit as usize)
+            }
+            _ => {
+                let mut bins = (0..counts.len() as u32).collect();
+                sort::sort_permutation(&keys, &mut bins);
+                if let Some(limit) = limit {
+                    bins.truncate(limit as usize);
+                }
+                bins
+            }
+        };
+        Ok(OrderedGroups {
+            counts: bins.iter().map(|&bin| counts[bin as usize]).collect(),
+            sums: if sums.is_empty() {
+                Vec::new()
+            } else {
+                bins.iter().map(|&bin| sums[bin as usize]).collect()
+            },
+            bins,
+        })
+    }
+}
+
+/// The `limit` bins with the smallest keys, ascending, ties by bin: a
+/// max-heap holds the best `(key, bin)` pairs seen so far.
+fn top(keys: &[u64], limit: usize) -> Vec<u32> {
+    let mut heap = BinaryHeap::with_capacity(limit);
+    for (bin, &key) in keys.iter().enumerate() {
+        let entry = (key, bin as u32);
+        if heap.len() < limit {
+            heap.push(entry);
+        } else if let Some(mut worst) = heap.peek_mut() {
+            if entry < *worst {
+                *worst = entry;
+            }
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|(_, bin)| bin)
+        .collect()
+}
+
+/// `accumulateBins` with the resulting bins ranked by count (`descending`
+/// for the largest first) and cut to the first `limit` when given.
+#[wasm_bindgen(js_name = accumulateBinsOrdered)]
+pub fn accumulate_bins_ordered(
+    bins: &[u16],
+    bin_count: u32,
+    dimension: Option<u32>,
+    descending: bool,
+    limit: Option<u32>,
+) -> Result<OrderedGroups, KernelError> {
+    crate::accumulate_bins_with(
+        "accumulateBinsOrdered",
+        bins,
+        bin_count,
+        dimension,
+        |counts| OrderedGroups::new(counts, &[], GroupOrder::Count, descending, limit),
+    )?
+}
+
+/// The `k` bins with the most rows after `accumulateBins`, largest first.
+#[wasm_bindgen(js_name = accumulateBinsTop)]
+pub fn accumulate_bins_top(
+    bins: &[u16],
+    bin_count: u32,
+    k: u32,
+    dimension: Option<u32>,
+) -> Result<OrderedGroups, KernelError> {
+    crate::accumulate_bins_with("accumulateBinsTop", bins, bin_count, dimension, |counts| {
+        OrderedGroups::new(counts, &[], GroupOrder::Count, true, Some(k))
+    })?
+}
+
+/// The `k` bins with the most rows after `accumulateScratch`, largest
+/// first.
+#[wasm_bindgen(js_name = accumulateScratchTop)]
+pub fn accumulate_scratch_top(
+    len: u32,
+    bin_count: u32,
+    k: u32,
+    dimension: Option<u32>,
+) -> Result<OrderedGroups, KernelError> {
+    crate::accumulate_scratch_with(
+        "accumulateScratchTop",
+        len as usize,
+        bin_count,
+        dimension,
+        |counts| OrderedGroups::new(counts, &[], GroupOrder::Count, true, Some(k)),
+    )?
+}
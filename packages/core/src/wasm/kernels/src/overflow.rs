@@ -0,0 +1,198 @@
+//! Histograms that detect `u32` overflow.
+//!
+//! A single `accumulateBins` call cannot overflow its `u32` counts, but
+//! running histograms fed chunk by chunk (streaming ingest, per-worker
+//! partials merged together) can, and a wrapped count draws as a tiny bar
+//! where the largest one should be. `accumulateBinsChecked` adds a chunk
+//! onto running counts in 64-bit arithmetic and lets the caller pick what
+//! happens to a bin that no longer fits.
+
+use wasm_bindgen::prelude::*;
+
+use crate::bigint;
+use crate::error::{ErrorKind, KernelError};
+
+/// What `accumulateBinsChecked` does with a count past `u32::MAX`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowMode {
+    /// Clamp the bin to `u32::MAX` and report it in `overflowedBins`.
+    Saturate = 0,
+    /// Return every count as an `f64` (`wideCounts`), exact up to 2^53, and
+    /// exactly as 64-bit integers (`bigCounts`, `countsHigh`/`countsLow`).
+    Promote = 1,
+    /// Fail with an `Overflow` error naming the first bin that overflowed.
+    Error = 2,
+}
+
+/// Counts from `accumulateBinsChecked`.
+#[wasm_bindgen]
+pub struct CheckedCounts {
+    counts: Vec<u32>,
+    wide_counts: Vec<f64>,
+    big_counts: Vec<u64>,
+    overflowed_bins: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl CheckedCounts {
+    /// Counts per bin; empty under `OverflowMode.Promote`.
+    #[wasm_bindgen(getter)]
+    pub fn counts(&self) -> Vec<u32> {
+        self.counts.clone()
+    }
+
+    /// Counts per bin under `OverflowMode.Promote`; empty otherwise.
+    #[wasm_bindgen(getter = wideCounts)]
+    pub fn wide_counts(&self) -> Vec<f64> {
+        self.wide_counts.clone()
+    }
+
+    /// Counts per bin as a `BigUint64Array` under `OverflowMode.Promote`;
+    /// empty otherwise.
+    #[wasm_bindgen(getter = bigCounts)]
+    pub fn big_counts(&self) -> Vec<u64> {
+        self.big_counts.clone()
+    }
+
+    /// High words of `bigCounts`, for engines without BigInt arrays.
+    #[wasm_bindgen(getter = countsHigh)]
+    pub fn counts_high(&self) -> Vec<u32> {
+        bigint::high_words(&self.big_counts)
+    }
+
+    /// Low words of `bigCounts`.
+    #[wasm_bindgen(getter = countsLow)]
+    pub fn counts_low(&self) -> Vec<u32> {
+        bigint::low_words(&self.big_counts)
+    }
+
+    /// Bins clamped under `OverflowMode.Saturate`, ascending.
+    #[wasm_bindgen(getter = overflowedBins)]
+    pub fn overflowed_bins(&self) -> Vec<u32> {
+        self.overflowed_bins.clone()
+    }
+}
+
+/// `accumulateBins` added onto the running counts `base` (a
+/// `BigUint64Array`, one per bin, zeros when omitted), with counts past
+/// `u32::MAX` handled as `overflow` says. `base` may come from an earlier
+/// call's `bigCounts` under `OverflowMode.Promote`, so running totals keep
+/// growing past `u32`, or from its `counts` in the other modes.
+#[wasm_bindgen(js_name = accumulateBinsChecked)]
+pub fn accumulate_bins_checked(
+    bins: &[u16],
+    bin_count: u32,
+    dimension: Option<u32>,
+    base: Option<Vec<u64>>,
+    overflow: OverflowMode,
+) -> Result<CheckedCounts, KernelError> {
+    if let Some(base) = &base {
+        if base.len() != bin_count as usize {
+            return Err(KernelError::invalid_argument("base length differs")
+                .with("expected", f64::from(bin_count))
+                .with("actual", base.len() as f64));
+        }
+    }
+    let totals: Vec<u64> = crate::accumulate_bins_with(
+        "accumulateBinsChecked",
+        bins,
+        bin_count,
+        dimension,
+        |counts| {
+            counts
+                .iter()
+                .enumerate()
+                .map(|(bin, &count)| {
+                    let base = base.as_ref().map_or(0, |base| base[bin]);
+                    base.checked_add(u64::from(count)).ok_or_else(|| {
+                        KernelError::new(ErrorKind::Overflow, "bin count exceeds u64")
+                            .with("bin", bin as f64)
+                    })
+                })
+                .collect::<Result<_, _>>()
+        },
+    )??;
+    let mut checked = CheckedCounts {
+        counts: Vec::new(),
+        wide_counts: Vec::new(),
+        big_counts: Vec::new(),
+        overflowed_bins: Vec::new(),
+    };
+    match overflow {
+        OverflowMode::Promote => {
+            checked.wide_counts = totals.iter().map(|&total| total as f64).collect();
+            checked.big_counts = totals;
+        }
+        OverflowMode::Saturate => {
+            checked.counts = totals
+                .iter()
+                .enumerate()
+                .map(|(bin, &total)| {
+                    u32::try_from(total).unwrap_or_else(|_| {
+                        checked.overflowed_bins.push(bin as u32);
+                        u32::MAX
+                    })
+                })
+                .collect();
+        }
+        OverflowMode::Error => {
+            checked.counts = totals
+                .iter()
+                .enumerate()
+                .map(|(bin, &total)| {
+                    u32::try_from(total).map_err(|_| {
+                        KernelError::new(ErrorKind::Overflow, "bin count exceeds u32")
+                            .with("bin", bin as f64)
+                            .with("count", total as f64)
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+    }
+    Ok(checked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn promoted_totals_keep_growing_past_u32() {
+        let base = vec![u64::from(u32::MAX), 5];
+        let checked =
+            accumulate_bins_checked(&[0, 0, 1], 2, None, Some(base), OverflowMode::Promote);
+        let checked = checked.unwrap();
+        assert!(checked.counts().is_empty());
+        assert_eq!(checked.big_counts(), [u64::from(u32::MAX) + 2, 6]);
+        // The promoted totals feed the next chunk.
+        let next = accumulate_bins_checked(
+            &[0],
+            2,
+            None,
+            Some(checked.big_counts()),
+            OverflowMode::Promote,
+        );
+        let next = next.unwrap();
+        assert_eq!(next.big_counts(), [u64::from(u32::MAX) + 3, 6]);
+        assert_eq!(next.wide_counts(), [4_294_967_298.0, 6.0]);
+        assert_eq!((next.counts_high()[0], next.counts_low()[0]), (1, 2));
+    }
+
+    #[test]
+    fn saturate_and_error_handle_u32_overflow() {
+        let base = Some(vec![u64::from(u32::MAX), 1]);
+        let saturated =
+            accumulate_bins_checked(&[0, 1], 2, None, base.clone(), OverflowMode::Saturate);
+        let saturated = saturated.unwrap();
+        assert_eq!(saturated.counts(), [u32::MAX, 2]);
+        assert_eq!(saturated.overflowed_bins(), [0]);
+        let error = accumulate_bins_checked(&[0], 2, None, base, OverflowMode::Error).err();
+        assert_eq!(error.unwrap().code(), ErrorKind::Overflow as u32);
+        let full = Some(vec![u64::MAX, 0]);
+        assert!(accumulate_bins_checked(&[0], 2, None, full, OverflowMode::Promote).is_err());
+        assert!(
+            accumulate_bins_checked(&[0], 2, None, Some(vec![0]), OverflowMode::Promote).is_err()
+        );
+    }
+}
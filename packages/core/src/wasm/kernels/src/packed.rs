@@ -0,0 +1,57 @@
+//! Batch results packed into one buffer.
+//!
+//! `accumulateBatch` returns an array of `Uint32Array`s, one allocation
+//! crossing the boundary per request. The `Packed` variants return a single
+//! `Uint32Array` instead, whose buffer can be transferred to another thread
+//! as is. Layout, in 32-bit words:
+//!
+//! * word `0` holds the number of results `n`;
+//! * words `1 ..= n + 1` hold word offsets, result `i` spanning
+//!   `[offsets[i], offsets[i + 1])`;
+//! * the results follow back to back in request order,
+//!
+//! so result `i` is `packed.subarray(packed[1 + i], packed[2 + i])`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+
+/// Runs the batch, appending what `write` makes of each result's counts
+/// after the header.
+fn pack(
+    entry: &'static str,
+    requests: &[u32],
+    mut write: impl FnMut(&[u32], &mut Vec<u32>),
+) -> Result<Vec<u32>, KernelError> {
+    let results = requests.len() / crate::BATCH_STRIDE;
+    let header = results + 2;
+    let mut packed = vec![0; header];
+    packed[0] = results as u32;
+    packed[1] = header as u32;
+    crate::visit_batch(entry, requests, |index, counts| {
+        write(counts, &mut packed);
+        packed[2 + index] = packed.len() as u32;
+    })?;
+    Ok(packed)
+}
+
+/// `accumulateBatch` returning every request's counts in one packed buffer.
+#[wasm_bindgen(js_name = accumulateBatchPacked)]
+pub fn accumulate_batch_packed(requests: &[u32]) -> Result<Vec<u32>, KernelError> {
+    pack("accumulateBatchPacked", requests, |counts, packed| {
+        packed.extend_from_slice(counts)
+    })
+}
+
+/// `accumulateBatchSparse` in one packed buffer: each result holds
+/// `[bin, count]` pairs for the bins that received rows, ascending.
+#[wasm_bindgen(js_name = accumulateBatchSparsePacked)]
+pub fn accumulate_batch_sparse_packed(requests: &[u32]) -> Result<Vec<u32>, KernelError> {
+    pack("accumulateBatchSparsePacked", requests, |counts, packed| {
+        for (bin, &count) in counts.iter().enumerate() {
+            if count > 0 {
+                packed.extend_from_slice(&[bin as u32, count]);
+            }
+        }
+    })
+}
@@ -0,0 +1,46 @@
+//! SIMD/scalar parity.
+//!
+//! The module is built twice, with and without `simd128`, and browsers
+//! without SIMD load the scalar build. Both builds must show the same
+//! numbers. The vector paths are exact today: histogram counting
+//! extracts lanes into the same integer increments, and `f16` widening
+//! multiplies by a power of two, like its scalar twin. Float aggregations
+//! (sums, means, moments, rolling windows) have no vector paths at all and
+//! add in row order on either build, so their rounding is the same too.
+//!
+//! `setSimdParity(true)` makes that guarantee independent of the vector
+//! code: every kernel with a vector path asks [`simd_allowed`] first and
+//! takes its scalar path while parity is on, so a SIMD build computes
+//! exactly what the scalar build does. New vector kernels, in particular
+//! any that sum floats lane by lane and so reorder the additions, must do
+//! the same.
+
+use std::cell::Cell;
+use wasm_bindgen::prelude::*;
+
+thread_local! {
+    static PARITY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Turns parity off; see `hardReset`.
+pub(crate) fn reset() {
+    PARITY.with(|cell| cell.set(false));
+}
+
+/// Turns SIMD/scalar parity on or off for every later call.
+#[wasm_bindgen(js_name = setSimdParity)]
+pub fn set_simd_parity(enabled: bool) {
+    PARITY.with(|cell| cell.set(enabled));
+}
+
+#[wasm_bindgen(js_name = simdParity)]
+pub fn simd_parity() -> bool {
+    PARITY.with(Cell::get)
+}
+
+/// Whether vector paths may run: `false` while parity is on.
+#[cfg(target_feature = "simd128")]
+#[inline]
+pub(crate) fn simd_allowed() -> bool {
+    !simd_parity()
+}
@@ -0,0 +1,711 @@
+//! Parquet column-chunk decoding.
+//!
+//! Decodes the pages of one flat (non-repeated) column chunk, as sliced out
+//! of a file by the caller using the footer's column metadata, and registers
+//! the result in the column store. Supports data pages v1 and v2 with PLAIN
+//! and dictionary (PLAIN_DICTIONARY / RLE_DICTIONARY) value encodings and
+//! RLE/bit-packed hybrid definition levels. Pages are inflated according to
+//! the chunk's codec: `UNCOMPRESSED`, `LZ4_RAW`, and `ZSTD` with the `zstd`
+//! feature; other codecs fail with `Unsupported`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, bit, gather, Bitmap, Column, Values};
+use crate::encodings::decode_hybrid;
+use crate::error::{ErrorKind, KernelError};
+use crate::memory;
+use crate::thrift::{self, Reader};
+
+// `PageType`.
+const DATA_PAGE: i32 = 0;
+const DICTIONARY_PAGE: i32 = 2;
+const DATA_PAGE_V2: i32 = 3;
+
+// `Encoding`.
+const PLAIN: i32 = 0;
+const PLAIN_DICTIONARY: i32 = 2;
+const RLE: i32 = 3;
+const RLE_DICTIONARY: i32 = 8;
+
+fn malformed(what: &str) -> KernelError {
+    KernelError::new(
+        ErrorKind::MalformedInput,
+        format!("malformed parquet chunk: {what}"),
+    )
+}
+
+fn unsupported(what: String) -> KernelError {
+    KernelError::new(ErrorKind::Unsupported, what)
+}
+
+/// `CompressionCodec` of the column chunk.
+#[derive(Clone, Copy, PartialEq)]
+enum Codec {
+    Uncompressed,
+    Lz4Raw,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Codec {
+    fn parse(name: &str) -> Result<Self, KernelError> {
+        Ok(match name {
+            "UNCOMPRESSED" => Codec::Uncompressed,
+            "LZ4_RAW" => Codec::Lz4Raw,
+            #[cfg(feature = "zstd")]
+            "ZSTD" => Codec::Zstd,
+            #[cfg(not(feature = "zstd"))]
+            "ZSTD" => {
+                return Err(unsupported(
+                    "zstd-compressed parquet pages need the `zstd` feature".to_owned(),
+                ))
+            }
+            _ => return Err(unsupported(format!("unsupported parquet codec {name:?}"))),
+        })
+    }
+
+    /// Appends `compressed` inflated to `out`, failing unless it comes to
+    /// exactly `len` bytes.
+    fn inflate(self, compressed: &[u8], len: usize, out: &mut Vec<u8>) -> Result<(), KernelError> {
+        let base = out.len();
+        match self {
+            Codec::Uncompressed => out.extend_from_slice(compressed),
+            Codec::Lz4Raw => crate::lz4::decompress_block(compressed, len, out)?,
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => crate::compression::zstd_decompress(compressed, out)?,
+        }
+        if out.len() - base != len {
+            return Err(malformed("page does not match its uncompressed size"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Physical {
+    Boolean,
+    Int32,
+    Int64,
+    Float,
+    Double,
+    ByteArray,
+}
+
+impl Physical {
+    fn parse(name: &str) -> Result<Self, KernelError> {
+        Ok(match name {
+            "BOOLEAN" => Physical::Boolean,
+            "INT32" => Physical::Int32,
+            "INT64" => Physical::Int64,
+            "FLOAT" => Physical::Float,
+            "DOUBLE" => Physical::Double,
+            "BYTE_ARRAY" => Physical::ByteArray,
+            _ => return Err(unsupported(format!("unsupported parquet type {name:?}"))),
+        })
+    }
+
+    /// Bytes one decoded value takes in column storage, rounded up.
+    fn width(self) -> usize {
+        match self {
+            Physical::Boolean => 1,
+            Physical::Int32 | Physical::Float | Physical::ByteArray => 4,
+            Physical::Int64 | Physical::Double => 8,
+        }
+    }
+}
+
+#[derive(Default)]
+struct PageHeader {
+    kind: i32,
+    uncompressed_size: usize,
+    compressed_size: usize,
+    num_values: usize,
+    encoding: i32,
+    definition_encoding: i32,
+    /// Data page v2 only: level byte lengths and the compression flag.
+    v2: Option<(usize, usize, bool)>,
+}
+
+fn read_size(reader: &mut Reader<'_>, field: thrift::FieldHeader) -> Result<usize, KernelError> {
+    usize::try_from(reader.i32(field)?).map_err(|_| malformed("negative size"))
+}
+
+fn read_page_header(reader: &mut Reader<'_>) -> Result<PageHeader, KernelError> {
+    let mut header = PageHeader {
+        definition_encoding: RLE,
+        ..PageHeader::default()
+    };
+    reader.begin_struct();
+    while let Some(field) = reader.field()? {
+        match field.id {
+            1 => header.kind = reader.i32(field)?,
+            2 => header.uncompressed_size = read_size(reader, field)?,
+            3 => header.compressed_size = read_size(reader, field)?,
+            5 | 7 => {
+                reader.expect_struct(field)?;
+                while let Some(inner) = reader.field()? {
+                    match inner.id {
+                        1 => header.num_values = read_size(reader, inner)?,
+                        2 => header.encoding = reader.i32(inner)?,
+                        3 if field.id == 5 => header.definition_encoding = reader.i32(inner)?,
+                        _ => reader.skip(inner)?,
+                    }
+                }
+            }
+            8 => {
+                reader.expect_struct(field)?;
+                let (mut definition_len, mut repetition_len, mut compressed) = (0, 0, true);
+                while let Some(inner) = reader.field()? {
+                    match inner.id {
+                        1 => header.num_values = read_size(reader, inner)?,
+                        4 => header.encoding = reader.i32(inner)?,
+                        5 => definition_len = read_size(reader, inner)?,
+                        6 => repetition_len = read_size(reader, inner)?,
+                        7 => compressed = reader.bool(inner)?,
+                        _ => reader.skip(inner)?,
+                    }
+                }
+                header.v2 = Some((definition_len, repetition_len, compressed));
+            }
+            _ => reader.skip(field)?,
+        }
+    }
+    Ok(header)
+}
+
+/// Bits needed to encode levels up to `max`.
+fn level_width(max: u32) -> u32 {
+    u32::BITS - max.leading_zeros()
+}
+
+/// One page's (or the dictionary's) non-null values.
+enum PageValues {
+    Bool(Vec<bool>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    Bytes { offsets: Vec<u32>, data: Vec<u8> },
+}
+
+impl PageValues {
+    fn len(&self) -> usize {
+        match self {
+            PageValues::Bool(values) => values.len(),
+            PageValues::Int32(values) => values.len(),
+            PageValues::Int64(values) => values.len(),
+            PageValues::Float(values) => values.len(),
+            PageValues::Double(values) => values.len(),
+            PageValues::Bytes { offsets, .. } => offsets.len() - 1,
+        }
+    }
+
+    fn decode_plain(physical: Physical, bytes: &[u8], count: usize) -> Result<Self, KernelError> {
+        fn fixed<T, const N: usize>(
+            bytes: &[u8],
+            count: usize,
+            decode: fn([u8; N]) -> T,
+        ) -> Result<Vec<T>, KernelError> {
+            let bytes = count
+                .checked_mul(N)
+                .and_then(|len| bytes.get(..len))
+                .ok_or_else(|| malformed("plain values truncated"))?;
+            Ok(bytes
+                .chunks_exact(N)
+                .map(|chunk| decode(chunk.try_into().expect("chunk of N bytes")))
+                .collect())
+        }
+        Ok(match physical {
+            Physical::Boolean => {
+                if bytes.len() < count.div_ceil(8) {
+                    return Err(malformed("plain booleans truncated"));
+                }
+                PageValues::Bool((0..count).map(|index| bit(bytes, index)).collect())
+            }
+            Physical::Int32 => PageValues::Int32(fixed(bytes, count, i32::from_le_bytes)?),
+            Physical::Int64 => PageValues::Int64(fixed(bytes, count, i64::from_le_bytes)?),
+            Physical::Float => PageValues::Float(fixed(bytes, count, f32::from_le_bytes)?),
+            Physical::Double => PageValues::Double(fixed(bytes, count, f64::from_le_bytes)?),
+            Physical::ByteArray => {
+                // Each value takes at least its 4-byte length.
+                let mut offsets = Vec::with_capacity(count.min(bytes.len() / 4) + 1);
+                let mut data = Vec::new();
+                offsets.push(0);
+                let mut pos = 0;
+                for _ in 0..count {
+                    let len = bytes
+                        .get(pos..pos + 4)
+                        .map(|raw| u32::from_le_bytes(raw.try_into().expect("4 bytes")) as usize)
+                        .ok_or_else(|| malformed("byte array length truncated"))?;
+                    let value = bytes
+                        .get(pos + 4..)
+                        .and_then(|rest| rest.get(..len))
+                        .ok_or_else(|| malformed("byte array truncated"))?;
+                    data.extend_from_slice(value);
+                    offsets.push(data.len() as u32);
+                    pos += 4 + len;
+                }
+                PageValues::Bytes { offsets, data }
+            }
+        })
+    }
+
+    /// Looks `indices` up in this dictionary.
+    fn gather(&self, indices: &[u32]) -> Result<Self, KernelError> {
+        let len = self.len();
+        if indices.iter().any(|&index| index as usize >= len) {
+            return Err(malformed("dictionary index out of range"));
+        }
+        Ok(match self {
+            PageValues::Bool(values) => PageValues::Bool(gather(values, indices)),
+            PageValues::Int32(values) => PageValues::Int32(gather(values, indices)),
+            PageValues::Int64(values) => PageValues::Int64(gather(values, indices)),
+            PageValues::Float(values) => PageValues::Float(gather(values, indices)),
+            PageValues::Double(values) => PageValues::Double(gather(values, indices)),
+            PageValues::Bytes { offsets, data } => {
+                let mut out_offsets = Vec::with_capacity(indices.len() + 1);
+                let mut out_data = Vec::new();
+                out_offsets.push(0);
+                for &index in indices {
+                    let index = index as usize;
+                    out_data.extend_from_slice(
+                        &data[offsets[index] as usize..offsets[index + 1] as usize],
+                    );
+                    out_offsets.push(out_data.len() as u32);
+                }
+                PageValues::Bytes {
+                    offsets: out_offsets,
+                    data: out_data,
+                }
+            }
+        })
+    }
+}
+
+/// Accumulates the chunk's pages into column storage.
+struct ChunkBuilder {
+    len: usize,
+    values: Values,
+    bools: Bitmap,
+    validity: Bitmap,
+    has_nulls: bool,
+}
+
+impl ChunkBuilder {
+    fn new(physical: Physical) -> Self {
+        let values = match physical {
+            Physical::Boolean => Values::Bool(Vec::new()),
+            Physical::Int32 => Values::Int32(Vec::new()),
+            Physical::Int64 => Values::Int64(Vec::new()),
+            Physical::Float => Values::Float32(Vec::new()),
+            Physical::Double => Values::Float64(Vec::new()),
+            Physical::ByteArray => Values::Utf8 {
+                offsets: vec![0],
+                data: Vec::new(),
+            },
+        };
+        ChunkBuilder {
+            len: 0,
+            values,
+            bools: Bitmap::default(),
+            validity: Bitmap::default(),
+            has_nulls: false,
+        }
+    }
+
+    /// Appends one page: `defined[row]` says whether the row has a value,
+    /// taken in order from `page`. Null rows get a zero placeholder.
+    fn append(&mut self, page: &PageValues, defined: &[bool]) -> Result<(), KernelError> {
+        let present = defined.iter().filter(|&&defined| defined).count();
+        if present != page.len() {
+            return Err(malformed("value count does not match definition levels"));
+        }
+        match (&mut self.values, page) {
+            (Values::Bool(_), PageValues::Bool(src)) => {
+                let mut src = src.iter();
+                for &defined in defined {
+                    self.bools.push(defined && *src.next().expect("counted"));
+                }
+            }
+            (Values::Int32(out), PageValues::Int32(src)) => scatter(out, src, defined),
+            (Values::Int64(out), PageValues::Int64(src)) => scatter(out, src, defined),
+            (Values::Float32(out), PageValues::Float(src)) => scatter(out, src, defined),
+            (Values::Float64(out), PageValues::Double(src)) => scatter(out, src, defined),
+            (
+                Values::Utf8 { offsets, data },
+                PageValues::Bytes {
+                    offsets: src_offsets,
+                    data: src_data,
+                },
+            ) => {
+                let base = data.len() as u32;
+                data.extend_from_slice(src_data);
+                let mut next = src_offsets[1..].iter();
+                let mut end = base;
+                for &defined in defined {
+                    if defined {
+                        end = base + next.next().expect("counted");
+                    }
+                    offsets.push(end);
+                }
+            }
+            _ => return Err(malformed("page type does not match column")),
+        }
+        for &defined in defined {
+            self.validity.push(defined);
+        }
+        self.has_nulls |= present < defined.len();
+        self.len += defined.len();
+        Ok(())
+    }
+
+    fn finish(self, name: String) -> Column {
+        let values = match self.values {
+            Values::Bool(_) => Values::Bool(self.bools.bytes),
+            values => values,
+        };
+        Column {
+            name,
+            len: self.len,
+            values,
+            validity: self.has_nulls.then_some(self.validity.bytes),
+        }
+    }
+}
+
+fn scatter<T: Copy + Default>(out: &mut Vec<T>, src: &[T], defined: &[bool]) {
+    let mut src = src.iter();
+    out.extend(defined.iter().map(|&defined| {
+        if defined {
+            *src.next().expect("counted")
+        } else {
+            T::default()
+        }
+    }));
+}
+
+/// Decodes a non-repeated Parquet column chunk and registers it as a column
+/// named `name`. `physicalType` is the Parquet physical type name
+/// (`"INT32"`, `"DOUBLE"`, `"BYTE_ARRAY"`, ...) and `codec` the chunk's
+/// compression codec name from the column metadata (`"UNCOMPRESSED"`,
+/// `"LZ4_RAW"`, `"ZSTD"`). `maxDefinitionLevel` comes from the schema path
+/// (0 for required columns, 1 for top-level optional ones). Returns the
+/// column handle.
+#[wasm_bindgen(js_name = ingestParquetColumn)]
+pub fn ingest_parquet_column(
+    name: String,
+    physical_type: &str,
+    codec: &str,
+    max_definition_level: u32,
+    chunk: &[u8],
+) -> Result<u32, KernelError> {
+    let physical = Physical::parse(physical_type)?;
+    let codec = Codec::parse(codec)?;
+    // Decoded values take about as much memory as the uncompressed chunk.
+    memory::check_budget(chunk.len())?;
+    let level_bits = level_width(max_definition_level);
+    let mut builder = ChunkBuilder::new(physical);
+    let mut dictionary: Option<PageValues> = None;
+    let mut levels = Vec::new();
+    let mut defined = Vec::new();
+    let mut indices = Vec::new();
+    let mut inflated = Vec::new();
+    let mut pos = 0;
+
+    while pos < chunk.len() {
+        let mut reader = Reader::new(&chunk[pos..]);
+        let header = read_page_header(&mut reader)?;
+        let start = pos + reader.position();
+        let end = start
+            .checked_add(header.compressed_size)
+            .ok_or_else(|| malformed("truncated page"))?;
+        let stored = chunk
+            .get(start..end)
+            .ok_or_else(|| malformed("truncated page"))?;
+        pos = end;
+
+        let compressed = header.v2.is_none_or(|(_, _, compressed)| compressed);
+        let page = if codec == Codec::Uncompressed || !compressed {
+            stored
+        } else {
+            // Data page v2 keeps its levels uncompressed ahead of the values.
+            let level_len = header
+                .v2
+                .and_then(|(definition_len, repetition_len, _)| {
+                    definition_len.checked_add(repetition_len)
+                })
+                .unwrap_or(0);
+            let body_len = header.uncompressed_size.checked_sub(level_len);
+            let (Some(body_len), Some(prefix), Some(body)) =
+                (body_len, stored.get(..level_len), stored.get(level_len..))
+            else {
+                return Err(malformed("levels exceed the page size"));
+            };
+            memory::check_budget(header.uncompressed_size)?;
+            inflated.clear();
+            inflated.extend_from_slice(prefix);
+            codec.inflate(body, body_len, &mut inflated)?;
+            &inflated[..]
+        };
+
+        match header.kind {
+            DICTIONARY_PAGE => {
+                if !matches!(header.encoding, PLAIN | PLAIN_DICTIONARY) {
+                    return Err(unsupported(format!(
+                        "unsupported dictionary encoding {}",
+                        header.encoding
+                    )));
+                }
+                dictionary = Some(PageValues::decode_plain(physical, page, header.num_values)?);
+                continue;
+            }
+            DATA_PAGE | DATA_PAGE_V2 => {}
+            _ => continue,
+        }
+
+        // Definition levels, then values. Check the budget for the levels,
+        // flags and values the header's row count asks for before any of
+        // them is allocated.
+        let rows = header.num_values;
+        memory::check_budget(rows.saturating_mul(physical.width() + 5))?;
+        levels.clear();
+        let values = match header.v2 {
+            Some((definition_len, repetition_len, _)) => {
+                if repetition_len > 0 {
+                    return Err(unsupported(
+                        "repeated parquet columns are not supported".to_owned(),
+                    ));
+                }
+                let encoded = page
+                    .get(..definition_len)
+                    .ok_or_else(|| malformed("definition levels truncated"))?;
+                if max_definition_level > 0 {
+                    decode_hybrid(encoded, level_bits, rows, &mut levels)?;
+                }
+                &page[definition_len..]
+            }
+            None if max_definition_level > 0 => {
+                if header.definition_encoding != RLE {
+                    return Err(unsupported(format!(
+                        "unsupported definition level encoding {}",
+                        header.definition_encoding
+                    )));
+                }
+                let len = page
+                    .get(..4)
+                    .map(|raw| u32::from_le_bytes(raw.try_into().expect("4 bytes")) as usize)
+                    .ok_or_else(|| malformed("definition levels truncated"))?;
+                let encoded = page
+                    .get(4..)
+                    .and_then(|rest| rest.get(..len))
+                    .ok_or_else(|| malformed("definition levels truncated"))?;
+                decode_hybrid(encoded, level_bits, rows, &mut levels)?;
+                &page[4 + len..]
+            }
+            None => page,
+        };
+        defined.clear();
+        if max_definition_level > 0 {
+            defined.extend(levels.iter().map(|&level| level == max_definition_level));
+        } else {
+            defined.resize(rows, true);
+        }
+        let present = defined.iter().filter(|&&defined| defined).count();
+
+        let decoded = match header.encoding {
+            PLAIN => PageValues::decode_plain(physical, values, present)?,
+            PLAIN_DICTIONARY | RLE_DICTIONARY => {
+                let dictionary = dictionary
+                    .as_ref()
+                    .ok_or_else(|| malformed("dictionary page missing"))?;
+                let (&width, encoded) = values
+                    .split_first()
+                    .ok_or_else(|| malformed("dictionary indices truncated"))?;
+                indices.clear();
+                decode_hybrid(encoded, u32::from(width), present, &mut indices)?;
+                dictionary.gather(&indices)?
+            }
+            other => return Err(unsupported(format!("unsupported parquet encoding {other}"))),
+        };
+        builder.append(&decoded, &defined)?;
+    }
+    Ok(columns::register(builder.finish(name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int_field(out: &mut Vec<u8>, value: i32) {
+        out.push(0x15);
+        let mut raw = ((value << 1) ^ (value >> 31)) as u32;
+        while raw >= 0x80 {
+            out.push(raw as u8 | 0x80);
+            raw >>= 7;
+        }
+        out.push(raw as u8);
+    }
+
+    /// One page: its header, with `fields` as the consecutive `i32` fields
+    /// of the nested page-type header `nested` (plus the compression flag
+    /// for data pages v2), then `body`.
+    fn page(
+        kind: i32,
+        uncompressed_size: usize,
+        nested: u8,
+        fields: &[i32],
+        compressed: Option<bool>,
+        body: &[u8],
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        int_field(&mut out, kind);
+        int_field(&mut out, uncompressed_size as i32);
+        int_field(&mut out, body.len() as i32);
+        out.push(((nested - 3) << 4) | 0x0c);
+        for &field in fields {
+            int_field(&mut out, field);
+        }
+        if let Some(compressed) = compressed {
+            out.push(if compressed { 0x11 } else { 0x12 });
+        }
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn data_page(rows: i32, encoding: i32, body: &[u8]) -> Vec<u8> {
+        page(
+            DATA_PAGE,
+            body.len(),
+            5,
+            &[rows, encoding, RLE, RLE],
+            None,
+            body,
+        )
+    }
+
+    fn le_bytes<const N: usize>(values: impl IntoIterator<Item = [u8; N]>) -> Vec<u8> {
+        values.into_iter().flatten().collect()
+    }
+
+    fn ingest(
+        physical: &str,
+        codec: &str,
+        max_definition_level: u32,
+        chunk: &[u8],
+    ) -> Result<Column, KernelError> {
+        let handle = ingest_parquet_column(
+            "column".to_owned(),
+            physical,
+            codec,
+            max_definition_level,
+            chunk,
+        )?;
+        columns::with_column(handle, |column| {
+            Ok(Column {
+                name: column.name.clone(),
+                len: column.len,
+                values: column.values.clone(),
+                validity: column.validity.clone(),
+            })
+        })
+    }
+
+    #[test]
+    fn plain_pages_decode_required_and_optional_columns() {
+        let chunk = data_page(3, PLAIN, &le_bytes([1i32, -2, 3].map(i32::to_le_bytes)));
+        let column = ingest("INT32", "UNCOMPRESSED", 0, &chunk).unwrap();
+        let Values::Int32(values) = &column.values else {
+            panic!("expected int32 values");
+        };
+        assert_eq!(values, &[1, -2, 3]);
+        assert!(column.validity.is_none());
+
+        // Levels 1, 0, 1, 1 bit-packed behind their length prefix.
+        let mut body = vec![2, 0, 0, 0, 0x03, 0x0d];
+        body.extend(le_bytes([1.5f64, 2.5, 3.5].map(f64::to_le_bytes)));
+        let column = ingest("DOUBLE", "UNCOMPRESSED", 1, &data_page(4, PLAIN, &body)).unwrap();
+        let Values::Float64(values) = &column.values else {
+            panic!("expected float64 values");
+        };
+        assert_eq!(values, &[1.5, 0.0, 2.5, 3.5]);
+        assert_eq!(column.validity, Some(vec![0b1101]));
+    }
+
+    #[test]
+    fn dictionary_pages_resolve_bit_packed_and_rle_indices() {
+        let dictionary = [1, 0, 0, 0, b'x', 2, 0, 0, 0, b'y', b'z'];
+        let mut chunk = page(DICTIONARY_PAGE, 11, 7, &[2, PLAIN], None, &dictionary);
+        // Width 1: indices 1, 0, 1 bit-packed, then a run of four 1s.
+        chunk.extend(data_page(3, RLE_DICTIONARY, &[0x01, 0x03, 0x05]));
+        chunk.extend(data_page(4, PLAIN_DICTIONARY, &[0x01, 0x08, 0x01]));
+        let column = ingest("BYTE_ARRAY", "UNCOMPRESSED", 0, &chunk).unwrap();
+        let Values::Utf8 { offsets, data } = &column.values else {
+            panic!("expected utf8 values");
+        };
+        assert_eq!(offsets, &[0, 2, 3, 5, 7, 9, 11, 13]);
+        assert_eq!(data, b"yzxyzyzyzyzyz");
+        assert_eq!(column.len, 7);
+    }
+
+    #[test]
+    fn rle_definition_levels_in_v2_pages() {
+        // Runs of two 1s and one 0, stored ahead of the values.
+        let mut body = vec![0x04, 0x01, 0x02, 0x00];
+        body.extend(le_bytes([10i64, 20].map(i64::to_le_bytes)));
+        let fields = [3, 1, 3, PLAIN, 4, 0];
+        let chunk = page(DATA_PAGE_V2, body.len(), 8, &fields, Some(false), &body);
+        let column = ingest("INT64", "UNCOMPRESSED", 1, &chunk).unwrap();
+        let Values::Int64(values) = &column.values else {
+            panic!("expected int64 values");
+        };
+        assert_eq!(values, &[10, 20, 0]);
+        assert_eq!(column.validity, Some(vec![0b011]));
+    }
+
+    #[test]
+    fn lz4_raw_pages_inflate_by_codec() {
+        // A literal-only LZ4 block holding 7, 8, 9.
+        let mut block = vec![0xc0];
+        block.extend(le_bytes([7i32, 8, 9].map(i32::to_le_bytes)));
+        let chunk = page(DATA_PAGE, 12, 5, &[3, PLAIN, RLE, RLE], None, &block);
+        let column = ingest("INT32", "LZ4_RAW", 0, &chunk).unwrap();
+        let Values::Int32(values) = &column.values else {
+            panic!("expected int32 values");
+        };
+        assert_eq!(values, &[7, 8, 9]);
+
+        // In a v2 page the levels (a run of three 1s) stay uncompressed.
+        let mut body = vec![0x06, 0x01];
+        body.extend_from_slice(&block);
+        let fields = [3, 0, 3, PLAIN, 2, 0];
+        let chunk = page(DATA_PAGE_V2, 14, 8, &fields, Some(true), &body);
+        let column = ingest("INT32", "LZ4_RAW", 1, &chunk).unwrap();
+        let Values::Int32(values) = &column.values else {
+            panic!("expected int32 values");
+        };
+        assert_eq!(values, &[7, 8, 9]);
+        assert!(column.validity.is_none());
+    }
+
+    #[test]
+    fn mismatched_sizes_and_codecs_are_rejected() {
+        let block = [0x30, 1, 2, 3];
+        // The page claims more bytes than the block inflates to.
+        let chunk = page(DATA_PAGE, 8, 5, &[2, PLAIN, RLE, RLE], None, &block);
+        let error = ingest("INT32", "LZ4_RAW", 0, &chunk).err().unwrap();
+        assert_eq!(error.code(), ErrorKind::MalformedInput as u32);
+        let error = ingest("INT32", "SNAPPY", 0, &chunk).err().unwrap();
+        assert_eq!(error.code(), ErrorKind::Unsupported as u32);
+        // Truncated pages and values.
+        let chunk = data_page(3, PLAIN, &le_bytes([1i32, 2, 3].map(i32::to_le_bytes)));
+        for len in [1, chunk.len() - 1] {
+            let error = ingest("INT32", "UNCOMPRESSED", 0, &chunk[..len])
+                .err()
+                .unwrap();
+            assert_eq!(error.code(), ErrorKind::MalformedInput as u32);
+        }
+        let chunk = data_page(4, PLAIN, &le_bytes([1i32, 2, 3].map(i32::to_le_bytes)));
+        assert!(ingest("INT32", "UNCOMPRESSED", 0, &chunk).is_err());
+    }
+}
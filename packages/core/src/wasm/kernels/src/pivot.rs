@@ -0,0 +1,133 @@
+//! Pivot tables.
+//!
+//! A pivot widget crosses a row dimension with a column dimension and
+//! shows one aggregate per cell. Building that grid in JS meant a pass
+//! over every row per redraw; here one pass over the rows passing the
+//! filters fills a dense grid over the bins that occur on each axis, so
+//! the grid stays small even when the dimensions have many bins.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns;
+use crate::error::KernelError;
+use crate::filters::{self, Reduce};
+use crate::summation::Sums;
+
+/// A pivot grid with its axis keys.
+#[wasm_bindgen]
+pub struct PivotTable {
+    row_keys: Vec<u16>,
+    column_keys: Vec<u16>,
+    values: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl PivotTable {
+    /// Row dimension bins with at least one row, ascending.
+    #[wasm_bindgen(getter = rowKeys)]
+    pub fn row_keys(&self) -> Vec<u16> {
+        self.row_keys.clone()
+    }
+
+    /// Column dimension bins with at least one row, ascending.
+    #[wasm_bindgen(getter = columnKeys)]
+    pub fn column_keys(&self) -> Vec<u16> {
+        self.column_keys.clone()
+    }
+
+    /// Cell aggregates, row after row: the cell of `rowKeys[r]` and
+    /// `columnKeys[c]` is at `r * columnKeys.length + c`. Empty cells are 0
+    /// for counts and sums, NaN for means.
+    #[wasm_bindgen(getter)]
+    pub fn values(&self) -> Vec<f64> {
+        self.values.clone()
+    }
+}
+
+/// Slot of each occurring bin on an axis, and the occurring bins.
+fn axis(bins: &[u16], passes: &impl Fn(usize) -> bool) -> (Vec<u32>, Vec<u16>) {
+    let mut slots = vec![u32::MAX; usize::from(u16::MAX) + 1];
+    for (row, &bin) in bins.iter().enumerate() {
+        if passes(row) {
+            slots[usize::from(bin)] = 0;
+        }
+    }
+    let mut keys = Vec::new();
+    for (bin, slot) in slots.iter_mut().enumerate() {
+        if *slot == 0 {
+            *slot = keys.len() as u32;
+            keys.push(bin as u16);
+        }
+    }
+    (slots, keys)
+}
+
+/// Crosses the bins of `rowBins` with those of `columnBins` (one bin per
+/// row of the filter state each) over the rows passing every filter but
+/// those of the dimensions in `ignore` (typically the pivot's own two, as a
+/// crossfilter group ignores its dimension's filter). Cells hold the row
+/// count, or the sum or mean of the numeric column behind `measure` (nulls
+/// and NaNs add zero to sums but still count).
+#[wasm_bindgen(js_name = pivotTable)]
+pub fn pivot_table(
+    row_bins: &[u16],
+    column_bins: &[u16],
+    reduce: Reduce,
+    measure: Option<u32>,
+    ignore: &[u32],
+) -> Result<PivotTable, KernelError> {
+    if reduce != Reduce::Count && measure.is_none() {
+        return Err(KernelError::invalid_argument(
+            "sum and mean reductions need a measure column",
+        ));
+    }
+    filters::with_filters(|filters| {
+        let rows = filters.rows();
+        for bins in [row_bins, column_bins] {
+            if bins.len() != rows {
+                return Err(KernelError::invalid_argument(
+                    "bins and filter state row counts differ",
+                )
+                .with("bins", bins.len() as f64)
+                .with("filters", rows as f64));
+            }
+        }
+        let measure = match reduce {
+            Reduce::Count => None,
+            _ => measure
+                .map(|handle| columns::measure(handle, rows))
+                .transpose()?,
+        };
+        let passes = filters.passes_except_all(ignore);
+        let (row_slots, row_keys) = axis(row_bins, &passes);
+        let (column_slots, column_keys) = axis(column_bins, &passes);
+        let width = column_keys.len();
+        let mut counts = vec![0u32; row_keys.len() * width];
+        let mut sums = Sums::new(counts.len());
+        for row in (0..rows).filter(|&row| passes(row)) {
+            let cell = row_slots[usize::from(row_bins[row])] as usize * width
+                + column_slots[usize::from(column_bins[row])] as usize;
+            counts[cell] += 1;
+            if let Some(measure) = &measure {
+                sums.add(cell, measure[row]);
+            }
+        }
+        let values = match reduce {
+            Reduce::Count => counts.iter().map(|&count| f64::from(count)).collect(),
+            Reduce::Sum => sums.to_vec(),
+            Reduce::Mean => counts
+                .iter()
+                .enumerate()
+                .map(|(cell, &count)| match count {
+                    0 => f64::NAN,
+                    count => sums.get(cell) / f64::from(count),
+                })
+                .collect(),
+        };
+        Ok(PivotTable {
+            row_keys,
+            column_keys,
+            values,
+        })
+    })
+}
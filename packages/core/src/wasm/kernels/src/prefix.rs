@@ -0,0 +1,58 @@
+//! Prefix sums over count arrays.
+//!
+//! Cumulative counts turn "how many rows fall in bins `[a, b)`" into one
+//! subtraction instead of a loop over the bins, which keeps brush feedback
+//! cheap while the pointer moves. Totals are exact: a sum that leaves the
+//! `u32` range fails the call instead of wrapping.
+//!
+//! The same running totals divided by the grand total give the empirical
+//! CDF of a histogram, the curve behind latency percentile charts.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{ErrorKind, KernelError};
+
+/// Running totals of `counts`. Entry `i` is the sum of `counts[..i]`
+/// (exclusive) or `counts[..=i]` (inclusive).
+pub(crate) fn scan(
+    counts: impl IntoIterator<Item = u32>,
+    inclusive: bool,
+) -> Result<Vec<u32>, KernelError> {
+    let counts = counts.into_iter();
+    let mut sums = Vec::with_capacity(counts.size_hint().0);
+    let mut total = 0u32;
+    for (index, count) in counts.enumerate() {
+        let next = total.checked_add(count).ok_or_else(|| {
+            KernelError::new(ErrorKind::Overflow, "prefix sum exceeds u32")
+                .with("index", index as f64)
+        })?;
+        sums.push(if inclusive { next } else { total });
+        total = next;
+    }
+    Ok(sums)
+}
+
+/// Returns the prefix sums of `counts` (e.g. a histogram from
+/// `accumulateBins`). With `inclusive` false, entry `i` sums the counts
+/// before bin `i`, so the rows in bins `[a, b)` are `sums[b] - sums[a]`
+/// (the grand total standing in for `sums[counts.length]`).
+#[wasm_bindgen(js_name = prefixSum)]
+pub fn prefix_sum(counts: &[u32], inclusive: bool) -> Result<Vec<u32>, KernelError> {
+    scan(counts.iter().copied(), inclusive)
+}
+
+/// The empirical CDF of the histogram `counts`: entry `i` is the share of
+/// rows in bins `..=i`, reaching 1 at the last non-empty bin. All NaN when
+/// every bin is empty.
+#[wasm_bindgen(js_name = empiricalCdf)]
+pub fn empirical_cdf(counts: &[u32]) -> Vec<f64> {
+    let total: u64 = counts.iter().map(|&count| u64::from(count)).sum();
+    let mut running = 0u64;
+    counts
+        .iter()
+        .map(|&count| {
+            running += u64::from(count);
+            running as f64 / total as f64
+        })
+        .collect()
+}
@@ -0,0 +1,164 @@
+//! FlatBuffers request/response protocol.
+//!
+//! `execute(bytes)` decodes one `RequestMessage` (see
+//! `schema/protocol.fbs`), runs it through the same internals as the
+//! individual exports and returns an encoded `ResponseMessage`. Failures come
+//! back as an `Error` response rather than a thrown exception, so the bytes
+//! can be relayed unchanged over `postMessage` to a remote worker.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns;
+use crate::error::{ErrorKind, KernelError};
+use crate::flatbuf::{Table, TableBuilder, Value};
+use crate::Strategy;
+
+// `Request` union tags.
+const ACCUMULATE_SCRATCH: u8 = 1;
+const ACCUMULATE_BINS: u8 = 2;
+const SET_STRATEGY: u8 = 3;
+const QUANTIZE_COLUMN: u8 = 4;
+const CATEGORIZE_COLUMN: u8 = 5;
+const RELEASE_COLUMN: u8 = 6;
+
+// `Response` union tags.
+const COUNTS: u8 = 1;
+const ROWS: u8 = 2;
+const ACK: u8 = 3;
+const ERROR: u8 = 4;
+
+const UNKEYED_DIMENSION: u32 = u32::MAX;
+
+enum Reply {
+    Counts(Vec<u32>),
+    Rows(u32),
+    Ack,
+}
+
+fn dimension(request: &Table<'_>, id: usize) -> Result<Option<u32>, KernelError> {
+    let dimension = request.u32_or(id, UNKEYED_DIMENSION)?;
+    Ok((dimension != UNKEYED_DIMENSION).then_some(dimension))
+}
+
+fn strategy(code: u8) -> Result<Strategy, KernelError> {
+    Ok(match code {
+        0 => Strategy::Auto,
+        1 => Strategy::Scalar,
+        2 => Strategy::Sharded,
+        3 => Strategy::Sorted,
+        4 => Strategy::Unrolled,
+        _ => {
+            return Err(
+                KernelError::invalid_argument("unknown strategy").with("strategy", f64::from(code))
+            )
+        }
+    })
+}
+
+fn dispatch(kind: u8, request: Table<'_>) -> Result<Reply, KernelError> {
+    match kind {
+        ACCUMULATE_SCRATCH => crate::accumulate_scratch_with(
+            "execute",
+            request.u32_or(0, 0)? as usize,
+            request.u32_or(1, 0)?,
+            dimension(&request, 2)?,
+            <[u32]>::to_vec,
+        )
+        .map(Reply::Counts),
+        ACCUMULATE_BINS => {
+            let bins: Vec<u16> = request
+                .structs(0, 2)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            crate::accumulate_bins_with(
+                "execute",
+                &bins,
+                request.u32_or(1, 0)?,
+                dimension(&request, 2)?,
+                <[u32]>::to_vec,
+            )
+            .map(Reply::Counts)
+        }
+        SET_STRATEGY => {
+            crate::set_strategy(strategy(request.u8_or(0, 0)?)?);
+            Ok(Reply::Ack)
+        }
+        QUANTIZE_COLUMN => columns::quantize_column(
+            request.u32_or(0, 0)?,
+            request.f64_or(1, 0.0)?,
+            request.f64_or(2, 0.0)?,
+            request.u32_or(3, 0)?,
+        )
+        .map(Reply::Rows),
+        CATEGORIZE_COLUMN => columns::categorize_column(
+            request.u32_or(0, 0)?,
+            request.u32_or(1, 0)?,
+            request.u32_or(2, 0)?,
+        )
+        .map(Reply::Rows),
+        RELEASE_COLUMN => {
+            columns::release_column(request.u32_or(0, 0)?);
+            Ok(Reply::Ack)
+        }
+        other => Err(KernelError::new(
+            ErrorKind::Unsupported,
+            format!("unknown request type {other}"),
+        )),
+    }
+}
+
+fn encode_response(id: u32, reply: Result<Reply, KernelError>) -> Vec<u8> {
+    let (kind, body) = match reply {
+        Ok(Reply::Counts(counts)) => {
+            let bytes = counts
+                .iter()
+                .flat_map(|count| count.to_le_bytes())
+                .collect();
+            (
+                COUNTS,
+                TableBuilder::new().add(
+                    0,
+                    Value::Structs {
+                        count: counts.len(),
+                        bytes,
+                    },
+                ),
+            )
+        }
+        Ok(Reply::Rows(rows)) => (ROWS, TableBuilder::new().add(0, Value::U32(rows))),
+        Ok(Reply::Ack) => (ACK, TableBuilder::new()),
+        Err(err) => (
+            ERROR,
+            TableBuilder::new()
+                .add(0, Value::U16(err.code() as u16))
+                .add(1, Value::String(err.message())),
+        ),
+    };
+    TableBuilder::new()
+        .add(0, Value::U32(id))
+        .add(1, Value::U8(kind))
+        .add(2, Value::Table(body))
+        .finish()
+}
+
+/// Runs one encoded `RequestMessage` and returns the encoded
+/// `ResponseMessage`. Undecodable input yields an `Error` response with id 0.
+#[wasm_bindgen]
+pub fn execute(request: &[u8]) -> Vec<u8> {
+    let decoded = Table::root(request).and_then(|message| {
+        let id = message.u32_or(0, 0)?;
+        let kind = message.u8_or(1, 0)?;
+        Ok((id, kind, message.table(2)?))
+    });
+    match decoded {
+        Ok((id, kind, Some(body))) => encode_response(id, dispatch(kind, body)),
+        Ok((id, _, None)) => encode_response(
+            id,
+            Err(KernelError::invalid_argument(
+                "request message has no request",
+            )),
+        ),
+        Err(err) => encode_response(0, Err(err)),
+    }
+}
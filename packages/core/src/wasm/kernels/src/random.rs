@@ -0,0 +1,80 @@
+//! Seedable random numbers for the sampling kernels.
+//!
+//! Screenshots, tests and shared dashboard links must redraw the exact same
+//! sampled points, so every randomized kernel draws from xoshiro256**
+//! seeded from one 32-bit seed: the `seed` argument when the kernel takes
+//! one, otherwise the seed set with `setRandomSeed` (0 until set). A kernel
+//! call starts a fresh generator, so the same call on the same rows gives
+//! the same result regardless of what ran before it.
+
+use std::cell::Cell;
+use wasm_bindgen::prelude::*;
+
+thread_local! {
+    static SEED: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Restores the default seed; see `hardReset`.
+pub(crate) fn reset() {
+    SEED.with(|seed| seed.set(0));
+}
+
+/// xoshiro256**: fast, small state and a 2^256 - 1 period.
+pub(crate) struct Rng([u64; 4]);
+
+impl Rng {
+    /// Expands `seed` into the state with SplitMix64, as the xoshiro
+    /// authors recommend, so nearby seeds give unrelated streams.
+    pub(crate) fn seeded(seed: u64) -> Self {
+        let mut state = seed;
+        let mut split = || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+        Rng([split(), split(), split(), split()])
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        let s = &mut self.0;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// Uniform in `(0, 1]`.
+    pub(crate) fn unit(&mut self) -> f64 {
+        ((self.next() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `0..bound`.
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        ((u128::from(self.next()) * bound as u128) >> 64) as usize
+    }
+}
+
+/// A generator seeded with `seed`, or with the `setRandomSeed` seed when
+/// omitted.
+pub(crate) fn generator(seed: Option<u32>) -> Rng {
+    Rng::seeded(u64::from(seed.unwrap_or_else(random_seed)))
+}
+
+/// Sets the seed of every sampling kernel called without one of its own.
+#[wasm_bindgen(js_name = setRandomSeed)]
+pub fn set_random_seed(seed: u32) {
+    SEED.with(|cell| cell.set(seed));
+}
+
+/// The seed set with `setRandomSeed`.
+#[wasm_bindgen(js_name = randomSeed)]
+pub fn random_seed() -> u32 {
+    SEED.with(Cell::get)
+}
@@ -0,0 +1,139 @@
+//! Changing a dimension's bin count without re-ingesting it.
+//!
+//! Bins from `quantizeColumn` are the rounded positions of values on a
+//! `[min, max]` grid, so bin `i` of `from` bins stands for the value
+//! `min + i * (max - min) / (from - 1)`. Coarsening maps that value onto the
+//! new grid, which depends only on `i`, `from` and the new count, so both
+//! bin indices and finished histograms can be re-binned inside wasm without
+//! the raw values. Refining needs the values: re-run `quantizeColumn` on the
+//! stored column instead.
+//!
+//! Re-grouping at another granularity (500 country codes into 6 regions)
+//! goes through a caller-supplied lookup table instead: `mapping[bin]` is
+//! each source bin's new bin.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+
+/// Bin of `to` bins holding the value of bin `bin` of `from`, rounding half
+/// up like the quantizer.
+fn map_bin(bin: u32, from: u32, to: u32) -> u16 {
+    if from <= 1 || to <= 1 {
+        return 0;
+    }
+    let (bin, from, to) = (u64::from(bin), u64::from(from - 1), u64::from(to - 1));
+    ((2 * bin * to + from) / (2 * from)) as u16
+}
+
+fn check_counts(from_count: u32, bin_count: u32) -> Result<(), KernelError> {
+    for count in [from_count, bin_count] {
+        if count == 0 || count > u32::from(u16::MAX) + 1 {
+            return Err(KernelError::bad_bin_count(count));
+        }
+    }
+    Ok(())
+}
+
+/// Re-bins the first `len` scratch entries, bin indices over `fromCount`
+/// bins, in place to `binCount` bins over the same range, ready for
+/// `accumulateScratch`. Every entry must be below `fromCount`. Returns
+/// `len`.
+#[wasm_bindgen(js_name = rebinScratch)]
+pub fn rebin_scratch(len: u32, from_count: u32, bin_count: u32) -> Result<u32, KernelError> {
+    check_counts(from_count, bin_count)?;
+    let map: Vec<u16> = (0..from_count)
+        .map(|bin| map_bin(bin, from_count, bin_count))
+        .collect();
+    crate::with_scratch_mut(len as usize, |scratch| {
+        if let Some(&bin) = scratch.iter().find(|&&bin| u32::from(bin) >= from_count) {
+            return Err(KernelError::invalid_argument("bin out of range")
+                .with("bin", f64::from(bin))
+                .with("binCount", f64::from(from_count)));
+        }
+        for bin in scratch.iter_mut() {
+            *bin = map[*bin as usize];
+        }
+        Ok(len)
+    })?
+}
+
+/// Folds a histogram over `counts.length` bins into `binCount` bins over
+/// the same range, as if its rows had been quantized to `binCount` bins
+/// from the start.
+#[wasm_bindgen(js_name = rebinCounts)]
+pub fn rebin_counts(counts: &[u32], bin_count: u32) -> Result<Vec<u32>, KernelError> {
+    let from_count = counts.len() as u32;
+    check_counts(from_count, bin_count)?;
+    let mut folded = vec![0u32; bin_count as usize];
+    for (bin, &count) in counts.iter().enumerate() {
+        folded[map_bin(bin as u32, from_count, bin_count) as usize] += count;
+    }
+    Ok(folded)
+}
+
+/// Checks that `mapping` maps a valid bin count into `binCount` bins.
+fn check_mapping(mapping: &[u16], bin_count: u32) -> Result<(), KernelError> {
+    check_counts(mapping.len() as u32, bin_count)?;
+    match mapping.iter().find(|&&bin| u32::from(bin) >= bin_count) {
+        Some(&bin) => Err(KernelError::invalid_argument("mapping target out of range")
+            .with("bin", f64::from(bin))
+            .with("binCount", f64::from(bin_count))),
+        None => Ok(()),
+    }
+}
+
+fn check_bins(bins: &[u16], from_count: usize) -> Result<(), KernelError> {
+    match bins.iter().find(|&&bin| usize::from(bin) >= from_count) {
+        Some(&bin) => Err(KernelError::invalid_argument("bin out of range")
+            .with("bin", f64::from(bin))
+            .with("binCount", from_count as f64)),
+        None => Ok(()),
+    }
+}
+
+/// A new bin-index column holding `mapping[bins[i]]` for each row, over
+/// `binCount` bins. `mapping` has one entry per source bin.
+#[wasm_bindgen(js_name = remapBins)]
+pub fn remap_bins(bins: &[u16], mapping: &[u16], bin_count: u32) -> Result<Vec<u16>, KernelError> {
+    check_mapping(mapping, bin_count)?;
+    check_bins(bins, mapping.len())?;
+    Ok(bins.iter().map(|&bin| mapping[bin as usize]).collect())
+}
+
+/// `remapBins` in place over the first `len` scratch entries, ready for
+/// `accumulateScratch`. Returns `len`.
+#[wasm_bindgen(js_name = remapScratch)]
+pub fn remap_scratch(len: u32, mapping: &[u16], bin_count: u32) -> Result<u32, KernelError> {
+    check_mapping(mapping, bin_count)?;
+    crate::with_scratch_mut(len as usize, |scratch| {
+        check_bins(scratch, mapping.len())?;
+        for bin in scratch.iter_mut() {
+            *bin = mapping[*bin as usize];
+        }
+        Ok(len)
+    })?
+}
+
+/// Folds a histogram over `mapping.length` bins into `binCount` bins
+/// through `mapping`, as if its rows had been remapped with `remapBins`.
+#[wasm_bindgen(js_name = remapCounts)]
+pub fn remap_counts(
+    counts: &[u32],
+    mapping: &[u16],
+    bin_count: u32,
+) -> Result<Vec<u32>, KernelError> {
+    check_mapping(mapping, bin_count)?;
+    if counts.len() != mapping.len() {
+        return Err(
+            KernelError::invalid_argument("counts and mapping lengths differ")
+                .with("counts", counts.len() as f64)
+                .with("mapping", mapping.len() as f64),
+        );
+    }
+    let mut folded = vec![0u32; bin_count as usize];
+    for (&count, &bin) in counts.iter().zip(mapping) {
+        folded[bin as usize] += count;
+    }
+    Ok(folded)
+}
@@ -0,0 +1,87 @@
+//! Panic detection and engine reset.
+//!
+//! Wasm panics abort the call without unwinding: the host sees a trap, and
+//! whatever the call had borrowed stays borrowed, so later calls into the
+//! same state panic again. The panic hook installed by `init_panic_hook`
+//! marks the module poisoned, which `isPoisoned` reports, and `hardReset`
+//! puts every registry, buffer and setting back to its start-up value, so
+//! the host can recover (re-ingest, rebuild its dimensions) without
+//! instantiating the module again. Handles issued before the reset are
+//! invalid afterwards.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use wasm_bindgen::prelude::*;
+
+static POISONED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the panic hook: marks the module poisoned, then logs the panic
+/// to the console.
+pub(crate) fn install_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        std::panic::set_hook(Box::new(|info| {
+            POISONED.store(true, Ordering::Relaxed);
+            console_error_panic_hook::hook(info);
+        }));
+    });
+}
+
+/// Replaces the contents of `cell` with `fresh`, also when a call aborted
+/// by a panic left it borrowed.
+pub(crate) fn reset_cell<T>(cell: &RefCell<T>, fresh: T) {
+    match cell.try_borrow_mut() {
+        Ok(mut value) => *value = fresh,
+        Err(_) => {
+            let cell = cell as *const RefCell<T> as *mut RefCell<T>;
+            // SAFETY: resets run from `hardReset`, an export, so no kernel
+            // frame is live and nothing references the value: the borrow
+            // flag is a leftover of a call that panicked, never to be
+            // released. Every field of a `RefCell` is interior-mutable, so
+            // the cell may be rewritten through a shared reference.
+            unsafe {
+                std::ptr::drop_in_place(cell);
+                cell.write(RefCell::new(fresh));
+            }
+        }
+    }
+}
+
+/// Whether a kernel has panicked since start-up or the last `hardReset`.
+/// Needs the hook from `init_panic_hook`.
+#[wasm_bindgen(js_name = isPoisoned)]
+pub fn is_poisoned() -> bool {
+    POISONED.load(Ordering::Relaxed)
+}
+
+/// Drops every column, index, sketch, session and cached buffer, restores
+/// every setting to its default and clears the poisoned flag. Allocator
+/// statistics and linear memory, which never shrinks, are kept.
+#[wasm_bindgen(js_name = hardReset)]
+pub fn hard_reset() {
+    crate::reset();
+    crate::arrow::reset();
+    crate::categories::reset();
+    crate::columns::reset();
+    crate::composite::reset();
+    crate::count_min::reset();
+    crate::fenwick::reset();
+    crate::filters::reset();
+    crate::history::reset();
+    crate::interval::reset();
+    crate::join::reset();
+    crate::log::reset();
+    crate::memory::reset();
+    crate::metrics::reset();
+    crate::parity::reset();
+    crate::random::reset();
+    crate::reducer::reset();
+    crate::results::reset();
+    crate::scratch::reset();
+    crate::sorted_index::reset();
+    crate::stream::reset();
+    crate::summation::reset();
+    crate::validate::reset();
+    POISONED.store(false, Ordering::Relaxed);
+}
@@ -0,0 +1,442 @@
+//! Incremental group reducers.
+//!
+//! crossfilter's reduce model: a group keeps per-bin state (row count and
+//! the sum of a measure) that filters update by adding and removing the rows
+//! whose filter state changed, so a brush step costs `O(changed rows)`
+//! instead of a pass over the column. Each row's bin and measure value are
+//! captured when the reducer is built; the reducer also remembers which rows
+//! are in, so adding a row twice (or removing one that is out) is a no-op.
+//!
+//! The reducer also tracks which bins it touched since changes were last
+//! read, so `reducerChanges` reports deltas in `O(touched bins)`.
+//!
+//! Once attached to the filter state with `attachReducer`, a reducer follows
+//! dimension filter changes by itself, ignoring its own dimension's filter
+//! as a crossfilter group does.
+//!
+//! Sums are kept in `f64` and accumulate rounding as rows come and go;
+//! rebuilding the reducer clears it.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, bit};
+use crate::delta::BinChanges;
+use crate::error::KernelError;
+use crate::filters;
+use crate::keyed::{GroupKeys, KeyedGroups};
+use crate::ordered::{GroupOrder, OrderedGroups};
+use crate::recovery;
+use crate::summation::Sums;
+
+struct Reducer {
+    /// Bin of each row; rows at or past `counts.len()` are dropped.
+    bins: Vec<u16>,
+    /// Measure value of each row (zero for nulls and NaNs), when summing.
+    values: Option<Vec<f64>>,
+    active: Vec<bool>,
+    counts: Vec<u32>,
+    sums: Sums,
+    /// Counts and sums as of the last `reducerChanges`.
+    reported_counts: Vec<u32>,
+    reported_sums: Vec<f64>,
+    /// Bins updated since then, each listed once.
+    touched: Vec<u32>,
+    is_touched: Vec<bool>,
+    /// Grouping dimension once attached to the filter state.
+    dimension: Option<u32>,
+}
+
+impl Reducer {
+    /// Moves `row` in or out; returns whether its state changed.
+    fn apply(&mut self, row: usize, add: bool) -> bool {
+        if self.active[row] == add {
+            return false;
+        }
+        self.active[row] = add;
+        let bin = self.bins[row] as usize;
+        if bin >= self.counts.len() {
+            return true;
+        }
+        let value = self.values.as_ref().map_or(0.0, |values| values[row]);
+        if !self.is_touched[bin] {
+            self.is_touched[bin] = true;
+            self.touched.push(bin as u32);
+        }
+        if add {
+            self.counts[bin] += 1;
+            self.sums.add(bin, value);
+        } else {
+            self.counts[bin] -= 1;
+            self.sums.add(bin, -value);
+        }
+        true
+    }
+
+    /// Bins whose count or sum moved since the last call, with the deltas.
+    fn changes(&mut self) -> BinChanges {
+        let mut changes = BinChanges::default();
+        self.touched.sort_unstable();
+        for bin in self.touched.drain(..) {
+            let at = bin as usize;
+            self.is_touched[at] = false;
+            let count_delta = self.counts[at] as i32 - self.reported_counts[at] as i32;
+            let sum_delta = self.sums.get(at) - self.reported_sums[at];
+            if count_delta == 0 && sum_delta == 0.0 {
+                continue;
+            }
+            self.reported_counts[at] = self.counts[at];
+            self.reported_sums[at] = self.sums.get(at);
+            changes.bins.push(bin);
+            changes.count_deltas.push(count_delta);
+            if self.values.is_some() {
+                changes.sum_deltas.push(sum_delta);
+            }
+        }
+        changes
+    }
+}
+
+thread_local! {
+    static REDUCERS: RefCell<HashMap<u32, Reducer>> = RefCell::new(HashMap::new());
+}
+
+/// Drops every reducer; see `hardReset`.
+pub(crate) fn reset() {
+    REDUCERS.with(|reducers| recovery::reset_cell(reducers, HashMap::new()));
+}
+
+fn with_reducer<T>(
+    group: u32,
+    run: impl FnOnce(&mut Reducer) -> Result<T, KernelError>,
+) -> Result<T, KernelError> {
+    REDUCERS.with(|reducers| {
+        let mut reducers = reducers.borrow_mut();
+        let reducer = reducers.get_mut(&group).ok_or_else(|| {
+            KernelError::invalid_state("no reducer for group").with("group", f64::from(group))
+        })?;
+        run(reducer)
+    })
+}
+
+/// Builds (or rebuilds) the reducer of `group`: `bins` holds one bin index
+/// per row (the grouping dimension's bins), `measure` optionally names a
+/// numeric column to sum, and `mask`, an LSB-first row bitmap in the
+/// layout's `activeMask` format, sets which rows start in (all when
+/// omitted). Returns the number of rows in.
+#[wasm_bindgen(js_name = buildReducer)]
+pub fn build_reducer(
+    group: u32,
+    bins: &[u16],
+    bin_count: u32,
+    measure: Option<u32>,
+    mask: Option<Vec<u8>>,
+) -> Result<u32, KernelError> {
+    if bin_count == 0 || bin_count > u32::from(u16::MAX) + 1 {
+        return Err(KernelError::bad_bin_count(bin_count));
+    }
+    let rows = bins.len();
+    if let Some(mask) = &mask {
+        if mask.len() < rows.div_ceil(8) {
+            return Err(KernelError::invalid_argument("mask is too short")
+                .with("needed", rows.div_ceil(8) as f64)
+                .with("available", mask.len() as f64));
+        }
+    }
+    let values = measure
+        .map(|handle| columns::measure(handle, rows))
+        .transpose()?;
+    let bin_count = bin_count as usize;
+    let mut reducer = Reducer {
+        bins: bins.to_vec(),
+        values,
+        active: vec![false; rows],
+        counts: vec![0; bin_count],
+        sums: Sums::new(bin_count),
+        reported_counts: vec![0; bin_count],
+        reported_sums: vec![0.0; bin_count],
+        touched: Vec::new(),
+        is_touched: vec![false; bin_count],
+        dimension: None,
+    };
+    let mut added = 0;
+    for row in 0..rows {
+        if mask.as_ref().is_none_or(|mask| bit(mask, row)) && reducer.apply(row, true) {
+            added += 1;
+        }
+    }
+    // The built state is the baseline for `reducerChanges`.
+    reducer.changes();
+    REDUCERS.with(|reducers| reducers.borrow_mut().insert(group, reducer));
+    Ok(added)
+}
+
+/// Moves the rows of `rows`, whose filter bit `flag` just flipped, in or
+/// out of every attached reducer they now pass or fail. `failing` holds each
+/// row's filter bits and `flag_of` a dimension's bit (zero when it has
+/// none).
+pub(crate) fn follow_filters(
+    flag: u32,
+    rows: &[u32],
+    failing: &[u32],
+    flag_of: impl Fn(u32) -> u32,
+) {
+    REDUCERS.with(|reducers| {
+        for reducer in reducers.borrow_mut().values_mut() {
+            let Some(dimension) = reducer.dimension else {
+                continue;
+            };
+            let own = flag_of(dimension);
+            if own == flag {
+                continue;
+            }
+            for &row in rows {
+                let row = row as usize;
+                if row < reducer.active.len() {
+                    reducer.apply(row, failing[row] & !own == 0);
+                }
+            }
+        }
+    });
+}
+
+/// Changes of every attached reducer that has any, by ascending group.
+pub(crate) fn attached_changes() -> Vec<(u32, BinChanges)> {
+    REDUCERS.with(|reducers| {
+        let mut reducers = reducers.borrow_mut();
+        let mut changes: Vec<(u32, BinChanges)> = reducers
+            .iter_mut()
+            .filter(|(_, reducer)| reducer.dimension.is_some())
+            .map(|(&group, reducer)| (group, reducer.changes()))
+            .filter(|(_, changes)| !changes.bins.is_empty())
+            .collect();
+        changes.sort_unstable_by_key(|&(group, _)| group);
+        changes
+    })
+}
+
+/// Attaches the reducer of `group`, grouping `dimension`, to the filter
+/// state: its rows are reset to those passing every filter but
+/// `dimension`'s, and later filter changes move rows by themselves. The
+/// reducer must cover the filter state's rows; attach again after
+/// `resetFilters`. Returns the number of rows in.
+#[wasm_bindgen(js_name = attachReducer)]
+pub fn attach_reducer(group: u32, dimension: u32) -> Result<u32, KernelError> {
+    with_reducer(group, |reducer| {
+        filters::with_filters(|filters| {
+            let rows = reducer.active.len();
+            if filters.rows() != rows {
+                return Err(KernelError::invalid_state(
+                    "reducer and filter state row counts differ",
+                )
+                .with("reducer", rows as f64)
+                .with("filters", filters.rows() as f64));
+            }
+            reducer.dimension = Some(dimension);
+            let mut active = 0;
+            for row in 0..rows {
+                let passes = filters.passes_except(row, dimension);
+                reducer.apply(row, passes);
+                active += u32::from(passes);
+            }
+            Ok(active)
+        })
+    })
+}
+
+fn update(group: u32, rows: &[u32], add: bool) -> Result<u32, KernelError> {
+    with_reducer(group, |reducer| {
+        let len = reducer.active.len();
+        if let Some(&row) = rows.iter().find(|&&row| row as usize >= len) {
+            return Err(KernelError::invalid_argument("row out of range")
+                .with("row", f64::from(row))
+                .with("length", len as f64));
+        }
+        Ok(rows
+            .iter()
+            .filter(|&&row| reducer.apply(row as usize, add))
+            .count() as u32)
+    })
+}
+
+/// Adds `rows` (e.g. rows a filter change let through) to the reducer of
+/// `group`. Rows already in are skipped; returns how many were added.
+#[wasm_bindgen(js_name = reducerAdd)]
+pub fn reducer_add(group: u32, rows: &[u32]) -> Result<u32, KernelError> {
+    update(group, rows, true)
+}
+
+/// Removes `rows` from the reducer of `group`; the counterpart of
+/// `reducerAdd`.
+#[wasm_bindgen(js_name = reducerRemove)]
+pub fn reducer_remove(group: u32, rows: &[u32]) -> Result<u32, KernelError> {
+    update(group, rows, false)
+}
+
+/// Rows in, per bin.
+#[wasm_bindgen(js_name = reducerCounts)]
+pub fn reducer_counts(group: u32) -> Result<Vec<u32>, KernelError> {
+    with_reducer(group, |reducer| Ok(reducer.counts.clone()))
+}
+
+/// Measure sum of the rows in, per bin; zeros when built without a measure.
+#[wasm_bindgen(js_name = reducerSums)]
+pub fn reducer_sums(group: u32) -> Result<Vec<f64>, KernelError> {
+    with_reducer(group, |reducer| Ok(reducer.sums.to_vec()))
+}
+
+/// Bins of the reducer of `group` whose count or sum changed since the last
+/// call (or the build), with their deltas; bins that moved and moved back
+/// are left out.
+#[wasm_bindgen(js_name = reducerChanges)]
+pub fn reducer_changes(group: u32) -> Result<BinChanges, KernelError> {
+    with_reducer(group, |reducer| Ok(reducer.changes()))
+}
+
+/// Bins of the reducer of `group` ranked by `by` (`descending` for the
+/// largest first) with counts and sums aligned, cut to the first `limit`
+/// when given.
+#[wasm_bindgen(js_name = reducerOrdered)]
+pub fn reducer_ordered(
+    group: u32,
+    by: GroupOrder,
+    descending: bool,
+    limit: Option<u32>,
+) -> Result<OrderedGroups, KernelError> {
+    with_reducer(group, |reducer| {
+        OrderedGroups::new(
+            &reducer.counts,
+            &reducer.sums.to_vec(),
+            by,
+            descending,
+            limit,
+        )
+    })
+}
+
+/// Bins of the reducer of `group` with their keys, counts and sums, leaving
+/// out empty bins when `skipEmpty`.
+#[wasm_bindgen(js_name = reducerKeyed)]
+pub fn reducer_keyed(
+    group: u32,
+    keys: &GroupKeys,
+    skip_empty: bool,
+) -> Result<KeyedGroups, KernelError> {
+    with_reducer(group, |reducer| {
+        Ok(KeyedGroups::new(
+            &reducer.counts,
+            &reducer.sums.to_vec(),
+            keys,
+            skip_empty,
+        ))
+    })
+}
+
+/// Drops the reducer of `group`, or every reducer when omitted.
+#[wasm_bindgen(js_name = releaseReducer)]
+pub fn release_reducer(group: Option<u32>) {
+    REDUCERS.with(|reducers| match group {
+        Some(group) => {
+            reducers.borrow_mut().remove(&group);
+        }
+        None => reducers.borrow_mut().clear(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::floats;
+
+    const ROWS: usize = 120;
+    const BINS: usize = 6;
+
+    /// Counts and sums of `rows` per bin, recomputed from scratch; bins at
+    /// or past `BINS` are dropped.
+    fn rescan(bins: &[u16], values: &[f64], rows: &[bool]) -> (Vec<u32>, Vec<f64>) {
+        let mut counts = vec![0; BINS];
+        let mut sums = vec![0.0; BINS];
+        for row in (0..ROWS).filter(|&row| rows[row] && (bins[row] as usize) < BINS) {
+            counts[bins[row] as usize] += 1;
+            sums[bins[row] as usize] += values[row];
+        }
+        (counts, sums)
+    }
+
+    #[test]
+    fn adds_and_removes_match_a_full_rescan() {
+        // Bin 6 is past the bin count; every 11th value is null.
+        let bins: Vec<u16> = (0..ROWS).map(|row| (row * 5 % 7) as u16).collect();
+        let measure: Vec<f64> = (0..ROWS).map(|row| (row % 9) as f64 - 3.0).collect();
+        let validity = (0..ROWS.div_ceil(8))
+            .map(|byte| {
+                (0..8)
+                    .filter(|&b| (byte * 8 + b) % 11 != 0)
+                    .fold(0u8, |mask, b| mask | 1 << b)
+            })
+            .collect();
+        let values: Vec<f64> = (0..ROWS)
+            .map(|row| if row % 11 == 0 { 0.0 } else { measure[row] })
+            .collect();
+        let handle = floats(measure, Some(validity));
+
+        // Start with the even rows.
+        let mut mask = vec![0x55; ROWS.div_ceil(8)];
+        let mut rows: Vec<bool> = (0..ROWS).map(|row| row % 2 == 0).collect();
+        let added = build_reducer(4, &bins, BINS as u32, Some(handle), Some(mask.clone()));
+        assert_eq!(added.unwrap(), ROWS as u32 / 2);
+        assert!(reducer_changes(4).unwrap().bins.is_empty());
+        let (mut reported_counts, mut reported_sums) = rescan(&bins, &values, &rows);
+
+        let steps: [(&[u32], bool); 6] = [
+            (&[1, 3, 5, 7, 9, 1], true),
+            (&[0, 2, 4, 3], false),
+            (&[0, 2, 4, 6, 8], false),
+            (&[100, 101, 102, 103, 104, 105, 106], true),
+            (&[1, 3, 100], false),
+            (&[0, 1, 2, 3], true),
+        ];
+        for (step, (step_rows, add)) in steps.into_iter().enumerate() {
+            let moved = step_rows
+                .iter()
+                .collect::<std::collections::BTreeSet<_>>()
+                .into_iter()
+                .filter(|&&row| rows[row as usize] != add)
+                .count();
+            let result = if add {
+                reducer_add(4, step_rows)
+            } else {
+                reducer_remove(4, step_rows)
+            };
+            assert_eq!(result.unwrap() as usize, moved, "step {step}");
+            for &row in step_rows {
+                rows[row as usize] = add;
+            }
+
+            let (counts, sums) = rescan(&bins, &values, &rows);
+            assert_eq!(reducer_counts(4).unwrap(), counts, "step {step}");
+            assert_eq!(reducer_sums(4).unwrap(), sums, "step {step}");
+            let changes = reducer_changes(4).unwrap();
+            assert!(changes.bins.windows(2).all(|pair| pair[0] < pair[1]));
+            for (index, &bin) in changes.bins.iter().enumerate() {
+                let bin = bin as usize;
+                reported_counts[bin] =
+                    (reported_counts[bin] as i32 + changes.count_deltas[index]) as u32;
+                reported_sums[bin] += changes.sum_deltas[index];
+            }
+            assert_eq!(reported_counts, counts, "step {step}");
+            assert_eq!(reported_sums, sums, "step {step}");
+        }
+
+        // A rebuild from the current rows starts over with the same totals.
+        mask.fill(0);
+        for row in (0..ROWS).filter(|&row| rows[row]) {
+            mask[row / 8] |= 1 << (row % 8);
+        }
+        let counts = reducer_counts(4).unwrap();
+        build_reducer(4, &bins, BINS as u32, Some(handle), Some(mask)).unwrap();
+        assert_eq!(reducer_counts(4).unwrap(), counts);
+        assert!(reducer_add(4, &[ROWS as u32]).is_err());
+    }
+}
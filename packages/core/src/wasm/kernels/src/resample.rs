@@ -0,0 +1,102 @@
+//! Resampling onto a fixed time grid.
+//!
+//! Sparse or irregular telemetry rendered as a step chart needs one value per
+//! interval: points are aggregated per grid interval and intervals without
+//! any are filled by policy, rather than interpolated by the chart. Interval
+//! `k` covers `[start + k * interval, start + (k + 1) * interval)`; points
+//! off the grid and NaN values are left out.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::rolling::check_times;
+use crate::summation::Sums;
+
+/// Aggregate of the points inside one interval.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResampleReduce {
+    Sum = 0,
+    Mean = 1,
+    Min = 2,
+    Max = 3,
+    Count = 4,
+    First = 5,
+    Last = 6,
+}
+
+/// Value given to intervals without points.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillPolicy {
+    Zero = 0,
+    /// NaN, which charts draw as a gap.
+    Null = 1,
+    /// The previous interval's value (NaN before the first point).
+    Forward = 2,
+}
+
+/// `count` intervals of the series `times`/`values` (ascending times, in
+/// numbers or epoch milliseconds) from `start`, each `interval` wide:
+/// `reduce` of the points inside, or the `fill` value when there are none.
+#[wasm_bindgen]
+pub fn resample(
+    times: &[f64],
+    values: &[f64],
+    start: f64,
+    interval: f64,
+    count: u32,
+    reduce: ResampleReduce,
+    fill: FillPolicy,
+) -> Result<Vec<f64>, KernelError> {
+    if !start.is_finite() {
+        return Err(KernelError::invalid_argument("start must be finite").with("start", start));
+    }
+    if !interval.is_finite() || interval <= 0.0 {
+        return Err(
+            KernelError::invalid_argument("interval must be positive").with("interval", interval)
+        );
+    }
+    check_times("resample", times, values)?;
+    let count = count as usize;
+    let mut out = vec![f64::NAN; count];
+    let mut counts = vec![0u32; count];
+    let mut sums = Sums::new(count);
+    for (&time, &value) in times.iter().zip(values) {
+        let at = ((time - start) / interval).floor();
+        if value.is_nan() || !(0.0..count as f64).contains(&at) {
+            continue;
+        }
+        let at = at as usize;
+        counts[at] += 1;
+        // Slots start as NaN, which `min` and `max` pass over.
+        let slot = &mut out[at];
+        match reduce {
+            ResampleReduce::Sum | ResampleReduce::Mean => sums.add(at, value),
+            ResampleReduce::Min => *slot = slot.min(value),
+            ResampleReduce::Max => *slot = slot.max(value),
+            ResampleReduce::First if counts[at] == 1 => *slot = value,
+            ResampleReduce::Last => *slot = value,
+            ResampleReduce::First | ResampleReduce::Count => {}
+        }
+    }
+    let mut previous = f64::NAN;
+    for (at, (value, &points)) in out.iter_mut().zip(&counts).enumerate() {
+        if points == 0 {
+            *value = match fill {
+                FillPolicy::Zero => 0.0,
+                FillPolicy::Null => f64::NAN,
+                FillPolicy::Forward => previous,
+            };
+        } else {
+            match reduce {
+                ResampleReduce::Sum => *value = sums.get(at),
+                ResampleReduce::Mean => *value = sums.get(at) / f64::from(points),
+                ResampleReduce::Count => *value = f64::from(points),
+                _ => {}
+            }
+        }
+        previous = *value;
+    }
+    Ok(out)
+}
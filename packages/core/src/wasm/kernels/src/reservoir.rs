@@ -0,0 +1,179 @@
+//! Reservoir sampling of filtered rows.
+//!
+//! Detail scatterplots show a uniform sample of the selection rather than
+//! its first K rows. Algorithm L keeps a reservoir of K rows and jumps
+//! straight to the next row that replaces one, so it draws
+//! `O(K log(N / K))` random numbers for `N` selected rows instead of one per
+//! row. A seed (the `setRandomSeed` seed when omitted) fixes the sample,
+//! so the same selection redraws the same points.
+//!
+//! Stratified sampling keeps one reservoir per bin of a dimension, so
+//! drill-down views show examples from every bucket instead of mostly the
+//! largest one.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns::bit;
+use crate::error::KernelError;
+use crate::random;
+
+/// Sampled rows grouped by bin.
+#[wasm_bindgen]
+pub struct StratifiedSample {
+    rows: Vec<u32>,
+    offsets: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl StratifiedSample {
+    /// Rows of bin `b` at `rows[offsets[b]..offsets[b + 1]]`, ascending.
+    #[wasm_bindgen(getter)]
+    pub fn rows(&self) -> Vec<u32> {
+        self.rows.clone()
+    }
+
+    /// Start of each bin's rows, plus the total (`binCount + 1` entries).
+    #[wasm_bindgen(getter)]
+    pub fn offsets(&self) -> Vec<u32> {
+        self.offsets.clone()
+    }
+}
+
+fn check_mask(mask: Option<&[u8]>, rows: usize) -> Result<(), KernelError> {
+    match mask {
+        Some(mask) if mask.len() < rows.div_ceil(8) => {
+            Err(KernelError::invalid_argument("mask is too short")
+                .with("needed", rows.div_ceil(8) as f64)
+                .with("available", mask.len() as f64))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Up to `count` rows drawn uniformly without replacement from the
+/// `rowCount` rows set in `mask` (an LSB-first row bitmap in the layout's
+/// `activeMask` format such as `filterMask()`; every row when omitted),
+/// ascending. Every selected row when there are no more than `count`.
+#[wasm_bindgen(js_name = reservoirSample)]
+pub fn reservoir_sample(
+    row_count: u32,
+    count: u32,
+    seed: Option<u32>,
+    mask: Option<Vec<u8>>,
+) -> Result<Vec<u32>, KernelError> {
+    let rows = row_count as usize;
+    check_mask(mask.as_deref(), rows)?;
+    let size = count as usize;
+    // `count` may be far above the rows ("all of them"); never reserve more.
+    let mut reservoir: Vec<u32> = Vec::with_capacity(size.min(rows));
+    if size == 0 {
+        return Ok(reservoir);
+    }
+    let mut rng = random::generator(seed);
+    let mut weight = (rng.unit().ln() / size as f64).exp();
+    // Selected rows to pass over before the next replacement.
+    let mut skip = 0usize;
+    let selected = (0..rows).filter(|&row| mask.as_ref().is_none_or(|mask| bit(mask, row)));
+    for row in selected {
+        if reservoir.len() < size {
+            reservoir.push(row as u32);
+            if reservoir.len() == size {
+                skip = (rng.unit().ln() / (1.0 - weight).ln()).floor() as usize;
+            }
+            continue;
+        }
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        let slot = rng.below(size);
+        reservoir[slot] = row as u32;
+        weight *= (rng.unit().ln() / size as f64).exp();
+        skip = (rng.unit().ln() / (1.0 - weight).ln()).floor() as usize;
+    }
+    reservoir.sort_unstable();
+    Ok(reservoir)
+}
+
+/// Up to `count` rows per bin, drawn uniformly without replacement from the
+/// rows set in `mask` (all when omitted), where `bins` holds each row's bin
+/// (the dimension's bins, below `binCount`). Bins with no more than `count`
+/// selected rows keep them all.
+#[wasm_bindgen(js_name = stratifiedSample)]
+pub fn stratified_sample(
+    bins: &[u16],
+    bin_count: u32,
+    count: u32,
+    seed: Option<u32>,
+    mask: Option<Vec<u8>>,
+) -> Result<StratifiedSample, KernelError> {
+    if bin_count == 0 || bin_count > u32::from(u16::MAX) + 1 {
+        return Err(KernelError::bad_bin_count(bin_count));
+    }
+    check_mask(mask.as_deref(), bins.len())?;
+    let size = count as usize;
+    let mut rng = random::generator(seed);
+    let mut reservoirs: Vec<Vec<u32>> = vec![Vec::new(); bin_count as usize];
+    let mut seen = vec![0usize; bin_count as usize];
+    for (row, &bin) in bins.iter().enumerate() {
+        if mask.as_ref().is_some_and(|mask| !bit(mask, row)) {
+            continue;
+        }
+        let bin = bin as usize;
+        let Some(reservoir) = reservoirs.get_mut(bin) else {
+            return Err(KernelError::invalid_argument("bin out of range")
+                .with("bin", bin as f64)
+                .with("binCount", f64::from(bin_count)));
+        };
+        seen[bin] += 1;
+        if reservoir.len() < size {
+            reservoir.push(row as u32);
+        } else {
+            // Algorithm R: the row replaces one with probability size / seen.
+            let slot = rng.below(seen[bin]);
+            if slot < size {
+                reservoir[slot] = row as u32;
+            }
+        }
+    }
+    let mut sample = StratifiedSample {
+        rows: Vec::new(),
+        offsets: Vec::with_capacity(reservoirs.len() + 1),
+    };
+    for mut reservoir in reservoirs {
+        sample.offsets.push(sample.rows.len() as u32);
+        reservoir.sort_unstable();
+        sample.rows.extend(reservoir);
+    }
+    sample.offsets.push(sample.rows.len() as u32);
+    Ok(sample)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_counts_return_every_selected_row() {
+        assert_eq!(
+            reservoir_sample(5, u32::MAX, None, None).unwrap(),
+            [0, 1, 2, 3, 4]
+        );
+        let masked = reservoir_sample(10, u32::MAX, None, Some(vec![0b1010, 0b10])).unwrap();
+        assert_eq!(masked, [1, 3, 9]);
+    }
+
+    #[test]
+    fn samples_are_seeded_and_within_the_selection() {
+        let mask = vec![0xaa; 125];
+        let sample = reservoir_sample(1_000, 50, Some(9), Some(mask.clone())).unwrap();
+        assert_eq!(sample.len(), 50);
+        assert!(sample.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(sample.iter().all(|&row| row % 2 == 1));
+        assert_eq!(
+            reservoir_sample(1_000, 50, Some(9), Some(mask)).unwrap(),
+            sample
+        );
+        assert!(reservoir_sample(1_000, 0, None, None).unwrap().is_empty());
+    }
+}
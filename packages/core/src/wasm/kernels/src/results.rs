@@ -0,0 +1,157 @@
+//! Zero-copy histogram results.
+//!
+//! `accumulateBins` and `accumulateScratch` copy their counts into a fresh
+//! `Uint32Array`, which for wide dimensions costs as much as counting. The
+//! `View` variants hand the counts buffer itself to the caller instead: the
+//! result is a `(ptr, len)` pair locating the counts in linear memory
+//! (`new Uint32Array(memory.buffer, ptr, len)`), valid until
+//! `releaseResult(handle)`. Until then the buffer is the caller's: no call
+//! writes to or moves it, so `ptr` survives memory growth (only JS views of
+//! the old `memory.buffer` must be made again). Releasing hands it back to
+//! its dimension, so a brush loop that releases each result before the
+//! next call allocates nothing.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::{recovery, SCRATCH, WORKSPACE};
+
+struct Held {
+    dimension: Option<u32>,
+    counts: Vec<u32>,
+}
+
+thread_local! {
+    static RESULTS: RefCell<HashMap<u32, Held>> = RefCell::new(HashMap::new());
+    static NEXT_RESULT: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Drops every held result; see `hardReset`.
+pub(crate) fn reset() {
+    RESULTS.with(|results| recovery::reset_cell(results, HashMap::new()));
+    NEXT_RESULT.with(|next| next.set(0));
+}
+
+/// Location of a held counts buffer.
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub struct ResultView {
+    handle: u32,
+    ptr: u32,
+    len: u32,
+}
+
+#[wasm_bindgen]
+impl ResultView {
+    /// Pass to `releaseResult` once done with the counts.
+    #[wasm_bindgen(getter)]
+    pub fn handle(&self) -> u32 {
+        self.handle
+    }
+
+    /// Byte offset of the counts in linear memory.
+    #[wasm_bindgen(getter)]
+    pub fn ptr(&self) -> u32 {
+        self.ptr
+    }
+
+    /// Number of counts, one per bin.
+    #[wasm_bindgen(getter = len)]
+    pub fn length(&self) -> u32 {
+        self.len
+    }
+}
+
+/// Accumulates `data` and takes the counts out of the workspace.
+fn hold(
+    entry: &'static str,
+    data: &[u16],
+    bin_count: u32,
+    dimension: Option<u32>,
+) -> Result<ResultView, KernelError> {
+    crate::begin_call();
+    let counts = WORKSPACE.with(|workspace| {
+        let mut workspace = workspace.borrow_mut();
+        crate::accumulate_slice(entry, data, bin_count, dimension, &mut workspace)?;
+        Ok::<_, KernelError>(
+            workspace
+                .counts
+                .get_mut(&dimension)
+                .map(std::mem::take)
+                .unwrap_or_default(),
+        )
+    })?;
+    let handle = NEXT_RESULT.with(|next| {
+        let handle = next.get().wrapping_add(1).max(1);
+        next.set(handle);
+        handle
+    });
+    let view = ResultView {
+        handle,
+        ptr: counts.as_ptr() as usize as u32,
+        len: counts.len() as u32,
+    };
+    RESULTS.with(|results| {
+        results
+            .borrow_mut()
+            .insert(handle, Held { dimension, counts })
+    });
+    Ok(view)
+}
+
+/// `accumulateBins` returning a view of the counts rather than a copy.
+#[wasm_bindgen(js_name = accumulateBinsView)]
+pub fn accumulate_bins_view(
+    bins: &[u16],
+    bin_count: u32,
+    dimension: Option<u32>,
+) -> Result<ResultView, KernelError> {
+    hold("accumulateBinsView", bins, bin_count, dimension)
+}
+
+/// `accumulateScratch` returning a view of the counts rather than a copy.
+#[wasm_bindgen(js_name = accumulateScratchView)]
+pub fn accumulate_scratch_view(
+    len: u32,
+    bin_count: u32,
+    dimension: Option<u32>,
+) -> Result<ResultView, KernelError> {
+    SCRATCH.with(|cell| {
+        let scratch = cell.borrow();
+        let len = len as usize;
+        if len > scratch.len() {
+            return Err(KernelError::scratch_overflow(len, scratch.len())
+                .in_kernel("accumulateScratchView"));
+        }
+        hold(
+            "accumulateScratchView",
+            &scratch[..len],
+            bin_count,
+            dimension,
+        )
+    })
+}
+
+/// Ends the caller's use of a result, or of every result when omitted;
+/// views of it must not be read afterwards. Unknown handles are ignored.
+#[wasm_bindgen(js_name = releaseResult)]
+pub fn release_result(handle: Option<u32>) {
+    let released: Vec<Held> = RESULTS.with(|results| {
+        let mut results = results.borrow_mut();
+        match handle {
+            Some(handle) => results.remove(&handle).into_iter().collect(),
+            None => results.drain().map(|(_, held)| held).collect(),
+        }
+    });
+    WORKSPACE.with(|workspace| {
+        let mut workspace = workspace.borrow_mut();
+        for held in released {
+            let counts = workspace.counts.entry(held.dimension).or_default();
+            if counts.capacity() < held.counts.capacity() {
+                *counts = held.counts;
+            }
+        }
+    });
+}
@@ -0,0 +1,208 @@
+//! Rolling window aggregates over a time-ordered series.
+//!
+//! Smoothed overlays (moving averages, rolling extremes) computed in one pass
+//! instead of re-aggregating every window in JS. Windows trail: the window of
+//! point `i` ends at `i` and reaches back either a fixed number of points or
+//! a time span. Sums and means keep a running total; minima and maxima keep a
+//! monotonic queue, so every point enters and leaves once.
+//!
+//! NaN values are skipped; a window with no other value yields NaN (zero for
+//! sums). Running sums accumulate rounding as values leave the window.
+
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::summation::Sum;
+use crate::validate;
+
+/// Aggregate computed over each window.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RollingReduce {
+    Sum = 0,
+    Mean = 1,
+    Min = 2,
+    Max = 3,
+}
+
+/// Aggregates `values` over the windows `[starts[i], i]`; `starts` must be
+/// non-decreasing.
+fn rolling(
+    values: &[f64],
+    starts: impl IntoIterator<Item = usize>,
+    reduce: RollingReduce,
+) -> Vec<f64> {
+    let mut out = Vec::with_capacity(values.len());
+    let mut left = 0;
+    match reduce {
+        RollingReduce::Sum | RollingReduce::Mean => {
+            let (mut sum, mut count) = (Sum::new(), 0u32);
+            for (end, start) in starts.into_iter().enumerate() {
+                if !values[end].is_nan() {
+                    sum.add(values[end]);
+                    count += 1;
+                }
+                while left < start {
+                    if !values[left].is_nan() {
+                        sum.add(-values[left]);
+                        count -= 1;
+                    }
+                    left += 1;
+                }
+                if count == 0 {
+                    // Drop the rounding left over from values that came and went.
+                    sum = Sum::new();
+                }
+                out.push(match reduce {
+                    RollingReduce::Mean if count == 0 => f64::NAN,
+                    RollingReduce::Mean => sum.value() / f64::from(count),
+                    _ => sum.value(),
+                });
+            }
+        }
+        RollingReduce::Min | RollingReduce::Max => {
+            let better = |a: f64, b: f64| {
+                if reduce == RollingReduce::Min {
+                    a <= b
+                } else {
+                    a >= b
+                }
+            };
+            // Positions whose values are strictly worse than every later one
+            // are dropped, so the front holds the window's extreme.
+            let mut queue: VecDeque<usize> = VecDeque::new();
+            for (end, start) in starts.into_iter().enumerate() {
+                let value = values[end];
+                if !value.is_nan() {
+                    while queue.back().is_some_and(|&at| better(value, values[at])) {
+                        queue.pop_back();
+                    }
+                    queue.push_back(end);
+                }
+                while queue.front().is_some_and(|&at| at < start) {
+                    queue.pop_front();
+                }
+                out.push(queue.front().map_or(f64::NAN, |&at| values[at]));
+            }
+        }
+    }
+    out
+}
+
+/// Checks that `times` is ascending and aligned with `values`.
+pub(crate) fn check_times(
+    kernel: &'static str,
+    times: &[f64],
+    values: &[f64],
+) -> Result<(), KernelError> {
+    validate::strict(kernel, |report| {
+        if times.len() != values.len() {
+            report.check(
+                "lengthsMatch",
+                std::iter::once(times.len().min(values.len())),
+            );
+        }
+        report.check(
+            "ascendingTimes",
+            (1..times.len()).filter(|&at| {
+                times[at - 1]
+                    .partial_cmp(&times[at])
+                    .is_none_or(|order| order.is_gt())
+            }),
+        );
+        report.check(
+            "finiteTimes",
+            (0..times.len()).filter(|&at| !times[at].is_finite()),
+        );
+        report.check(
+            "finiteValues",
+            (0..values.len()).filter(|&at| values[at].is_infinite()),
+        );
+    })?;
+    if times.len() != values.len() {
+        return Err(
+            KernelError::invalid_argument("times and values lengths differ")
+                .in_kernel(kernel)
+                .with("times", times.len() as f64)
+                .with("values", values.len() as f64),
+        );
+    }
+    if let Some(at) = times.windows(2).position(|pair| {
+        pair[0]
+            .partial_cmp(&pair[1])
+            .is_none_or(|order| order.is_gt())
+    }) {
+        return Err(KernelError::invalid_argument("times must be ascending")
+            .in_kernel(kernel)
+            .with("index", (at + 1) as f64));
+    }
+    Ok(())
+}
+
+/// Moving `reduce` of `values` over trailing windows of `window` points:
+/// entry `i` covers points `i + 1 - window` through `i` (fewer at the
+/// start).
+#[wasm_bindgen(js_name = rollingByCount)]
+pub fn rolling_by_count(
+    values: &[f64],
+    window: u32,
+    reduce: RollingReduce,
+) -> Result<Vec<f64>, KernelError> {
+    if window == 0 {
+        return Err(KernelError::invalid_argument(
+            "window must hold at least one point",
+        ));
+    }
+    let window = window as usize;
+    let starts = (0..values.len()).map(|end| (end + 1).saturating_sub(window));
+    Ok(rolling(values, starts, reduce))
+}
+
+/// Moving `reduce` of `values` over trailing time windows: entry `i` covers
+/// the points with times in `(times[i] - span, times[i]]`. `times` (numbers
+/// or epoch milliseconds) must be ascending and aligned with `values`.
+#[wasm_bindgen(js_name = rollingByTime)]
+pub fn rolling_by_time(
+    times: &[f64],
+    values: &[f64],
+    span: f64,
+    reduce: RollingReduce,
+) -> Result<Vec<f64>, KernelError> {
+    if span.is_nan() || span <= 0.0 {
+        return Err(KernelError::invalid_argument("span must be positive").with("span", span));
+    }
+    check_times("rollingByTime", times, values)?;
+    let mut start = 0;
+    // A window always keeps its own point, even when an infinite time
+    // leaves `time - span` at or past every time.
+    let starts = times.iter().enumerate().map(|(index, &time)| {
+        while start < index && times[start] <= time - span {
+            start += 1;
+        }
+        start
+    });
+    Ok(rolling(values, starts, reduce))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_windows_trail_by_the_span() {
+        let times = [0.0, 1.0, 2.5, 3.0, 10.0];
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let sums = rolling_by_time(&times, &values, 2.0, RollingReduce::Sum).unwrap();
+        assert_eq!(sums, [1.0, 3.0, 5.0, 7.0, 5.0]);
+    }
+
+    #[test]
+    fn infinite_times_keep_their_own_point() {
+        let sums = rolling_by_time(&[0.0, f64::INFINITY], &[1.0, 2.0], 1.0, RollingReduce::Sum);
+        assert_eq!(sums.unwrap(), [1.0, 2.0]);
+        let times = [f64::NEG_INFINITY, f64::NEG_INFINITY, 0.0];
+        let maxima = rolling_by_time(&times, &[1.0, 2.0, 3.0], 1.0, RollingReduce::Max);
+        assert_eq!(maxima.unwrap(), [1.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,95 @@
+//! Parallel sample sort for large index builds.
+//!
+//! Splits the serial radix sort of [`sort::sort_permutation`] across
+//! threads: an evenly spaced sample of the keys picks one splitter per
+//! thread boundary, every row is classified into the bucket between two
+//! splitters, and the buckets, which hold disjoint key ranges, are radix
+//! sorted concurrently and laid end to end. Rows are classified in input
+//! order and equal keys always land in the same bucket, so the result is as
+//! stable as the serial sort.
+//!
+//! Threads come from `std::thread::scope`, so the build needs a target whose
+//! std can spawn them. Where it cannot (`available_parallelism` fails) or
+//! the input is small, the serial sort runs instead.
+
+use std::thread;
+
+use crate::sort::{self, RadixKey};
+
+/// Below this many rows thread start-up outweighs the split.
+const PARALLEL_MIN: usize = 1 << 20;
+/// Sample keys taken per bucket; more evens out bucket sizes.
+const OVERSAMPLE: usize = 64;
+const MAX_THREADS: usize = 16;
+
+/// Stably sorts `order` (indices into `keys`) by ascending key, across
+/// threads when there are enough rows.
+pub(crate) fn sort_permutation<K: RadixKey + Ord + Send + Sync>(keys: &[K], order: &mut Vec<u32>) {
+    let threads = thread::available_parallelism()
+        .map_or(1, usize::from)
+        .min(MAX_THREADS);
+    if threads < 2 || order.len() < PARALLEL_MIN {
+        sort::sort_permutation(keys, order);
+        return;
+    }
+    let splitters = splitters(keys, order, threads);
+    let chunk = order.len().div_ceil(threads);
+
+    // Classify rows into buckets, one chunk of `order` per thread.
+    let mut buckets = vec![0u16; order.len()];
+    thread::scope(|scope| {
+        for (rows, out) in order.chunks(chunk).zip(buckets.chunks_mut(chunk)) {
+            let splitters = &splitters;
+            scope.spawn(move || {
+                for (&row, bucket) in rows.iter().zip(out) {
+                    let key = keys[row as usize];
+                    *bucket = splitters.partition_point(|&splitter| splitter <= key) as u16;
+                }
+            });
+        }
+    });
+
+    // Scatter rows to their buckets, keeping input order within each.
+    let mut offsets = vec![0usize; splitters.len() + 2];
+    for &bucket in &buckets {
+        offsets[bucket as usize + 1] += 1;
+    }
+    for bucket in 1..offsets.len() {
+        offsets[bucket] += offsets[bucket - 1];
+    }
+    let mut next = offsets.clone();
+    let mut scattered = vec![0u32; order.len()];
+    for (&row, &bucket) in order.iter().zip(&buckets) {
+        scattered[next[bucket as usize]] = row;
+        next[bucket as usize] += 1;
+    }
+
+    // Sort the buckets concurrently.
+    thread::scope(|scope| {
+        let mut rest = scattered.as_mut_slice();
+        for bounds in offsets.windows(2) {
+            let (bucket, tail) = rest.split_at_mut(bounds[1] - bounds[0]);
+            rest = tail;
+            scope.spawn(move || {
+                let mut rows = bucket.to_vec();
+                sort::sort_permutation(keys, &mut rows);
+                bucket.copy_from_slice(&rows);
+            });
+        }
+    });
+    *order = scattered;
+}
+
+/// `threads - 1` ascending splitters drawn from an evenly spaced sample of
+/// the keys of `order`.
+fn splitters<K: RadixKey + Ord>(keys: &[K], order: &[u32], threads: usize) -> Vec<K> {
+    let samples = threads * OVERSAMPLE;
+    let stride = order.len() / samples;
+    let mut sample: Vec<K> = (0..samples)
+        .map(|at| keys[order[at * stride] as usize])
+        .collect();
+    sample.sort_unstable();
+    (1..threads)
+        .map(|bucket| sample[bucket * OVERSAMPLE])
+        .collect()
+}
@@ -0,0 +1,221 @@
+//! Trimming the scratch buffers and tracking views into them.
+//!
+//! The scratch buffer and the row-sized workspace buffers keep their
+//! largest size, so the steady-state brush loop never reallocates. One huge
+//! query then pins that memory for the life of the worker. `trimScratch`
+//! releases whatever lies past a byte budget. With `setScratchDecay(calls)`
+//! they trim themselves instead: every `calls` scratch requests, they shrink
+//! to the largest request among them. Count arrays are bounded by the bin
+//! count and are left alone.
+//!
+//! Trimming keeps the start of the scratch buffer but moves it, so views
+//! from `scratchBuffer` must be fetched again afterwards. So must views
+//! after the buffer grows, or after linear memory grows, which detaches
+//! every view. A stale view reads freed memory or nothing at all, without
+//! any error. `scratchView` pairs the view with a generation token that
+//! changes whenever the buffer moves or memory grows; `isViewValid(token)`
+//! compares it with the current generation, and the handle's `array`
+//! getter hands out a fresh view to replace a stale one.
+
+use std::cell::Cell;
+use std::mem::size_of;
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::memory::{self, memory_pages};
+use crate::{SCRATCH, WORKSPACE};
+
+#[derive(Clone, Copy)]
+struct Decay {
+    /// Requests per window; zero turns decay off.
+    calls: u32,
+    seen: u32,
+    /// Largest request of the current window, in bytes.
+    peak: usize,
+}
+
+/// Where the scratch buffer lives; a view made for one placement is stale
+/// in any other.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Placement {
+    address: usize,
+    capacity: usize,
+    pages: usize,
+}
+
+thread_local! {
+    static GENERATION: Cell<(u32, Option<Placement>)> = const { Cell::new((0, None)) };
+    static DECAY: Cell<Decay> = const {
+        Cell::new(Decay {
+            calls: 0,
+            seen: 0,
+            peak: 0,
+        })
+    };
+}
+
+/// Turns decay off and moves the generation on, so tokens from before the
+/// reset read as stale; see `hardReset`.
+pub(crate) fn reset() {
+    DECAY.with(|cell| {
+        cell.set(Decay {
+            calls: 0,
+            seen: 0,
+            peak: 0,
+        })
+    });
+    GENERATION.with(|cell| cell.set((cell.get().0.wrapping_add(1), None)));
+}
+
+/// Shrinks `buffer` to at most `max_bytes`, returning the bytes released.
+fn trim<T>(buffer: &mut Vec<T>, max_bytes: usize) -> usize {
+    let keep = max_bytes / size_of::<T>();
+    let before = buffer.capacity();
+    if before <= keep {
+        return 0;
+    }
+    buffer.truncate(keep);
+    buffer.shrink_to(keep);
+    (before - buffer.capacity()) * size_of::<T>()
+}
+
+/// Trims every buffer not borrowed right now.
+fn trim_all(max_bytes: usize) -> usize {
+    let mut released = 0;
+    SCRATCH.with(|cell| {
+        if let Ok(mut scratch) = cell.try_borrow_mut() {
+            released += trim(&mut scratch, max_bytes);
+        }
+    });
+    WORKSPACE.with(|cell| {
+        if let Ok(mut workspace) = cell.try_borrow_mut() {
+            released += trim(&mut workspace.input, max_bytes);
+            released += trim(&mut workspace.kernel.sorted, max_bytes);
+        }
+    });
+    released
+}
+
+/// Grows `scratch` to at least `len` entries within the memory budget.
+pub(crate) fn grow(scratch: &mut Vec<u16>, len: usize) -> Result<(), KernelError> {
+    if scratch.len() < len {
+        memory::check_budget(len.saturating_sub(scratch.capacity()) * 2)?;
+        scratch.resize(len, 0);
+    }
+    Ok(())
+}
+
+/// Records a scratch request of `bytes`, trimming the buffers when it ends
+/// a decay window. Call it before borrowing any of them.
+pub(crate) fn request(bytes: usize) {
+    let mut decay = DECAY.with(Cell::get);
+    if decay.calls == 0 {
+        return;
+    }
+    decay.seen += 1;
+    decay.peak = decay.peak.max(bytes);
+    if decay.seen >= decay.calls {
+        trim_all(decay.peak);
+        decay.seen = 0;
+        decay.peak = 0;
+    }
+    DECAY.with(|cell| cell.set(decay));
+}
+
+/// Releases scratch and workspace memory past `max_bytes` per buffer and
+/// returns the bytes released. Scratch contents past the budget are lost.
+#[wasm_bindgen(js_name = trimScratch)]
+pub fn trim_scratch(max_bytes: u32) -> f64 {
+    trim_all(max_bytes as usize) as f64
+}
+
+/// Trims the buffers to the largest request every `calls` scratch
+/// requests; zero (the default) turns decay off.
+#[wasm_bindgen(js_name = setScratchDecay)]
+pub fn set_scratch_decay(calls: u32) {
+    DECAY.with(|cell| {
+        cell.set(Decay {
+            calls,
+            seen: 0,
+            peak: 0,
+        })
+    });
+}
+
+#[wasm_bindgen(js_name = scratchDecay)]
+pub fn scratch_decay() -> u32 {
+    DECAY.with(|cell| cell.get().calls)
+}
+
+/// The generation of `scratch`, moved on when its placement changed since
+/// the last look.
+fn generation(scratch: &Vec<u16>) -> u32 {
+    let placement = Placement {
+        address: scratch.as_ptr() as usize,
+        capacity: scratch.capacity(),
+        pages: memory_pages(),
+    };
+    GENERATION.with(|cell| {
+        let (mut generation, seen) = cell.get();
+        if seen != Some(placement) {
+            if seen.is_some() {
+                generation = generation.wrapping_add(1);
+            }
+            cell.set((generation, Some(placement)));
+        }
+        generation
+    })
+}
+
+/// A scratch view with the generation it was made in.
+#[wasm_bindgen]
+pub struct ScratchView {
+    len: usize,
+    token: u32,
+}
+
+#[wasm_bindgen]
+impl ScratchView {
+    /// A fresh view of the first `len` scratch entries, regrowing them if
+    /// they were trimmed away. Reading it again is how a stale view is
+    /// recovered; the scratch contents carry over.
+    #[wasm_bindgen(getter)]
+    pub fn array(&mut self) -> Result<js_sys::Uint16Array, KernelError> {
+        SCRATCH.with(|cell| {
+            let mut scratch = cell.borrow_mut();
+            grow(&mut scratch, self.len)?;
+            self.token = generation(&scratch);
+            Ok(unsafe { js_sys::Uint16Array::view(&scratch[..self.len]) })
+        })
+    }
+
+    /// Generation of the last view returned, for `isViewValid`.
+    #[wasm_bindgen(getter)]
+    pub fn token(&self) -> u32 {
+        self.token
+    }
+}
+
+/// `scratchBuffer(len)` with a generation token: read `array` for the view
+/// and check `token` with `isViewValid` before reusing it.
+#[wasm_bindgen(js_name = scratchView)]
+pub fn scratch_view(len: u32) -> Result<ScratchView, KernelError> {
+    request(len as usize * 2);
+    let mut view = ScratchView {
+        len: len as usize,
+        token: 0,
+    };
+    SCRATCH.with(|cell| {
+        let mut scratch = cell.borrow_mut();
+        grow(&mut scratch, view.len)?;
+        view.token = generation(&scratch);
+        Ok(view)
+    })
+}
+
+/// Whether views made in generation `token` still point at the scratch
+/// buffer: it has not moved and linear memory has not grown since.
+#[wasm_bindgen(js_name = isViewValid)]
+pub fn is_view_valid(token: u32) -> bool {
+    SCRATCH.with(|cell| generation(&cell.borrow()) == token)
+}
@@ -0,0 +1,192 @@
+//! Partial top-N selection.
+//!
+//! Finds the N rows with the largest (or smallest) values without sorting
+//! the whole column: a bounded min-heap of the best N seen so far when N is
+//! small next to the row count, and quickselect over every candidate when it
+//! is not. Only the N winners are sorted.
+//!
+//! Exact quantiles use the same idea: introselect (`select_nth_unstable`)
+//! places each requested order statistic without sorting the values, for
+//! when sketch-based approximations are not acceptable.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, bit, Column};
+use crate::error::{ErrorKind, KernelError};
+use crate::sort;
+
+/// Above this share of the candidates (1/`HEAP_LIMIT`), quickselect beats
+/// the heap's per-row `log N`.
+const HEAP_LIMIT: usize = 16;
+
+/// Sort rank of a row: higher is better; among equal values the earlier
+/// row wins.
+type Rank = (u64, Reverse<u32>);
+
+fn select(candidates: impl Iterator<Item = Rank>, count: usize, total: usize) -> Vec<u32> {
+    let mut best: Vec<Rank> = if count.saturating_mul(HEAP_LIMIT) >= total {
+        let mut all: Vec<Rank> = candidates.collect();
+        if count < all.len() {
+            all.select_nth_unstable_by(count, |a, b| b.cmp(a));
+            all.truncate(count);
+        }
+        all
+    } else {
+        let mut heap = BinaryHeap::with_capacity(count + 1);
+        for rank in candidates {
+            if heap.len() < count {
+                heap.push(Reverse(rank));
+            } else if heap.peek().is_some_and(|worst| rank > worst.0) {
+                heap.pop();
+                heap.push(Reverse(rank));
+            }
+        }
+        heap.into_iter().map(|Reverse(rank)| rank).collect()
+    };
+    best.sort_unstable_by(|a, b| b.cmp(a));
+    best.into_iter().map(|(_, Reverse(row))| row).collect()
+}
+
+fn check_numeric(column: &Column, kernel: &str) -> Result<(), KernelError> {
+    if !column.values.is_numeric() {
+        return Err(KernelError::new(
+            ErrorKind::Unsupported,
+            format!("{kernel} requires a numeric column"),
+        ));
+    }
+    Ok(())
+}
+
+fn check_mask(mask: Option<&[u8]>, len: usize) -> Result<(), KernelError> {
+    match mask {
+        Some(mask) if mask.len() < len.div_ceil(8) => {
+            Err(KernelError::invalid_argument("mask is too short")
+                .with("needed", len.div_ceil(8) as f64)
+                .with("available", mask.len() as f64))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Value of `row` if it is valid, not NaN and set in `mask`.
+fn candidate(column: &Column, mask: Option<&[u8]>, row: usize) -> Option<f64> {
+    if mask.is_some_and(|mask| !bit(mask, row)) || !column.is_valid(row) {
+        return None;
+    }
+    column.values.number(row).filter(|value| !value.is_nan())
+}
+
+/// Values of the numeric column behind `handle` at the rows set in `mask`,
+/// in row order, skipping nulls and NaNs.
+pub(crate) fn masked_values(
+    handle: u32,
+    mask: Option<&[u8]>,
+    kernel: &str,
+) -> Result<Vec<f64>, KernelError> {
+    columns::with_column(handle, |column| {
+        check_numeric(column, kernel)?;
+        check_mask(mask, column.len)?;
+        Ok((0..column.len)
+            .filter_map(|row| candidate(column, mask, row))
+            .collect())
+    })
+}
+
+pub(crate) fn check_quantiles(quantiles: &[f64]) -> Result<(), KernelError> {
+    if let Some(&quantile) = quantiles
+        .iter()
+        .find(|quantile| !(0.0..=1.0).contains(*quantile))
+    {
+        return Err(
+            KernelError::invalid_argument("quantiles must lie in [0, 1]")
+                .with("quantile", quantile),
+        );
+    }
+    Ok(())
+}
+
+/// Returns the rows of the `count` largest values of the numeric column
+/// behind `handle` (smallest when `largest` is false), best first; equal
+/// values keep row order. Nulls and NaNs are never selected. `mask`, when
+/// given, is an LSB-first row bitmap in the layout's `activeMask` format and
+/// restricts the selection to rows whose bit is set.
+#[wasm_bindgen(js_name = topRows)]
+pub fn top_rows(
+    handle: u32,
+    count: u32,
+    largest: bool,
+    mask: Option<Vec<u8>>,
+) -> Result<Vec<u32>, KernelError> {
+    columns::with_column(handle, |column| {
+        check_numeric(column, "topRows")?;
+        check_mask(mask.as_deref(), column.len)?;
+        let candidates = (0..column.len).filter_map(|row| {
+            let key = sort::f64_key(candidate(column, mask.as_deref(), row)?);
+            Some((if largest { key } else { !key }, Reverse(row as u32)))
+        });
+        Ok(select(candidates, count as usize, column.len))
+    })
+}
+
+/// Returns the exact `quantiles` (each in `[0, 1]`) of the numeric column
+/// behind `handle`, interpolating linearly between order statistics as
+/// `d3.quantile` does. Nulls and NaNs are skipped and `mask`, as for
+/// `topRows`, restricts the rows considered. Every quantile of an empty
+/// selection is NaN.
+#[wasm_bindgen(js_name = exactQuantiles)]
+pub fn exact_quantiles(
+    handle: u32,
+    quantiles: &[f64],
+    mask: Option<Vec<u8>>,
+) -> Result<Vec<f64>, KernelError> {
+    check_quantiles(quantiles)?;
+    let mut values = masked_values(handle, mask.as_deref(), "exactQuantiles")?;
+    let mut results = vec![f64::NAN; quantiles.len()];
+    if values.is_empty() {
+        return Ok(results);
+    }
+    // Ascending quantiles only need to search right of the previous rank.
+    let mut order: Vec<usize> = (0..quantiles.len()).collect();
+    order.sort_by(|&a, &b| quantiles[a].total_cmp(&quantiles[b]));
+    let last = values.len() - 1;
+    let mut from = 0;
+    for at in order {
+        let position = quantiles[at] * last as f64;
+        let rank = position.floor() as usize;
+        values[from..].select_nth_unstable_by(rank - from, f64::total_cmp);
+        from = rank;
+        let lower = values[rank];
+        let fraction = position - rank as f64;
+        results[at] = if fraction > 0.0 {
+            let upper = values[rank + 1..]
+                .iter()
+                .copied()
+                .fold(f64::INFINITY, f64::min);
+            lower + (upper - lower) * fraction
+        } else {
+            lower
+        };
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::{Column, Values};
+
+    #[test]
+    fn empty_numeric_columns_select_nothing() {
+        let empty = columns::register(Column {
+            name: "x".to_owned(),
+            len: 0,
+            values: Values::Int32(Vec::new()),
+            validity: None,
+        });
+        assert!(top_rows(empty, 3, true, None).unwrap().is_empty());
+        assert!(exact_quantiles(empty, &[0.5], None).unwrap()[0].is_nan());
+        assert!(crate::autocorrelation::autocorrelation(empty, 2, None).is_ok());
+    }
+}
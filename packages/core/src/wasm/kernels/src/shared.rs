@@ -0,0 +1,129 @@
+//! Results written into a `SharedArrayBuffer`.
+//!
+//! A worker running the kernels normally posts each result to the main
+//! thread, which copies it. The `Shared` variants instead write the counts
+//! into a caller-owned `SharedArrayBuffer` at a given byte offset, then
+//! publish them by incrementing an `Int32` sequence word in the same buffer
+//! with `Atomics.add` and waking its waiters with `Atomics.notify`. Atomics
+//! are sequentially consistent, so a reader that sees the new sequence
+//! (`Atomics.load`, `Atomics.wait` or `Atomics.waitAsync`) also sees the
+//! counts, without a message or a copy.
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+
+/// Checks that `bytes` bytes at `offset` fit `buffer_len` and are 4-byte
+/// aligned.
+fn check_region(
+    name: &'static str,
+    offset: u32,
+    bytes: usize,
+    buffer_len: u32,
+) -> Result<(), KernelError> {
+    if !offset.is_multiple_of(4) {
+        return Err(
+            KernelError::invalid_argument("shared region is not 4-byte aligned")
+                .with(name, f64::from(offset)),
+        );
+    }
+    // Unchecked, the end wraps on wasm32 and the JS view throws instead.
+    let end = (offset as usize).checked_add(bytes);
+    if end.is_none_or(|end| end > buffer_len as usize) {
+        return Err(
+            KernelError::invalid_argument("shared region past the buffer end")
+                .with(name, f64::from(offset))
+                .with("bytes", bytes as f64)
+                .with("bufferLength", f64::from(buffer_len)),
+        );
+    }
+    Ok(())
+}
+
+/// Copies `counts` to `byte_offset` and bumps the sequence word at
+/// `sequence_offset`, returning its new value.
+fn publish(
+    counts: &[u32],
+    target: &js_sys::SharedArrayBuffer,
+    byte_offset: u32,
+    sequence_offset: u32,
+) -> Result<i32, KernelError> {
+    let buffer_len = target.byte_length();
+    let bytes = counts.len() * 4;
+    check_region("byteOffset", byte_offset, bytes, buffer_len)?;
+    check_region("sequenceOffset", sequence_offset, 4, buffer_len)?;
+    let (start, end) = (byte_offset as usize, byte_offset as usize + bytes);
+    if (start..end).contains(&(sequence_offset as usize)) {
+        return Err(
+            KernelError::invalid_argument("sequence word overlaps the counts")
+                .with("byteOffset", f64::from(byte_offset))
+                .with("sequenceOffset", f64::from(sequence_offset)),
+        );
+    }
+    js_sys::Uint32Array::new_with_byte_offset_and_length(target, byte_offset, counts.len() as u32)
+        .copy_from(counts);
+    let sequence = js_sys::Int32Array::new_with_byte_offset_and_length(target, sequence_offset, 1);
+    let published = js_sys::Atomics::add(&sequence, 0, 1)
+        .and_then(|previous| js_sys::Atomics::notify(&sequence, 0).map(|_| previous))
+        .map_err(|_| KernelError::invalid_state("could not publish to the shared buffer"))?;
+    Ok(published.wrapping_add(1))
+}
+
+/// `accumulateBins` writing the counts into `target` at `byteOffset` and
+/// publishing them through the `Int32` at `sequenceOffset`. Returns the new
+/// sequence value.
+#[wasm_bindgen(js_name = accumulateBinsShared)]
+pub fn accumulate_bins_shared(
+    bins: &[u16],
+    bin_count: u32,
+    dimension: Option<u32>,
+    target: &js_sys::SharedArrayBuffer,
+    byte_offset: u32,
+    sequence_offset: u32,
+) -> Result<i32, KernelError> {
+    crate::accumulate_bins_with(
+        "accumulateBinsShared",
+        bins,
+        bin_count,
+        dimension,
+        |counts| {
+            publish(counts, target, byte_offset, sequence_offset)
+                .map_err(|error| error.in_kernel("accumulateBinsShared"))
+        },
+    )?
+}
+
+/// `accumulateScratch` counterpart of `accumulateBinsShared`.
+#[wasm_bindgen(js_name = accumulateScratchShared)]
+pub fn accumulate_scratch_shared(
+    len: u32,
+    bin_count: u32,
+    dimension: Option<u32>,
+    target: &js_sys::SharedArrayBuffer,
+    byte_offset: u32,
+    sequence_offset: u32,
+) -> Result<i32, KernelError> {
+    crate::accumulate_scratch_with(
+        "accumulateScratchShared",
+        len as usize,
+        bin_count,
+        dimension,
+        |counts| {
+            publish(counts, target, byte_offset, sequence_offset)
+                .map_err(|error| error.in_kernel("accumulateScratchShared"))
+        },
+    )?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_must_be_aligned_and_inside_the_buffer() {
+        assert!(check_region("byteOffset", 8, 8, 16).is_ok());
+        assert!(check_region("byteOffset", 6, 4, 16).is_err());
+        assert!(check_region("byteOffset", 12, 8, 16).is_err());
+        assert!(check_region("byteOffset", u32::MAX - 3, usize::MAX, u32::MAX).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! Radix sorting into permutations.
+//!
+//! Sorts return a permutation (row indices in sorted order) instead of
+//! reordering data, so one sort can back sorted dimension indexes, top-K
+//! queries and ordered detail tables. Keys are first mapped to unsigned
+//! integers whose order matches the source order, then sorted by a stable
+//! LSD radix sort with 8-bit digits. Passes whose digit is the same for
+//! every key are skipped, so narrow keys cost fewer passes.
+//!
+//! Multi-key sorts over store columns run one stable pass per key, last key
+//! first, so earlier keys take precedence and full ties keep input order.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, Column, Values};
+use crate::error::KernelError;
+
+const RADIX: usize = 256;
+
+/// Unsigned key sorted one 8-bit digit per pass, least significant first.
+pub(crate) trait RadixKey: Copy {
+    const DIGITS: usize;
+
+    fn digit(self, pass: usize) -> usize;
+}
+
+impl RadixKey for u32 {
+    const DIGITS: usize = 4;
+
+    fn digit(self, pass: usize) -> usize {
+        (self >> (pass * 8)) as usize & (RADIX - 1)
+    }
+}
+
+impl RadixKey for u64 {
+    const DIGITS: usize = 8;
+
+    fn digit(self, pass: usize) -> usize {
+        (self >> (pass * 8)) as usize & (RADIX - 1)
+    }
+}
+
+/// Order-preserving key for `i32`.
+pub(crate) fn i32_key(value: i32) -> u32 {
+    value as u32 ^ 0x8000_0000
+}
+
+/// Order-preserving key for `f32`: negatives flip entirely, positives flip
+/// the sign bit. NaNs map to the largest key.
+pub(crate) fn f32_key(value: f32) -> u32 {
+    if value.is_nan() {
+        return u32::MAX;
+    }
+    let bits = value.to_bits();
+    if bits & 0x8000_0000 != 0 {
+        !bits
+    } else {
+        bits | 0x8000_0000
+    }
+}
+
+/// Order-preserving key for `i64`.
+fn i64_key(value: i64) -> u64 {
+    value as u64 ^ 0x8000_0000_0000_0000
+}
+
+/// Order-preserving key for a non-NaN `f64`, as [`f32_key`].
+pub(crate) fn f64_key(value: f64) -> u64 {
+    let bits = value.to_bits();
+    if bits & 0x8000_0000_0000_0000 != 0 {
+        !bits
+    } else {
+        bits | 0x8000_0000_0000_0000
+    }
+}
+
+/// Stably sorts `order` (indices into `keys`) by ascending key.
+pub(crate) fn sort_permutation<K: RadixKey>(keys: &[K], order: &mut Vec<u32>) {
+    let len = order.len();
+    let mut histograms = vec![[0usize; RADIX]; K::DIGITS];
+    for &index in order.iter() {
+        let key = keys[index as usize];
+        for (pass, histogram) in histograms.iter_mut().enumerate() {
+            histogram[key.digit(pass)] += 1;
+        }
+    }
+    let mut scratch = vec![0u32; len];
+    for (pass, histogram) in histograms.iter_mut().enumerate() {
+        if histogram.contains(&len) {
+            continue;
+        }
+        let mut total = 0;
+        for slot in histogram.iter_mut() {
+            let count = *slot;
+            *slot = total;
+            total += count;
+        }
+        for &index in order.iter() {
+            let digit = keys[index as usize].digit(pass);
+            scratch[histogram[digit]] = index;
+            histogram[digit] += 1;
+        }
+        std::mem::swap(order, &mut scratch);
+    }
+}
+
+/// [`sort_permutation`] for dimension index builds, split across threads by
+/// a sample sort when built with the `threads` feature.
+pub(crate) fn sort_index_permutation(keys: &[u64], order: &mut Vec<u32>) {
+    #[cfg(feature = "threads")]
+    crate::sample_sort::sort_permutation(keys, order);
+    #[cfg(not(feature = "threads"))]
+    sort_permutation(keys, order);
+}
+
+/// Permutation sorting `values` by `key`; ties keep their input order, also
+/// when `descending`.
+fn argsort<T: Copy>(values: &[T], descending: bool, key: impl Fn(T) -> u32) -> Vec<u32> {
+    let keys: Vec<u32> = values
+        .iter()
+        .map(|&value| {
+            let key = key(value);
+            if descending {
+                !key
+            } else {
+                key
+            }
+        })
+        .collect();
+    let mut order = (0..values.len() as u32).collect();
+    sort_permutation(&keys, &mut order);
+    order
+}
+
+/// Returns the row indices of `keys` in sorted order (a stable sort, so
+/// equal keys keep their input order).
+#[wasm_bindgen(js_name = argsortU32)]
+pub fn argsort_u32(keys: &[u32], descending: bool) -> Vec<u32> {
+    argsort(keys, descending, |value| value)
+}
+
+/// `argsortU32` for signed keys.
+#[wasm_bindgen(js_name = argsortI32)]
+pub fn argsort_i32(keys: &[i32], descending: bool) -> Vec<u32> {
+    argsort(keys, descending, i32_key)
+}
+
+/// `argsortU32` for float keys. `-0` sorts before `0`; NaNs sort after
+/// every number (before them when `descending`).
+#[wasm_bindgen(js_name = argsortF32)]
+pub fn argsort_f32(keys: &[f32], descending: bool) -> Vec<u32> {
+    argsort(keys, descending, f32_key)
+}
+
+/// Dense ranks of `count` labels in byte order; equal labels share a rank.
+fn label_ranks<'a>(count: usize, label: impl Fn(usize) -> &'a [u8]) -> Vec<u64> {
+    let mut sorted: Vec<usize> = (0..count).collect();
+    sorted.sort_by(|&a, &b| label(a).cmp(label(b)));
+    let mut ranks = vec![0; count];
+    let mut rank = 0;
+    for (position, &index) in sorted.iter().enumerate() {
+        if position > 0 && label(sorted[position - 1]) != label(index) {
+            rank += 1;
+        }
+        ranks[index] = rank;
+    }
+    ranks
+}
+
+/// Stably orders `order` by one column, nulls and NaNs last in either
+/// direction (NaNs before nulls).
+pub(crate) fn sort_by_column(column: &Column, descending: bool, order: &mut Vec<u32>) {
+    const NAN: u32 = 1;
+    const NULL: u32 = 2;
+    let len = column.len;
+    // Rows sorted last share one key so they keep their relative order.
+    let mut classes: Option<Vec<u32>> = None;
+    let keys = |key: &dyn Fn(usize) -> u64| -> Vec<u64> {
+        (0..len)
+            .map(|row| match (column.is_valid(row), descending) {
+                (false, _) => 0,
+                (true, false) => key(row),
+                (true, true) => !key(row),
+            })
+            .collect()
+    };
+    match &column.values {
+        Values::Utf8 { .. } => {
+            let ranks = label_ranks(len, |row| column.values.label(row).unwrap_or_default());
+            sort_permutation(&keys(&|row| ranks[row]), order);
+        }
+        Values::Dictionary { indices, values } => {
+            let ranks = label_ranks(values.label_count(), |index| {
+                values.label(index).unwrap_or_default()
+            });
+            sort_permutation(&keys(&|row| ranks[indices[row] as usize]), order);
+        }
+        Values::Decimal128 { values, .. } => {
+            // Two passes: low word unsigned, then high word signed.
+            sort_permutation(&keys(&|row| values[row] as u64), order);
+            sort_permutation(&keys(&|row| i64_key((values[row] >> 64) as i64)), order);
+        }
+        Values::Int64(values) => sort_permutation(&keys(&|row| i64_key(values[row])), order),
+        Values::UInt64(values) => sort_permutation(&keys(&|row| values[row]), order),
+        values => {
+            let numbers: Vec<f64> = (0..len)
+                .map(|row| values.number(row).unwrap_or(f64::NAN))
+                .collect();
+            let key = |row: usize| match numbers[row] {
+                value if value.is_nan() => 0,
+                value => f64_key(value),
+            };
+            sort_permutation(&keys(&key), order);
+            if (0..len).any(|row| column.is_valid(row) && numbers[row].is_nan()) {
+                let nan = |value: &f64| if value.is_nan() { NAN } else { 0 };
+                classes = Some(numbers.iter().map(nan).collect());
+            }
+        }
+    }
+    if column.validity.is_some() {
+        let classes = classes.get_or_insert_with(|| vec![0; len]);
+        for (row, class) in classes.iter_mut().enumerate() {
+            if !column.is_valid(row) {
+                *class = NULL;
+            }
+        }
+    }
+    if let Some(classes) = classes {
+        sort_permutation(&classes, order);
+    }
+}
+
+/// Returns the row order that sorts the columns behind `handles`, which
+/// must have equal lengths, by the first column, then the second, and so on.
+/// `descending[i]` nonzero sorts key `i` in descending order (missing
+/// entries sort ascending). The sort is stable: rows equal on every key keep
+/// their input order. Strings compare by bytes; nulls sort last, after NaNs,
+/// in either direction.
+#[wasm_bindgen(js_name = sortColumns)]
+pub fn sort_columns(handles: &[u32], descending: &[u8]) -> Result<Vec<u32>, KernelError> {
+    let Some(&first) = handles.first() else {
+        return Err(KernelError::invalid_argument(
+            "sortColumns needs at least one key",
+        ));
+    };
+    let len = columns::with_column(first, |column| Ok(column.len))?;
+    let mut order = (0..len as u32).collect();
+    for (key, &handle) in handles.iter().enumerate().rev() {
+        columns::with_column(handle, |column| {
+            if column.len != len {
+                return Err(KernelError::invalid_argument("sort key lengths differ")
+                    .with("expected", len as f64)
+                    .with("actual", column.len as f64));
+            }
+            let descending = descending.get(key).is_some_and(|&flag| flag != 0);
+            sort_by_column(column, descending, &mut order);
+            Ok(())
+        })?;
+    }
+    Ok(order)
+}
@@ -0,0 +1,428 @@
+//! Per-dimension sorted indexes.
+//!
+//! The crossfilter-style index of a dimension: its values in ascending order
+//! next to the row each one came from. Built once from a store column and
+//! kept in wasm memory, it answers range filters with two binary searches
+//! and top/bottom queries by slicing either end. Nulls and NaNs are left out
+//! since no range can match them. Equal values keep row order.
+//!
+//! Appended rows are sorted on their own and merged in, rather than
+//! re-sorting the dimension. Batches too small to be worth a merge wait in a
+//! pending buffer until enough accumulate or the index is next read.
+//!
+//! A row mask (typically the rows passing every other dimension's filter)
+//! can be attached as a prefix count over index positions, so the masked
+//! rows inside a brush extent are counted with two binary searches and a
+//! subtraction rather than a scan.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, bit, Column};
+use crate::error::{ErrorKind, KernelError};
+use crate::recovery;
+use crate::{prefix, sort};
+
+/// Pending rows are merged once they reach `1/MERGE_FRACTION` of the index
+/// (but at least `MIN_MERGE` rows), keeping merge cost amortized.
+const MERGE_FRACTION: usize = 32;
+const MIN_MERGE: usize = 4096;
+
+pub(crate) struct SortedIndex {
+    pub(crate) values: Vec<f64>,
+    pub(crate) rows: Vec<u32>,
+    /// Appended `(value, row)` pairs not merged yet, in arrival order.
+    pending: Vec<(f64, u32)>,
+    /// Masked rows before each index position (`len + 1` entries).
+    active: Option<Vec<u32>>,
+}
+
+/// The indexable rows of `column` (non-null, non-NaN) sorted by value, as
+/// `(values, rows)` with rows numbered from `first_row`.
+fn sorted_batch(column: &Column, first_row: u32) -> Result<(Vec<f64>, Vec<u32>), KernelError> {
+    if !column.values.is_numeric() {
+        return Err(KernelError::new(
+            ErrorKind::Unsupported,
+            "sorted indexes require a numeric column",
+        ));
+    }
+    let mut numbers = Vec::with_capacity(column.len);
+    let mut order = Vec::with_capacity(column.len);
+    for row in 0..column.len {
+        let value = column.values.number(row).unwrap_or(f64::NAN);
+        if column.is_valid(row) && !value.is_nan() {
+            order.push(row as u32);
+        }
+        numbers.push(value);
+    }
+    let keys: Vec<u64> = numbers.iter().map(|&value| sort::f64_key(value)).collect();
+    sort::sort_index_permutation(&keys, &mut order);
+    let values = order.iter().map(|&row| numbers[row as usize]).collect();
+    let rows = order.iter().map(|&row| first_row + row).collect();
+    Ok((values, rows))
+}
+
+/// Positions `[start, end)` of the ascending `values` in `[lo, hi)`.
+pub(crate) fn value_range(values: &[f64], lo: f64, hi: f64) -> (usize, usize) {
+    let start = values.partition_point(|&value| value < lo);
+    let end = values.partition_point(|&value| value < hi).max(start);
+    (start, end)
+}
+
+impl SortedIndex {
+    /// Merges sorted `(values, rows)` in; on ties existing entries stay
+    /// first, which keeps row order since appended rows come later. An
+    /// attached mask carries over with the merged rows unmasked.
+    fn merge(&mut self, values: Vec<f64>, rows: Vec<u32>) {
+        let len = self.values.len() + values.len();
+        let mut merged_values = Vec::with_capacity(len);
+        let mut merged_rows = Vec::with_capacity(len);
+        let mut active = self.active.as_ref().map(|_| {
+            let mut active = Vec::with_capacity(len + 1);
+            active.push(0);
+            active
+        });
+        let (mut left, mut right) = (0, 0);
+        while left < self.values.len() || right < values.len() {
+            let masked = if left == self.values.len()
+                || (right < values.len() && values[right] < self.values[left])
+            {
+                merged_values.push(values[right]);
+                merged_rows.push(rows[right]);
+                right += 1;
+                0
+            } else {
+                merged_values.push(self.values[left]);
+                merged_rows.push(self.rows[left]);
+                left += 1;
+                self.active
+                    .as_ref()
+                    .map_or(0, |old| old[left] - old[left - 1])
+            };
+            if let Some(active) = &mut active {
+                active.push(active[active.len() - 1] + masked);
+            }
+        }
+        self.values = merged_values;
+        self.rows = merged_rows;
+        self.active = active;
+    }
+
+    /// Sorts and merges the pending rows.
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let keys: Vec<u64> = pending
+            .iter()
+            .map(|&(value, _)| sort::f64_key(value))
+            .collect();
+        let mut order = (0..pending.len() as u32).collect();
+        sort::sort_permutation(&keys, &mut order);
+        let values = order.iter().map(|&at| pending[at as usize].0).collect();
+        let rows = order.iter().map(|&at| pending[at as usize].1).collect();
+        self.merge(values, rows);
+    }
+
+    fn range(&self, lo: f64, hi: f64) -> (usize, usize) {
+        value_range(&self.values, lo, hi)
+    }
+
+    /// First position whose value is not `before` `query`, for each query;
+    /// NaN queries map past the end. Ascending query runs resume from the
+    /// previous answer, so a sorted batch only searches the shrinking tail.
+    fn bounds(&self, queries: &[f64], before: impl Fn(f64, f64) -> bool) -> Vec<u32> {
+        let mut previous = (f64::NEG_INFINITY, 0);
+        queries
+            .iter()
+            .map(|&query| {
+                if query.is_nan() {
+                    return self.values.len() as u32;
+                }
+                let from = if query >= previous.0 { previous.1 } else { 0 };
+                let position =
+                    from + self.values[from..].partition_point(|&value| before(value, query));
+                previous = (query, position);
+                position as u32
+            })
+            .collect()
+    }
+
+    fn clamp(&self, start: u32, end: u32) -> (usize, usize) {
+        let end = (end as usize).min(self.rows.len());
+        ((start as usize).min(end), end)
+    }
+}
+
+thread_local! {
+    static INDEXES: RefCell<HashMap<u32, SortedIndex>> = RefCell::new(HashMap::new());
+}
+
+/// Drops every sorted index; see `hardReset`.
+pub(crate) fn reset() {
+    INDEXES.with(|indexes| recovery::reset_cell(indexes, HashMap::new()));
+}
+
+/// Runs `read` against the index of `dimension`, merging pending rows
+/// first.
+pub(crate) fn with_index<T>(
+    dimension: u32,
+    read: impl FnOnce(&SortedIndex) -> Result<T, KernelError>,
+) -> Result<T, KernelError> {
+    with_index_mut(dimension, |index| {
+        index.flush();
+        read(index)
+    })
+}
+
+fn with_index_mut<T>(
+    dimension: u32,
+    update: impl FnOnce(&mut SortedIndex) -> Result<T, KernelError>,
+) -> Result<T, KernelError> {
+    INDEXES.with(|indexes| {
+        let mut indexes = indexes.borrow_mut();
+        let index = indexes.get_mut(&dimension).ok_or_else(|| {
+            KernelError::invalid_state("no sorted index for dimension")
+                .with("dimension", f64::from(dimension))
+        })?;
+        update(index)
+    })
+}
+
+/// Builds (or rebuilds) the sorted index of `dimension` from the numeric
+/// column behind `handle`. Returns the number of indexed rows.
+#[wasm_bindgen(js_name = buildSortedIndex)]
+pub fn build_sorted_index(dimension: u32, handle: u32) -> Result<u32, KernelError> {
+    let (values, rows) = columns::with_column(handle, |column| sorted_batch(column, 0))?;
+    let len = rows.len() as u32;
+    let index = SortedIndex {
+        values,
+        rows,
+        pending: Vec::new(),
+        active: None,
+    };
+    INDEXES.with(|indexes| indexes.borrow_mut().insert(dimension, index));
+    Ok(len)
+}
+
+/// Adds the rows of the column behind `handle`, which were appended to the
+/// dataset starting at row `firstRow`, to the sorted index of `dimension`.
+/// The batch is sorted on its own and merged; small batches are deferred
+/// until enough accumulate or the index is next queried. Returns the number
+/// of indexed rows, pending ones included.
+#[wasm_bindgen(js_name = appendSortedIndex)]
+pub fn append_sorted_index(
+    dimension: u32,
+    handle: u32,
+    first_row: u32,
+) -> Result<u32, KernelError> {
+    with_index_mut(dimension, |index| {
+        let threshold = (index.rows.len() / MERGE_FRACTION).max(MIN_MERGE);
+        columns::with_column(handle, |column| {
+            if index.pending.len() + column.len < threshold {
+                let (values, rows) = sorted_batch(column, first_row)?;
+                index.pending.extend(values.into_iter().zip(rows));
+            } else {
+                index.flush();
+                let (values, rows) = sorted_batch(column, first_row)?;
+                index.merge(values, rows);
+            }
+            Ok(())
+        })?;
+        Ok((index.rows.len() + index.pending.len()) as u32)
+    })
+}
+
+/// Attaches `mask`, an LSB-first row bitmap in the layout's `activeMask`
+/// format, to the index of `dimension` for `sortedIndexCount`; `None`
+/// detaches it. Rows appended afterwards count as unmasked until the mask
+/// is set again, since their bits are not known yet.
+#[wasm_bindgen(js_name = setSortedIndexMask)]
+pub fn set_sorted_index_mask(dimension: u32, mask: Option<Vec<u8>>) -> Result<(), KernelError> {
+    with_index_mut(dimension, |index| {
+        index.flush();
+        index.active = match mask {
+            Some(mask) => {
+                if let Some(&row) = index
+                    .rows
+                    .iter()
+                    .find(|&&row| row as usize >= mask.len() * 8)
+                {
+                    return Err(KernelError::invalid_argument("mask is too short")
+                        .with("row", f64::from(row))
+                        .with("available", mask.len() as f64));
+                }
+                let bits = index
+                    .rows
+                    .iter()
+                    .map(|&row| u32::from(bit(&mask, row as usize)));
+                Some(prefix::scan(bits.chain([0]), false)?)
+            }
+            None => None,
+        };
+        Ok(())
+    })
+}
+
+/// Number of indexed rows with values in `[lo, hi)`, counting only rows set
+/// in the mask from `setSortedIndexMask` when one is attached. Costs two
+/// binary searches either way.
+#[wasm_bindgen(js_name = sortedIndexCount)]
+pub fn sorted_index_count(dimension: u32, lo: f64, hi: f64) -> Result<u32, KernelError> {
+    with_index(dimension, |index| {
+        let (start, end) = index.range(lo, hi);
+        Ok(match &index.active {
+            Some(active) => active[end] - active[start],
+            None => (end - start) as u32,
+        })
+    })
+}
+
+/// Drops the sorted index of `dimension`, or every index when omitted.
+#[wasm_bindgen(js_name = releaseSortedIndex)]
+pub fn release_sorted_index(dimension: Option<u32>) {
+    INDEXES.with(|indexes| match dimension {
+        Some(dimension) => {
+            indexes.borrow_mut().remove(&dimension);
+        }
+        None => indexes.borrow_mut().clear(),
+    });
+}
+
+/// Number of indexed (non-null, non-NaN) rows.
+#[wasm_bindgen(js_name = sortedIndexLength)]
+pub fn sorted_index_length(dimension: u32) -> Result<u32, KernelError> {
+    with_index(dimension, |index| Ok(index.rows.len() as u32))
+}
+
+/// Index positions `[start, end)` holding values in `[lo, hi)`, as a
+/// two-element array; the matching rows are `sortedIndexRows(start, end)`.
+#[wasm_bindgen(js_name = sortedIndexRange)]
+pub fn sorted_index_range(dimension: u32, lo: f64, hi: f64) -> Result<Vec<u32>, KernelError> {
+    with_index(dimension, |index| {
+        let (start, end) = index.range(lo, hi);
+        Ok(vec![start as u32, end as u32])
+    })
+}
+
+/// For each query, the first index position whose value is `>= query`
+/// (`sortedIndexLength` when none is), like C++ `lower_bound`. Pairs with
+/// `sortedIndexUpperBound` to turn brush extents into position ranges, and
+/// with `sortedIndexValues` for quantile lookups. NaN queries map past the
+/// end.
+#[wasm_bindgen(js_name = sortedIndexLowerBound)]
+pub fn sorted_index_lower_bound(dimension: u32, queries: &[f64]) -> Result<Vec<u32>, KernelError> {
+    with_index(dimension, |index| {
+        Ok(index.bounds(queries, |value, query| value < query))
+    })
+}
+
+/// For each query, the first index position whose value is `> query`, like
+/// C++ `upper_bound`.
+#[wasm_bindgen(js_name = sortedIndexUpperBound)]
+pub fn sorted_index_upper_bound(dimension: u32, queries: &[f64]) -> Result<Vec<u32>, KernelError> {
+    with_index(dimension, |index| {
+        Ok(index.bounds(queries, |value, query| value <= query))
+    })
+}
+
+/// Source rows at index positions `[start, end)`, clamped to the index.
+#[wasm_bindgen(js_name = sortedIndexRows)]
+pub fn sorted_index_rows(dimension: u32, start: u32, end: u32) -> Result<Vec<u32>, KernelError> {
+    with_index(dimension, |index| {
+        let (start, end) = index.clamp(start, end);
+        Ok(index.rows[start..end].to_vec())
+    })
+}
+
+/// Values at index positions `[start, end)`, clamped to the index.
+#[wasm_bindgen(js_name = sortedIndexValues)]
+pub fn sorted_index_values(dimension: u32, start: u32, end: u32) -> Result<Vec<f64>, KernelError> {
+    with_index(dimension, |index| {
+        let (start, end) = index.clamp(start, end);
+        Ok(index.values[start..end].to_vec())
+    })
+}
+
+/// Rows of the `count` largest values, largest first (ties: later rows
+/// first, as crossfilter's `top`).
+#[wasm_bindgen(js_name = sortedIndexTop)]
+pub fn sorted_index_top(dimension: u32, count: u32) -> Result<Vec<u32>, KernelError> {
+    with_index(dimension, |index| {
+        let take = (count as usize).min(index.rows.len());
+        Ok(index.rows.iter().rev().take(take).copied().collect())
+    })
+}
+
+/// Rows of the `count` smallest values, smallest first.
+#[wasm_bindgen(js_name = sortedIndexBottom)]
+pub fn sorted_index_bottom(dimension: u32, count: u32) -> Result<Vec<u32>, KernelError> {
+    with_index(dimension, |index| {
+        let take = (count as usize).min(index.rows.len());
+        Ok(index.rows[..take].to_vec())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::floats;
+    use crate::columns::Values;
+
+    #[test]
+    fn mask_survives_pending_appends() {
+        build_sorted_index(1, floats(vec![3.0, 1.0, f64::NAN, 2.0], None)).unwrap();
+        // Rows 0 and 3: values 3 and 2.
+        set_sorted_index_mask(1, Some(vec![0b1001])).unwrap();
+        assert_eq!(sorted_index_count(1, 0.0, 10.0).unwrap(), 2);
+        assert_eq!(
+            append_sorted_index(1, floats(vec![2.5, 0.5], None), 4).unwrap(),
+            5
+        );
+        assert_eq!(sorted_index_count(1, 0.0, 10.0).unwrap(), 2);
+        assert_eq!(sorted_index_count(1, 2.0, 3.0).unwrap(), 1);
+        assert_eq!(sorted_index_length(1).unwrap(), 5);
+        assert_eq!(sorted_index_rows(1, 0, 5).unwrap(), [5, 1, 3, 4, 0]);
+        // Setting the mask again covers the appended rows.
+        set_sorted_index_mask(1, Some(vec![0b0011_0000])).unwrap();
+        assert_eq!(sorted_index_count(1, 0.0, 10.0).unwrap(), 2);
+        set_sorted_index_mask(1, None).unwrap();
+        assert_eq!(sorted_index_count(1, 0.0, 10.0).unwrap(), 5);
+    }
+
+    #[test]
+    fn mask_survives_merged_appends() {
+        build_sorted_index(2, floats(vec![4.0, 2.0], None)).unwrap();
+        set_sorted_index_mask(2, Some(vec![0b01])).unwrap();
+        // Large enough to merge straight away.
+        let appended: Vec<f64> = (0..MIN_MERGE).map(|row| row as f64).collect();
+        append_sorted_index(2, floats(appended, None), 2).unwrap();
+        assert_eq!(sorted_index_count(2, 0.0, 1e9).unwrap(), 1);
+        assert_eq!(sorted_index_count(2, 0.0, 4.0).unwrap(), 0);
+        assert_eq!(sorted_index_count(2, 4.0, 5.0).unwrap(), 1);
+        assert_eq!(sorted_index_length(2).unwrap(), MIN_MERGE as u32 + 2);
+    }
+
+    #[test]
+    fn empty_columns_index_no_rows() {
+        build_sorted_index(3, floats(Vec::new(), None)).unwrap();
+        assert_eq!(sorted_index_length(3).unwrap(), 0);
+        assert_eq!(
+            sorted_index_count(3, f64::NEG_INFINITY, f64::INFINITY).unwrap(),
+            0
+        );
+        assert!(sorted_index_top(3, 5).unwrap().is_empty());
+        let strings = columns::register(Column {
+            name: "s".to_owned(),
+            len: 0,
+            values: Values::Utf8 {
+                offsets: vec![0],
+                data: Vec::new(),
+            },
+            validity: None,
+        });
+        assert!(build_sorted_index(4, strings).is_err());
+    }
+}
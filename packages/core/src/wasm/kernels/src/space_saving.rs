@@ -0,0 +1,117 @@
+//! SpaceSaving heavy hitters.
+//!
+//! Top keys of an ultra-high-cardinality column in a fixed number of
+//! counters, instead of a full group-by. Each monitored key has a count; an
+//! unmonitored key takes over the smallest counter and inherits its count
+//! as its error, so a key's true count lies in `[count - error, count]`.
+//! With `capacity` counters every key occurring more than
+//! `total / capacity` times is guaranteed to be monitored, and the counts of
+//! frequent keys are exact or nearly so.
+
+use std::collections::{BTreeSet, HashMap};
+use wasm_bindgen::prelude::*;
+
+use crate::count_min::HeavyHitters;
+use crate::error::KernelError;
+use crate::hash;
+
+struct Counter {
+    /// Row the key was last taken from.
+    row: u32,
+    hash: u64,
+    count: u32,
+    error: u32,
+}
+
+/// The keys of the column behind `handle` over the rows set in `mask` (an
+/// LSB-first row bitmap in the layout's `activeMask` format such as
+/// `filterMask()`; every row when omitted), tracked with `capacity`
+/// counters: up to `capacity` keys, largest estimated count first, each
+/// with the most its count may overstate.
+#[wasm_bindgen(js_name = spaceSaving)]
+pub fn space_saving(
+    handle: u32,
+    capacity: u32,
+    mask: Option<Vec<u8>>,
+) -> Result<HeavyHitters, KernelError> {
+    if capacity == 0 {
+        return Err(KernelError::invalid_argument(
+            "spaceSaving needs at least one counter",
+        ));
+    }
+    let capacity = capacity as usize;
+    let hashes = hash::row_hashes(handle, mask.as_deref())?;
+    // No more counters than rows can fill, whatever `capacity` asks for.
+    let counters_needed = capacity.min(hashes.len());
+    let mut counters: Vec<Counter> = Vec::with_capacity(counters_needed);
+    let mut slots: HashMap<u64, usize> = HashMap::with_capacity(counters_needed);
+    // `(count, slot)` of every counter, smallest first.
+    let mut order: BTreeSet<(u32, usize)> = BTreeSet::new();
+    for (row, hash) in hashes {
+        let slot = match slots.get(&hash) {
+            Some(&slot) => slot,
+            None if counters.len() < capacity => {
+                counters.push(Counter {
+                    row,
+                    hash,
+                    count: 0,
+                    error: 0,
+                });
+                slots.insert(hash, counters.len() - 1);
+                order.insert((0, counters.len() - 1));
+                counters.len() - 1
+            }
+            None => {
+                let (count, slot) = *order.first().expect("counters are full");
+                let counter = &mut counters[slot];
+                slots.remove(&counter.hash);
+                slots.insert(hash, slot);
+                *counter = Counter {
+                    row,
+                    hash,
+                    count,
+                    error: count,
+                };
+                slot
+            }
+        };
+        let counter = &mut counters[slot];
+        order.remove(&(counter.count, slot));
+        counter.count += 1;
+        order.insert((counter.count, slot));
+    }
+    let ranked: Vec<&Counter> = order
+        .iter()
+        .rev()
+        .map(|&(_, slot)| &counters[slot])
+        .collect();
+    Ok(HeavyHitters {
+        rows: ranked.iter().map(|counter| counter.row).collect(),
+        counts: ranked.iter().map(|counter| counter.count).collect(),
+        errors: ranked.iter().map(|counter| counter.error).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::skewed_keys as keys;
+
+    #[test]
+    fn frequent_keys_come_first_within_their_error() {
+        let hitters = space_saving(keys(), 8, None).unwrap();
+        assert_eq!(hitters.rows().len(), 8);
+        assert_eq!(hitters.rows()[0], 0);
+        let (count, error) = (hitters.counts()[0], hitters.errors()[0]);
+        assert!(count >= 50 && count - error <= 50);
+    }
+
+    #[test]
+    fn oversized_capacities_allocate_only_what_the_rows_need() {
+        let hitters = space_saving(keys(), u32::MAX, None).unwrap();
+        assert_eq!(hitters.rows().len(), 51);
+        assert_eq!(hitters.counts()[0], 50);
+        assert!(hitters.errors().iter().all(|&error| error == 0));
+        assert!(space_saving(keys(), 0, None).is_err());
+    }
+}
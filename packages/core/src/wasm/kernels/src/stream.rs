@@ -0,0 +1,217 @@
+//! Histograms over row streams longer than `u32` counts allow.
+//!
+//! Every kernel call takes at most `u32::MAX` rows, and a wasm32 heap could
+//! not hold more, but a streamed dataset of several billion rows passes
+//! through chunk by chunk. A row stream accumulates its chunks into 64-bit
+//! counts and tracks the logical offset of each chunk as a 64-bit row
+//! number, so counts, row totals and dropped-row positions stay exact end to
+//! end. Offsets and counts reach JS as `number`s, exact up to 2^53 rows, and
+//! counts also as 64-bit integers. While a chunk is pushed, metrics report
+//! its logical offset as `rowOffset`, which turns the call-relative indices
+//! of `droppedSamples` into stream rows.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::{bigint, metrics, recovery};
+
+struct RowStream {
+    bin_count: u32,
+    dimension: Option<u32>,
+    counts: Vec<u64>,
+    rows: u64,
+    dropped: u64,
+    chunks: u64,
+}
+
+thread_local! {
+    static STREAMS: RefCell<HashMap<u32, RowStream>> = RefCell::new(HashMap::new());
+    static NEXT_STREAM: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Drops every row stream; see `hardReset`.
+pub(crate) fn reset() {
+    STREAMS.with(|streams| recovery::reset_cell(streams, HashMap::new()));
+    NEXT_STREAM.with(|next| next.set(0));
+}
+
+fn unknown_stream(stream: u32) -> KernelError {
+    KernelError::invalid_argument("unknown row stream").with("stream", f64::from(stream))
+}
+
+/// Totals of a row stream.
+#[wasm_bindgen]
+pub struct StreamCounts {
+    counts: Vec<u64>,
+    rows: u64,
+    dropped: u64,
+    chunks: u64,
+}
+
+#[wasm_bindgen]
+impl StreamCounts {
+    /// Rows per bin.
+    #[wasm_bindgen(getter)]
+    pub fn counts(&self) -> Vec<f64> {
+        self.counts.iter().map(|&count| count as f64).collect()
+    }
+
+    /// Rows per bin as a `BigUint64Array`.
+    #[wasm_bindgen(getter = bigCounts)]
+    pub fn big_counts(&self) -> Vec<u64> {
+        self.counts.clone()
+    }
+
+    /// High words of `bigCounts`, for engines without BigInt arrays.
+    #[wasm_bindgen(getter = countsHigh)]
+    pub fn counts_high(&self) -> Vec<u32> {
+        bigint::high_words(&self.counts)
+    }
+
+    /// Low words of `bigCounts`.
+    #[wasm_bindgen(getter = countsLow)]
+    pub fn counts_low(&self) -> Vec<u32> {
+        bigint::low_words(&self.counts)
+    }
+
+    /// Rows pushed, dropped ones included.
+    #[wasm_bindgen(getter)]
+    pub fn rows(&self) -> f64 {
+        self.rows as f64
+    }
+
+    /// Rows skipped because their bin was out of range.
+    #[wasm_bindgen(getter = droppedRows)]
+    pub fn dropped_rows(&self) -> f64 {
+        self.dropped as f64
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn chunks(&self) -> f64 {
+        self.chunks as f64
+    }
+}
+
+/// Starts a row stream over `binCount` bins; `dimension` keys the
+/// accumulation as in `accumulateBins`. Returns the stream id.
+#[wasm_bindgen(js_name = beginRowStream)]
+pub fn begin_row_stream(bin_count: u32, dimension: Option<u32>) -> Result<u32, KernelError> {
+    if bin_count == 0 || bin_count > u32::from(u16::MAX) + 1 {
+        return Err(KernelError::bad_bin_count(bin_count));
+    }
+    let stream = NEXT_STREAM.with(|next| {
+        let stream = next.get().wrapping_add(1).max(1);
+        next.set(stream);
+        stream
+    });
+    STREAMS.with(|streams| {
+        streams.borrow_mut().insert(
+            stream,
+            RowStream {
+                bin_count,
+                dimension,
+                counts: vec![0; bin_count as usize],
+                rows: 0,
+                dropped: 0,
+                chunks: 0,
+            },
+        )
+    });
+    Ok(stream)
+}
+
+/// Accumulates the next chunk of bin indices into the stream and returns
+/// the logical row number of its first row.
+#[wasm_bindgen(js_name = pushRowChunk)]
+pub fn push_row_chunk(stream: u32, bins: &[u16]) -> Result<f64, KernelError> {
+    STREAMS.with(|streams| {
+        let mut streams = streams.borrow_mut();
+        let state = streams
+            .get_mut(&stream)
+            .ok_or_else(|| unknown_stream(stream))?;
+        let counted = crate::accumulate_bins_with(
+            "pushRowChunk",
+            bins,
+            state.bin_count,
+            state.dimension,
+            |counts| {
+                let mut counted = 0;
+                for (total, &count) in state.counts.iter_mut().zip(counts) {
+                    *total += u64::from(count);
+                    counted += u64::from(count);
+                }
+                counted
+            },
+        )?;
+        let offset = state.rows;
+        metrics::record(|metrics| metrics.row_offset = offset);
+        state.rows += bins.len() as u64;
+        state.dropped += bins.len() as u64 - counted;
+        state.chunks += 1;
+        Ok(offset as f64)
+    })
+}
+
+/// The stream's totals so far; the stream stays open.
+#[wasm_bindgen(js_name = rowStreamCounts)]
+pub fn row_stream_counts(stream: u32) -> Result<StreamCounts, KernelError> {
+    STREAMS.with(|streams| {
+        let streams = streams.borrow();
+        let state = streams.get(&stream).ok_or_else(|| unknown_stream(stream))?;
+        Ok(StreamCounts {
+            counts: state.counts.clone(),
+            rows: state.rows,
+            dropped: state.dropped,
+            chunks: state.chunks,
+        })
+    })
+}
+
+/// Drops the stream, or every stream when omitted. Unknown ids are
+/// ignored.
+#[wasm_bindgen(js_name = releaseRowStream)]
+pub fn release_row_stream(stream: Option<u32>) {
+    STREAMS.with(|streams| {
+        let mut streams = streams.borrow_mut();
+        match stream {
+            Some(stream) => {
+                streams.remove(&stream);
+            }
+            None => streams.clear(),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn bin_counts_beyond_u16_bins_are_rejected() {
+        for bin_count in [0, u32::from(u16::MAX) + 2, u32::MAX] {
+            let error = begin_row_stream(bin_count, None).unwrap_err();
+            assert_eq!(error.code(), ErrorKind::BadBinCount as u32);
+        }
+        let stream = begin_row_stream(u32::from(u16::MAX) + 1, None).unwrap();
+        assert_eq!(row_stream_counts(stream).unwrap().counts().len(), 65_536);
+        release_row_stream(Some(stream));
+    }
+
+    #[test]
+    fn chunks_report_their_row_offsets() {
+        let stream = begin_row_stream(4, None).unwrap();
+        assert_eq!(push_row_chunk(stream, &[0, 1, 1]).unwrap(), 0.0);
+        assert_eq!(push_row_chunk(stream, &[3, 7]).unwrap(), 3.0);
+        let counts = row_stream_counts(stream).unwrap();
+        assert_eq!(counts.counts(), [1.0, 2.0, 0.0, 1.0]);
+        assert_eq!(
+            (counts.rows(), counts.dropped_rows(), counts.chunks()),
+            (5.0, 1.0, 2.0)
+        );
+        release_row_stream(None);
+        assert!(row_stream_counts(stream).is_err());
+    }
+}
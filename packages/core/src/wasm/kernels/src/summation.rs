@@ -0,0 +1,144 @@
+//! Float summation modes.
+//!
+//! Plain `f64` sums round at every step, so the same rows summed in another
+//! order (a different chunking, rows filtered in and out along another
+//! path) can differ in the last bits, and snapshot tests comparing sums
+//! across devices flake. `Summation::Compensated` switches the float
+//! aggregation kernels to Neumaier's compensated summation: each sum
+//! carries the rounding error of its additions in a second term, so the
+//! result is the correctly rounded sum in all but contrived cases whatever
+//! the order, at the cost of a few more operations per row.
+//!
+//! Stateful aggregates (reducers, `groupAllValue` totals) keep the mode
+//! they were built under; the others read it per call.
+
+use std::cell::Cell;
+use wasm_bindgen::prelude::*;
+
+/// How float aggregation kernels add.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Summation {
+    /// One rounding per addition; fastest.
+    Naive = 0,
+    /// Neumaier compensated summation; order-independent in practice.
+    Compensated = 1,
+}
+
+thread_local! {
+    static MODE: Cell<Summation> = const { Cell::new(Summation::Naive) };
+}
+
+/// Restores naive summation; see `hardReset`.
+pub(crate) fn reset() {
+    MODE.with(|cell| cell.set(Summation::Naive));
+}
+
+/// Sets the summation mode of float aggregations from now on.
+#[wasm_bindgen(js_name = setSummation)]
+pub fn set_summation(mode: Summation) {
+    MODE.with(|cell| cell.set(mode));
+}
+
+#[wasm_bindgen(js_name = summation)]
+pub fn summation() -> Summation {
+    MODE.with(Cell::get)
+}
+
+/// Adds `value` to `total`, collecting the rounding error in `error`.
+#[inline]
+fn neumaier(total: &mut f64, error: &mut f64, value: f64) {
+    let sum = *total + value;
+    *error += if total.abs() >= value.abs() {
+        (*total - sum) + value
+    } else {
+        (value - sum) + *total
+    };
+    *total = sum;
+}
+
+/// One running sum in the mode current when it was created.
+#[derive(Clone, Copy)]
+pub(crate) struct Sum {
+    total: f64,
+    error: f64,
+    compensated: bool,
+}
+
+impl Sum {
+    pub(crate) fn new() -> Self {
+        Sum {
+            total: 0.0,
+            error: 0.0,
+            compensated: summation() == Summation::Compensated,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn add(&mut self, value: f64) {
+        if self.compensated {
+            neumaier(&mut self.total, &mut self.error, value);
+        } else {
+            self.total += value;
+        }
+    }
+
+    pub(crate) fn value(&self) -> f64 {
+        self.total + self.error
+    }
+}
+
+/// Running sums per bin or group in the mode current when they were
+/// created.
+pub(crate) struct Sums {
+    totals: Vec<f64>,
+    /// Rounding error per sum; empty when naive.
+    errors: Vec<f64>,
+    compensated: bool,
+}
+
+impl Sums {
+    pub(crate) fn new(len: usize) -> Self {
+        let compensated = summation() == Summation::Compensated;
+        Sums {
+            totals: vec![0.0; len],
+            errors: if compensated {
+                vec![0.0; len]
+            } else {
+                Vec::new()
+            },
+            compensated,
+        }
+    }
+
+    /// Appends a zero sum.
+    pub(crate) fn push(&mut self) {
+        self.totals.push(0.0);
+        if self.compensated {
+            self.errors.push(0.0);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn add(&mut self, index: usize, value: f64) {
+        if self.compensated {
+            neumaier(&mut self.totals[index], &mut self.errors[index], value);
+        } else {
+            self.totals[index] += value;
+        }
+    }
+
+    pub(crate) fn get(&self, index: usize) -> f64 {
+        if self.compensated {
+            self.totals[index] + self.errors[index]
+        } else {
+            self.totals[index]
+        }
+    }
+
+    pub(crate) fn to_vec(&self) -> Vec<f64> {
+        (0..self.totals.len())
+            .map(|index| self.get(index))
+            .collect()
+    }
+}
@@ -0,0 +1,265 @@
+//! t-digest quantile sketches.
+//!
+//! Percentile readouts over the filtered rows without sorting them: a
+//! t-digest summarizes the values as weighted centroids, kept small in the
+//! middle of the distribution and tiny at the tails, so extreme percentiles
+//! (p99, p99.9) stay accurate. This is the merging variant with the `k1`
+//! (arcsine) scale function: values are buffered and folded into the
+//! centroid list in sorted batches, and `compression` bounds the centroid
+//! count at roughly `compression / 2`.
+//!
+//! Digests serialize as little-endian `f64`s: compression, total weight,
+//! minimum and maximum, then `(mean, weight)` per centroid in ascending
+//! order. Merging two digests re-compresses the union of their centroids,
+//! so digests built per worker or per chunk combine.
+
+use std::f64::consts::PI;
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::select::{check_quantiles, masked_values};
+
+const DEFAULT_COMPRESSION: f64 = 100.0;
+/// Values buffered per compression unit before a merge pass.
+const BUFFER_FACTOR: f64 = 5.0;
+const HEADER: usize = 4;
+
+struct Digest {
+    compression: f64,
+    /// `(mean, weight)`, ascending by mean.
+    centroids: Vec<(f64, f64)>,
+    total: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Digest {
+    fn new(compression: f64) -> Self {
+        Digest {
+            compression,
+            centroids: Vec::new(),
+            total: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Folds `incoming` centroids (any order) into the digest.
+    fn merge(&mut self, mut incoming: Vec<(f64, f64)>) {
+        if incoming.is_empty() {
+            return;
+        }
+        for &(mean, weight) in &incoming {
+            self.min = self.min.min(mean);
+            self.max = self.max.max(mean);
+            self.total += weight;
+        }
+        incoming.append(&mut self.centroids);
+        incoming.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        let scale = self.compression / (2.0 * PI);
+        let k = |q: f64| scale * (2.0 * q - 1.0).asin();
+        let k_inverse = |k: f64| ((k / scale).min(PI / 2.0).sin() + 1.0) / 2.0;
+        let mut merged = Vec::with_capacity(incoming.len().min(self.compression as usize + 1));
+        let mut before = 0.0;
+        let mut limit = k_inverse(k(0.0) + 1.0) * self.total;
+        let mut current = incoming[0];
+        for &(mean, weight) in &incoming[1..] {
+            if before + current.1 + weight <= limit {
+                let combined = current.1 + weight;
+                current.0 += (mean - current.0) * weight / combined;
+                current.1 = combined;
+            } else {
+                before += current.1;
+                merged.push(current);
+                limit = k_inverse(k(before / self.total) + 1.0) * self.total;
+                current = (mean, weight);
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    fn quantile(&self, q: f64) -> f64 {
+        let Some(&(first, first_weight)) = self.centroids.first() else {
+            return f64::NAN;
+        };
+        let target = q * self.total;
+        // Piecewise linear through (0, min), each centroid's center and
+        // (total, max).
+        let (mut left_at, mut left_value) = (0.0, self.min);
+        let mut at = first_weight / 2.0;
+        if target <= at {
+            return interpolate(left_at, left_value, at, first, target);
+        }
+        for pair in self.centroids.windows(2) {
+            let ((mean, weight), (next_mean, next_weight)) = (pair[0], pair[1]);
+            (left_at, left_value) = (at, mean);
+            at += (weight + next_weight) / 2.0;
+            if target <= at {
+                return interpolate(left_at, left_value, at, next_mean, target);
+            }
+        }
+        interpolate(
+            at,
+            self.centroids[self.centroids.len() - 1].0,
+            self.total,
+            self.max,
+            target,
+        )
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let header = [self.compression, self.total, self.min, self.max];
+        let centroids = self
+            .centroids
+            .iter()
+            .flat_map(|&(mean, weight)| [mean, weight]);
+        header
+            .into_iter()
+            .chain(centroids)
+            .flat_map(f64::to_le_bytes)
+            .collect()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, KernelError> {
+        if bytes.len() < HEADER * 8 || !bytes.len().is_multiple_of(16) {
+            return Err(
+                KernelError::invalid_argument("t-digest has an invalid length")
+                    .with("length", bytes.len() as f64),
+            );
+        }
+        let numbers: Vec<f64> = bytes
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().expect("8-byte chunk")))
+            .collect();
+        check_compression(numbers[0])?;
+        Ok(Digest {
+            compression: numbers[0],
+            total: numbers[1],
+            min: numbers[2],
+            max: numbers[3],
+            centroids: numbers[HEADER..]
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .collect(),
+        })
+    }
+}
+
+/// Value at `target` on the line from `(from, low)` to `(to, high)`.
+fn interpolate(from: f64, low: f64, to: f64, high: f64, target: f64) -> f64 {
+    if to <= from {
+        return high;
+    }
+    low + (high - low) * ((target - from) / (to - from)).clamp(0.0, 1.0)
+}
+
+fn check_compression(compression: f64) -> Result<(), KernelError> {
+    if !(10.0..=10_000.0).contains(&compression) {
+        return Err(
+            KernelError::invalid_argument("compression must be between 10 and 10000")
+                .with("compression", compression),
+        );
+    }
+    Ok(())
+}
+
+/// Serialized t-digest of the numeric column behind `handle` over the rows
+/// set in `mask` (an LSB-first row bitmap in the layout's `activeMask`
+/// format such as `filterMask()`; every row when omitted), skipping nulls
+/// and NaNs. `compression` (10 to 10000, default 100) trades size for
+/// accuracy.
+#[wasm_bindgen(js_name = tdigestSketch)]
+pub fn tdigest_sketch(
+    handle: u32,
+    compression: Option<f64>,
+    mask: Option<Vec<u8>>,
+) -> Result<Vec<u8>, KernelError> {
+    let compression = compression.unwrap_or(DEFAULT_COMPRESSION);
+    check_compression(compression)?;
+    let values = masked_values(handle, mask.as_deref(), "tdigestSketch")?;
+    let mut digest = Digest::new(compression);
+    for batch in values.chunks((compression * BUFFER_FACTOR) as usize) {
+        digest.merge(batch.iter().map(|&value| (value, 1.0)).collect());
+    }
+    Ok(digest.to_bytes())
+}
+
+/// The digest of the union of the values behind digests `a` and `b`, at
+/// the larger of their compressions.
+#[wasm_bindgen(js_name = tdigestMerge)]
+pub fn tdigest_merge(a: &[u8], b: &[u8]) -> Result<Vec<u8>, KernelError> {
+    let (a, b) = (Digest::from_bytes(a)?, Digest::from_bytes(b)?);
+    let mut merged = Digest::new(a.compression.max(b.compression));
+    // Keep the exact extremes; centroid means lie inside them.
+    merged.min = a.min.min(b.min);
+    merged.max = a.max.max(b.max);
+    merged.merge(a.centroids.into_iter().chain(b.centroids).collect());
+    Ok(merged.to_bytes())
+}
+
+/// Estimated `quantiles` (each in `[0, 1]`) of the values behind `digest`;
+/// NaN for an empty digest. Quantile 0 and 1 are the exact minimum and
+/// maximum.
+#[wasm_bindgen(js_name = tdigestQuantiles)]
+pub fn tdigest_quantiles(digest: &[u8], quantiles: &[f64]) -> Result<Vec<f64>, KernelError> {
+    check_quantiles(quantiles)?;
+    let digest = Digest::from_bytes(digest)?;
+    Ok(quantiles.iter().map(|&q| digest.quantile(q)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::scrambled;
+
+    /// Gap between each quantile and the normalized rank of its estimate
+    /// among `0..len`, scaled by how close the quantile is to a tail: the
+    /// digest is most accurate at the extremes.
+    fn worst_scaled_error(digest: &[u8], len: usize) -> f64 {
+        let quantiles = [
+            0.001, 0.01, 0.05, 0.1, 0.25, 0.5, 0.75, 0.9, 0.95, 0.99, 0.999,
+        ];
+        let estimates = tdigest_quantiles(digest, &quantiles).unwrap();
+        quantiles
+            .iter()
+            .zip(estimates)
+            .map(|(&q, estimate)| {
+                let error = ((estimate + 0.5) / len as f64 - q).abs();
+                error / (q * (1.0 - q)).sqrt()
+            })
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn quantiles_track_the_sorted_values() {
+        let len = 20_000;
+        let digest = tdigest_sketch(scrambled(0, len), None, None).unwrap();
+        assert!(digest.len() / 16 - 2 <= DEFAULT_COMPRESSION as usize);
+        assert!(worst_scaled_error(&digest, len) < 0.02);
+        let exact = tdigest_quantiles(&digest, &[0.0, 1.0]).unwrap();
+        assert_eq!(exact, [0.0, (len - 1) as f64]);
+    }
+
+    #[test]
+    fn merged_digests_track_the_sorted_values() {
+        let (half, len) = (7_000, 14_000);
+        let a = tdigest_sketch(scrambled(0, half), Some(50.0), None).unwrap();
+        let b = tdigest_sketch(scrambled(half, half), None, None).unwrap();
+        let merged = tdigest_merge(&a, &b).unwrap();
+        let decoded = Digest::from_bytes(&merged).unwrap();
+        assert_eq!((decoded.compression, decoded.total), (100.0, len as f64));
+        assert!(worst_scaled_error(&merged, len) < 0.02);
+        let exact = tdigest_quantiles(&merged, &[0.0, 1.0]).unwrap();
+        assert_eq!(exact, [0.0, (len - 1) as f64]);
+    }
+
+    #[test]
+    fn malformed_digests_are_rejected() {
+        assert!(tdigest_quantiles(&[0; 24], &[0.5]).is_err());
+        assert!(tdigest_quantiles(&[0; 40], &[0.5]).is_err());
+        let empty = tdigest_sketch(scrambled(0, 1), None, Some(vec![0])).unwrap();
+        assert!(tdigest_quantiles(&empty, &[0.5]).unwrap()[0].is_nan());
+        assert!(tdigest_quantiles(&empty, &[1.5]).is_err());
+    }
+}
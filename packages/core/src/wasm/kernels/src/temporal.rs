@@ -0,0 +1,61 @@
+//! Arrow temporal types to the engine's canonical epoch milliseconds.
+//!
+//! Timestamps with a timezone are UTC instants, so only the unit changes.
+//! Timestamps without one are wall-clock values; like arrow-js we read them
+//! as if they were UTC. Sub-millisecond precision survives as the fractional
+//! part of the `f64`.
+
+use crate::error::{ErrorKind, KernelError};
+
+pub(crate) const DAY_MS: f64 = 86_400_000.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum TimeUnit {
+    Second,
+    Millisecond,
+    Microsecond,
+    Nanosecond,
+}
+
+impl TimeUnit {
+    /// From the Arrow `TimeUnit` enum in IPC metadata.
+    pub(crate) fn from_arrow(unit: i16) -> Result<Self, KernelError> {
+        Ok(match unit {
+            0 => TimeUnit::Second,
+            1 => TimeUnit::Millisecond,
+            2 => TimeUnit::Microsecond,
+            3 => TimeUnit::Nanosecond,
+            other => {
+                return Err(KernelError::new(
+                    ErrorKind::MalformedInput,
+                    format!("unknown arrow time unit {other}"),
+                ))
+            }
+        })
+    }
+
+    /// From the `s`/`ms`/`us`/`ns` suffix used in type names such as
+    /// `"timestamp[us]"`.
+    pub(crate) fn parse(unit: &str) -> Option<Self> {
+        Some(match unit {
+            "s" => TimeUnit::Second,
+            "ms" => TimeUnit::Millisecond,
+            "us" => TimeUnit::Microsecond,
+            "ns" => TimeUnit::Nanosecond,
+            _ => return None,
+        })
+    }
+
+    /// Epoch milliseconds for a raw value in this unit. The integer and
+    /// fractional parts are converted separately so large nanosecond values
+    /// keep millisecond exactness.
+    pub(crate) fn to_epoch_ms(self, value: i64) -> f64 {
+        let per_ms = match self {
+            TimeUnit::Second => return value as f64 * 1000.0,
+            TimeUnit::Millisecond => return value as f64,
+            TimeUnit::Microsecond => 1_000,
+            TimeUnit::Nanosecond => 1_000_000,
+        };
+        (value / per_ms) as f64 + (value % per_ms) as f64 / per_ms as f64
+    }
+}
@@ -0,0 +1,274 @@
+//! Minimal Thrift compact-protocol reader.
+//!
+//! Enough to walk Parquet page headers: structs of integer, boolean and
+//! nested struct fields, skipping anything else. Like the FlatBuffers
+//! reader, every read is bounds-checked and reports malformed input as a
+//! [`KernelError`].
+
+use crate::error::{ErrorKind, KernelError};
+
+const BOOLEAN_TRUE: u8 = 1;
+const BOOLEAN_FALSE: u8 = 2;
+const BYTE: u8 = 3;
+const I16: u8 = 4;
+const I32: u8 = 5;
+const I64: u8 = 6;
+const DOUBLE: u8 = 7;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const SET: u8 = 10;
+const MAP: u8 = 11;
+const STRUCT: u8 = 12;
+
+/// Nesting bound for skipped values, so hostile input cannot blow the stack.
+const MAX_DEPTH: usize = 32;
+
+fn malformed(what: &str) -> KernelError {
+    KernelError::new(
+        ErrorKind::MalformedInput,
+        format!("malformed thrift: {what}"),
+    )
+}
+
+/// Header of one struct field.
+#[derive(Clone, Copy)]
+pub(crate) struct FieldHeader {
+    pub(crate) id: i16,
+    kind: u8,
+}
+
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    last_field: Vec<i16>,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Reader {
+            buf,
+            pos: 0,
+            last_field: Vec::new(),
+        }
+    }
+
+    /// Bytes consumed so far.
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn byte(&mut self) -> Result<u8, KernelError> {
+        let byte = *self
+            .buf
+            .get(self.pos)
+            .ok_or_else(|| malformed("unexpected end of input"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, KernelError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(malformed("varint too long"))
+    }
+
+    fn zigzag(&mut self) -> Result<i64, KernelError> {
+        let raw = self.varint()?;
+        Ok((raw >> 1) as i64 ^ -((raw & 1) as i64))
+    }
+
+    pub(crate) fn begin_struct(&mut self) {
+        self.last_field.push(0);
+    }
+
+    /// Next field header, or `None` at the struct's stop byte (which also
+    /// ends the struct).
+    pub(crate) fn field(&mut self) -> Result<Option<FieldHeader>, KernelError> {
+        let byte = self.byte()?;
+        if byte == 0 {
+            self.last_field.pop();
+            return Ok(None);
+        }
+        let kind = byte & 0x0f;
+        let delta = byte >> 4;
+        let id = if delta == 0 {
+            i16::try_from(self.zigzag()?).map_err(|_| malformed("field id out of range"))?
+        } else {
+            let last = self.last_field.last().copied().unwrap_or_default();
+            last.wrapping_add(i16::from(delta))
+        };
+        let last = self
+            .last_field
+            .last_mut()
+            .ok_or_else(|| malformed("field outside struct"))?;
+        *last = id;
+        Ok(Some(FieldHeader { id, kind }))
+    }
+
+    pub(crate) fn bool(&self, field: FieldHeader) -> Result<bool, KernelError> {
+        match field.kind {
+            BOOLEAN_TRUE => Ok(true),
+            BOOLEAN_FALSE => Ok(false),
+            _ => Err(malformed("expected bool field")),
+        }
+    }
+
+    pub(crate) fn i32(&mut self, field: FieldHeader) -> Result<i32, KernelError> {
+        match field.kind {
+            BYTE => Ok(i32::from(self.byte()? as i8)),
+            I16 | I32 => i32::try_from(self.zigzag()?).map_err(|_| malformed("i32 out of range")),
+            _ => Err(malformed("expected integer field")),
+        }
+    }
+
+    pub(crate) fn expect_struct(&mut self, field: FieldHeader) -> Result<(), KernelError> {
+        if field.kind != STRUCT {
+            return Err(malformed("expected struct field"));
+        }
+        self.begin_struct();
+        Ok(())
+    }
+
+    /// Skips the value of a field the caller does not need.
+    pub(crate) fn skip(&mut self, field: FieldHeader) -> Result<(), KernelError> {
+        self.skip_value(field.kind, 0)
+    }
+
+    fn skip_value(&mut self, kind: u8, depth: usize) -> Result<(), KernelError> {
+        if depth > MAX_DEPTH {
+            return Err(malformed("nesting too deep"));
+        }
+        match kind {
+            BOOLEAN_TRUE | BOOLEAN_FALSE => {}
+            BYTE => {
+                self.byte()?;
+            }
+            I16 | I32 | I64 => {
+                self.varint()?;
+            }
+            DOUBLE => self.advance(8)?,
+            BINARY => {
+                let len = self.varint()? as usize;
+                self.advance(len)?;
+            }
+            LIST | SET => {
+                let header = self.byte()?;
+                let mut len = usize::from(header >> 4);
+                if len == 15 {
+                    len = self.varint()? as usize;
+                }
+                let element = header & 0x0f;
+                for _ in 0..len {
+                    self.skip_element(element, depth + 1)?;
+                }
+            }
+            MAP => {
+                let len = self.varint()? as usize;
+                if len > 0 {
+                    let types = self.byte()?;
+                    for _ in 0..len {
+                        self.skip_element(types >> 4, depth + 1)?;
+                        self.skip_element(types & 0x0f, depth + 1)?;
+                    }
+                }
+            }
+            STRUCT => {
+                self.begin_struct();
+                while let Some(field) = self.field()? {
+                    self.skip_value(field.kind, depth + 1)?;
+                }
+            }
+            _ => return Err(malformed("unknown field type")),
+        }
+        Ok(())
+    }
+
+    /// Skips one list, set or map element. Booleans inside collections take
+    /// a full byte, unlike boolean struct fields, whose value is the type.
+    fn skip_element(&mut self, kind: u8, depth: usize) -> Result<(), KernelError> {
+        if kind == BOOLEAN_TRUE || kind == BOOLEAN_FALSE {
+            self.byte()?;
+            return Ok(());
+        }
+        self.skip_value(kind, depth)
+    }
+
+    fn advance(&mut self, len: usize) -> Result<(), KernelError> {
+        if self.buf.len() - self.pos < len {
+            return Err(malformed("unexpected end of input"));
+        }
+        self.pos += len;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads a struct whose field 1 is skipped and field 2 is an `i32`.
+    fn field_after_skipped(bytes: &[u8]) -> Result<i32, KernelError> {
+        let mut reader = Reader::new(bytes);
+        reader.begin_struct();
+        let skipped = reader
+            .field()?
+            .ok_or_else(|| malformed("missing field 1"))?;
+        assert_eq!(skipped.id, 1);
+        reader.skip(skipped)?;
+        let field = reader
+            .field()?
+            .ok_or_else(|| malformed("missing field 2"))?;
+        assert_eq!(field.id, 2);
+        let value = reader.i32(field)?;
+        assert!(reader.field()?.is_none());
+        assert_eq!(reader.position(), bytes.len());
+        Ok(value)
+    }
+
+    #[test]
+    fn skips_maps_with_boolean_values() {
+        // 1: map<i32, bool> {3: true, 4: false}, 2: i32 21.
+        let bytes = [0x1b, 0x02, 0x51, 0x06, 0x01, 0x08, 0x02, 0x15, 0x2a, 0x00];
+        assert_eq!(field_after_skipped(&bytes).unwrap(), 21);
+    }
+
+    #[test]
+    fn skips_maps_with_boolean_keys() {
+        // 1: map<bool, binary> {true: "ab"}, 2: i32 -3.
+        let bytes = [0x1b, 0x01, 0x18, 0x01, 0x02, b'a', b'b', 0x15, 0x05, 0x00];
+        assert_eq!(field_after_skipped(&bytes).unwrap(), -3);
+    }
+
+    #[test]
+    fn skips_lists_of_booleans_and_structs() {
+        // 1: list<bool> [true, false, true], 2: i32 7.
+        let bytes = [0x19, 0x31, 0x01, 0x02, 0x01, 0x15, 0x0e, 0x00];
+        assert_eq!(field_after_skipped(&bytes).unwrap(), 7);
+        // 1: list<struct> [{1: bool true}, {}], 2: i32 1.
+        let bytes = [0x19, 0x2c, 0x11, 0x00, 0x00, 0x15, 0x02, 0x00];
+        assert_eq!(field_after_skipped(&bytes).unwrap(), 1);
+    }
+
+    #[test]
+    fn truncated_collections_are_malformed() {
+        // A map promising two boolean values but holding one.
+        let bytes = [0x1b, 0x02, 0x51, 0x06, 0x01];
+        assert!(field_after_skipped(&bytes).is_err());
+        // A list claiming 2^40 boolean elements.
+        let bytes = [0x19, 0xf1, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20, 0x01];
+        assert!(field_after_skipped(&bytes).is_err());
+    }
+
+    #[test]
+    fn nesting_is_bounded() {
+        let mut bytes = vec![0x1c];
+        bytes.extend(std::iter::repeat_n(0x1c, 64));
+        assert!(field_after_skipped(&bytes).is_err());
+    }
+}
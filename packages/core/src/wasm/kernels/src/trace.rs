@@ -0,0 +1,195 @@
+//! Optional bridge from the `tracing` crate to browser devtools.
+//!
+//! With the `tracing` feature enabled, `initTracing()` installs a small
+//! subscriber that prints events through `console.debug` and turns every span
+//! into a `performance.mark`/`performance.measure` pair, so kernel phases show
+//! up in the DevTools performance flame chart. Without the feature the
+//! `kernel_span!`/`kernel_event!` macros compile to nothing.
+
+/// Opens a debug-level span for a kernel phase; the span closes when the
+/// returned guard drops.
+macro_rules! kernel_span {
+    ($name:literal $(, $($fields:tt)*)?) => {{
+        #[cfg(feature = "tracing")]
+        let guard = tracing::debug_span!($name $(, $($fields)*)?).entered();
+        #[cfg(not(feature = "tracing"))]
+        let guard = $crate::trace::NoSpan;
+        guard
+    }};
+}
+
+/// Stand-in span guard when tracing is compiled out.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
+/// Emits a debug-level event inside the current span.
+macro_rules! kernel_event {
+    ($($args:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($args)*);
+    }};
+}
+
+#[cfg(feature = "tracing")]
+pub use bridge::init_tracing;
+
+#[cfg(feature = "tracing")]
+mod bridge {
+    use crate::error::KernelError;
+    use std::collections::HashMap;
+    use std::fmt::{self, Write};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Level, Metadata, Subscriber};
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = console, js_name = debug)]
+        fn console_debug(message: &str);
+        #[wasm_bindgen(js_namespace = performance, js_name = mark)]
+        fn performance_mark(name: &str);
+        #[wasm_bindgen(catch, js_namespace = performance, js_name = measure)]
+        fn performance_measure(name: &str, start: &str, end: &str) -> Result<JsValue, JsValue>;
+        #[wasm_bindgen(js_namespace = performance, js_name = clearMarks)]
+        fn performance_clear_marks(name: &str);
+    }
+
+    struct SpanState {
+        label: String,
+        refs: usize,
+    }
+
+    struct BrowserSubscriber {
+        max_level: Level,
+        next_id: AtomicU64,
+        spans: Mutex<HashMap<u64, SpanState>>,
+    }
+
+    /// Collects span/event fields as `name=value` pairs.
+    struct FieldWriter<'a>(&'a mut String);
+
+    impl Visit for FieldWriter<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "message" {
+                let _ = write!(self.0, " {value:?}");
+            } else {
+                let _ = write!(self.0, " {}={value:?}", field.name());
+            }
+        }
+    }
+
+    fn start_mark(id: u64) -> String {
+        format!("cfx-span-{id}-start")
+    }
+
+    fn end_mark(id: u64) -> String {
+        format!("cfx-span-{id}-end")
+    }
+
+    impl Subscriber for BrowserSubscriber {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            *metadata.level() <= self.max_level
+        }
+
+        fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let mut label = format!("cfx:{}", attrs.metadata().name());
+            attrs.record(&mut FieldWriter(&mut label));
+            if let Ok(mut spans) = self.spans.lock() {
+                spans.insert(id, SpanState { label, refs: 1 });
+            }
+            Id::from_u64(id)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            if let Ok(mut spans) = self.spans.lock() {
+                if let Some(state) = spans.get_mut(&span.into_u64()) {
+                    values.record(&mut FieldWriter(&mut state.label));
+                }
+            }
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let metadata = event.metadata();
+            let mut message = format!("[crossfilterx] {} {}:", metadata.level(), metadata.target());
+            event.record(&mut FieldWriter(&mut message));
+            console_debug(&message);
+        }
+
+        fn enter(&self, span: &Id) {
+            performance_mark(&start_mark(span.into_u64()));
+        }
+
+        fn exit(&self, span: &Id) {
+            let id = span.into_u64();
+            let (start, end) = (start_mark(id), end_mark(id));
+            performance_mark(&end);
+            if let Ok(spans) = self.spans.lock() {
+                if let Some(state) = spans.get(&id) {
+                    let _ = performance_measure(&state.label, &start, &end);
+                }
+            }
+            performance_clear_marks(&start);
+            performance_clear_marks(&end);
+        }
+
+        fn clone_span(&self, span: &Id) -> Id {
+            if let Ok(mut spans) = self.spans.lock() {
+                if let Some(state) = spans.get_mut(&span.into_u64()) {
+                    state.refs += 1;
+                }
+            }
+            span.clone()
+        }
+
+        fn try_close(&self, span: Id) -> bool {
+            let Ok(mut spans) = self.spans.lock() else {
+                return false;
+            };
+            let id = span.into_u64();
+            let closed = match spans.get_mut(&id) {
+                Some(state) => {
+                    state.refs -= 1;
+                    state.refs == 0
+                }
+                None => false,
+            };
+            if closed {
+                spans.remove(&id);
+            }
+            closed
+        }
+    }
+
+    fn parse_level(level: Option<String>) -> Result<Level, KernelError> {
+        match level.as_deref().map(str::to_ascii_lowercase).as_deref() {
+            None | Some("debug") => Ok(Level::DEBUG),
+            Some("trace") => Ok(Level::TRACE),
+            Some("info") => Ok(Level::INFO),
+            Some("warn") => Ok(Level::WARN),
+            Some("error") => Ok(Level::ERROR),
+            Some(other) => Err(KernelError::invalid_argument(format!(
+                "unknown tracing level: {other}"
+            ))),
+        }
+    }
+
+    /// Installs the browser subscriber as the global `tracing` default.
+    /// `maxLevel` is one of `trace`, `debug` (default), `info`, `warn`,
+    /// `error`. Fails if a subscriber is already installed.
+    #[wasm_bindgen(js_name = initTracing)]
+    pub fn init_tracing(max_level: Option<String>) -> Result<(), KernelError> {
+        let subscriber = BrowserSubscriber {
+            max_level: parse_level(max_level)?,
+            next_id: AtomicU64::new(1),
+            spans: Mutex::new(HashMap::new()),
+        };
+        tracing::subscriber::set_global_default(subscriber)
+            .map_err(|_| KernelError::invalid_state("a tracing subscriber is already installed"))
+    }
+}
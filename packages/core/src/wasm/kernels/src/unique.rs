@@ -0,0 +1,149 @@
+//! Unique values of a column.
+//!
+//! Legend pickers and ordinal scales need a column's distinct values in
+//! order, which for an arbitrary column used to mean a JS `Set` and sort
+//! over every row. `uniqueValues` sorts the rows inside wasm, in
+//! `sortColumns` order, and keeps the first row of each run of equal values.
+//! Values compare as in `distinctRows`: NaNs equal each other and `-0`
+//! equals `0`. Nulls are left out of the values and reported apart.
+
+use wasm_bindgen::prelude::*;
+
+use crate::columns::{self, Values};
+use crate::distinct::encode;
+use crate::error::KernelError;
+use crate::sort::sort_by_column;
+
+/// Distinct values in ascending order, each with the row it first occurs
+/// at.
+#[wasm_bindgen]
+pub struct UniqueValues {
+    rows: Vec<u32>,
+    values: Vec<f64>,
+    labels: Vec<String>,
+    codes: Vec<u32>,
+    null_row: Option<u32>,
+}
+
+#[wasm_bindgen]
+impl UniqueValues {
+    /// First-occurrence row of each value.
+    #[wasm_bindgen(getter)]
+    pub fn rows(&self) -> Vec<u32> {
+        self.rows.clone()
+    }
+
+    /// The values of a numeric column (64-bit integers and decimals
+    /// rounded to `number`); empty for string columns.
+    #[wasm_bindgen(getter)]
+    pub fn values(&self) -> Vec<f64> {
+        self.values.clone()
+    }
+
+    /// The values of a string or dictionary column; empty otherwise.
+    #[wasm_bindgen(getter)]
+    pub fn labels(&self) -> Vec<String> {
+        self.labels.clone()
+    }
+
+    /// Dictionary index of each value of a dictionary column; empty
+    /// otherwise.
+    #[wasm_bindgen(getter)]
+    pub fn codes(&self) -> Vec<u32> {
+        self.codes.clone()
+    }
+
+    /// First null row; `undefined` when the column has no nulls.
+    #[wasm_bindgen(getter = nullRow)]
+    pub fn null_row(&self) -> Option<u32> {
+        self.null_row
+    }
+}
+
+impl UniqueValues {
+    /// Drops the last value, to replace it with an earlier row.
+    fn pop(&mut self) {
+        self.rows.pop();
+        self.values.pop();
+        self.labels.pop();
+        self.codes.pop();
+    }
+}
+
+/// The distinct values of the column behind `handle`, ascending (strings
+/// by bytes, NaN last), with the row each first occurs at.
+#[wasm_bindgen(js_name = uniqueValues)]
+pub fn unique_values(handle: u32) -> Result<UniqueValues, KernelError> {
+    columns::with_column(handle, |column| {
+        let mut order = (0..column.len as u32).collect();
+        sort_by_column(column, false, &mut order);
+        let mut unique = UniqueValues {
+            rows: Vec::new(),
+            values: Vec::new(),
+            labels: Vec::new(),
+            codes: Vec::new(),
+            null_row: None,
+        };
+        let (mut previous, mut key) = (Vec::new(), Vec::new());
+        for row in order {
+            let row_index = row as usize;
+            if !column.is_valid(row_index) {
+                // Nulls sort last, in row order.
+                unique.null_row = Some(row);
+                break;
+            }
+            key.clear();
+            encode(column, row_index, &mut key);
+            if !unique.rows.is_empty() && key == previous {
+                // Equal values may sort apart (`-0` before `0`, NaN
+                // payloads), so a run keeps its smallest row.
+                if unique.rows.last().is_some_and(|&first| first < row) {
+                    continue;
+                }
+                unique.pop();
+            }
+            std::mem::swap(&mut previous, &mut key);
+            unique.rows.push(row);
+            let label = || {
+                let bytes = column.values.label(row_index).unwrap_or_default();
+                String::from_utf8_lossy(bytes).into_owned()
+            };
+            match &column.values {
+                Values::Dictionary { indices, .. } => {
+                    unique.codes.push(indices[row_index]);
+                    unique.labels.push(label());
+                }
+                Values::Utf8 { .. } => unique.labels.push(label()),
+                values => unique
+                    .values
+                    .push(values.number(row_index).unwrap_or(f64::NAN)),
+            }
+        }
+        Ok(unique)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::fixtures::floats;
+
+    #[test]
+    fn signed_zeros_report_their_first_row() {
+        let unique = unique_values(floats(vec![0.0, -0.0, 1.0, -0.0], None)).unwrap();
+        assert_eq!(unique.rows(), [0, 2]);
+        assert_eq!(unique.values(), [0.0, 1.0]);
+        let unique = unique_values(floats(vec![-0.0, 0.0], None)).unwrap();
+        assert_eq!(unique.rows(), [0]);
+    }
+
+    #[test]
+    fn nans_and_nulls_come_last() {
+        let nan = f64::from_bits(0x7ff8_0000_0000_0001);
+        let values = vec![2.0, nan, 5.0, f64::NAN, 2.0, 7.0];
+        let unique = unique_values(floats(values, Some(vec![0b01_1111]))).unwrap();
+        assert_eq!(unique.rows(), [0, 2, 1]);
+        assert!(unique.values()[2].is_nan());
+        assert_eq!(unique.null_row(), Some(5));
+    }
+}
@@ -0,0 +1,166 @@
+//! Strict validation mode.
+//!
+//! By default kernels are best-effort: `accumulateBins` drops rows whose
+//! bin is out of range (counting them in the metrics), time-series kernels
+//! carry infinities through. That keeps dashboards drawing, but hides the
+//! bug that produced the input. With `setStrictMode(true)` the kernels run
+//! their full input checks (lengths match, bins in range, sorted inputs
+//! ascending, finite floats) and fail on the first call with violations;
+//! the error names the kernel and the total, and `takeDiagnostics` returns
+//! the full report: each failed check with its violation count and first
+//! offending indices.
+
+use std::cell::{Cell, RefCell};
+use wasm_bindgen::prelude::*;
+
+use crate::error::KernelError;
+use crate::recovery;
+
+/// Offending indices kept per check.
+const FIRST_INDICES: usize = 8;
+
+thread_local! {
+    static STRICT: Cell<bool> = const { Cell::new(false) };
+    static LAST: RefCell<Option<Diagnostics>> = const { RefCell::new(None) };
+}
+
+/// Turns strict mode off and drops the last report; see `hardReset`.
+pub(crate) fn reset() {
+    STRICT.with(|cell| cell.set(false));
+    LAST.with(|last| recovery::reset_cell(last, None));
+}
+
+struct Violation {
+    check: &'static str,
+    count: u32,
+    first: Vec<u32>,
+}
+
+/// Input checks that failed in one strict-mode call.
+#[wasm_bindgen]
+pub struct Diagnostics {
+    kernel: &'static str,
+    violations: Vec<Violation>,
+}
+
+#[wasm_bindgen]
+impl Diagnostics {
+    /// Entry point that ran the checks, e.g. `"accumulateBins"`.
+    #[wasm_bindgen(getter)]
+    pub fn kernel(&self) -> String {
+        self.kernel.to_string()
+    }
+
+    /// Name of each failed check, e.g. `"binsInRange"`.
+    #[wasm_bindgen(getter)]
+    pub fn checks(&self) -> Vec<String> {
+        self.violations
+            .iter()
+            .map(|violation| violation.check.to_string())
+            .collect()
+    }
+
+    /// Number of offending elements per check.
+    #[wasm_bindgen(getter)]
+    pub fn counts(&self) -> Vec<u32> {
+        self.violations
+            .iter()
+            .map(|violation| violation.count)
+            .collect()
+    }
+
+    /// Up to 8 first offending indices per check: those of check `c` at
+    /// `firstIndices[offsets[c]..offsets[c + 1]]`.
+    #[wasm_bindgen(getter = firstIndices)]
+    pub fn first_indices(&self) -> Vec<u32> {
+        self.violations
+            .iter()
+            .flat_map(|violation| violation.first.iter().copied())
+            .collect()
+    }
+
+    /// Start of each check's indices, plus the total.
+    #[wasm_bindgen(getter)]
+    pub fn offsets(&self) -> Vec<u32> {
+        let mut offsets = vec![0];
+        for violation in &self.violations {
+            offsets.push(offsets[offsets.len() - 1] + violation.first.len() as u32);
+        }
+        offsets
+    }
+}
+
+impl Diagnostics {
+    /// Records check `check` failing at each of `offending`.
+    pub(crate) fn check(&mut self, check: &'static str, offending: impl Iterator<Item = usize>) {
+        let mut violation = Violation {
+            check,
+            count: 0,
+            first: Vec::new(),
+        };
+        for index in offending {
+            violation.count += 1;
+            if violation.first.len() < FIRST_INDICES {
+                violation.first.push(index as u32);
+            }
+        }
+        if violation.count > 0 {
+            self.violations.push(violation);
+        }
+    }
+}
+
+/// In strict mode, runs `checks` for `kernel` and fails the call when any
+/// of them found violations, keeping the report for `takeDiagnostics`.
+/// Does nothing otherwise.
+pub(crate) fn strict(
+    kernel: &'static str,
+    checks: impl FnOnce(&mut Diagnostics),
+) -> Result<(), KernelError> {
+    if !STRICT.with(Cell::get) {
+        return Ok(());
+    }
+    let mut diagnostics = Diagnostics {
+        kernel,
+        violations: Vec::new(),
+    };
+    checks(&mut diagnostics);
+    let Some(first) = diagnostics.violations.first() else {
+        return Ok(());
+    };
+    let error = KernelError::invalid_argument(format!(
+        "{kernel} failed strict validation ({})",
+        first.check
+    ))
+    .in_kernel(kernel)
+    .with("checks", diagnostics.violations.len() as f64)
+    .with(
+        "violations",
+        diagnostics
+            .violations
+            .iter()
+            .map(|violation| f64::from(violation.count))
+            .sum(),
+    )
+    .with("firstIndex", f64::from(first.first[0]));
+    LAST.with(|last| *last.borrow_mut() = Some(diagnostics));
+    Err(error)
+}
+
+/// Turns strict validation on or off for every later call.
+#[wasm_bindgen(js_name = setStrictMode)]
+pub fn set_strict_mode(enabled: bool) {
+    STRICT.with(|cell| cell.set(enabled));
+}
+
+#[wasm_bindgen(js_name = strictMode)]
+pub fn strict_mode() -> bool {
+    STRICT.with(Cell::get)
+}
+
+/// The report of the last call that failed strict validation, once; later
+/// calls return nothing until another call fails.
+#[wasm_bindgen(js_name = takeDiagnostics)]
+pub fn take_diagnostics() -> Option<Diagnostics> {
+    LAST.with(|last| last.borrow_mut().take())
+}
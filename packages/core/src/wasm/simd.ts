@@ -12,13 +12,32 @@ import type { HistogramView } from '../memory/layout';
 
 type Delta = -1 | 1;
 
+/** Mirrors the `Metrics` class exported by the kernels (see `metrics.rs`). */
+type KernelMetrics = {
+  readonly flushes: number;
+  readonly evictions: number;
+  readonly finalFlushes: number;
+  readonly bins: number;
+  readonly rows: number;
+  readonly allocations: number;
+  readonly invocations: number;
+  readonly droppedBins: number;
+  readonly droppedSamples: Uint32Array;
+  readonly rowOffset: number;
+  readonly timing: { readonly kernelMs: number; readonly simdMs: number; readonly tailMs: number };
+  readonly flushSizes: { readonly binsPerFlush: Float64Array; readonly rowsPerFlush: Float64Array };
+  toJSON(): Record<string, unknown>;
+};
+
 type HistogramBindings = {
   init_panic_hook: () => void;
   scratchBuffer: (size: number) => Uint16Array;
-  accumulateScratch: (len: number, binCount: number) => Uint32Array;
-  accumulateBins: (bins: Uint16Array, binCount: number) => Uint32Array;
+  accumulateScratch: (len: number, binCount: number, dimension?: number) => Uint32Array;
+  accumulateBins: (bins: Uint16Array, binCount: number, dimension?: number) => Uint32Array;
+  accumulateBatch?: (requests: Uint32Array) => Uint32Array[];
+  setStrategy?: (strategy: number) => void;
   resetMetrics?: () => void;
-  takeMetrics?: () => unknown;
+  takeMetrics?: () => KernelMetrics;
 };
 
 let wasmInitPromise: Promise<HistogramBindings | null> | null = null;
@@ -232,7 +251,7 @@ function applyWithWasm(
           wasm.resetMetrics();
         }
         const wasmStart = profileShards ? now() : 0;
-        const accum = wasm.accumulateScratch(span, binCount);
+        const accum = wasm.accumulateScratch(span, binCount, dim);
         if (profileShards) {
           totalWasm += now() - wasmStart;
         }
@@ -244,7 +263,7 @@ function applyWithWasm(
           histograms[dim].back[bin] += contribution;
         }
         if (profileShards && typeof wasm.takeMetrics === 'function') {
-          const metrics = wasm.takeMetrics();
+          const metrics = wasm.takeMetrics().toJSON();
           if (!aggregatedMetrics) {
             aggregatedMetrics = metrics;
           } else {
@@ -304,12 +323,18 @@ function recordShardProfile(entry: {
 
 function mergeShardMetrics(target: unknown, source: unknown) {
   if (!target || !source) return;
-  const t = target as Record<string, number>;
-  const s = source as Record<string, number>;
+  const t = target as Record<string, unknown>;
+  const s = source as Record<string, unknown>;
   for (const key of Object.keys(s)) {
-    if (typeof s[key] !== 'number') continue;
+    const value = s[key];
+    if (value && typeof value === 'object') {
+      if (!t[key] || typeof t[key] !== 'object') t[key] = {};
+      mergeShardMetrics(t[key], value);
+      continue;
+    }
+    if (typeof value !== 'number') continue;
     const current = typeof t[key] === 'number' ? (t[key] as number) : 0;
-    t[key] = current + (s[key] as number);
+    t[key] = current + value;
   }
 }
 